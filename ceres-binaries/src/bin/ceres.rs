@@ -11,6 +11,8 @@ fn main() {
             (about: "Uses the build.lua file in the current directory to build a map.")
             (setting: clap::AppSettings::TrailingVarArg)
             (@arg dir: --dir -d +takes_value "Sets the project directory.")
+            (@arg check_reproducible: --("check-reproducible")
+                "Builds twice and checks that both builds produce identical files.")
             (@arg BUILD_ARGS: ... "Arguments to pass to the build script.")
         )
         (@subcommand run =>
@@ -25,6 +27,24 @@ fn main() {
             (@arg script: +required +takes_value)
             (@arg BUILD_ARGS: ... "Arguments to pass to the build script.")
         )
+        (@subcommand diff =>
+            (about: "Lists the files which differ between two maps, with details where known.")
+            (@arg json: --json "Prints the differences as JSON.")
+            (@arg OLD: +required +takes_value "The map to compare against.")
+            (@arg NEW: +required +takes_value "The changed map.")
+        )
+        (@subcommand mpq =>
+            (about: "Inspects MPQ archives and WC3 maps.")
+            (setting: clap::AppSettings::SubcommandRequiredElseHelp)
+            (@subcommand ls =>
+                (about: "Lists every file in the archive along with its sizes, flags and hash.")
+                (@arg sort: --sort -s +takes_value
+                    possible_value[block name size compressed] "Sets the sort order.")
+                (@arg listfile: --listfile -l +takes_value +multiple number_of_values(1)
+                    "Additional listfile used to recover file names.")
+                (@arg ARCHIVE: +required +takes_value "The archive to list.")
+            )
+        )
     )
     .get_matches();
 
@@ -56,7 +76,11 @@ fn run_build(arg: &clap::ArgMatches, mode: ceres_core::CeresRunMode) -> Result<(
         .map(std::iter::Iterator::collect)
         .unwrap_or_else(Vec::new);
 
-    ceres_core::run_build_script(mode, project_dir, script_args)?;
+    if arg.is_present("check_reproducible") {
+        ceres_core::check_reproducible(project_dir, script_args)?;
+    } else {
+        ceres_core::run_build_script(mode, project_dir, script_args)?;
+    }
 
     Ok(())
 }
@@ -83,6 +107,97 @@ fn exec(arg: &clap::ArgMatches) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+fn mpq_ls(arg: &clap::ArgMatches) -> Result<(), anyhow::Error> {
+    let path = arg.value_of("ARCHIVE").unwrap();
+    let file = std::fs::File::open(path)?;
+    let mut archive = ceres_mpq::Archive::open(std::io::BufReader::new(file))?;
+
+    let mut listfiles = Vec::new();
+    for listfile in arg.values_of("listfile").into_iter().flatten() {
+        listfiles.extend(ceres_core::listfile::read_listfile(listfile)?);
+    }
+
+    if !listfiles.is_empty() || ceres_core::listfile::needs_recovery(&mut archive) {
+        ceres_core::listfile::recover_names(&mut archive, &listfiles);
+    }
+
+    let mut entries = archive.entries();
+    match arg.value_of("sort") {
+        Some("name") => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+        Some("size") => entries.sort_by(|a, b| b.file_size.cmp(&a.file_size)),
+        Some("compressed") => entries.sort_by(|a, b| b.compressed_size.cmp(&a.compressed_size)),
+        _ => {}
+    }
+
+    println!(
+        "{:>6}  {:>10}  {:>10}  {:>6}  {:<5}  {:<16}  Name",
+        "Block", "Size", "Packed", "Ratio", "Flags", "Hash"
+    );
+
+    for entry in &entries {
+        let ratio = if entry.file_size > 0 {
+            format!(
+                "{:.1}%",
+                f64::from(entry.compressed_size) / f64::from(entry.file_size) * 100.0
+            )
+        } else {
+            "-".into()
+        };
+
+        let flags: String = [
+            (entry.is_compressed(), 'C'),
+            (entry.is_encrypted(), 'E'),
+            (entry.is_key_adjusted(), 'K'),
+            (entry.is_single_unit(), 'S'),
+            (entry.is_deleted(), 'D'),
+        ]
+        .iter()
+        .map(|(set, c)| if *set { *c } else { '-' })
+        .collect();
+
+        println!(
+            "{:>6}  {:>10}  {:>10}  {:>6}  {:<5}  {:<16}  {}",
+            entry.block_index,
+            entry.file_size,
+            entry.compressed_size,
+            ratio,
+            flags,
+            entry.hash_string(),
+            entry.name.as_deref().unwrap_or("<unknown>")
+        );
+    }
+
+    let size: u64 = entries.iter().map(|e| u64::from(e.file_size)).sum();
+    let compressed_size: u64 = entries.iter().map(|e| u64::from(e.compressed_size)).sum();
+    let unnamed = entries.iter().filter(|e| e.name.is_none()).count();
+
+    println!();
+    println!(
+        "{} blocks ({} unnamed), {} bytes, {} bytes packed",
+        entries.len(),
+        unnamed,
+        size,
+        compressed_size
+    );
+
+    Ok(())
+}
+
+fn diff(arg: &clap::ArgMatches) -> Result<(), anyhow::Error> {
+    let old = arg.value_of("OLD").unwrap();
+    let new = arg.value_of("NEW").unwrap();
+
+    let diff = ceres_core::diff::diff_maps(old, new)?;
+
+    if arg.is_present("json") {
+        println!("{}", diff.to_json());
+    } else {
+        print!("{}", diff);
+    }
+
+    Ok(())
+}
+
 fn run(matches: clap::ArgMatches) -> Result<(), anyhow::Error> {
     if let Some(arg) = matches.subcommand_matches("build") {
         run_build(arg, ceres_core::CeresRunMode::Build)?;
@@ -90,6 +205,12 @@ fn run(matches: clap::ArgMatches) -> Result<(), anyhow::Error> {
         run_build(arg, ceres_core::CeresRunMode::RunMap)?;
     } else if let Some(arg) = matches.subcommand_matches("exec") {
         exec(arg)?;
+    } else if let Some(arg) = matches.subcommand_matches("diff") {
+        diff(arg)?;
+    } else if let Some(arg) = matches.subcommand_matches("mpq") {
+        if let Some(arg) = arg.subcommand_matches("ls") {
+            mpq_ls(arg)?;
+        }
     }
 
     Ok(())
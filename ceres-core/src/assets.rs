@@ -0,0 +1,447 @@
+//! Validation of the files a map refers to.
+//!
+//! Paths come from path-typed object data fields (models, icons, textures),
+//! from the sounds in `war3map.w3s`, from string literals in the map script
+//! which end in an asset extension, and from the textures of the models in
+//! the map. Each of them has to exist either in the map or in the game.
+//! The game's files are taken from the paths which the bundled game data refers to,
+//! plus any listfiles given by the user. Paths into the game's storage, like
+//! `war3.w3mod:units\human\footman\footman.mdx`, are not checked.
+//!
+//! The bundled paths are not a complete listfile of the game, so stock files which
+//! neither objects nor the editor data refer to, like some UI textures, are reported
+//! as missing unless a listfile names them. Such a listfile can be exported from
+//! the game's CASC storage with tools like CascView.
+//!
+//! Imported files which nothing refers to are reported as unused.
+
+use std::collections::HashSet;
+
+use ceres_formats::error::FormatError;
+use ceres_formats::model::Model;
+use ceres_formats::object::{FieldKind, Object, Value};
+use ceres_formats::objectstore::ObjectStore;
+use ceres_formats::parser::{mdl, mdx, w3s};
+use ceres_formats::ObjectId;
+
+use crate::listfile::{extension, is_scanned, path_variants, referenced_paths, string_literals};
+use crate::vfs::{is_map_file, to_archive_path, Vfs};
+
+/// Script string literals with these extensions are taken as asset paths.
+const ASSET_EXTENSIONS: &[&str] = &[
+    "mdx", "mdl", "blp", "tga", "dds", "mp3", "wav", "flac", "ogg",
+];
+
+const SCRIPTS: &[&str] = &[
+    "war3map.lua",
+    "war3map.j",
+    "scripts/war3map.lua",
+    "scripts/war3map.j",
+];
+
+/// Where a path is referred to from.
+#[derive(Debug)]
+pub(crate) enum AssetSource {
+    Object {
+        id:    ObjectId,
+        field: String,
+        level: Option<u32>,
+    },
+    Sound {
+        name: String,
+    },
+    Script {
+        file: String,
+    },
+    Model {
+        file: String,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) struct AssetReference {
+    pub(crate) path:   String,
+    pub(crate) source: AssetSource,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct AssetReport {
+    /// References to files which neither the map nor the game has.
+    pub(crate) missing: Vec<AssetReference>,
+    /// Imported files which nothing refers to.
+    pub(crate) unused:  Vec<String>,
+}
+
+/// Reads an MDX or MDL model, depending on its contents.
+pub(crate) fn read_model(contents: &[u8]) -> Result<Model, FormatError> {
+    if contents.starts_with(b"MDLX") {
+        mdx::read::read_mdx(contents)
+    } else {
+        mdl::read::read_mdl(contents)
+    }
+}
+
+fn asset_key(path: &str) -> String {
+    to_archive_path(path).to_ascii_lowercase()
+}
+
+/// Values of empty path fields, which refer to nothing.
+fn is_empty_path(path: &str) -> bool {
+    path.is_empty() || path == "_"
+}
+
+/// Paths which name a file in the game's storage explicitly, and can't be in the map.
+fn is_storage_path(path: &str) -> bool {
+    path.to_ascii_lowercase().starts_with("war3.w3mod")
+}
+
+/// Paths in the path-typed fields of an object, with the name and level of the field.
+/// Fields holding lists have one entry for every path.
+fn object_paths(object: &Object) -> Vec<(&'static str, Option<u32>, String)> {
+    let metadata = w3data::metadata();
+    let mut paths = Vec::new();
+
+    for (id, field) in object.fields() {
+        let desc = match metadata.field_by_id(*id) {
+            Some(desc) if desc.is_path() => desc,
+            _ => continue,
+        };
+
+        let values: Vec<(Option<u32>, &Value)> = match &field.kind {
+            FieldKind::Simple { value } => vec![(None, value)],
+            FieldKind::Leveled { values } => values
+                .iter()
+                .map(|value| (Some(value.level), &value.value))
+                .collect(),
+        };
+
+        for (level, value) in values {
+            if let Value::String(value) = value {
+                paths.extend(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|path| !is_empty_path(path))
+                        .map(|path| (desc.variant.name(), level, path.to_string())),
+                );
+            }
+        }
+    }
+
+    paths
+}
+
+/// Keys of the files the game has, as far as we know.
+fn stock_assets(listfiles: &[String]) -> HashSet<String> {
+    let mut assets: HashSet<String> = listfiles.iter().map(|path| asset_key(path)).collect();
+
+    for path in w3data::stock_paths() {
+        assets.extend(path_variants(path).iter().map(|path| asset_key(path)));
+    }
+
+    for object in w3data::data().objects() {
+        for (_, _, path) in object_paths(object) {
+            assets.extend(path_variants(&path).iter().map(|path| asset_key(path)));
+        }
+    }
+
+    assets
+}
+
+fn collect_references(vfs: &mut Vfs, stores: &[&ObjectStore]) -> Vec<AssetReference> {
+    let mut references = Vec::new();
+
+    for store in stores {
+        for object in store.objects() {
+            let object = object.borrow();
+
+            for (field, level, path) in object_paths(&object) {
+                references.push(AssetReference {
+                    path,
+                    source: AssetSource::Object {
+                        id: object.id(),
+                        field: field.into(),
+                        level,
+                    },
+                });
+            }
+        }
+    }
+
+    if let Ok(data) = vfs.read_file("war3map.w3s") {
+        match w3s::read::read_sounds(&data) {
+            Ok(file) => references.extend(
+                file.sounds
+                    .into_iter()
+                    .filter(|sound| !is_empty_path(&sound.file))
+                    .map(|sound| AssetReference {
+                        path:   sound.file,
+                        source: AssetSource::Sound { name: sound.name },
+                    }),
+            ),
+            Err(error) => eprintln!("assets: could not read war3map.w3s: {}", error),
+        }
+    }
+
+    for script in SCRIPTS {
+        let contents = match vfs.read_file(script) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+
+        references.extend(
+            string_literals(&contents)
+                .into_iter()
+                .filter(|literal| ASSET_EXTENSIONS.contains(&extension(literal).as_str()))
+                .map(|path| AssetReference {
+                    path,
+                    source: AssetSource::Script {
+                        file: script.to_string(),
+                    },
+                }),
+        );
+    }
+
+    for file in vfs.list_files() {
+        let ext = extension(&file);
+        if ext != "mdx" && ext != "mdl" {
+            continue;
+        }
+
+        let model = match vfs.read_file(&file).map(|contents| read_model(&contents)) {
+            Ok(Ok(model)) => model,
+            Ok(Err(error)) => {
+                eprintln!("assets: could not read {}: {}", file, error);
+                continue;
+            }
+            Err(_) => continue,
+        };
+
+        references.extend(model.texture_paths().map(|path| AssetReference {
+            path:   path.to_string(),
+            source: AssetSource::Model { file: file.clone() },
+        }));
+    }
+
+    references
+}
+
+/// Checks the files referred to by the map, given the map's object stores
+/// and listfiles of files in the game.
+pub(crate) fn validate_assets(
+    vfs: &mut Vfs,
+    stores: &[&ObjectStore],
+    listfiles: &[String],
+) -> AssetReport {
+    let stock = stock_assets(listfiles);
+    let files = vfs.list_files();
+    let file_keys: HashSet<String> = files.iter().map(|path| asset_key(path)).collect();
+
+    let references = collect_references(vfs, stores);
+    let mut used = HashSet::new();
+    let mut report = AssetReport::default();
+
+    for reference in references {
+        if is_storage_path(&reference.path) {
+            continue;
+        }
+
+        let variants: Vec<String> = path_variants(&reference.path)
+            .iter()
+            .map(|path| asset_key(path))
+            .collect();
+
+        let exists = variants
+            .iter()
+            .any(|key| file_keys.contains(key) || stock.contains(key));
+
+        used.extend(variants);
+
+        if !exists {
+            report.missing.push(reference);
+        }
+    }
+
+    // models refer to their textures, UI definitions to other definitions, and so on
+    for file in &files {
+        if !is_scanned(file) {
+            continue;
+        }
+
+        if let Ok(contents) = vfs.read_file(file) {
+            for path in referenced_paths(file, &contents) {
+                used.extend(path_variants(&path).iter().map(|path| asset_key(path)));
+            }
+        }
+    }
+
+    // imports which replace a file of the game are used by the game
+    report.unused = files
+        .into_iter()
+        .filter(|file| !is_map_file(file))
+        .filter(|file| {
+            let key = asset_key(file);
+
+            !used.contains(&key) && !stock.contains(&key)
+        })
+        .collect();
+
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    use ceres_formats::sounds::{Sound, SoundsFile};
+    use ceres_formats::ObjectKind;
+
+    use crate::vfs::Base;
+
+    use super::*;
+
+    fn sounds() -> Vec<u8> {
+        let file = SoundsFile {
+            sounds: vec![
+                Sound::new(
+                    "gg_snd_RainAmbience".into(),
+                    "Sound\\Ambient\\RainAmbience.wav".into(),
+                ),
+                Sound::new(
+                    "gg_snd_Theme".into(),
+                    "Sound\\Music\\mp3Music\\Human1.mp3".into(),
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        w3s::write::write_sounds(&mut buf, &file).unwrap();
+        buf
+    }
+
+    fn unit(id: &[u8; 4], fields: &[(&[u8; 4], &str)]) -> Object {
+        let mut object = Object::with_parent(
+            ObjectId::from_bytes(id).unwrap(),
+            ObjectId::from_bytes(b"hfoo").unwrap(),
+            ObjectKind::UNIT,
+        );
+        for (field, value) in fields {
+            let field = ObjectId::from_bytes(*field).unwrap();
+            object.set_simple_field(field, Value::String(value.to_string()));
+        }
+
+        object
+    }
+
+    fn map(dir: &Path) -> Vfs {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+
+        let mut vfs = Vfs::new(Base::Dir(dir.into()));
+        let script = "local boom = \"Sound\\\\Custom\\\\Boom.wav\"\n\
+                      local used = 'war3mapImported/Used.blp'\n\
+                      local hd = 'war3.w3mod:units\\\\human\\\\footman\\\\footman.mdx'\n\
+                      print(\"not a path.txt\")";
+        vfs.add_file_contents("war3map.lua", script.into());
+        vfs.add_file_contents("war3map.w3s", sounds());
+        for file in &[
+            "war3mapImported/BTNCaptain.blp",
+            "war3mapImported/Used.blp",
+            "war3mapImported/Orphan.blp",
+            "ReplaceableTextures/CommandButtons/BTNFootman.blp",
+        ] {
+            vfs.add_file_contents(file, vec![0; 16]);
+        }
+
+        vfs
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ceres-assets-{}-{}", name, process::id()))
+    }
+
+    fn store() -> ObjectStore {
+        let mut store = ObjectStore::default();
+        store.insert_object(unit(
+            b"h000",
+            &[
+                (b"umdl", "Units\\Custom\\Captain.mdl"),
+                (b"uico", "war3mapImported\\BTNCaptain.blp"),
+            ],
+        ));
+        store.insert_object(unit(b"h001", &[(b"umdl", "units\\human\\Footman\\Footman")]));
+
+        store
+    }
+
+    fn missing(report: &AssetReport) -> Vec<String> {
+        let mut missing: Vec<String> = report
+            .missing
+            .iter()
+            .map(|reference| match &reference.source {
+                AssetSource::Object { id, field, level } => {
+                    format!("{} ({} {} {:?})", reference.path, id, field, level)
+                }
+                AssetSource::Sound { name } => format!("{} (sound {})", reference.path, name),
+                AssetSource::Script { file } => format!("{} ({})", reference.path, file),
+                AssetSource::Model { file } => format!("{} (model {})", reference.path, file),
+            })
+            .collect();
+        missing.sort();
+
+        missing
+    }
+
+    #[test]
+    fn missing_assets_and_unused_imports() {
+        let dir = temp_dir("report");
+        let mut vfs = map(&dir);
+        let store = store();
+        let report = validate_assets(&mut vfs, &[&store], &[]);
+
+        // the bundled game data names Human1.mp3, but not RainAmbience.wav
+        assert_eq!(
+            missing(&report),
+            [
+                "Sound\\Ambient\\RainAmbience.wav (sound gg_snd_RainAmbience)",
+                "Sound\\Custom\\Boom.wav (war3map.lua)",
+                "Units\\Custom\\Captain.mdl (h000 file None)",
+            ]
+        );
+        // BTNFootman.blp replaces the icon of the game
+        assert_eq!(report.unused, ["war3mapImported/Orphan.blp"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn listfiles_name_files_of_the_game() {
+        let dir = temp_dir("listfiles");
+        let mut vfs = map(&dir);
+        let store = store();
+        let listfile = vec!["sound/ambient/rainambience.wav".to_string()];
+        let report = validate_assets(&mut vfs, &[&store], &listfile);
+
+        assert_eq!(
+            missing(&report),
+            [
+                "Sound\\Custom\\Boom.wav (war3map.lua)",
+                "Units\\Custom\\Captain.mdl (h000 file None)",
+            ]
+        );
+
+        // importing the missing files makes them used
+        vfs.add_file_contents("Sound/Custom/Boom.wav", vec![0; 16]);
+        vfs.add_file_contents("Units/Custom/Captain.mdx", vec![0; 16]);
+        vfs.remove_file("war3mapImported/Orphan.blp");
+
+        let report = validate_assets(&mut vfs, &[&store], &listfile);
+        assert!(report.missing.is_empty());
+        assert!(report.unused.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
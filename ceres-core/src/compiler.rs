@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -14,6 +15,7 @@ use walkdir::WalkDir;
 use ceres_parsers::lua;
 
 use crate::error::*;
+use crate::obfuscate;
 use crate::lua::util::evaluate_macro_args;
 use crate::lua::util::lvalue_to_str;
 
@@ -26,7 +28,7 @@ pub trait ModuleProvider {
 pub struct ProjectModuleProvider {
     directories: Vec<PathBuf>,
 
-    known_modules: HashMap<String, PathBuf>,
+    known_modules: BTreeMap<String, PathBuf>,
 }
 
 impl ProjectModuleProvider {
@@ -44,9 +46,14 @@ impl ProjectModuleProvider {
         }
     }
 
-    fn scan_dir<P: AsRef<Path>>(modules: &mut HashMap<String, PathBuf>, path: P) {
+    fn scan_dir<P: AsRef<Path>>(modules: &mut BTreeMap<String, PathBuf>, path: P) {
         let path = path.as_ref();
-        for entry in WalkDir::new(path).follow_links(true) {
+        // sorted, so that the same sources always resolve to the same modules
+        let entries = WalkDir::new(path)
+            .follow_links(true)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()));
+
+        for entry in entries {
             let entry = entry.unwrap();
 
             let ext = entry.path().extension();
@@ -119,6 +126,7 @@ pub struct ScriptCompiler<'lua, MO: ModuleProvider, MA: MacroProvider> {
     pub(crate) ctx: LuaContext<'lua>,
 
     map_script: Option<String>,
+    obfuscate:  bool,
 
     // map of modules that have already been compiled
     compiled_modules:  IndexMap<String, CompiledModule>,
@@ -139,6 +147,7 @@ impl<'lua, MO: ModuleProvider, MA: MacroProvider> ScriptCompiler<'lua, MO, MA> {
             ctx,
 
             map_script: None,
+            obfuscate: false,
 
             compiled_modules: Default::default(),
             compiling_modules: Default::default(),
@@ -148,7 +157,7 @@ impl<'lua, MO: ModuleProvider, MA: MacroProvider> ScriptCompiler<'lua, MO, MA> {
         }
     }
 
-    pub fn emit_script(&self) -> String {
+    pub fn emit_script(&self) -> Result<String, CompilerError> {
         const SCRIPT_HEADER: &str = include_str!("resource/map_header.lua");
         const SCRIPT_FOOTER: &str = include_str!("resource/map_footer.lua");
 
@@ -159,7 +168,7 @@ impl<'lua, MO: ModuleProvider, MA: MacroProvider> ScriptCompiler<'lua, MO, MA> {
 
         if let Some(map_script) = &self.map_script {
             out += "--[[ map script start ]]\n";
-            out += map_script.trim();
+            out += self.emit_source("map script", map_script)?.trim();
             out += "\n--[[ map script end ]]\n\n";
         }
 
@@ -169,7 +178,7 @@ impl<'lua, MO: ModuleProvider, MA: MacroProvider> ScriptCompiler<'lua, MO, MA> {
                 r#"ceres.modules["{name}"] = {{initialized = false, cached = nil, source = [================["#,
                 name = id
             );
-            let module_source = format!("\n{}\n", compiled_module.src);
+            let module_source = format!("\n{}\n", self.emit_source(id, &compiled_module.src)?);
             let module_footer = "]================]}\n";
             let module_footer_comment = format!("--[[ end of module \"{}\" ]]\n\n", id);
 
@@ -183,7 +192,7 @@ impl<'lua, MO: ModuleProvider, MA: MacroProvider> ScriptCompiler<'lua, MO, MA> {
         out += SCRIPT_FOOTER.trim();
         out += "\n";
 
-        out
+        Ok(out)
     }
 
     /// tries to find and compile the given module by it's module name
@@ -242,6 +251,30 @@ impl<'lua, MO: ModuleProvider, MA: MacroProvider> ScriptCompiler<'lua, MO, MA> {
         self.map_script = Some(map_script);
     }
 
+    /// Obfuscates the map script and all modules when the script is emitted.
+    /// Emitting the script fails if any of them cannot be obfuscated, rather
+    /// than leaving it readable in a map which is meant to be protected.
+    pub fn set_obfuscate(&mut self, obfuscate: bool) {
+        self.obfuscate = obfuscate;
+    }
+
+    fn emit_source<'src>(
+        &self,
+        name: &str,
+        src: &'src str,
+    ) -> Result<Cow<'src, str>, CompilerError> {
+        if !self.obfuscate {
+            return Ok(Cow::Borrowed(src));
+        }
+
+        obfuscate::obfuscate(src)
+            .map(Cow::Owned)
+            .map_err(|error| CompilerError::ObfuscationFailed {
+                name: name.into(),
+                error,
+            })
+    }
+
     /// will compile a single module with the given module name and source,
     /// as well as all of it's transitive dependencies, while processing macros
     fn compile_module(
@@ -0,0 +1,575 @@
+//! Compares two maps file by file, with semantic diffs for known formats.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use ceres_formats::object::{FieldKind, Object, Value};
+use ceres_formats::objectstore::ObjectStore;
+use ceres_formats::parser::{w3i, w3obj};
+use ceres_formats::{ObjectId, ObjectKind};
+
+use crate::vfs::Vfs;
+
+pub mod text;
+
+use text::{Hunk, LineKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Changed => "changed",
+        };
+
+        f.pad(name)
+    }
+}
+
+/// A single field of an object which differs, `None` meaning unset.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDiff {
+    pub field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+    pub old:   Option<String>,
+    pub new:   Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectDiff {
+    pub id:     String,
+    pub change: Change,
+    pub fields: Vec<FieldDiff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "changes", rename_all = "lowercase")]
+pub enum Details {
+    Objects(Vec<ObjectDiff>),
+    /// Fields of the map info, named by their path, e.g. `players[0].name`.
+    Info(Vec<FieldDiff>),
+    Text(Vec<Hunk>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiff {
+    pub path:     String,
+    pub change:   Change,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details:  Option<Details>,
+}
+
+impl FileDiff {
+    pub fn size_delta(&self) -> i64 {
+        self.new_size.unwrap_or(0) as i64 - self.old_size.unwrap_or(0) as i64
+    }
+}
+
+/// Every file which differs between two maps, sorted by path.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MapDiff {
+    pub files: Vec<FileDiff>,
+}
+
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Compares two maps, which can be archives or directories.
+pub fn diff_maps<P, Q>(old: P, new: Q) -> Result<MapDiff, anyhow::Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut old = Vfs::open(old)?;
+    let mut new = Vfs::open(new)?;
+
+    let old_files = files_by_key(&mut old);
+    let new_files = files_by_key(&mut new);
+    let keys: BTreeSet<&String> = old_files.keys().chain(new_files.keys()).collect();
+
+    let mut diff = MapDiff::default();
+
+    for key in keys {
+        let old_contents = old_files.get(key).and_then(|path| read(&mut old, path));
+        let new_contents = new_files.get(key).and_then(|path| read(&mut new, path));
+        let path = new_files.get(key).or_else(|| old_files.get(key)).unwrap();
+
+        let change = match (&old_contents, &new_contents) {
+            (None, None) => continue,
+            (None, Some(_)) => Change::Added,
+            (Some(_), None) => Change::Removed,
+            (Some(old), Some(new)) if old == new => continue,
+            (Some(_), Some(_)) => Change::Changed,
+        };
+
+        let details = match (&old_contents, &new_contents) {
+            (Some(old), Some(new)) => diff_contents(path, old, new),
+            _ => None,
+        };
+
+        diff.files.push(FileDiff {
+            path: path.clone(),
+            change,
+            old_size: old_contents.map(|c| c.len() as u64),
+            new_size: new_contents.map(|c| c.len() as u64),
+            details,
+        });
+    }
+
+    Ok(diff)
+}
+
+fn files_by_key(vfs: &mut Vfs) -> BTreeMap<String, String> {
+    vfs.list_files()
+        .into_iter()
+        .map(|path| (path.to_ascii_lowercase(), path))
+        .collect()
+}
+
+fn read(vfs: &mut Vfs, path: &str) -> Option<Vec<u8>> {
+    match vfs.read_file(path) {
+        Ok(contents) => Some(contents),
+        Err(error) => {
+            eprintln!("diff: could not read file {}: {}", path, error);
+            None
+        }
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// A semantic diff of a file present in both maps, for the formats we know.
+fn diff_contents(path: &str, old: &[u8], new: &[u8]) -> Option<Details> {
+    let extension = extension(path);
+    let kind = ObjectKind::from_ext(&extension);
+
+    if kind != ObjectKind::empty() {
+        return diff_objects(old, new, kind).map(Details::Objects);
+    }
+
+    if extension == "w3i" {
+        return diff_info(old, new).map(Details::Info);
+    }
+
+    if extension == "lua" || extension == "j" {
+        let old = String::from_utf8_lossy(old);
+        let new = String::from_utf8_lossy(new);
+
+        return Some(Details::Text(text::diff_lines(&old, &new)));
+    }
+
+    None
+}
+
+fn diff_info(old: &[u8], new: &[u8]) -> Option<Vec<FieldDiff>> {
+    let old = info_fields(old)?;
+    let new = info_fields(new)?;
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    let diffs = keys
+        .into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| FieldDiff {
+            field: key.clone(),
+            level: None,
+            old:   old.get(key).cloned(),
+            new:   new.get(key).cloned(),
+        })
+        .collect();
+
+    Some(diffs)
+}
+
+/// Flattens the map info into its fields and their values.
+fn info_fields(data: &[u8]) -> Option<BTreeMap<String, String>> {
+    let info = w3i::read::read_map_info(data).ok()?;
+    let mut fields = BTreeMap::new();
+
+    flatten_json(String::new(), serde_json::to_value(&info).ok()?, &mut fields);
+
+    Some(fields)
+}
+
+fn flatten_json(path: String, value: JsonValue, fields: &mut BTreeMap<String, String>) {
+    match value {
+        // object IDs serialize as `{ "id": ... }`
+        JsonValue::Object(map) if map.len() == 1 && map.contains_key("id") => {
+            let id = map.get("id").and_then(|id| id.as_u64()).unwrap_or(0);
+            fields.insert(path, format!("{}", ObjectId::new(id as u32)));
+        }
+        JsonValue::Object(map) => {
+            for (key, value) in map {
+                let path = if path.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", path, key)
+                };
+
+                flatten_json(path, value, fields);
+            }
+        }
+        JsonValue::Array(values) => {
+            for (i, value) in values.into_iter().enumerate() {
+                flatten_json(format!("{}[{}]", path, i), value, fields);
+            }
+        }
+        value => {
+            fields.insert(path, value.to_string());
+        }
+    }
+}
+
+fn read_objects(data: &[u8], kind: ObjectKind) -> Option<ObjectStore> {
+    let mut store = ObjectStore::default();
+    w3obj::read::read_object_file(data, &mut store, kind).ok()?;
+
+    Some(store)
+}
+
+fn diff_objects(old: &[u8], new: &[u8], kind: ObjectKind) -> Option<Vec<ObjectDiff>> {
+    let old = read_objects(old, kind)?;
+    let new = read_objects(new, kind)?;
+
+    let ids: BTreeSet<ObjectId> = old
+        .objects()
+        .chain(new.objects())
+        .map(|object| object.borrow().id())
+        .collect();
+
+    let mut diffs = Vec::new();
+
+    for id in ids {
+        let old_object = old.object(id).map(|object| object.borrow());
+        let new_object = new.object(id).map(|object| object.borrow());
+
+        let change = match (&old_object, &new_object) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Removed,
+            _ => Change::Changed,
+        };
+
+        let fields = diff_fields(old_object.as_deref(), new_object.as_deref());
+
+        if change != Change::Changed || !fields.is_empty() {
+            diffs.push(ObjectDiff {
+                id: format!("{}", id),
+                change,
+                fields,
+            });
+        }
+    }
+
+    Some(diffs)
+}
+
+type FieldValues<'a> = BTreeMap<(ObjectId, Option<u32>), &'a Value>;
+
+fn field_values(object: Option<&Object>) -> FieldValues<'_> {
+    let mut values = BTreeMap::new();
+
+    for (id, field) in object.into_iter().flat_map(|object| object.fields()) {
+        match &field.kind {
+            FieldKind::Simple { value } => {
+                values.insert((*id, None), value);
+            }
+            FieldKind::Leveled { values: leveled } => {
+                for leveled in leveled {
+                    values.insert((*id, Some(leveled.level)), &leveled.value);
+                }
+            }
+        }
+    }
+
+    values
+}
+
+fn diff_fields(old: Option<&Object>, new: Option<&Object>) -> Vec<FieldDiff> {
+    let mut diffs = Vec::new();
+
+    let old_parent = old.and_then(|object| object.parent_id());
+    let new_parent = new.and_then(|object| object.parent_id());
+
+    if old_parent != new_parent {
+        diffs.push(FieldDiff {
+            field: "parent".into(),
+            level: None,
+            old:   old_parent.map(|id| format!("{}", id)),
+            new:   new_parent.map(|id| format!("{}", id)),
+        });
+    }
+
+    let old_values = field_values(old);
+    let new_values = field_values(new);
+    let keys: BTreeSet<_> = old_values.keys().chain(new_values.keys()).collect();
+
+    for key in keys {
+        let old_value = old_values.get(key);
+        let new_value = new_values.get(key);
+
+        if old_value != new_value {
+            diffs.push(FieldDiff {
+                field: field_name(key.0),
+                level: key.1,
+                old:   old_value.map(|value| format_value(value)),
+                new:   new_value.map(|value| format_value(value)),
+            });
+        }
+    }
+
+    diffs
+}
+
+/// The raw field ID, followed by its name if the field is known.
+fn field_name(id: ObjectId) -> String {
+    match w3data::metadata().field_by_id(id) {
+        Some(desc) => format!("{} ({})", id, desc.variant.name()),
+        None => format!("{}", id),
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => format!("{:?}", value),
+        Value::Int(value) => value.to_string(),
+        Value::Real(value) | Value::Unreal(value) => value.to_string(),
+    }
+}
+
+impl fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.files.is_empty() {
+            return writeln!(f, "no differences");
+        }
+
+        for file in &self.files {
+            write!(f, "{:<8} {}", file.change, file.path)?;
+
+            match (file.old_size, file.new_size) {
+                (Some(old), Some(new)) => {
+                    writeln!(f, " ({} -> {} bytes, {:+})", old, new, file.size_delta())?
+                }
+                _ => writeln!(f, " ({:+} bytes)", file.size_delta())?,
+            }
+
+            match &file.details {
+                Some(Details::Objects(objects)) => {
+                    for object in objects {
+                        writeln!(f, "    {} {}", object.change, object.id)?;
+
+                        for field in &object.fields {
+                            let level = field
+                                .level
+                                .map(|level| format!(" [level {}]", level))
+                                .unwrap_or_default();
+
+                            writeln!(
+                                f,
+                                "        {}{}: {} -> {}",
+                                field.field,
+                                level,
+                                field.old.as_deref().unwrap_or("<unset>"),
+                                field.new.as_deref().unwrap_or("<unset>")
+                            )?;
+                        }
+                    }
+                }
+                Some(Details::Info(fields)) => {
+                    for field in fields {
+                        writeln!(
+                            f,
+                            "    {}: {} -> {}",
+                            field.field,
+                            field.old.as_deref().unwrap_or("<unset>"),
+                            field.new.as_deref().unwrap_or("<unset>")
+                        )?;
+                    }
+                }
+                Some(Details::Text(hunks)) => {
+                    for hunk in hunks {
+                        writeln!(
+                            f,
+                            "    @@ -{},{} +{},{} @@",
+                            hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines
+                        )?;
+
+                        for line in &hunk.lines {
+                            let prefix = match line.kind {
+                                LineKind::Context => ' ',
+                                LineKind::Removed => '-',
+                                LineKind::Added => '+',
+                            };
+
+                            writeln!(f, "    {}{}", prefix, line.text)?;
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use ceres_formats::parser::w3obj;
+
+    use super::*;
+
+    fn id(id: &str) -> ObjectId {
+        ObjectId::from_bytes(id.as_bytes()).unwrap()
+    }
+
+    fn map_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ceres-diff-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for (path, contents) in files {
+            fs::write(dir.join(path), contents).unwrap();
+        }
+
+        dir
+    }
+
+    fn units(hit_points: i32, extra: bool) -> Vec<u8> {
+        let mut store = ObjectStore::default();
+
+        let mut footman = Object::with_parent(id("h000"), id("hfoo"), ObjectKind::UNIT);
+        footman.set_simple_field(id("uhpm"), Value::Int(hit_points));
+        store.insert_object(footman);
+
+        if extra {
+            store.insert_object(Object::with_parent(id("h001"), id("hkni"), ObjectKind::UNIT));
+        }
+
+        let mut buf = Vec::new();
+        w3obj::write::write_object_file(&mut buf, w3data::metadata(), &store, ObjectKind::UNIT)
+            .unwrap();
+
+        buf
+    }
+
+    #[test]
+    fn files_are_matched_ignoring_case() {
+        let old = map_dir(
+            "old",
+            &[
+                ("war3map.lua", "a\nb\nc"),
+                ("notes.txt", "same"),
+                ("readme.txt", "old"),
+            ],
+        );
+        let new = map_dir(
+            "new",
+            &[
+                ("war3map.lua", "a\nB\nc"),
+                ("NOTES.txt", "same"),
+                ("added.txt", "new file"),
+            ],
+        );
+
+        let diff = diff_maps(&old, &new).unwrap();
+        let files: Vec<(&str, Change)> = diff
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.change))
+            .collect();
+
+        assert_eq!(
+            files,
+            vec![
+                ("added.txt", Change::Added),
+                ("readme.txt", Change::Removed),
+                ("war3map.lua", Change::Changed),
+            ]
+        );
+        assert_eq!(diff.files[0].size_delta(), 8);
+        assert_eq!(diff.files[1].size_delta(), -3);
+
+        let output = diff.to_string();
+        assert!(output.contains("war3map.lua (5 -> 5 bytes, +0)"));
+        assert!(output.contains("    @@ -1,3 +1,3 @@\n     a\n    -b\n    +B\n     c\n"));
+
+        let json: JsonValue = serde_json::from_str(&diff.to_json()).unwrap();
+        assert_eq!(json["files"][2]["details"]["type"], "text");
+        assert_eq!(json["files"][2]["details"]["changes"][0]["oldStart"], 1);
+        assert!(json["files"][0].get("details").is_none());
+
+        fs::remove_dir_all(old).unwrap();
+        fs::remove_dir_all(new).unwrap();
+    }
+
+    #[test]
+    fn identical_maps_have_no_differences() {
+        let dir = map_dir("same", &[("war3map.lua", "print('hi')")]);
+
+        let diff = diff_maps(&dir, &dir).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no differences\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn object_fields_are_compared() {
+        let diffs = diff_objects(&units(420, false), &units(500, true), ObjectKind::UNIT).unwrap();
+        assert_eq!(diffs.len(), 2);
+
+        assert_eq!(diffs[0].id, "h000");
+        assert_eq!(diffs[0].change, Change::Changed);
+        assert_eq!(diffs[0].fields.len(), 1);
+        assert!(diffs[0].fields[0].field.starts_with("uhpm"));
+        assert_eq!(diffs[0].fields[0].old.as_deref(), Some("420"));
+        assert_eq!(diffs[0].fields[0].new.as_deref(), Some("500"));
+
+        assert_eq!(diffs[1].id, "h001");
+        assert_eq!(diffs[1].change, Change::Added);
+        assert_eq!(diffs[1].fields[0].field, "parent");
+        assert_eq!(diffs[1].fields[0].new.as_deref(), Some("hkni"));
+
+        let unchanged = diff_objects(&units(420, true), &units(420, true), ObjectKind::UNIT);
+        assert!(unchanged.unwrap().is_empty());
+    }
+
+    #[test]
+    fn unreadable_files_have_no_details() {
+        assert!(diff_contents("war3map.w3u", b"garbage", b"more garbage").is_none());
+        assert!(diff_contents("war3map.w3i", b"garbage", b"more garbage").is_none());
+        assert!(diff_contents("war3map.mdx", b"a", b"b").is_none());
+    }
+}
@@ -0,0 +1,376 @@
+//! Line based diffs of text files, e.g. the map script.
+
+use serde::Serialize;
+
+/// Lines of unchanged context around every change.
+const CONTEXT: usize = 3;
+
+/// Past this many edits, the changed region is reported as
+/// replaced as a whole, which keeps huge diffs cheap.
+const MAX_EDITS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Context,
+    Removed,
+    Added,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Line {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+/// A group of changes with their context. Line numbers start at 1.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines:     Vec<Line>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Compares two texts line by line.
+pub fn diff_lines(old: &str, new: &str) -> Vec<Hunk> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let ops = edit_script(&old, &new);
+
+    // positions of every operation in both texts
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        positions.push((i, j));
+
+        match op {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|i| ops[*i] != Op::Equal).collect();
+    let mut hunks = Vec::new();
+    let mut c = 0;
+
+    while c < changes.len() {
+        let start = changes[c].saturating_sub(CONTEXT);
+        let mut last = changes[c];
+
+        while c + 1 < changes.len() && changes[c + 1] <= last + 2 * CONTEXT + 1 {
+            c += 1;
+            last = changes[c];
+        }
+
+        let end = (last + CONTEXT + 1).min(ops.len());
+        let mut hunk = Hunk {
+            old_start: positions[start].0 + 1,
+            old_lines: 0,
+            new_start: positions[start].1 + 1,
+            new_lines: 0,
+            lines:     Vec::new(),
+        };
+
+        for index in start..end {
+            let (i, j) = positions[index];
+            let line = match ops[index] {
+                Op::Equal => {
+                    hunk.old_lines += 1;
+                    hunk.new_lines += 1;
+
+                    Line {
+                        kind: LineKind::Context,
+                        text: old[i].into(),
+                    }
+                }
+                Op::Delete => {
+                    hunk.old_lines += 1;
+
+                    Line {
+                        kind: LineKind::Removed,
+                        text: old[i].into(),
+                    }
+                }
+                Op::Insert => {
+                    hunk.new_lines += 1;
+
+                    Line {
+                        kind: LineKind::Added,
+                        text: new[j].into(),
+                    }
+                }
+            };
+
+            hunk.lines.push(line);
+        }
+
+        hunks.push(hunk);
+        c += 1;
+    }
+
+    hunks
+}
+
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut ops = vec![Op::Equal; prefix];
+
+    match shortest_edit(old_middle, new_middle) {
+        Some(middle) => ops.extend(middle),
+        None => {
+            ops.resize(ops.len() + old_middle.len(), Op::Delete);
+            ops.resize(ops.len() + new_middle.len(), Op::Insert);
+        }
+    }
+
+    ops.resize(ops.len() + suffix, Op::Equal);
+
+    ops
+}
+
+/// Myers' diff algorithm. Returns `None` if the texts differ by more
+/// than `MAX_EDITS` lines.
+fn shortest_edit(old: &[&str], new: &[&str]) -> Option<Vec<Op>> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let offset = n + m + 1;
+
+    let mut v = vec![0isize; (2 * offset + 1) as usize];
+    // the part of `v` which is read during every step, for backtracking
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=(n + m) {
+        if d as usize > MAX_EDITS {
+            return None;
+        }
+
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let i = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[i] = x;
+
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for (d, snapshot) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| snapshot[(k + d + 1) as usize];
+
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+
+        if d > 0 {
+            ops.push(if x == prev_x { Op::Insert } else { Op::Delete });
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    ops.reverse();
+
+    Some(ops)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(count: usize) -> Vec<String> {
+        (1..=count).map(|i| format!("line {}", i)).collect()
+    }
+
+    fn text(lines: &[String]) -> String {
+        lines.join("\n")
+    }
+
+    fn kinds(hunk: &Hunk) -> Vec<LineKind> {
+        hunk.lines.iter().map(|line| line.kind).collect()
+    }
+
+    /// Applies an edit script, checking that it turns `old` into `new`.
+    fn apply(ops: &[Op], old: &[&str], new: &[&str]) -> usize {
+        let (mut i, mut j) = (0, 0);
+
+        for op in ops {
+            match op {
+                Op::Equal => {
+                    assert_eq!(old[i], new[j]);
+                    i += 1;
+                    j += 1;
+                }
+                Op::Delete => i += 1,
+                Op::Insert => j += 1,
+            }
+        }
+
+        assert_eq!((i, j), (old.len(), new.len()));
+
+        ops.iter().filter(|op| **op != Op::Equal).count()
+    }
+
+    #[test]
+    fn identical_texts_have_no_hunks() {
+        let old = text(&lines(20));
+
+        assert!(diff_lines(&old, &old).is_empty());
+        assert!(diff_lines("", "").is_empty());
+    }
+
+    #[test]
+    fn shortest_edit_is_minimal() {
+        // the example from Myers' paper, which needs 5 edits
+        let old: Vec<&str> = "ABCABBA".split("").filter(|s| !s.is_empty()).collect();
+        let new: Vec<&str> = "CBABAC".split("").filter(|s| !s.is_empty()).collect();
+
+        let ops = shortest_edit(&old, &new).unwrap();
+        assert_eq!(apply(&ops, &old, &new), 5);
+
+        let ops = shortest_edit(&[], &new).unwrap();
+        assert_eq!(ops, vec![Op::Insert; new.len()]);
+
+        let ops = shortest_edit(&old, &[]).unwrap();
+        assert_eq!(ops, vec![Op::Delete; old.len()]);
+    }
+
+    #[test]
+    fn single_change_with_context() {
+        let old = lines(10);
+        let mut new = old.clone();
+        new[4] = "changed".into();
+
+        let hunks = diff_lines(&text(&old), &text(&new));
+        assert_eq!(hunks.len(), 1);
+
+        let hunk = &hunks[0];
+        assert_eq!((hunk.old_start, hunk.old_lines), (2, 7));
+        assert_eq!((hunk.new_start, hunk.new_lines), (2, 7));
+
+        use LineKind::*;
+        assert_eq!(
+            kinds(hunk),
+            vec![Context, Context, Context, Removed, Added, Context, Context, Context]
+        );
+        assert_eq!(hunk.lines[3].text, "line 5");
+        assert_eq!(hunk.lines[4].text, "changed");
+    }
+
+    #[test]
+    fn changes_at_the_edges() {
+        let old = lines(5);
+        let mut new = old.clone();
+        new.insert(0, "first".into());
+        new.push("last".into());
+
+        let hunks = diff_lines(&text(&old), &text(&new));
+        assert_eq!(hunks.len(), 1);
+
+        let hunk = &hunks[0];
+        assert_eq!((hunk.old_start, hunk.old_lines), (1, 5));
+        assert_eq!((hunk.new_start, hunk.new_lines), (1, 7));
+        assert_eq!(hunk.lines[0].kind, LineKind::Added);
+        assert_eq!(hunk.lines[6].kind, LineKind::Added);
+    }
+
+    #[test]
+    fn nearby_changes_share_a_hunk() {
+        let old = lines(40);
+
+        // 6 unchanged lines between the changes, less than twice the context
+        let mut close = old.clone();
+        close[9] = "a".into();
+        close[16] = "b".into();
+
+        let hunks = diff_lines(&text(&old), &text(&close));
+        assert_eq!(hunks.len(), 1);
+        assert_eq!((hunks[0].old_start, hunks[0].old_lines), (7, 14));
+
+        let mut far = old.clone();
+        far[9] = "a".into();
+        far[30] = "b".into();
+
+        let hunks = diff_lines(&text(&old), &text(&far));
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old_start, hunks[0].old_lines), (7, 7));
+        assert_eq!((hunks[1].new_start, hunks[1].new_lines), (28, 7));
+    }
+
+    #[test]
+    fn huge_changes_are_replaced_as_a_whole() {
+        let old: Vec<String> = (0..MAX_EDITS).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..MAX_EDITS).map(|i| format!("new {}", i)).collect();
+        let old_refs: Vec<&str> = old.iter().map(|s| s.as_str()).collect();
+        let new_refs: Vec<&str> = new.iter().map(|s| s.as_str()).collect();
+
+        assert!(shortest_edit(&old_refs, &new_refs).is_none());
+
+        let mut old_text = vec!["same".to_string()];
+        old_text.extend(old);
+        let mut new_text = vec!["same".to_string()];
+        new_text.extend(new);
+
+        let hunks = diff_lines(&text(&old_text), &text(&new_text));
+        assert_eq!(hunks.len(), 1);
+
+        let hunk = &hunks[0];
+        assert_eq!((hunk.old_lines, hunk.new_lines), (MAX_EDITS + 1, MAX_EDITS + 1));
+        assert_eq!(hunk.lines[0].kind, LineKind::Context);
+        assert!(hunk.lines[1..=MAX_EDITS].iter().all(|l| l.kind == LineKind::Removed));
+        assert!(hunk.lines[MAX_EDITS + 1..].iter().all(|l| l.kind == LineKind::Added));
+    }
+}
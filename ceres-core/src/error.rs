@@ -96,6 +96,11 @@ pub enum CompilerError {
         module_path: PathBuf,
         error:       Box<CompilerError>,
     },
+    #[error("Could not obfuscate {}:\n{}", name, error)]
+    ObfuscationFailed {
+        name:  String,
+        error: PestError<lua::Rule>,
+    },
     #[error("Cyclical dependency found involving module {}", module_name)]
     CyclicalDependency { module_name: String },
     #[error("Macro invocation failed: {}\n{}", error, diagnostic)]
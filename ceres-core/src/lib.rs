@@ -5,18 +5,25 @@ extern crate ceres_mpq as mpq;
 
 use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use rlua::prelude::*;
 
-use crate::error::ContextError;
+use crate::error::{ContextError, IoError, StringError};
 use crate::evloop::wait_on_evloop;
 
 pub(crate) mod lua;
 pub(crate) mod error;
 pub(crate) mod compiler;
 pub(crate) mod evloop;
+pub(crate) mod vfs;
+pub(crate) mod reproducible;
+pub(crate) mod obfuscate;
+pub(crate) mod assets;
+pub(crate) mod minimap;
+pub mod diff;
+pub mod listfile;
 
 #[derive(Copy, Clone)]
 pub enum CeresRunMode {
@@ -60,8 +67,6 @@ pub fn execute_script<F>(
 where
     F: FnOnce(LuaContext) -> Result<(), anyhow::Error>,
 {
-    const DEFAULT_BUILD_SCRIPT: &str = include_str!("resource/buildscript_default.lua");
-
     let lua = Rc::new(Lua::new());
 
     let result: Result<(), anyhow::Error> = lua.context(|ctx| {
@@ -76,9 +81,11 @@ where
         Ok(())
     });
 
-    if result.is_err() {
-        handle_lua_result(result);
-        std::process::exit(1);
+    if let Err(error) = result {
+        return Err(match error.downcast::<LuaError>() {
+            Ok(error) => lua_error_root_cause(&error),
+            Err(error) => error,
+        });
     }
 
     wait_on_evloop(Rc::clone(&lua));
@@ -91,6 +98,106 @@ pub fn run_build_script(
     project_dir: PathBuf,
     script_args: Vec<&str>,
 ) -> Result<(), anyhow::Error> {
+    run_build_script_then(run_mode, project_dir, script_args, |_| Ok(()))
+}
+
+/// Builds the project twice, and compares the contents of the target directory
+/// after each build. Fails with a list of differences if they are not identical.
+///
+/// The second build runs in a copy of the project in a temporary directory,
+/// so that artifacts which depend on where the project is stored show up as
+/// differences as well. Both builds run with their project directory as the
+/// working directory, which relative paths in `ceres.layout` are resolved against.
+///
+/// Only nondeterminism which shows up between two builds on the same machine,
+/// a few seconds apart, can be found this way: builds which depend on the date,
+/// the environment or the installed tools are not caught. Files outside of the
+/// project directory are shared by both builds, and only the target directory
+/// is compared.
+pub fn check_reproducible(
+    project_dir: PathBuf,
+    script_args: Vec<&str>,
+) -> Result<(), anyhow::Error> {
+    let work_dir = reproducible::ScratchDir::new(
+        std::env::temp_dir().join(format!("ceres-reproducible-{}", std::process::id())),
+    );
+    let differences = {
+        let _cwd = reproducible::WorkingDir::remember()?;
+
+        build_twice(&project_dir, work_dir.path(), script_args)?
+    };
+
+    if !differences.is_empty() {
+        for difference in &differences {
+            println!("[DIFF] {}", difference);
+        }
+
+        return Err(StringError::new(format!(
+            "the build is not reproducible, found {} differences",
+            differences.len()
+        ))
+        .into());
+    }
+
+    println!("The build is reproducible.");
+
+    Ok(())
+}
+
+/// Builds the project, and then a copy of it inside `work_dir`,
+/// returning the differences between the target directories.
+fn build_twice(
+    project_dir: &Path,
+    work_dir: &Path,
+    script_args: Vec<&str>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let snapshot_dir = work_dir.join("snapshot");
+    let copy_dir = work_dir.join("project");
+
+    let project_dir = project_dir
+        .canonicalize()
+        .map_err(|cause| IoError::new(project_dir, cause))?;
+    std::env::set_current_dir(&project_dir).map_err(|cause| IoError::new(&project_dir, cause))?;
+
+    let target_dir = run_build_script_then(
+        CeresRunMode::Build,
+        project_dir.clone(),
+        script_args.clone(),
+        |ctx| Ok(reproducible::target_directory(ctx)?),
+    )?;
+
+    if !target_dir.is_dir() {
+        return Err(StringError::new(format!(
+            "the build did not produce anything in {}",
+            target_dir.display()
+        ))
+        .into());
+    }
+
+    reproducible::snapshot(&target_dir, &snapshot_dir)?;
+    reproducible::copy_project(&project_dir, &copy_dir, &target_dir)?;
+
+    std::env::set_current_dir(&copy_dir).map_err(|cause| IoError::new(&copy_dir, cause))?;
+    let copy_target_dir = run_build_script_then(
+        CeresRunMode::Build,
+        copy_dir.clone(),
+        script_args,
+        |ctx| Ok(reproducible::target_directory(ctx)?),
+    )?;
+
+    reproducible::compare_dirs(&snapshot_dir, &copy_dir.join(copy_target_dir))
+}
+
+/// Runs the build script, and then `after` on the resulting Lua state.
+fn run_build_script_then<T, F>(
+    run_mode: CeresRunMode,
+    project_dir: PathBuf,
+    script_args: Vec<&str>,
+    after: F,
+) -> Result<T, anyhow::Error>
+where
+    F: FnOnce(LuaContext) -> Result<T, anyhow::Error>,
+{
     const DEFAULT_BUILD_SCRIPT: &str = include_str!("resource/buildscript_default.lua");
 
     let build_script_path = project_dir.join("build.lua");
@@ -104,10 +211,14 @@ pub fn run_build_script(
         None
     };
 
+    let mut result = None;
+
     execute_script(run_mode, script_args, |ctx| {
+        lua::require::install_build_searcher(ctx, &project_dir)?;
+
         if let Some(build_script) = build_script {
             ctx.load(&build_script)
-                .set_name("custom build script")
+                .set_name(&format!("@{}", build_script_path.display()))
                 .unwrap()
                 .exec()?;
         }
@@ -117,6 +228,11 @@ pub fn run_build_script(
             .unwrap()
             .exec()?;
 
+        result = Some(after(ctx)?);
+
         Ok(())
-    })
+    })?;
+
+    // execute_script only succeeds once `after` has run
+    Ok(result.unwrap())
 }
@@ -0,0 +1,439 @@
+//! Recovery of file names for archives without a complete `(listfile)`.
+//!
+//! MPQ archives only store hashes of file names, so names have to be guessed.
+//! Candidates come from a bundled list of the files maps themselves consist of,
+//! the paths of game files named by the bundled game data, user-supplied listfiles,
+//! and paths referenced by files which were already found: string literals in
+//! the map script, path fields in object data, and paths embedded in models,
+//! UI definitions and other map files.
+//!
+//! No complete listfile of the game is bundled, only the paths which the bundled
+//! object and editor data refer to. Imports which nothing refers to and which
+//! replace other game files, like UI textures, still need a listfile.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use ceres_formats::object::{FieldKind, Value};
+use ceres_formats::objectstore::ObjectStore;
+use ceres_formats::parser::w3obj;
+use ceres_formats::ObjectKind;
+use mpq::{Archive, FileEntry};
+
+use crate::error::IoError;
+
+/// Names of the files WC3 and the World Editor put into maps, like `war3map.w3i`,
+/// and of a few common imports.
+const MAP_FILE_NAMES: &str = include_str!("resource/map_file_names.txt");
+
+/// Files with these extensions are scanned for embedded paths.
+const SCANNED_EXTENSIONS: &[&str] = &[
+    "txt", "slk", "fdf", "toc", "mdl", "mdx", "w3i", "wts", "imp", "w3s", "w3c", "w3r",
+];
+
+/// Embedded paths are only picked up if they have one of these extensions,
+/// to avoid trying every printable string in a binary file.
+const PATH_EXTENSIONS: &[&str] = &[
+    "mdx", "mdl", "blp", "tga", "dds", "mp3", "wav", "flac", "ogg", "txt", "slk", "fdf", "toc",
+    "j", "lua", "ai", "ttf",
+];
+
+pub struct RecoveryReport {
+    /// Names which were not known before.
+    pub recovered: Vec<String>,
+    /// Blocks which are still without a name.
+    pub unnamed:   Vec<FileEntry>,
+}
+
+/// Whether any file in the archive lacks a name.
+pub fn needs_recovery<R: Read + Seek>(archive: &mut Archive<R>) -> bool {
+    archive
+        .entries()
+        .iter()
+        .any(|entry| entry.name.is_none() && !entry.is_deleted())
+}
+
+/// Reads a listfile from disk. Names are separated by newlines or semicolons.
+pub fn read_listfile<P: AsRef<Path>>(path: P) -> Result<Vec<String>, anyhow::Error> {
+    let path = path.as_ref();
+    let contents = fs::read(path).map_err(|cause| IoError::new(path, cause))?;
+
+    Ok(String::from_utf8_lossy(&contents)
+        .split(&['\r', '\n', ';'][..])
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect())
+}
+
+/// Tries to find names for every unnamed file in the archive. Names found
+/// this way are remembered by the archive, and show up in `Archive::files()`.
+///
+/// Every newly named file is in turn searched for more names,
+/// until no new names turn up.
+pub fn recover_names<R: Read + Seek>(
+    archive: &mut Archive<R>,
+    listfiles: &[String],
+) -> RecoveryReport {
+    let mut tried: HashSet<String> = HashSet::new();
+    let mut candidates: Vec<String> = MAP_FILE_NAMES
+        .lines()
+        .map(|s| s.trim().to_string())
+        .chain(w3data::stock_paths().flat_map(path_variants))
+        .chain(listfiles.iter().cloned())
+        .collect();
+    let mut to_scan: Vec<String> = archive.files().unwrap_or_default();
+    let mut recovered = Vec::new();
+
+    loop {
+        let new_candidates = candidates
+            .drain(..)
+            .filter(|name| !name.is_empty() && tried.insert(name.to_ascii_lowercase()));
+        let added = archive.add_names(new_candidates.collect::<Vec<_>>());
+
+        to_scan.extend(added.iter().cloned());
+        recovered.extend(added);
+
+        if to_scan.is_empty() {
+            break;
+        }
+
+        for name in to_scan.drain(..) {
+            if let Ok(contents) = archive.read_file(&name) {
+                for path in referenced_paths(&name, &contents) {
+                    candidates.extend(path_variants(&path));
+                }
+            }
+        }
+    }
+
+    let unnamed = archive
+        .entries()
+        .into_iter()
+        .filter(|entry| entry.name.is_none() && !entry.is_deleted())
+        .collect();
+
+    RecoveryReport { recovered, unnamed }
+}
+
+pub(crate) fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Whether `referenced_paths` looks into files with this name.
+pub(crate) fn is_scanned(name: &str) -> bool {
+    let ext = extension(name);
+
+    !ObjectKind::from_ext(&ext).is_empty()
+        || ext == "j"
+        || ext == "lua"
+        || SCANNED_EXTENSIONS.contains(&ext.as_str())
+}
+
+/// Collects paths referenced by a file, depending on its type.
+pub(crate) fn referenced_paths(name: &str, contents: &[u8]) -> Vec<String> {
+    let ext = extension(name);
+    let object_kind = ObjectKind::from_ext(&ext);
+
+    if !object_kind.is_empty() {
+        object_paths(contents, object_kind)
+    } else if ext == "j" || ext == "lua" {
+        string_literals(contents)
+    } else if SCANNED_EXTENSIONS.contains(&ext.as_str()) {
+        embedded_paths(contents)
+    } else {
+        Vec::new()
+    }
+}
+
+/// Values of path-typed object data fields, such as models and icons.
+fn object_paths(contents: &[u8], kind: ObjectKind) -> Vec<String> {
+    let mut store = ObjectStore::default();
+    if w3obj::read::read_object_file(contents, &mut store, kind).is_err() {
+        return Vec::new();
+    }
+
+    let metadata = w3data::metadata();
+    let mut paths = Vec::new();
+
+    for object in store.objects() {
+        let object = object.borrow();
+
+        for (id, field) in object.fields() {
+            if !metadata.field_by_id(*id).map(|f| f.is_path()).unwrap_or(false) {
+                continue;
+            }
+
+            let values: Vec<&Value> = match &field.kind {
+                FieldKind::Simple { value } => vec![value],
+                FieldKind::Leveled { values } => values.iter().map(|v| &v.value).collect(),
+            };
+
+            for value in values {
+                if let Value::String(value) = value {
+                    paths.extend(value.split(',').map(|s| s.trim().to_string()));
+                }
+            }
+        }
+    }
+
+    paths
+}
+
+/// Contents of all quoted string literals in a Lua or JASS script.
+pub(crate) fn string_literals(contents: &[u8]) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut i = 0;
+
+    while i < contents.len() {
+        let quote = contents[i];
+        i += 1;
+
+        if quote != b'"' && quote != b'\'' {
+            continue;
+        }
+
+        let mut literal = Vec::new();
+        while i < contents.len() && contents[i] != quote && contents[i] != b'\n' {
+            if contents[i] == b'\\' && i + 1 < contents.len() {
+                i += 1;
+            }
+
+            literal.push(contents[i]);
+            i += 1;
+        }
+        i += 1;
+
+        literals.push(String::from_utf8_lossy(&literal).into_owned());
+    }
+
+    literals
+}
+
+fn is_path_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"_-\\/.()!&+#$~".contains(&c)
+}
+
+/// Runs of path-like characters ending in a known file extension.
+fn embedded_paths(contents: &[u8]) -> Vec<String> {
+    contents
+        .split(|c| !is_path_byte(*c))
+        .filter(|run| run.len() > 4)
+        .map(|run| String::from_utf8_lossy(run).replace("\\\\", "\\"))
+        .filter(|path| PATH_EXTENSIONS.contains(&extension(path).as_str()))
+        .collect()
+}
+
+/// Replaces the first case-insensitive occurence of `from`.
+fn replace_ignore_case(source: &str, from: &str, to: &str) -> Option<String> {
+    let start = source.to_ascii_lowercase().find(&from.to_ascii_lowercase())?;
+
+    Some(format!("{}{}{}", &source[..start], to, &source[start + from.len()..]))
+}
+
+/// WC3 is lenient about paths: models may be referenced as `.mdl` or without
+/// an extension, textures as `.tga` or `.blp`, and a model also brings its portrait.
+/// Disabled icon variants are derived from the icon path as well.
+pub(crate) fn path_variants(path: &str) -> Vec<String> {
+    let path = path.trim().replace('/', "\\");
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    let ext = extension(&path);
+    let stem = if ext.is_empty() {
+        path.as_str()
+    } else {
+        &path[..path.len() - ext.len() - 1]
+    };
+
+    let mut variants = vec![path.clone()];
+
+    match ext.as_str() {
+        "" | "mdl" | "mdx" => {
+            variants.push(format!("{}.mdx", stem));
+            variants.push(format!("{}_portrait.mdx", stem));
+        }
+        "blp" => variants.push(format!("{}.tga", stem)),
+        "tga" => variants.push(format!("{}.blp", stem)),
+        _ => {}
+    }
+
+    let disabled_icons = [
+        ("CommandButtons\\BTN", "CommandButtonsDisabled\\DISBTN"),
+        ("PassiveButtons\\PASBTN", "CommandButtonsDisabled\\DISPASBTN"),
+    ];
+    for (from, to) in &disabled_icons {
+        if let Some(variant) = replace_ignore_case(&path, from, to) {
+            variants.push(variant);
+        }
+    }
+
+    // imports without a directory end up in war3mapImported by default
+    if !path.contains('\\') {
+        variants.push(format!("war3mapImported\\{}", path));
+    }
+
+    variants
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use mpq::{Creator, CreatorOptions, FileOptions};
+
+    use super::*;
+
+    /// Writes a protected archive, which has no `(listfile)`.
+    fn protected_archive(files: &[(&str, &[u8])]) -> Archive<Cursor<Vec<u8>>> {
+        let mut creator = Creator::new(CreatorOptions {
+            protect: true,
+            ..Default::default()
+        });
+
+        for (name, contents) in files {
+            creator.add_file(name, *contents, FileOptions::default());
+        }
+
+        let mut buf = Cursor::new(Vec::new());
+        creator.write(&mut buf).unwrap();
+
+        Archive::open(Cursor::new(buf.into_inner())).unwrap()
+    }
+
+    fn sorted(mut names: Vec<String>) -> Vec<String> {
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn names_are_recovered_by_hash() {
+        let script = br#"
+            BlzLoadTOCFile("war3mapImported\\ui.toc")
+            local model = 'Units\\Hero.mdl'
+            local icon = "ReplaceableTextures\\CommandButtons\\BTNHero.blp"
+        "#;
+        let mut archive = protected_archive(&[
+            ("war3map.lua", script),
+            (
+                "war3mapImported\\ui.toc",
+                b"war3mapImported\\Frames.fdf\r\n",
+            ),
+            ("war3mapImported\\Frames.fdf", b"// frames"),
+            ("Units\\Hero.mdx", b"MDLX"),
+            ("Units\\Hero_portrait.mdx", b"MDLX"),
+            ("ReplaceableTextures\\CommandButtons\\BTNHero.blp", b"BLP1"),
+            (
+                "ReplaceableTextures\\CommandButtonsDisabled\\DISBTNHero.blp",
+                b"BLP1",
+            ),
+            // replaces an icon of the game, which the bundled game data names
+            ("ReplaceableTextures\\CommandButtons\\BTNFootman.blp", b"BLP1"),
+            ("secret.txt", b"only in a listfile"),
+            ("hidden.bin", b"nothing refers to this"),
+        ]);
+
+        assert!(archive.files().is_none());
+        assert!(needs_recovery(&mut archive));
+
+        let report = recover_names(&mut archive, &["secret.txt".into()]);
+
+        assert_eq!(
+            sorted(report.recovered),
+            vec![
+                "ReplaceableTextures\\CommandButtonsDisabled\\DISBTNHero.blp",
+                "ReplaceableTextures\\CommandButtons\\BTNFootman.blp",
+                "ReplaceableTextures\\CommandButtons\\BTNHero.blp",
+                "Units\\Hero.mdx",
+                "Units\\Hero_portrait.mdx",
+                "secret.txt",
+                "war3map.lua",
+                "war3mapImported\\Frames.fdf",
+                "war3mapImported\\ui.toc",
+            ]
+        );
+        assert_eq!(report.unnamed.len(), 1);
+        assert_eq!(report.unnamed[0].file_size, 22);
+        assert!(needs_recovery(&mut archive));
+
+        // recovered names are remembered by the archive
+        assert!(archive
+            .files()
+            .unwrap()
+            .contains(&"Units\\Hero.mdx".to_string()));
+    }
+
+    #[test]
+    fn complete_archives_need_no_recovery() {
+        let mut creator = Creator::default();
+        creator.add_file(
+            "war3map.j",
+            &b"function main takes nothing returns nothing"[..],
+            FileOptions::default(),
+        );
+
+        let mut buf = Cursor::new(Vec::new());
+        creator.write(&mut buf).unwrap();
+        let mut archive = Archive::open(Cursor::new(buf.into_inner())).unwrap();
+
+        assert!(!needs_recovery(&mut archive));
+    }
+
+    #[test]
+    fn string_literals_of_scripts() {
+        let script = br#"call Foo("a\\b.mdx", 'it''s', "say \"hi\"")
+local s = "unterminated
+x = 'next line'"#;
+
+        assert_eq!(
+            string_literals(script),
+            vec![
+                "a\\b.mdx",
+                "it",
+                "s",
+                "say \"hi\"",
+                "unterminated",
+                "next line"
+            ]
+        );
+    }
+
+    #[test]
+    fn path_variants_of_models_textures_and_icons() {
+        assert_eq!(
+            path_variants("units/human/Footman/Footman.mdl"),
+            vec![
+                "units\\human\\Footman\\Footman.mdl",
+                "units\\human\\Footman\\Footman.mdx",
+                "units\\human\\Footman\\Footman_portrait.mdx",
+            ]
+        );
+        assert_eq!(
+            path_variants("Doodads\\Tree"),
+            vec![
+                "Doodads\\Tree",
+                "Doodads\\Tree.mdx",
+                "Doodads\\Tree_portrait.mdx",
+            ]
+        );
+        assert_eq!(
+            path_variants("ReplaceableTextures\\PassiveButtons\\PASBTNAura.tga"),
+            vec![
+                "ReplaceableTextures\\PassiveButtons\\PASBTNAura.tga",
+                "ReplaceableTextures\\PassiveButtons\\PASBTNAura.blp",
+                "ReplaceableTextures\\CommandButtonsDisabled\\DISPASBTNAura.tga",
+            ]
+        );
+        assert_eq!(
+            path_variants("music.mp3"),
+            vec!["music.mp3", "war3mapImported\\music.mp3"]
+        );
+        assert!(path_variants("  ").is_empty());
+    }
+}
@@ -30,9 +30,10 @@ fn compile_script(ctx: LuaContext, args: LuaTable) -> Result<String, anyhow::Err
     let mut compiler = compiler::ScriptCompiler::new(ctx, module_provider, macro_provider);
 
     compiler.set_map_script(map_script.to_str()?.into());
+    compiler.set_obfuscate(args.get::<_, Option<bool>>("obfuscate")?.unwrap_or(false));
     compiler.add_module("main", false)?;
     compiler.add_module("config", true)?;
     compiler.add_module("init", true)?;
 
-    Ok(compiler.emit_script())
+    Ok(compiler.emit_script()?)
 }
@@ -0,0 +1,185 @@
+//! `map.doodads`, the doodads and destructables placed on the map (`war3map.doo`).
+
+use rlua::prelude::*;
+
+use ceres_formats::doodads::*;
+use ceres_formats::parser::doo;
+use ceres_formats::{ObjectId, ObjectKind};
+
+use crate::error::StringError;
+use crate::lua::object::objects_contain;
+use crate::lua::units::{item_sets_from_table, item_sets_to_table};
+use crate::lua::util::{array_to_table, get_array, get_or_default, list_to_table};
+
+pub(crate) struct LuaDoodads {
+    file:    DoodadsFile,
+    dirty:   bool,
+    /// The object stores of the map, against which new doodads are validated.
+    objects: LuaRegistryKey,
+}
+
+impl LuaDoodads {
+    pub(crate) fn new(file: DoodadsFile, objects: LuaRegistryKey) -> LuaDoodads {
+        LuaDoodads {
+            file,
+            dirty: false,
+            objects,
+        }
+    }
+
+    pub(crate) fn set_objects(&mut self, objects: LuaRegistryKey) {
+        self.objects = objects;
+    }
+
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub(crate) fn write_to_vec(&mut self) -> Result<Vec<u8>, std::io::Error> {
+        let mut buf = Vec::new();
+        doo::write::write_doodads(&mut buf, &self.file)?;
+        self.dirty = false;
+
+        Ok(buf)
+    }
+
+    fn doodad_to_table<'lua>(
+        &self,
+        ctx: LuaContext<'lua>,
+        doodad: &PlacedDoodad,
+    ) -> Result<LuaTable<'lua>, LuaError> {
+        let objects: LuaTable = ctx.registry_value(&self.objects)?;
+        let kind = if objects_contain(&objects, &[ObjectKind::DESTRUCTABLE], doodad.id)? {
+            "destructable"
+        } else {
+            "doodad"
+        };
+
+        let table = ctx.create_table()?;
+
+        table.set("type", doodad.id)?;
+        table.set("kind", kind)?;
+        table.set("creationNumber", doodad.creation_number)?;
+        table.set("variation", doodad.variation)?;
+        table.set("x", doodad.x)?;
+        table.set("y", doodad.y)?;
+        table.set("z", doodad.z)?;
+        table.set("facing", doodad.facing.to_degrees())?;
+        table.set("scale", array_to_table(ctx, &doodad.scale)?)?;
+        table.set("skin", doodad.skin)?;
+        table.set("flags", doodad.flags)?;
+        table.set("life", doodad.life)?;
+        table.set("itemTable", doodad.item_table)?;
+        table.set("itemSets", item_sets_to_table(ctx, &doodad.item_sets)?)?;
+
+        Ok(table)
+    }
+
+    fn add<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+        table: LuaTable<'lua>,
+    ) -> Result<LuaTable<'lua>, LuaError> {
+        let doodad = doodad_from_table(&table)?;
+        let objects: LuaTable = ctx.registry_value(&self.objects)?;
+        let kinds = [ObjectKind::DOODAD, ObjectKind::DESTRUCTABLE];
+
+        if !objects_contain(&objects, &kinds, doodad.id)? {
+            return Err(StringError::new(format!(
+                "cannot place {}, there is no such doodad or destructable",
+                doodad.id
+            ))
+            .into());
+        }
+
+        let creation_number = self.file.add_doodad(doodad);
+        self.dirty = true;
+
+        self.doodad_to_table(ctx, self.file.doodad(creation_number).unwrap())
+    }
+
+    fn remove<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+        doodad: LuaValue<'lua>,
+    ) -> Result<bool, LuaError> {
+        let creation_number = match doodad {
+            LuaValue::Table(doodad) => doodad.get("creationNumber")?,
+            value => u32::from_lua(value, ctx)?,
+        };
+
+        let removed = self.file.remove_doodad(creation_number).is_some();
+        self.dirty |= removed;
+
+        Ok(removed)
+    }
+}
+
+/// Builds a doodad from a table passed to `doodads:add()`. Only `type`, `x` and `y`
+/// are required, everything else defaults to what the World Editor uses.
+fn doodad_from_table(table: &LuaTable) -> Result<PlacedDoodad, LuaError> {
+    let mut doodad = PlacedDoodad::new(table.get("type")?, table.get("x")?, table.get("y")?);
+
+    doodad.skin = table.get::<_, Option<ObjectId>>("skin")?.unwrap_or(doodad.id);
+    doodad.variation = get_or_default(table, "variation")?;
+    doodad.z = get_or_default(table, "z")?;
+    doodad.facing = get_or_default::<f32>(table, "facing")?.to_radians();
+    get_array(table, "scale", &mut doodad.scale)?;
+
+    if let Some(flags) = table.get("flags")? {
+        doodad.flags = flags;
+    }
+    if let Some(life) = table.get("life")? {
+        doodad.life = life;
+    }
+    if let Some(item_table) = table.get("itemTable")? {
+        doodad.item_table = item_table;
+    }
+
+    doodad.item_sets = item_sets_from_table(table, "itemSets")?;
+
+    Ok(doodad)
+}
+
+impl LuaUserData for LuaDoodads {
+    fn add_methods<'lua, T>(methods: &mut T)
+    where
+        T: LuaUserDataMethods<'lua, Self>,
+    {
+        methods.add_method("all", |ctx, doodads, _: ()| {
+            list_to_table(ctx, &doodads.file.doodads, |doodad| {
+                doodads.doodad_to_table(ctx, doodad)
+            })
+        });
+
+        methods.add_method("get", |ctx, doodads, creation_number: u32| {
+            doodads
+                .file
+                .doodad(creation_number)
+                .map(|doodad| doodads.doodad_to_table(ctx, doodad))
+                .transpose()
+        });
+
+        methods.add_method("specials", |ctx, doodads, _: ()| {
+            list_to_table(ctx, &doodads.file.special_doodads, |special| {
+                let table = ctx.create_table()?;
+                table.set("type", special.id)?;
+                table.set("variation", special.variation)?;
+                table.set("x", special.x)?;
+                table.set("y", special.y)?;
+
+                Ok(table)
+            })
+        });
+
+        methods.add_method_mut("add", |ctx, doodads, table: LuaTable| doodads.add(ctx, table));
+
+        methods.add_method_mut("remove", |ctx, doodads, doodad: LuaValue| {
+            doodads.remove(ctx, doodad)
+        });
+
+        methods.add_meta_method(LuaMetaMethod::Len, |_, doodads, _: ()| {
+            Ok(doodads.file.doodads.len())
+        });
+    }
+}
@@ -78,7 +78,10 @@ fn lua_read_dir<'lua>(
         return Err(LuaFileError::NotADir.into());
     }
 
-    let entries: Vec<_> = fs::read_dir(path)?.collect();
+    let mut entries: Vec<_> = fs::read_dir(path)?.collect();
+
+    // read_dir returns entries in whatever order the file system keeps them
+    entries.sort_by_key(|entry| entry.as_ref().ok().map(|entry| entry.path()));
 
     let files = entries
         .iter()
@@ -0,0 +1,184 @@
+//! The `image` module, which converts PNG, TGA and BLP images to BLP textures,
+//! e.g. to generate icons.
+
+use std::fs;
+
+use anyhow::anyhow;
+use rlua::prelude::*;
+
+use ceres_formats::blp::{Blp, Compression};
+use ceres_formats::image::Image;
+use ceres_formats::parser::{blp, tga};
+
+use crate::lua::util::wrap_result;
+
+const PNG_MAGIC: &[u8] = b"\x89PNG";
+// BLP2 textures are taken as BLP, so that reading them fails with a useful error
+const BLP_MAGIC: &[u8] = b"BLP";
+
+const DEFAULT_QUALITY: u8 = 80;
+
+fn decode_png(data: &[u8]) -> Result<Image, anyhow::Error> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+
+    let pixels = match info.color_type {
+        png::ColorType::RGBA => buf,
+        png::ColorType::RGB => buf
+            .chunks(3)
+            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks(2)
+            .flat_map(|gray| vec![gray[0], gray[0], gray[0], gray[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf
+            .iter()
+            .flat_map(|gray| vec![*gray, *gray, *gray, 255])
+            .collect(),
+        // expanded to RGB(A) by the decoder
+        png::ColorType::Indexed => return Err(anyhow!("unexpected indexed PNG")),
+    };
+
+    Image::from_rgba(info.width, info.height, pixels).ok_or_else(|| anyhow!("invalid PNG size"))
+}
+
+/// Decodes a PNG, BLP or TGA image. TGA has no magic,
+/// so anything else is taken to be one.
+fn decode_image(data: &[u8]) -> Result<Image, anyhow::Error> {
+    if data.starts_with(PNG_MAGIC) {
+        decode_png(data)
+    } else if data.starts_with(BLP_MAGIC) {
+        let blp = blp::read::read_blp(data)?;
+
+        Ok(blp.mipmaps.into_iter().next().unwrap())
+    } else {
+        Ok(tga::read::read_tga(data)?)
+    }
+}
+
+fn compression_from_str(compression: &str) -> Result<Compression, LuaError> {
+    match compression {
+        "jpeg" => Ok(Compression::Jpeg),
+        "palette" => Ok(Compression::Palette),
+        _ => Err(LuaError::external(anyhow!(
+            "unknown BLP compression {}, expected jpeg or palette",
+            compression
+        ))),
+    }
+}
+
+/// An image in Lua. It is never modified, every operation returns a new one.
+pub(crate) struct LuaImage {
+    inner: Image,
+}
+
+impl LuaImage {
+    fn to_blp(&self, options: Option<LuaTable>) -> Result<Vec<u8>, LuaError> {
+        let mut compression = Compression::Jpeg;
+        let mut quality = DEFAULT_QUALITY;
+        let mut mipmaps = true;
+
+        if let Some(options) = options {
+            if let Some(value) = options.get::<_, Option<String>>("compression")? {
+                compression = compression_from_str(&value)?;
+            }
+            if let Some(value) = options.get::<_, Option<u8>>("quality")? {
+                quality = value;
+            }
+            if let Some(value) = options.get::<_, Option<bool>>("mipmaps")? {
+                mipmaps = value;
+            }
+        }
+
+        let blp = Blp::new(self.inner.clone(), compression, mipmaps);
+        let mut buf = Vec::new();
+        blp::write::write_blp(&mut buf, &blp, quality).map_err(LuaError::external)?;
+
+        Ok(buf)
+    }
+
+    fn to_tga(&self) -> Result<Vec<u8>, LuaError> {
+        let mut buf = Vec::new();
+        tga::write::write_tga(&mut buf, &self.inner).map_err(LuaError::external)?;
+
+        Ok(buf)
+    }
+}
+
+impl LuaUserData for LuaImage {
+    fn add_methods<'lua, T>(methods: &mut T)
+    where
+        T: LuaUserDataMethods<'lua, Self>,
+    {
+        methods.add_meta_method(LuaMetaMethod::Index, |_, image, key: LuaString| {
+            Ok(match key.as_bytes() {
+                b"width" => LuaValue::Integer(image.inner.width as LuaInteger),
+                b"height" => LuaValue::Integer(image.inner.height as LuaInteger),
+                _ => LuaValue::Nil,
+            })
+        });
+
+        methods.add_method("resize", |_, image, (width, height): (u32, u32)| {
+            Ok(LuaImage {
+                inner: image.inner.resized(width, height),
+            })
+        });
+
+        methods.add_method("disabled", |_, image, _: ()| {
+            Ok(LuaImage {
+                inner: image.inner.disabled(),
+            })
+        });
+
+        methods.add_method("passive", |_, image, _: ()| {
+            Ok(LuaImage {
+                inner: image.inner.with_passive_frame(),
+            })
+        });
+
+        methods.add_method("toBlp", |ctx, image, options: Option<LuaTable>| {
+            ctx.create_string(&image.to_blp(options)?)
+        });
+
+        methods.add_method("toTga", |ctx, image, _: ()| {
+            ctx.create_string(&image.to_tga()?)
+        });
+    }
+}
+
+fn get_image_load_luafn(ctx: LuaContext) -> LuaFunction {
+    ctx.create_function(|ctx, path: String| {
+        let result = fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| decode_image(&data))
+            .map(|inner| LuaImage { inner });
+
+        Ok(wrap_result(ctx, result))
+    })
+    .unwrap()
+}
+
+fn get_image_from_string_luafn(ctx: LuaContext) -> LuaFunction {
+    ctx.create_function(|ctx, data: LuaString| {
+        let result = decode_image(data.as_bytes()).map(|inner| LuaImage { inner });
+
+        Ok(wrap_result(ctx, result))
+    })
+    .unwrap()
+}
+
+pub fn get_image_module(ctx: LuaContext) -> LuaTable {
+    let table = ctx.create_table().unwrap();
+
+    table.set("load", get_image_load_luafn(ctx)).unwrap();
+    table
+        .set("fromString", get_image_from_string_luafn(ctx))
+        .unwrap();
+
+    table
+}
@@ -0,0 +1,314 @@
+//! Translation catalogs, which map the strings of a map to their translation
+//! for one locale. They are read from and written to PO or JSON files.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::anyhow;
+use rlua::prelude::*;
+
+use crate::lua::util::wrap_result;
+
+#[derive(Debug, Default)]
+pub(crate) struct Catalog {
+    /// Source strings and their translations, which are empty for untranslated
+    /// strings, like in PO files.
+    entries: BTreeMap<String, String>,
+}
+
+impl Catalog {
+    /// The translation of `source`, if it has one.
+    pub(crate) fn translation(&self, source: &str) -> Option<&str> {
+        self.entries
+            .get(source)
+            .map(String::as_str)
+            .filter(|translation| !translation.is_empty() && *translation != source)
+    }
+
+    pub(crate) fn from_table(table: LuaTable) -> Result<Catalog, LuaError> {
+        let entries = table
+            .pairs::<String, String>()
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        Ok(Catalog { entries })
+    }
+
+    fn to_table<'lua>(&self, ctx: LuaContext<'lua>) -> Result<LuaTable<'lua>, LuaError> {
+        ctx.create_table_from(
+            self.entries
+                .iter()
+                .map(|(source, translation)| (source.as_str(), translation.as_str())),
+        )
+    }
+
+    fn read(path: &Path) -> Result<Catalog, anyhow::Error> {
+        let source = fs::read_to_string(path)?;
+
+        let entries = if is_json(path) {
+            serde_json::from_str(&source)?
+        } else {
+            read_po(&source)?
+        };
+
+        Ok(Catalog { entries })
+    }
+
+    fn write(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let content = if is_json(path) {
+            serde_json::to_string_pretty(&self.entries)?
+        } else {
+            write_po(&self.entries)
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+
+        Ok(())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+fn escape_po(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Parses a quoted PO string, e.g. `"Hello\n"`.
+fn unescape_po(value: &str) -> Option<String> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next()? {
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            't' => unescaped.push('\t'),
+            c => unescaped.push(c),
+        }
+    }
+
+    Some(unescaped)
+}
+
+/// Reads the `msgid`/`msgstr` pairs of a PO file. Plural forms and contexts
+/// are not supported, since the strings of a map have neither.
+fn read_po(source: &str) -> Result<BTreeMap<String, String>, anyhow::Error> {
+    let mut entries = BTreeMap::new();
+    let mut msgid: Option<String> = None;
+    let mut msgstr: Option<String> = None;
+
+    let mut finish = |msgid: &mut Option<String>, msgstr: &mut Option<String>| {
+        if let (Some(id), Some(translation)) = (msgid.take(), msgstr.take()) {
+            // the entry with an empty msgid is the header
+            if !id.is_empty() {
+                entries.insert(id, translation);
+            }
+        }
+    };
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        let invalid = |message: &str| anyhow!("line {}: {}", number + 1, message);
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix("msgid ") {
+            finish(&mut msgid, &mut msgstr);
+            msgid = Some(unescape_po(value.trim()).ok_or_else(|| invalid("invalid string"))?);
+        } else if let Some(value) = line.strip_prefix("msgstr ") {
+            if msgid.is_none() || msgstr.is_some() {
+                return Err(invalid("msgstr without msgid"));
+            }
+            msgstr = Some(unescape_po(value.trim()).ok_or_else(|| invalid("invalid string"))?);
+        } else if line.starts_with('"') {
+            let value = unescape_po(line).ok_or_else(|| invalid("invalid string"))?;
+            match (&mut msgid, &mut msgstr) {
+                (_, Some(msgstr)) => msgstr.push_str(&value),
+                (Some(msgid), None) => msgid.push_str(&value),
+                (None, None) => return Err(invalid("string outside of an entry")),
+            }
+        } else {
+            return Err(invalid("expected msgid, msgstr or a string"));
+        }
+    }
+    finish(&mut msgid, &mut msgstr);
+
+    Ok(entries)
+}
+
+fn write_po(entries: &BTreeMap<String, String>) -> String {
+    let mut content =
+        String::from("msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+
+    for (source, translation) in entries {
+        content.push_str(&format!(
+            "\nmsgid \"{}\"\nmsgstr \"{}\"\n",
+            escape_po(source),
+            escape_po(translation)
+        ));
+    }
+
+    content
+}
+
+pub fn get_read_catalog_luafn(ctx: LuaContext) -> LuaFunction {
+    ctx.create_function(|ctx, path: String| {
+        let result = Catalog::read(Path::new(&path))
+            .and_then(|catalog| catalog.to_table(ctx).map_err(anyhow::Error::from));
+
+        Ok(wrap_result(ctx, result))
+    })
+    .unwrap()
+}
+
+pub fn get_write_catalog_luafn(ctx: LuaContext) -> LuaFunction {
+    ctx.create_function(|ctx, (path, catalog): (String, LuaTable)| {
+        let catalog = Catalog::from_table(catalog)?;
+        let result = catalog.write(Path::new(&path)).map(|_| true);
+
+        Ok(wrap_result(ctx, result))
+    })
+    .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use std::process;
+
+    use super::*;
+
+    fn entries(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(source, translation)| (source.to_string(), translation.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn po_files_are_read() {
+        let source = r#"# Translation of my map
+msgid ""
+msgstr ""
+"Content-Type: text/plain; charset=UTF-8\n"
+"Language: de\n"
+
+#: war3map.w3u
+msgid "Footman"
+msgstr "Fußsoldat"
+
+msgid ""
+"A seasoned officer.\r\n"
+"|cffffcc00Level 2|r"
+msgstr "Ein erfahrener Offizier.\r\n|cffffcc00Stufe 2|r"
+
+msgid "Say \"hi\"\tplease \\ thanks"
+msgstr ""
+"#;
+
+        assert_eq!(
+            read_po(source).unwrap(),
+            entries(&[
+                ("Footman", "Fußsoldat"),
+                (
+                    "A seasoned officer.\r\n|cffffcc00Level 2|r",
+                    "Ein erfahrener Offizier.\r\n|cffffcc00Stufe 2|r"
+                ),
+                ("Say \"hi\"\tplease \\ thanks", ""),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_po_files_are_rejected() {
+        let error = |source: &str| read_po(source).unwrap_err().to_string();
+
+        assert_eq!(error("msgstr \"x\""), "line 1: msgstr without msgid");
+        assert_eq!(
+            error("msgid \"a\"\nmsgstr \"b\"\nmsgstr \"c\""),
+            "line 3: msgstr without msgid"
+        );
+        assert_eq!(error("msgid \"a"), "line 1: invalid string");
+        assert_eq!(error("\"orphan\""), "line 1: string outside of an entry");
+        assert_eq!(error("msgctxt \"menu\""), "line 1: expected msgid, msgstr or a string");
+    }
+
+    #[test]
+    fn po_files_roundtrip() {
+        let entries = entries(&[
+            ("Footman", "Fußsoldat"),
+            ("Line one\r\nLine two", ""),
+            ("C:\\path \"quoted\"\t", "übersetzt"),
+        ]);
+
+        let content = write_po(&entries);
+        assert!(content.starts_with("msgid \"\"\nmsgstr \"\"\n"));
+        assert!(content.contains("\nmsgid \"Line one\\r\\nLine two\"\nmsgstr \"\"\n"));
+
+        assert_eq!(read_po(&content).unwrap(), entries);
+    }
+
+    #[test]
+    fn catalogs_roundtrip_through_files() {
+        let dir = std::env::temp_dir().join(format!("ceres-catalog-{}", process::id()));
+        let catalog = Catalog {
+            entries: entries(&[("Footman", "Fußsoldat"), ("Knight", "")]),
+        };
+
+        for name in &["de.po", "de.JSON"] {
+            let path = dir.join("locales").join(name);
+            catalog.write(&path).unwrap();
+
+            let read = Catalog::read(&path).unwrap();
+            assert_eq!(read.entries, catalog.entries, "{}", name);
+        }
+
+        let json = fs::read_to_string(dir.join("locales").join("de.JSON")).unwrap();
+        assert!(json.trim_start().starts_with('{'));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn empty_and_identical_translations_are_ignored() {
+        let catalog = Catalog {
+            entries: entries(&[
+                ("Footman", "Fußsoldat"),
+                ("Knight", ""),
+                ("Rifleman", "Rifleman"),
+            ]),
+        };
+
+        assert_eq!(catalog.translation("Footman"), Some("Fußsoldat"));
+        assert_eq!(catalog.translation("Knight"), None);
+        assert_eq!(catalog.translation("Rifleman"), None);
+        assert_eq!(catalog.translation("Peasant"), None);
+    }
+}
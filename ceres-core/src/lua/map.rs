@@ -0,0 +1,1070 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::rc::Rc;
+
+use rlua::prelude::*;
+
+use ceres_formats::blp::{Blp, Compression};
+use ceres_formats::cameras::CamerasFile;
+use ceres_formats::error::FormatError;
+use ceres_formats::mapinfo::MapInfo;
+use ceres_formats::objectstore::ObjectStore;
+use ceres_formats::parser::{blp, doo, mmp, w3c, w3e, w3i, w3r, w3s, wts};
+use ceres_formats::regions::RegionsFile;
+use ceres_formats::sounds::SoundsFile;
+use ceres_formats::triggerstrings::TriggerStrings;
+use ceres_formats::ObjectKind;
+
+use crate::assets::{self, AssetReference, AssetSource};
+use crate::error::StringError;
+use crate::listfile::read_listfile;
+use crate::lua::doodads::LuaDoodads;
+use crate::lua::localization::Catalog;
+use crate::lua::mapinfo::{mapinfo_from_table, mapinfo_to_table};
+use crate::lua::mpq::{
+    creatoroptions_from_table, filerules_from_table, listfiles_from_table, progress_reporter,
+    report_to_table, LuaMapHeader,
+};
+use crate::lua::object::{open_store_from_str, LuaObjectStoreWrapper};
+use crate::lua::regions::*;
+use crate::lua::strings::{LuaStringTable, SharedStringTable, StringTable};
+use crate::lua::terrain::LuaTerrain;
+use crate::lua::units::LuaUnits;
+use crate::lua::util::{list_to_table, lvalue_to_objid, wrap_result};
+use crate::minimap::{minimap_icons, render_minimap, MinimapView, TileColors};
+use crate::vfs::{read_map_header, Vfs, WriteMode};
+
+const OBJECT_KINDS: &[ObjectKind] = &[
+    ObjectKind::ABILITY,
+    ObjectKind::ITEM,
+    ObjectKind::UNIT,
+    ObjectKind::BUFF,
+    ObjectKind::DOODAD,
+    ObjectKind::DESTRUCTABLE,
+    ObjectKind::UPGRADE,
+];
+
+const MINIMAP_QUALITY: u8 = 90;
+
+/// A file which build scripts edit as a plain table, like the regions.
+struct TableFile<T> {
+    /// The file as it was last read or committed, or `None` if it could not be read.
+    file:  Option<T>,
+    /// The editable table, or nil if the file could not be read.
+    table: LuaRegistryKey,
+}
+
+impl<T> TableFile<T> {
+    fn new<'lua, F>(ctx: LuaContext<'lua>, file: Option<T>, to_table: F) -> Result<Self, LuaError>
+    where
+        F: Fn(LuaContext<'lua>, &T) -> Result<LuaTable<'lua>, LuaError>,
+    {
+        let table = match &file {
+            Some(file) => ctx.create_registry_value(to_table(ctx, file)?)?,
+            None => ctx.create_registry_value(LuaValue::Nil)?,
+        };
+
+        Ok(TableFile { file, table })
+    }
+}
+
+/// A map opened by a build script. All modifications are kept
+/// in the overlay of the underlying `Vfs` until the map is written out.
+///
+/// The parts of the map are read when a script first uses them, so that a build
+/// which only touches a few of them does not parse every file of the map.
+/// `None` means that a part was not read yet.
+pub struct LuaMap {
+    vfs:     Vfs,
+    /// The header of the map file, or `None` if it has none.
+    header:  Option<LuaMapHeader>,
+    /// The table of object stores behind `map.objects`.
+    objects: Option<LuaRegistryKey>,
+    /// The map info behind `map.info`.
+    info:    Option<TableFile<MapInfo>>,
+    /// The `LuaUnits` behind `map.units`, or nil if the units could not be read.
+    units:   Option<LuaRegistryKey>,
+    /// The `LuaDoodads` behind `map.doodads`, or nil if they could not be read.
+    doodads: Option<LuaRegistryKey>,
+    /// The `LuaTerrain` behind `map.terrain`, or nil if the map has no readable terrain.
+    terrain: Option<LuaRegistryKey>,
+    regions: Option<TableFile<RegionsFile>>,
+    cameras: Option<TableFile<CamerasFile>>,
+    sounds:  Option<TableFile<SoundsFile>>,
+    /// The string table, shared with the object stores. The inner `None` means
+    /// that it could not be read.
+    strings: Option<Option<SharedStringTable>>,
+}
+
+impl LuaMap {
+    fn strings(&mut self) -> Option<SharedStringTable> {
+        let vfs = &mut self.vfs;
+
+        self.strings
+            .get_or_insert_with(|| read_strings(vfs))
+            .clone()
+    }
+
+    fn objects<'lua>(&mut self, ctx: LuaContext<'lua>) -> Result<LuaTable<'lua>, anyhow::Error> {
+        if let Some(objects) = &self.objects {
+            return Ok(ctx.registry_value(objects)?);
+        }
+
+        let strings = self.strings();
+        let objects = init_objects(ctx, &mut self.vfs, strings.as_ref())?;
+        self.objects = Some(ctx.create_registry_value(objects.clone())?);
+
+        Ok(objects)
+    }
+
+    fn info(&mut self, ctx: LuaContext) -> Result<&mut TableFile<MapInfo>, anyhow::Error> {
+        if self.info.is_none() {
+            let info = read_info(&mut self.vfs);
+            self.info = Some(TableFile::new(ctx, info, mapinfo_to_table)?);
+        }
+
+        Ok(self.info.as_mut().unwrap())
+    }
+
+    fn units<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+    ) -> Result<Option<LuaAnyUserData<'lua>>, anyhow::Error> {
+        if self.units.is_none() {
+            let objects = self.objects(ctx)?;
+            self.units = Some(init_units(ctx, &mut self.vfs, &objects)?);
+        }
+
+        Ok(ctx.registry_value(self.units.as_ref().unwrap())?)
+    }
+
+    fn doodads<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+    ) -> Result<Option<LuaAnyUserData<'lua>>, anyhow::Error> {
+        if self.doodads.is_none() {
+            let objects = self.objects(ctx)?;
+            self.doodads = Some(init_doodads(ctx, &mut self.vfs, &objects)?);
+        }
+
+        Ok(ctx.registry_value(self.doodads.as_ref().unwrap())?)
+    }
+
+    fn terrain<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+    ) -> Result<Option<LuaAnyUserData<'lua>>, anyhow::Error> {
+        if self.terrain.is_none() {
+            self.terrain = Some(init_terrain(ctx, &mut self.vfs)?);
+        }
+
+        Ok(ctx.registry_value(self.terrain.as_ref().unwrap())?)
+    }
+
+    fn regions(&mut self, ctx: LuaContext) -> Result<&mut TableFile<RegionsFile>, anyhow::Error> {
+        if self.regions.is_none() {
+            let regions = read_table_file(&mut self.vfs, "war3map.w3r", w3r::read::read_regions);
+            self.regions = Some(TableFile::new(ctx, regions, regions_to_table)?);
+        }
+
+        Ok(self.regions.as_mut().unwrap())
+    }
+
+    fn cameras(&mut self, ctx: LuaContext) -> Result<&mut TableFile<CamerasFile>, anyhow::Error> {
+        if self.cameras.is_none() {
+            let cameras = read_table_file(&mut self.vfs, "war3map.w3c", w3c::read::read_cameras);
+            self.cameras = Some(TableFile::new(ctx, cameras, cameras_to_table)?);
+        }
+
+        Ok(self.cameras.as_mut().unwrap())
+    }
+
+    fn sounds(&mut self, ctx: LuaContext) -> Result<&mut TableFile<SoundsFile>, anyhow::Error> {
+        if self.sounds.is_none() {
+            let sounds = read_table_file(&mut self.vfs, "war3map.w3s", w3s::read::read_sounds);
+            self.sounds = Some(TableFile::new(ctx, sounds, sounds_to_table)?);
+        }
+
+        Ok(self.sounds.as_mut().unwrap())
+    }
+
+    /// Looks up a field of `map`, reading the part of the map behind it if needed.
+    fn get<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+        key: &[u8],
+    ) -> Result<LuaValue<'lua>, anyhow::Error> {
+        let value = match key {
+            b"objects" => LuaValue::Table(self.objects(ctx)?),
+            b"header" => self.header.clone().to_lua(ctx)?,
+            b"info" => ctx.registry_value(&self.info(ctx)?.table)?,
+            b"units" => self.units(ctx)?.to_lua(ctx)?,
+            b"doodads" => self.doodads(ctx)?.to_lua(ctx)?,
+            b"terrain" => self.terrain(ctx)?.to_lua(ctx)?,
+            b"regions" => ctx.registry_value(&self.regions(ctx)?.table)?,
+            b"cameras" => ctx.registry_value(&self.cameras(ctx)?.table)?,
+            b"sounds" => ctx.registry_value(&self.sounds(ctx)?.table)?,
+            b"strings" => self.strings().map(LuaStringTable::new).to_lua(ctx)?,
+            _ => LuaValue::Nil,
+        };
+
+        Ok(value)
+    }
+
+    fn read_file<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+        path: &str,
+    ) -> Result<LuaString<'lua>, anyhow::Error> {
+        let contents = self.vfs.read_file(path)?;
+
+        Ok(ctx.create_string(&contents)?)
+    }
+
+    fn write_to_mpq<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+        path: &str,
+        options: Option<LuaTable<'lua>>,
+    ) -> Result<bool, anyhow::Error> {
+        let header = self.header.as_ref().map(LuaMapHeader::get);
+        let creator_options = creatoroptions_from_table(options.as_ref())?;
+        let mode = writemode_from_table(options.as_ref())?;
+        let progress = progress_reporter(ctx, options.as_ref())?;
+        let rules = filerules_from_table(options)?;
+
+        self.vfs.write_to_mpq(
+            path,
+            &rules,
+            creator_options,
+            header.as_ref(),
+            mode,
+            progress,
+        )?;
+
+        Ok(true)
+    }
+
+    fn write_to_dir(&mut self, path: &str) -> Result<bool, anyhow::Error> {
+        self.vfs.write_to_dir(path)?;
+
+        Ok(true)
+    }
+}
+
+/// `patch = true` appends changed files to the existing output archive,
+/// `update = true` copies unchanged files from the base archive as they are.
+fn writemode_from_table(options: Option<&LuaTable>) -> Result<WriteMode, LuaError> {
+    let options = match options {
+        Some(options) => options,
+        None => return Ok(WriteMode::default()),
+    };
+
+    if options.get::<_, Option<bool>>("patch")?.unwrap_or(false) {
+        Ok(WriteMode::Patch)
+    } else if options.get::<_, Option<bool>>("update")?.unwrap_or(false) {
+        Ok(WriteMode::Update)
+    } else {
+        Ok(WriteMode::Rebuild)
+    }
+}
+
+/// Reads the string table. Maps without a `war3map.wts` get an empty one,
+/// which is only written if strings are added.
+fn read_strings(vfs: &mut Vfs) -> Option<SharedStringTable> {
+    let strings = match vfs.read_file("war3map.wts") {
+        Ok(data) => match wts::read::read_string_table(&data) {
+            Ok(strings) => strings,
+            Err(error) => {
+                eprintln!("could not read war3map.wts, map.strings will be nil: {}", error);
+                return None;
+            }
+        },
+        Err(_) => TriggerStrings::default(),
+    };
+
+    Some(Rc::new(StringTable::new(strings).into()))
+}
+
+/// Reads every object data file in the map into a table of object stores,
+/// keyed by the object type. Missing files produce blank stores.
+fn init_objects<'lua>(
+    ctx: LuaContext<'lua>,
+    vfs: &mut Vfs,
+    strings: Option<&SharedStringTable>,
+) -> Result<LuaTable<'lua>, anyhow::Error> {
+    let objects = ctx.create_table()?;
+
+    for kind in OBJECT_KINDS {
+        let mut store = match vfs.read_file(&format!("war3map.{}", kind.to_ext())) {
+            Ok(data) => open_store_from_str(&data, *kind)?,
+            Err(_) => LuaObjectStoreWrapper::new(*kind),
+        };
+
+        if let Some(strings) = strings {
+            store.set_strings(Rc::clone(strings));
+        }
+
+        objects.set(kind.to_typestr(), store)?;
+    }
+
+    Ok(objects)
+}
+
+/// The value of a part of the map which was read already, or `None`
+/// if it was not read yet or is nil.
+fn loaded<'lua, T: FromLua<'lua>>(
+    ctx: LuaContext<'lua>,
+    key: &Option<LuaRegistryKey>,
+) -> Result<Option<T>, LuaError> {
+    match key {
+        Some(key) => ctx.registry_value(key),
+        None => Ok(None),
+    }
+}
+
+/// Writes out all object stores which were modified since they were read.
+/// With `moveLongStrings = n`, text values of at least n bytes are moved
+/// into the string table first, which `commit_strings` writes out.
+fn commit_objects(
+    ctx: LuaContext,
+    map: &mut LuaMap,
+    options: Option<LuaTable>,
+) -> Result<bool, anyhow::Error> {
+    let objects: LuaTable = match loaded(ctx, &map.objects)? {
+        Some(objects) => objects,
+        None => return Ok(false),
+    };
+    let min_length = match options {
+        Some(options) => options.get::<_, Option<usize>>("moveLongStrings")?,
+        None => None,
+    };
+
+    if min_length.is_some() && map.strings().is_none() {
+        eprintln!("the map has no readable string table, long strings are kept in place");
+    }
+
+    for pair in objects.pairs::<LuaValue, LuaAnyUserData>() {
+        let (_, store) = pair?;
+        let store = store.borrow::<LuaObjectStoreWrapper>()?;
+
+        if let Some(min_length) = min_length {
+            store.move_long_strings(min_length);
+        }
+
+        if store.is_dirty() {
+            let data = store.write_to_vec()?;
+            let path = format!("war3map.{}", store.kind().to_ext());
+
+            map.vfs.add_file_contents(&path, data);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Writes out the string table if strings were added or changed.
+fn commit_strings(map: &mut LuaMap) -> Result<bool, anyhow::Error> {
+    let strings = match &map.strings {
+        Some(Some(strings)) => strings,
+        _ => return Ok(false),
+    };
+
+    let mut strings = strings.borrow_mut();
+    if strings.is_dirty() {
+        let data = strings.write_to_vec()?;
+
+        map.vfs.add_file_contents("war3map.wts", data);
+    }
+
+    Ok(true)
+}
+
+/// The strings of the map which are shown to players: the string table, and the
+/// text fields of object data which are not in the string table. Each string is
+/// listed once.
+fn extract_strings(ctx: LuaContext, map: &mut LuaMap) -> Result<Vec<String>, anyhow::Error> {
+    let mut strings = BTreeSet::new();
+
+    if let Some(table) = map.strings() {
+        let table = table.borrow();
+        strings.extend(
+            table
+                .strings()
+                .strings()
+                .map(|(_, string)| string.value.clone()),
+        );
+    }
+
+    let objects = map.objects(ctx)?;
+    for pair in objects.pairs::<LuaValue, LuaAnyUserData>() {
+        let (_, store) = pair?;
+        strings.extend(store.borrow::<LuaObjectStoreWrapper>()?.text_strings());
+    }
+
+    strings.remove("");
+
+    Ok(strings.into_iter().collect())
+}
+
+/// Replaces the strings of the map which have a translation in `catalog`, both
+/// in the string table and in object data. Returns the number of replaced strings.
+fn translate(ctx: LuaContext, map: &mut LuaMap, catalog: LuaTable) -> Result<usize, anyhow::Error> {
+    let catalog = Catalog::from_table(catalog)?;
+    let mut translated = 0;
+
+    if let Some(strings) = map.strings() {
+        translated += strings.borrow_mut().translate(&catalog);
+    }
+
+    let objects = map.objects(ctx)?;
+    for pair in objects.pairs::<LuaValue, LuaAnyUserData>() {
+        let (_, store) = pair?;
+        translated += store.borrow::<LuaObjectStoreWrapper>()?.translate(&catalog);
+    }
+
+    Ok(translated)
+}
+
+fn asset_reference_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    reference: &AssetReference,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+    table.set("path", reference.path.as_str())?;
+
+    match &reference.source {
+        AssetSource::Object { id, field, level } => {
+            table.set("object", *id)?;
+            table.set("field", field.as_str())?;
+            table.set("level", *level)?;
+        }
+        AssetSource::Sound { name } => table.set("sound", name.as_str())?,
+        AssetSource::Script { file } => table.set("script", file.as_str())?,
+        AssetSource::Model { file } => table.set("model", file.as_str())?,
+    }
+
+    Ok(table)
+}
+
+/// Calls `f` with the object stores of the map, borrowed from their Lua wrappers.
+fn with_object_stores<R, F>(objects: LuaTable, f: F) -> Result<R, LuaError>
+where
+    F: FnOnce(&[&ObjectStore]) -> R,
+{
+    let stores = objects
+        .pairs::<LuaValue, LuaAnyUserData>()
+        .map(|pair| pair.map(|(_, store)| store))
+        .collect::<Result<Vec<_>, _>>()?;
+    let stores = stores
+        .iter()
+        .map(|store| store.borrow::<LuaObjectStoreWrapper>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let stores: Vec<_> = stores.iter().map(|store| store.store()).collect();
+
+    Ok(f(&stores))
+}
+
+/// Checks the files referred to by object data, sounds and the script,
+/// see `assets::validate_assets`.
+fn validate_assets<'lua>(
+    ctx: LuaContext<'lua>,
+    map: &mut LuaMap,
+    listfiles: &[String],
+) -> Result<LuaTable<'lua>, anyhow::Error> {
+    let objects = map.objects(ctx)?;
+    let vfs = &mut map.vfs;
+    let report = with_object_stores(objects, |stores| {
+        assets::validate_assets(vfs, stores, listfiles)
+    })?;
+
+    let table = ctx.create_table()?;
+    table.set(
+        "missing",
+        list_to_table(ctx, &report.missing, |reference| {
+            asset_reference_to_table(ctx, reference)
+        })?,
+    )?;
+    table.set("unused", report.unused)?;
+
+    Ok(table)
+}
+
+/// Options of `map:updateMinimap()`.
+struct MinimapOptions {
+    colors: TileColors,
+    image:  bool,
+    icons:  bool,
+}
+
+fn minimap_options_from_table(options: Option<LuaTable>) -> Result<MinimapOptions, LuaError> {
+    let mut result = MinimapOptions {
+        colors: TileColors::new(),
+        image:  true,
+        icons:  true,
+    };
+
+    if let Some(options) = options {
+        if let Some(colors) = options.get::<_, Option<LuaTable>>("colors")? {
+            for pair in colors.pairs::<LuaValue, Vec<u8>>() {
+                let (id, color) = pair?;
+                if color.len() != 3 {
+                    return Err(StringError::new("minimap colors must be { r, g, b }").into());
+                }
+
+                result
+                    .colors
+                    .insert(lvalue_to_objid(id)?, [color[0], color[1], color[2]]);
+            }
+        }
+        if let Some(image) = options.get::<_, Option<bool>>("image")? {
+            result.image = image;
+        }
+        if let Some(icons) = options.get::<_, Option<bool>>("icons")? {
+            result.icons = icons;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Renders `war3mapMap.blp` and `war3map.mmp` from the terrain, units and info
+/// as they are now, including changes which have not been committed yet.
+fn update_minimap(
+    ctx: LuaContext,
+    map: &mut LuaMap,
+    options: MinimapOptions,
+) -> Result<bool, anyhow::Error> {
+    let terrain = match map.terrain(ctx)? {
+        Some(terrain) => terrain,
+        None => return Ok(false),
+    };
+    let terrain = terrain.borrow::<LuaTerrain>()?;
+
+    let info: Option<LuaTable> = ctx.registry_value(&map.info(ctx)?.table)?;
+    let info = info.map(|table| mapinfo_from_table(&table)).transpose()?;
+    let view = MinimapView::new(terrain.terrain(), info.as_ref());
+
+    if options.image {
+        let image = render_minimap(terrain.terrain(), &view, &options.colors);
+        let blp = Blp::new(image, Compression::Jpeg, true);
+        let mut data = Vec::new();
+        blp::write::write_blp(&mut data, &blp, MINIMAP_QUALITY)?;
+
+        map.vfs.add_file_contents("war3mapMap.blp", data);
+    }
+
+    // maps whose units could not be read keep their icons
+    let units = if options.icons { map.units(ctx)? } else { None };
+    if let Some(units) = units {
+        let objects = map.objects(ctx)?;
+        let units = units.borrow::<LuaUnits>()?;
+        let icons = with_object_stores(objects, |stores| {
+            minimap_icons(&units.file().units, &view, info.as_ref(), stores)
+        })?;
+        let mut data = Vec::new();
+        mmp::write::write_minimap_icons(&mut data, &icons)?;
+
+        map.vfs.add_file_contents("war3map.mmp", data);
+    }
+
+    Ok(true)
+}
+
+/// Reads the map info. Maps without a readable `war3map.w3i` get no info,
+/// since there is nothing sensible to fill it with.
+fn read_info(vfs: &mut Vfs) -> Option<MapInfo> {
+    let data = vfs.read_file("war3map.w3i").ok()?;
+
+    match w3i::read::read_map_info(&data) {
+        Ok(info) => Some(info),
+        Err(error) => {
+            eprintln!(
+                "could not read war3map.w3i, map.info will be nil: {}",
+                error
+            );
+            None
+        }
+    }
+}
+
+/// Writes out the map info if it was modified since it was read.
+fn commit_info(ctx: LuaContext, map: &mut LuaMap) -> Result<bool, anyhow::Error> {
+    commit_table_file(
+        ctx,
+        &mut map.vfs,
+        "war3map.w3i",
+        map.info.as_mut(),
+        |table, _| mapinfo_from_table(table),
+        |buf, info| w3i::write::write_map_info(buf, info),
+    )
+}
+
+/// Reads the placed units. Maps without a `war3mapUnits.doo` get an empty
+/// one, which is only written if units are added.
+fn init_units<'lua>(
+    ctx: LuaContext<'lua>,
+    vfs: &mut Vfs,
+    objects: &LuaTable<'lua>,
+) -> Result<LuaRegistryKey, LuaError> {
+    let file = match vfs.read_file("war3mapUnits.doo") {
+        Ok(data) => match doo::read::read_units(&data) {
+            Ok(file) => file,
+            Err(error) => {
+                eprintln!("could not read war3mapUnits.doo, map.units will be nil: {}", error);
+                return ctx.create_registry_value(LuaValue::Nil);
+            }
+        },
+        Err(_) => Default::default(),
+    };
+
+    let objects = ctx.create_registry_value(objects.clone())?;
+
+    ctx.create_registry_value(LuaUnits::new(file, objects))
+}
+
+/// Reads the placed doodads, like `init_units`.
+fn init_doodads<'lua>(
+    ctx: LuaContext<'lua>,
+    vfs: &mut Vfs,
+    objects: &LuaTable<'lua>,
+) -> Result<LuaRegistryKey, LuaError> {
+    let file = match vfs.read_file("war3map.doo") {
+        Ok(data) => match doo::read::read_doodads(&data) {
+            Ok(file) => file,
+            Err(error) => {
+                eprintln!("could not read war3map.doo, map.doodads will be nil: {}", error);
+                return ctx.create_registry_value(LuaValue::Nil);
+            }
+        },
+        Err(_) => Default::default(),
+    };
+
+    let objects = ctx.create_registry_value(objects.clone())?;
+
+    ctx.create_registry_value(LuaDoodads::new(file, objects))
+}
+
+/// Reads the terrain. Unlike units and doodads, there is no sensible default
+/// for a map without a `war3map.w3e`, so `map.terrain` is nil then.
+fn init_terrain(ctx: LuaContext, vfs: &mut Vfs) -> Result<LuaRegistryKey, LuaError> {
+    let data = match vfs.read_file("war3map.w3e") {
+        Ok(data) => data,
+        Err(_) => return ctx.create_registry_value(LuaValue::Nil),
+    };
+
+    match w3e::read::read_terrain(&data) {
+        Ok(terrain) => ctx.create_registry_value(LuaTerrain::new(terrain)),
+        Err(error) => {
+            eprintln!("could not read war3map.w3e, map.terrain will be nil: {}", error);
+            ctx.create_registry_value(LuaValue::Nil)
+        }
+    }
+}
+
+/// Reads a file which build scripts edit as a plain table, like the regions.
+/// Maps without the file get an empty one, which is only written if it is modified.
+fn read_table_file<T, F>(vfs: &mut Vfs, path: &str, read: F) -> Option<T>
+where
+    T: Default,
+    F: Fn(&[u8]) -> Result<T, FormatError>,
+{
+    let data = match vfs.read_file(path) {
+        Ok(data) => data,
+        Err(_) => return Some(T::default()),
+    };
+
+    match read(&data) {
+        Ok(file) => Some(file),
+        Err(error) => {
+            eprintln!("could not read {}, its table will be nil: {}", path, error);
+            None
+        }
+    }
+}
+
+/// Writes out a file edited as a plain table if it differs from the file
+/// as it was last read or committed. Files which were never read are unchanged.
+fn commit_table_file<'lua, T, F, W>(
+    ctx: LuaContext<'lua>,
+    vfs: &mut Vfs,
+    path: &str,
+    table_file: Option<&mut TableFile<T>>,
+    from_table: F,
+    write: W,
+) -> Result<bool, anyhow::Error>
+where
+    T: PartialEq,
+    F: Fn(&LuaTable<'lua>, &T) -> Result<T, LuaError>,
+    W: Fn(&mut Vec<u8>, &T) -> Result<(), std::io::Error>,
+{
+    let table_file = match table_file {
+        Some(table_file) => table_file,
+        None => return Ok(false),
+    };
+
+    let table: Option<LuaTable> = ctx.registry_value(&table_file.table)?;
+    let (table, current) = match (table, table_file.file.as_ref()) {
+        (Some(table), Some(current)) => (table, current),
+        _ => return Ok(false),
+    };
+
+    let file = from_table(&table, current)?;
+
+    if current != &file {
+        let mut data = Vec::new();
+        write(&mut data, &file)?;
+
+        vfs.add_file_contents(path, data);
+        table_file.file = Some(file);
+    }
+
+    Ok(true)
+}
+
+/// Writes out the placed units if any were added or removed.
+fn commit_units(ctx: LuaContext, map: &mut LuaMap) -> Result<bool, anyhow::Error> {
+    let units: Option<LuaAnyUserData> = loaded(ctx, &map.units)?;
+    let units = match units {
+        Some(units) => units,
+        None => return Ok(false),
+    };
+
+    let mut units = units.borrow_mut::<LuaUnits>()?;
+    if units.is_dirty() {
+        let data = units.write_to_vec()?;
+
+        map.vfs.add_file_contents("war3mapUnits.doo", data);
+    }
+
+    Ok(true)
+}
+
+/// Writes out the placed doodads if any were added or removed.
+fn commit_doodads(ctx: LuaContext, map: &mut LuaMap) -> Result<bool, anyhow::Error> {
+    let doodads: Option<LuaAnyUserData> = loaded(ctx, &map.doodads)?;
+    let doodads = match doodads {
+        Some(doodads) => doodads,
+        None => return Ok(false),
+    };
+
+    let mut doodads = doodads.borrow_mut::<LuaDoodads>()?;
+    if doodads.is_dirty() {
+        let data = doodads.write_to_vec()?;
+
+        map.vfs.add_file_contents("war3map.doo", data);
+    }
+
+    Ok(true)
+}
+
+/// Writes out the terrain if it was modified.
+fn commit_terrain(ctx: LuaContext, map: &mut LuaMap) -> Result<bool, anyhow::Error> {
+    let terrain: Option<LuaAnyUserData> = loaded(ctx, &map.terrain)?;
+    let terrain = match terrain {
+        Some(terrain) => terrain,
+        None => return Ok(false),
+    };
+
+    let mut terrain = terrain.borrow_mut::<LuaTerrain>()?;
+    if terrain.is_dirty() {
+        let data = terrain.write_to_vec()?;
+
+        map.vfs.add_file_contents("war3map.w3e", data);
+    }
+
+    Ok(true)
+}
+
+impl LuaUserData for LuaMap {
+    fn add_methods<'lua, T>(methods: &mut T)
+    where
+        T: LuaUserDataMethods<'lua, Self>,
+    {
+        methods.add_method_mut("readFile", |ctx, map, path: String| {
+            let result = map.read_file(ctx, &path);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("hasFile", |_, map, path: String| Ok(map.vfs.has_file(&path)));
+
+        methods.add_method_mut("listFiles", |_, map, _: ()| Ok(map.vfs.list_files()));
+
+        methods.add_method_mut(
+            "addFileString",
+            |_, map, (path, contents): (String, LuaString)| {
+                map.vfs.add_file_contents(&path, contents.as_bytes().into());
+
+                Ok(())
+            },
+        );
+
+        methods.add_method_mut(
+            "addFileDisk",
+            |_, map, (archive_path, file_path): (String, String)| {
+                map.vfs.add_file_disk(&archive_path, file_path);
+
+                Ok(())
+            },
+        );
+
+        methods.add_method_mut("addDir", |ctx, map, path: String| {
+            let result = map.vfs.add_dir(&path).map(|_| true);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("removeFile", |_, map, path: String| {
+            map.vfs.remove_file(&path);
+
+            Ok(())
+        });
+
+        methods.add_method_mut("renameFile", |ctx, map, (from, to): (String, String)| {
+            let result = map.vfs.rename_file(&from, &to).map(|_| true);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("syncImports", |ctx, map, options: Option<LuaTable>| {
+            let prune = match options {
+                Some(options) => options.get::<_, Option<bool>>("prune")?.unwrap_or(false),
+                None => false,
+            };
+            let result = map.vfs.sync_imports(prune);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("exclude", |_, map, patterns: LuaValue| {
+            match patterns {
+                LuaValue::String(pattern) => map.vfs.exclude(pattern.to_str()?),
+                LuaValue::Table(patterns) => {
+                    for pattern in patterns.sequence_values::<String>() {
+                        map.vfs.exclude(&pattern?);
+                    }
+                }
+                _ => {
+                    return Err(StringError::new(
+                        "exclude patterns must be a string or a list of strings",
+                    )
+                    .into())
+                }
+            }
+
+            Ok(())
+        });
+
+        methods.add_method_mut("writeToDir", |ctx, map, path: String| {
+            let result = map.write_to_dir(&path);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut(
+            "writeToMpq",
+            |ctx, map, (path, options): (String, Option<LuaTable>)| {
+                let result = map.write_to_mpq(ctx, &path, options);
+
+                Ok(wrap_result(ctx, result))
+            },
+        );
+
+        methods.add_method_mut("recoverNames", |ctx, map, listfiles: Option<Vec<String>>| {
+            let result = listfiles
+                .unwrap_or_default()
+                .iter()
+                .map(read_listfile)
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|names| match map.vfs.recover_names(&names.concat()) {
+                    Some(report) => Ok(LuaValue::Table(report_to_table(ctx, &report)?)),
+                    None => Ok(LuaValue::Nil),
+                });
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("validateAssets", |ctx, map, listfiles: Option<Vec<String>>| {
+            let result = listfiles
+                .unwrap_or_default()
+                .iter()
+                .map(read_listfile)
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|names| validate_assets(ctx, map, &names.concat()));
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("updateMinimap", |ctx, map, options: Option<LuaTable>| {
+            let result = minimap_options_from_table(options)
+                .map_err(anyhow::Error::from)
+                .and_then(|options| update_minimap(ctx, map, options));
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("initObjects", |ctx, map, _: ()| {
+            let strings = map.strings();
+            let result = init_objects(ctx, &mut map.vfs, strings.as_ref()).and_then(|objects| {
+                // placements are validated against the new stores from now on
+                let units: Option<LuaAnyUserData> = loaded(ctx, &map.units)?;
+                if let Some(units) = units {
+                    let key = ctx.create_registry_value(objects.clone())?;
+                    units.borrow_mut::<LuaUnits>()?.set_objects(key);
+                }
+
+                let doodads: Option<LuaAnyUserData> = loaded(ctx, &map.doodads)?;
+                if let Some(doodads) = doodads {
+                    let key = ctx.create_registry_value(objects.clone())?;
+                    doodads.borrow_mut::<LuaDoodads>()?.set_objects(key);
+                }
+
+                map.objects = Some(ctx.create_registry_value(objects)?);
+
+                Ok(true)
+            });
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitObjects", |ctx, map, options: Option<LuaTable>| {
+            let result = commit_objects(ctx, map, options);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitStrings", |ctx, map, _: ()| {
+            let result = commit_strings(map);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("extractStrings", |ctx, map, _: ()| {
+            let result = extract_strings(ctx, map);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("translate", |ctx, map, catalog: LuaTable| {
+            let result = translate(ctx, map, catalog);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("initInfo", |ctx, map, _: ()| {
+            let info = read_info(&mut map.vfs);
+            let result = TableFile::new(ctx, info, mapinfo_to_table)
+                .map_err(anyhow::Error::from)
+                .map(|info| {
+                    map.info = Some(info);
+                    true
+                });
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitInfo", |ctx, map, _: ()| {
+            let result = commit_info(ctx, map);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitUnits", |ctx, map, _: ()| {
+            let result = commit_units(ctx, map);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitDoodads", |ctx, map, _: ()| {
+            let result = commit_doodads(ctx, map);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitTerrain", |ctx, map, _: ()| {
+            let result = commit_terrain(ctx, map);
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitRegions", |ctx, map, _: ()| {
+            let result = commit_table_file(
+                ctx,
+                &mut map.vfs,
+                "war3map.w3r",
+                map.regions.as_mut(),
+                regions_from_table,
+                |buf, file| w3r::write::write_regions(buf, file),
+            );
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitCameras", |ctx, map, _: ()| {
+            let result = commit_table_file(
+                ctx,
+                &mut map.vfs,
+                "war3map.w3c",
+                map.cameras.as_mut(),
+                cameras_from_table,
+                |buf, file| w3c::write::write_cameras(buf, file),
+            );
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("commitSounds", |ctx, map, _: ()| {
+            let result = commit_table_file(
+                ctx,
+                &mut map.vfs,
+                "war3map.w3s",
+                map.sounds.as_mut(),
+                sounds_from_table,
+                |buf, file| w3s::write::write_sounds(buf, file),
+            );
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_meta_method_mut(LuaMetaMethod::Index, |ctx, map, key: LuaValue| match key {
+            LuaValue::String(key) => map.get(ctx, key.as_bytes()).map_err(LuaError::external),
+            _ => Ok(LuaValue::Nil),
+        });
+    }
+}
+
+fn open_map(path: &str, options: Option<LuaTable>) -> Result<LuaMap, anyhow::Error> {
+    let listfiles = listfiles_from_table(options.as_ref())?;
+    let vfs = Vfs::open_with_listfiles(path, listfiles)?;
+
+    // maps stored as directories have no header
+    let header = if Path::new(path).is_file() {
+        read_map_header(path)?
+    } else {
+        None
+    };
+
+    Ok(LuaMap {
+        vfs,
+        header: header.map(LuaMapHeader::new),
+        objects: None,
+        info: None,
+        units: None,
+        doodads: None,
+        terrain: None,
+        regions: None,
+        cameras: None,
+        sounds: None,
+        strings: None,
+    })
+}
+
+pub fn get_open_map_luafn(ctx: LuaContext) -> LuaFunction {
+    ctx.create_function(|ctx, (path, options): (String, Option<LuaTable>)| {
+        let result = open_map(&path, options);
+
+        Ok(wrap_result(ctx, result))
+    })
+    .unwrap()
+}
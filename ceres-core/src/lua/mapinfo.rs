@@ -0,0 +1,408 @@
+//! Conversion of the map info (`war3map.w3i`) to and from plain Lua tables,
+//! which build scripts can edit like any other table.
+
+use rlua::prelude::*;
+
+use ceres_formats::mapinfo::*;
+use ceres_formats::ObjectId;
+
+use crate::error::StringError;
+use crate::lua::util::{array_to_table, get_array, get_list, get_or_default, list_to_table};
+
+fn tileset_to_str(tileset: u8) -> String {
+    if tileset == 0 {
+        String::new()
+    } else {
+        (tileset as char).to_string()
+    }
+}
+
+fn tileset_from_str(tileset: &str) -> u8 {
+    tileset.bytes().next().unwrap_or(0)
+}
+
+fn player_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    player: &PlayerInfo,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("id", player.id)?;
+    table.set("kind", player.kind)?;
+    table.set("race", player.race)?;
+    table.set("fixedStartPosition", player.fixed_start_position)?;
+    table.set("name", player.name.as_str())?;
+    table.set("startX", player.start_x)?;
+    table.set("startY", player.start_y)?;
+    table.set("allyLowPriorities", player.ally_low_priorities)?;
+    table.set("allyHighPriorities", player.ally_high_priorities)?;
+    table.set("enemyLowPriorities", player.enemy_low_priorities)?;
+    table.set("enemyHighPriorities", player.enemy_high_priorities)?;
+
+    Ok(table)
+}
+
+fn player_from_table(table: LuaTable) -> Result<PlayerInfo, LuaError> {
+    Ok(PlayerInfo {
+        id:                    get_or_default(&table, "id")?,
+        kind:                  get_or_default(&table, "kind")?,
+        race:                  get_or_default(&table, "race")?,
+        fixed_start_position:  get_or_default(&table, "fixedStartPosition")?,
+        name:                  get_or_default(&table, "name")?,
+        start_x:               get_or_default(&table, "startX")?,
+        start_y:               get_or_default(&table, "startY")?,
+        ally_low_priorities:   get_or_default(&table, "allyLowPriorities")?,
+        ally_high_priorities:  get_or_default(&table, "allyHighPriorities")?,
+        enemy_low_priorities:  get_or_default(&table, "enemyLowPriorities")?,
+        enemy_high_priorities: get_or_default(&table, "enemyHighPriorities")?,
+    })
+}
+
+fn force_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    force: &ForceInfo,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("flags", force.flags)?;
+    table.set("players", force.players)?;
+    table.set("name", force.name.as_str())?;
+
+    Ok(table)
+}
+
+fn force_from_table(table: LuaTable) -> Result<ForceInfo, LuaError> {
+    Ok(ForceInfo {
+        flags:   get_or_default(&table, "flags")?,
+        players: get_or_default(&table, "players")?,
+        name:    get_or_default(&table, "name")?,
+    })
+}
+
+fn upgrade_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    upgrade: &UpgradeAvailability,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("players", upgrade.players)?;
+    table.set("id", upgrade.id)?;
+    table.set("level", upgrade.level)?;
+    table.set("availability", upgrade.availability)?;
+
+    Ok(table)
+}
+
+fn upgrade_from_table(table: LuaTable) -> Result<UpgradeAvailability, LuaError> {
+    Ok(UpgradeAvailability {
+        players:      get_or_default(&table, "players")?,
+        id:           table.get("id")?,
+        level:        get_or_default(&table, "level")?,
+        availability: get_or_default(&table, "availability")?,
+    })
+}
+
+fn tech_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    tech: &TechAvailability,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("players", tech.players)?;
+    table.set("id", tech.id)?;
+
+    Ok(table)
+}
+
+fn tech_from_table(table: LuaTable) -> Result<TechAvailability, LuaError> {
+    Ok(TechAvailability {
+        players: get_or_default(&table, "players")?,
+        id:      table.get("id")?,
+    })
+}
+
+fn unit_table_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    unit_table: &RandomUnitTable,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("id", unit_table.id)?;
+    table.set("name", unit_table.name.as_str())?;
+    table.set("columns", array_to_table(ctx, &unit_table.columns)?)?;
+    table.set(
+        "rows",
+        list_to_table(ctx, &unit_table.rows, |row| {
+            let table = ctx.create_table()?;
+            table.set("chance", row.chance)?;
+            table.set("ids", array_to_table(ctx, &row.ids)?)?;
+
+            Ok(table)
+        })?,
+    )?;
+
+    Ok(table)
+}
+
+fn unit_table_from_table(table: LuaTable) -> Result<RandomUnitTable, LuaError> {
+    Ok(RandomUnitTable {
+        id:      get_or_default(&table, "id")?,
+        name:    get_or_default(&table, "name")?,
+        columns: get_or_default(&table, "columns")?,
+        rows:    get_list(&table, "rows", |row| {
+            Ok(RandomUnitRow {
+                chance: get_or_default(&row, "chance")?,
+                ids:    get_or_default(&row, "ids")?,
+            })
+        })?,
+    })
+}
+
+fn item_table_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    item_table: &RandomItemTable,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("id", item_table.id)?;
+    table.set("name", item_table.name.as_str())?;
+    table.set(
+        "sets",
+        list_to_table(ctx, &item_table.sets, |set| {
+            list_to_table(ctx, set, |item| {
+                let table = ctx.create_table()?;
+                table.set("chance", item.chance)?;
+                table.set("id", item.id)?;
+
+                Ok(table)
+            })
+        })?,
+    )?;
+
+    Ok(table)
+}
+
+fn item_table_from_table(table: LuaTable) -> Result<RandomItemTable, LuaError> {
+    Ok(RandomItemTable {
+        id:   get_or_default(&table, "id")?,
+        name: get_or_default(&table, "name")?,
+        sets: get_list(&table, "sets", |set| {
+            set.sequence_values::<LuaTable>()
+                .map(|item| {
+                    let item = item?;
+
+                    Ok(RandomItem {
+                        chance: get_or_default(&item, "chance")?,
+                        id:     item.get::<_, ObjectId>("id")?,
+                    })
+                })
+                .collect()
+        })?,
+    })
+}
+
+pub(crate) fn mapinfo_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    info: &MapInfo,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("version", info.version)?;
+    table.set("mapVersion", info.map_version)?;
+    table.set("editorVersion", info.editor_version)?;
+    table.set("gameVersion", array_to_table(ctx, &info.game_version)?)?;
+    table.set("name", info.name.as_str())?;
+    table.set("author", info.author.as_str())?;
+    table.set("description", info.description.as_str())?;
+    table.set("suggestedPlayers", info.suggested_players.as_str())?;
+    table.set("cameraBounds", array_to_table(ctx, &info.camera_bounds)?)?;
+    table.set(
+        "cameraComplements",
+        array_to_table(ctx, &info.camera_complements)?,
+    )?;
+    table.set("playableWidth", info.playable_width)?;
+    table.set("playableHeight", info.playable_height)?;
+    table.set("flags", info.flags)?;
+    table.set("tileset", tileset_to_str(info.tileset))?;
+
+    let loading_screen = ctx.create_table()?;
+    loading_screen.set("number", info.loading_screen.number)?;
+    loading_screen.set("model", info.loading_screen.model.as_str())?;
+    loading_screen.set("text", info.loading_screen.text.as_str())?;
+    loading_screen.set("title", info.loading_screen.title.as_str())?;
+    loading_screen.set("subtitle", info.loading_screen.subtitle.as_str())?;
+    table.set("loadingScreen", loading_screen)?;
+
+    table.set("campaignBackground", info.campaign_background)?;
+    table.set("gameDataSet", info.game_data_set)?;
+
+    let prologue = ctx.create_table()?;
+    prologue.set("model", info.prologue.model.as_str())?;
+    prologue.set("text", info.prologue.text.as_str())?;
+    prologue.set("title", info.prologue.title.as_str())?;
+    prologue.set("subtitle", info.prologue.subtitle.as_str())?;
+    table.set("prologue", prologue)?;
+
+    let fog = ctx.create_table()?;
+    fog.set("style", info.fog.style)?;
+    fog.set("startZ", info.fog.start_z)?;
+    fog.set("endZ", info.fog.end_z)?;
+    fog.set("density", info.fog.density)?;
+    fog.set("color", array_to_table(ctx, &info.fog.color)?)?;
+    table.set("fog", fog)?;
+
+    // weather effects are identified by their 4-char ID, 0 meaning none
+    let weather = Some(info.weather)
+        .filter(|weather| *weather != 0)
+        .map(ObjectId::new);
+    table.set("weather", weather)?;
+    table.set("soundEnvironment", info.sound_environment.as_str())?;
+    table.set("lightTileset", tileset_to_str(info.light_tileset))?;
+    table.set("waterColor", array_to_table(ctx, &info.water_color)?)?;
+
+    let script_language = match info.script_language {
+        ScriptLanguage::Jass => "jass",
+        ScriptLanguage::Lua => "lua",
+    };
+    table.set("scriptLanguage", script_language)?;
+    table.set("supportedModes", info.supported_modes)?;
+    table.set("gameDataVersion", info.game_data_version)?;
+
+    table.set(
+        "players",
+        list_to_table(ctx, &info.players, |player| player_to_table(ctx, player))?,
+    )?;
+    table.set(
+        "forces",
+        list_to_table(ctx, &info.forces, |force| force_to_table(ctx, force))?,
+    )?;
+    table.set(
+        "upgrades",
+        list_to_table(ctx, &info.upgrades, |upgrade| {
+            upgrade_to_table(ctx, upgrade)
+        })?,
+    )?;
+    table.set(
+        "techs",
+        list_to_table(ctx, &info.techs, |tech| tech_to_table(ctx, tech))?,
+    )?;
+    table.set(
+        "randomUnitTables",
+        list_to_table(ctx, &info.random_unit_tables, |unit_table| {
+            unit_table_to_table(ctx, unit_table)
+        })?,
+    )?;
+    table.set(
+        "randomItemTables",
+        list_to_table(ctx, &info.random_item_tables, |item_table| {
+            item_table_to_table(ctx, item_table)
+        })?,
+    )?;
+
+    Ok(table)
+}
+
+pub(crate) fn mapinfo_from_table(table: &LuaTable) -> Result<MapInfo, LuaError> {
+    let mut game_version = [0; 4];
+    get_array(table, "gameVersion", &mut game_version)?;
+    let mut camera_bounds = [0.0; 8];
+    get_array(table, "cameraBounds", &mut camera_bounds)?;
+    let mut camera_complements = [0; 4];
+    get_array(table, "cameraComplements", &mut camera_complements)?;
+    let mut water_color = [255; 4];
+    get_array(table, "waterColor", &mut water_color)?;
+
+    let loading_screen = match table.get::<_, Option<LuaTable>>("loadingScreen")? {
+        Some(loading_screen) => LoadingScreen {
+            number:   loading_screen
+                .get::<_, Option<i32>>("number")?
+                .unwrap_or(-1),
+            model:    get_or_default(&loading_screen, "model")?,
+            text:     get_or_default(&loading_screen, "text")?,
+            title:    get_or_default(&loading_screen, "title")?,
+            subtitle: get_or_default(&loading_screen, "subtitle")?,
+        },
+        None => LoadingScreen {
+            number: -1,
+            ..Default::default()
+        },
+    };
+
+    let prologue = match table.get::<_, Option<LuaTable>>("prologue")? {
+        Some(prologue) => Prologue {
+            model:    get_or_default(&prologue, "model")?,
+            text:     get_or_default(&prologue, "text")?,
+            title:    get_or_default(&prologue, "title")?,
+            subtitle: get_or_default(&prologue, "subtitle")?,
+        },
+        None => Prologue::default(),
+    };
+
+    let fog = match table.get::<_, Option<LuaTable>>("fog")? {
+        Some(fog) => {
+            let mut color = [255; 4];
+            get_array(&fog, "color", &mut color)?;
+
+            Fog {
+                style: get_or_default(&fog, "style")?,
+                start_z: get_or_default(&fog, "startZ")?,
+                end_z: get_or_default(&fog, "endZ")?,
+                density: get_or_default(&fog, "density")?,
+                color,
+            }
+        }
+        None => Fog::default(),
+    };
+
+    let script_language = match get_or_default::<String>(table, "scriptLanguage")?.as_str() {
+        "lua" => ScriptLanguage::Lua,
+        "jass" | "" => ScriptLanguage::Jass,
+        other => {
+            return Err(StringError::new(format!(
+                "unknown script language {}, expected \"jass\" or \"lua\"",
+                other
+            ))
+            .into())
+        }
+    };
+
+    Ok(MapInfo {
+        version: table.get("version")?,
+        map_version: get_or_default(table, "mapVersion")?,
+        editor_version: get_or_default(table, "editorVersion")?,
+        game_version,
+        name: get_or_default(table, "name")?,
+        author: get_or_default(table, "author")?,
+        description: get_or_default(table, "description")?,
+        suggested_players: get_or_default(table, "suggestedPlayers")?,
+        camera_bounds,
+        camera_complements,
+        playable_width: get_or_default(table, "playableWidth")?,
+        playable_height: get_or_default(table, "playableHeight")?,
+        flags: get_or_default(table, "flags")?,
+        tileset: tileset_from_str(&get_or_default::<String>(table, "tileset")?),
+        loading_screen,
+        campaign_background: table
+            .get::<_, Option<i32>>("campaignBackground")?
+            .unwrap_or(-1),
+        game_data_set: get_or_default(table, "gameDataSet")?,
+        prologue,
+        fog,
+        weather: table
+            .get::<_, Option<ObjectId>>("weather")?
+            .map(ObjectId::to_u32)
+            .unwrap_or(0),
+        sound_environment: get_or_default(table, "soundEnvironment")?,
+        light_tileset: tileset_from_str(&get_or_default::<String>(table, "lightTileset")?),
+        water_color,
+        script_language,
+        supported_modes: get_or_default(table, "supportedModes")?,
+        game_data_version: get_or_default(table, "gameDataVersion")?,
+        players: get_list(table, "players", player_from_table)?,
+        forces: get_list(table, "forces", force_from_table)?,
+        upgrades: get_list(table, "upgrades", upgrade_from_table)?,
+        techs: get_list(table, "techs", tech_from_table)?,
+        random_unit_tables: get_list(table, "randomUnitTables", unit_table_from_table)?,
+        random_item_tables: get_list(table, "randomItemTables", item_table_from_table)?,
+    })
+}
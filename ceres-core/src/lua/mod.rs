@@ -10,6 +10,17 @@ pub mod fs;
 pub mod mpq;
 pub mod launcher;
 pub mod object;
+pub mod map;
+pub mod mapinfo;
+pub mod units;
+pub mod doodads;
+pub mod terrain;
+pub mod regions;
+pub mod strings;
+pub mod localization;
+pub mod image;
+pub mod model;
+pub mod require;
 
 #[derive(Serialize, Deserialize)]
 struct ProjectLayout {
@@ -57,17 +68,32 @@ pub fn setup_ceres_environ(ctx: LuaContext, run_mode: CeresRunMode, script_args:
         )
         .unwrap();
 
+    ceres_table
+        .set("readCatalog", localization::get_read_catalog_luafn(ctx))
+        .unwrap();
+    ceres_table
+        .set("writeCatalog", localization::get_write_catalog_luafn(ctx))
+        .unwrap();
+
     ceres_table
         .set("runWarcraft", launcher::get_runmap_luafn(ctx))
         .unwrap();
 
+    ceres_table
+        .set("openMap", map::get_open_map_luafn(ctx))
+        .unwrap();
+
     let fs_table = fs::get_fs_module(ctx);
     let mpq_table = mpq::get_mpq_module(ctx);
     let object_table = object::get_object_module(ctx);
+    let image_table = image::get_image_module(ctx);
+    let model_table = model::get_model_module(ctx);
 
     globals.set("fs", fs_table).unwrap();
     globals.set("mpq", mpq_table).unwrap();
     globals.set("objdata", object_table).unwrap();
+    globals.set("image", image_table).unwrap();
+    globals.set("model", model_table).unwrap();
     globals.set("ceres", ceres_table).unwrap();
 
     ctx.load(CERES_BUILDSCRIPT_LIB)
@@ -76,3 +102,139 @@ pub fn setup_ceres_environ(ctx: LuaContext, run_mode: CeresRunMode, script_args:
         .exec()
         .unwrap();
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    use ceres_formats::parser::w3i;
+
+    use super::*;
+
+    /// A TFT map info, which is always in JASS mode.
+    fn jass_map_info() -> Vec<u8> {
+        Lua::new().context(|ctx| {
+            let table: LuaTable = ctx.load("{ version = 25 }").eval().unwrap();
+            let info = mapinfo::mapinfo_from_table(&table).unwrap();
+
+            let mut buf = Vec::new();
+            w3i::write::write_map_info(&mut buf, &info).unwrap();
+            buf
+        })
+    }
+
+    fn write_jass_map(dir: &Path) {
+        fs::create_dir_all(dir).unwrap();
+
+        fs::write(dir.join("war3map.w3i"), jass_map_info()).unwrap();
+        let jass = "function main takes nothing returns nothing\nendfunction";
+        fs::write(dir.join("war3map.j"), jass).unwrap();
+        fs::write(dir.join("war3map.wtg"), "WTG!").unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ceres-lua-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn eval(src: &str) -> String {
+        let lua = Lua::new();
+
+        lua.context(|ctx| {
+            setup_ceres_environ(ctx, CeresRunMode::Build, Vec::new());
+
+            ctx.load(src).eval::<String>().unwrap()
+        })
+    }
+
+    #[test]
+    fn convert_to_lua_requires_main_and_config() {
+        let dir = temp_dir("convert");
+        write_jass_map(&dir);
+        let src = format!(
+            r#"
+            local map = assert(ceres.openMap("{}"))
+            local out = {{}}
+
+            local converted, errorMsg = ceres.convertToLua(map, nil)
+            out[#out + 1] = tostring(converted) .. ": " .. errorMsg
+
+            converted, errorMsg = ceres.convertToLua(map, "function main() end")
+            out[#out + 1] = tostring(converted) .. ": " .. errorMsg
+            out[#out + 1] = map.info.scriptLanguage .. " " .. map.info.version
+            out[#out + 1] = tostring(map:hasFile("war3map.j"))
+
+            local script = "function main() end\nconfig = function() end"
+            converted = ceres.convertToLua(map, script, {{ removeTriggers = true }})
+            out[#out + 1] = tostring(converted)
+            out[#out + 1] = map.info.scriptLanguage .. " " .. map.info.version
+            out[#out + 1] = tostring(map:hasFile("war3map.j"))
+            out[#out + 1] = tostring(map:hasFile("war3map.wtg"))
+            out[#out + 1] = tostring(ceres.convertToLua(map, script))
+
+            assert(map:commitInfo())
+            local info = map:readFile("war3map.w3i")
+            out[#out + 1] = tostring(string.unpack("<I4", info))
+
+            return table.concat(out, "\n")
+            "#,
+            dir.display()
+        );
+
+        assert_eq!(
+            eval(&src),
+            "nil: neither a module nor the map script defines main(), \
+             which the game calls in place of the JASS one\n\
+             nil: neither a module nor the map script defines config(), \
+             which the game calls in place of the JASS one\n\
+             jass 25\n\
+             true\n\
+             true\n\
+             lua 28\n\
+             false\n\
+             false\n\
+             false\n\
+             28"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn build_converts_jass_map_with_main_and_config_modules() {
+        let dir = temp_dir("convert-build");
+        write_jass_map(&dir.join("maps").join("jass.w3x"));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src").join("main.lua"), "print('main')").unwrap();
+        fs::write(dir.join("src").join("config.lua"), "SetPlayers(1)").unwrap();
+
+        let src = format!(
+            r#"
+            local dir = "{}/"
+            ceres.layout = {{
+                mapsDirectory = dir .. "maps/",
+                srcDirectories = {{ dir .. "src/" }},
+                targetDirectory = dir .. "target/"
+            }}
+
+            local artifact = ceres.buildMap {{
+                input = "jass.w3x",
+                output = "dir",
+                convertToLua = true
+            }}
+
+            local map = assert(ceres.openMap(artifact.path))
+            return map.info.scriptLanguage .. " " .. tostring(map:hasFile("war3map.j"))
+            "#,
+            dir.display()
+        );
+
+        assert_eq!(eval(&src), "lua false");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
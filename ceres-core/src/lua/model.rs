@@ -0,0 +1,136 @@
+//! The `model` module, which reads the textures, sequences, attachment points
+//! and extents of MDX and MDL models into plain Lua tables.
+
+use std::fs;
+
+use rlua::prelude::*;
+
+use ceres_formats::model::*;
+
+use crate::assets::read_model;
+use crate::lua::util::{array_to_table, list_to_table, wrap_result};
+
+fn extent_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    extent: &Extent,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("min", array_to_table(ctx, &extent.min)?)?;
+    table.set("max", array_to_table(ctx, &extent.max)?)?;
+    table.set("radius", extent.radius)?;
+
+    Ok(table)
+}
+
+fn texture_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    texture: &Texture,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("path", texture.path.as_str())?;
+    table.set("replaceableId", texture.replaceable_id)?;
+    table.set("flags", texture.flags)?;
+
+    Ok(table)
+}
+
+fn sequence_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    sequence: &Sequence,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("name", sequence.name.as_str())?;
+    table.set("start", sequence.interval[0])?;
+    table.set("end", sequence.interval[1])?;
+    table.set("moveSpeed", sequence.move_speed)?;
+    table.set("nonLooping", sequence.non_looping)?;
+    table.set("rarity", sequence.rarity)?;
+    table.set("extent", extent_to_table(ctx, &sequence.extent)?)?;
+
+    Ok(table)
+}
+
+fn attachment_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    attachment: &Attachment,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("name", attachment.name.as_str())?;
+    table.set("objectId", attachment.object_id)?;
+    table.set("attachmentId", attachment.attachment_id)?;
+    table.set("path", attachment.path.as_str())?;
+
+    Ok(table)
+}
+
+fn model_to_table<'lua>(ctx: LuaContext<'lua>, model: &Model) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("version", model.version)?;
+    table.set("name", model.name.as_str())?;
+    table.set("extent", extent_to_table(ctx, &model.extent)?)?;
+    table.set(
+        "textures",
+        list_to_table(ctx, &model.textures, |texture| {
+            texture_to_table(ctx, texture)
+        })?,
+    )?;
+    table.set(
+        "sequences",
+        list_to_table(ctx, &model.sequences, |sequence| {
+            sequence_to_table(ctx, sequence)
+        })?,
+    )?;
+    table.set(
+        "attachments",
+        list_to_table(ctx, &model.attachments, |attachment| {
+            attachment_to_table(ctx, attachment)
+        })?,
+    )?;
+
+    Ok(table)
+}
+
+fn decode_model<'lua>(
+    ctx: LuaContext<'lua>,
+    contents: &[u8],
+) -> Result<LuaTable<'lua>, anyhow::Error> {
+    let model = read_model(contents)?;
+
+    Ok(model_to_table(ctx, &model)?)
+}
+
+fn get_model_load_luafn(ctx: LuaContext) -> LuaFunction {
+    ctx.create_function(|ctx, path: String| {
+        let result = fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| decode_model(ctx, &contents));
+
+        Ok(wrap_result(ctx, result))
+    })
+    .unwrap()
+}
+
+fn get_model_from_string_luafn(ctx: LuaContext) -> LuaFunction {
+    ctx.create_function(|ctx, contents: LuaString| {
+        let result = decode_model(ctx, contents.as_bytes());
+
+        Ok(wrap_result(ctx, result))
+    })
+    .unwrap()
+}
+
+pub fn get_model_module(ctx: LuaContext) -> LuaTable {
+    let table = ctx.create_table().unwrap();
+
+    table.set("load", get_model_load_luafn(ctx)).unwrap();
+    table
+        .set("fromString", get_model_from_string_luafn(ctx))
+        .unwrap();
+
+    table
+}
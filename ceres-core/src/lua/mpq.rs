@@ -1,27 +1,159 @@
+use std::cell::RefCell;
 use std::fs;
-use std::io::{BufReader, BufWriter};
+use std::io::BufReader;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
 
+use ceres_formats::mapheader::MapHeader;
 use mpq::Archive;
+use mpq::AttributesOptions;
+use mpq::Compression;
 use mpq::Creator;
+use mpq::CreatorOptions;
+use mpq::FileEntry;
 use mpq::FileOptions;
+use mpq::Progress;
 use rlua::prelude::*;
 use walkdir::WalkDir;
 
 use crate::error::ContextError;
+use crate::error::IoError;
 use crate::error::StringError;
+use crate::listfile::{needs_recovery, read_listfile, recover_names, RecoveryReport};
 use crate::lua::util::wrap_result;
-
-type FileArchive = Archive<BufReader<fs::File>>;
+use crate::vfs::{
+    normalize_path, read_map_header, write_archive, FileArchive, FileRules, PathFilter,
+};
 
 struct Viewer {
     archive: FileArchive,
+    header:  Option<LuaMapHeader>,
 }
 
 struct Builder {
     creator: Creator,
 }
 
+/// The `HM3W` header of a map file, shared between the Lua
+/// userdata and whatever owns the header on the Rust side.
+///
+/// Fields are accessed directly: `name`, `flags`, `maxPlayers`, `unknown`
+/// and `signature`, where the latter can be set to `nil` to drop the footer.
+#[derive(Clone, Default)]
+pub(crate) struct LuaMapHeader {
+    inner: Rc<RefCell<MapHeader>>,
+}
+
+impl LuaMapHeader {
+    pub(crate) fn new(header: MapHeader) -> LuaMapHeader {
+        LuaMapHeader {
+            inner: Rc::new(RefCell::new(header)),
+        }
+    }
+
+    pub(crate) fn get(&self) -> MapHeader {
+        self.inner.borrow().clone()
+    }
+}
+
+impl LuaUserData for LuaMapHeader {
+    fn add_methods<'lua, T>(methods: &mut T)
+    where
+        T: LuaUserDataMethods<'lua, Self>,
+    {
+        methods.add_meta_method(LuaMetaMethod::Index, |ctx, header, key: LuaString| {
+            let header = header.inner.borrow();
+
+            Ok(match key.as_bytes() {
+                b"name" => LuaValue::String(ctx.create_string(&header.name)?),
+                b"flags" => LuaValue::Integer(header.flags as LuaInteger),
+                b"maxPlayers" => LuaValue::Integer(header.max_players as LuaInteger),
+                b"unknown" => LuaValue::Integer(header.unknown as LuaInteger),
+                b"signature" => match &header.signature {
+                    Some(signature) => LuaValue::String(ctx.create_string(signature)?),
+                    None => LuaValue::Nil,
+                },
+                _ => LuaValue::Nil,
+            })
+        });
+
+        methods.add_meta_method(
+            LuaMetaMethod::NewIndex,
+            |ctx, header, (key, value): (LuaString, LuaValue)| {
+                let mut header = header.inner.borrow_mut();
+
+                match key.as_bytes() {
+                    b"name" => header.name = String::from_lua(value, ctx)?,
+                    b"flags" => header.flags = u32::from_lua(value, ctx)?,
+                    b"maxPlayers" => header.max_players = u32::from_lua(value, ctx)?,
+                    b"unknown" => header.unknown = u32::from_lua(value, ctx)?,
+                    b"signature" => {
+                        header.signature = Option::<LuaString>::from_lua(value, ctx)?
+                            .map(|signature| signature.as_bytes().into())
+                    }
+                    _ => {
+                        return Err(StringError::new(format!(
+                            "map header has no field {}",
+                            key.to_str()?
+                        ))
+                        .into())
+                    }
+                }
+
+                Ok(())
+            },
+        );
+    }
+}
+
+/// Selects which files are taken by `addFromDir` and `addFromMpq`.
+///
+/// In Lua, this is either a function receiving the file path and returning
+/// `true` if the file should be added, or a table with optional `include`
+/// and `exclude` lists of glob patterns.
+enum FileFilter<'lua> {
+    All,
+    Globs(PathFilter),
+    Callback(LuaFunction<'lua>),
+}
+
+impl<'lua> FileFilter<'lua> {
+    fn from_lua_value(value: Option<LuaValue<'lua>>) -> Result<FileFilter<'lua>, LuaError> {
+        Ok(match value {
+            None | Some(LuaValue::Nil) => FileFilter::All,
+            Some(LuaValue::Function(callback)) => FileFilter::Callback(callback),
+            Some(LuaValue::Table(table)) => FileFilter::Globs(PathFilter::new(
+                glob_list(table.get("include")?)?,
+                glob_list(table.get("exclude")?)?,
+            )),
+            Some(_) => {
+                return Err(StringError::new("filter must be a function or a table").into());
+            }
+        })
+    }
+
+    fn accepts(&self, path: &str) -> Result<bool, LuaError> {
+        let path = normalize_path(path);
+
+        match self {
+            FileFilter::All => Ok(true),
+            FileFilter::Globs(filter) => Ok(filter.accepts(&path)),
+            FileFilter::Callback(callback) => callback.call(path),
+        }
+    }
+}
+
+/// Accepts either a single glob pattern or a list of them.
+fn glob_list(value: LuaValue) -> Result<Vec<String>, LuaError> {
+    match value {
+        LuaValue::Nil => Ok(Vec::new()),
+        LuaValue::String(pattern) => Ok(vec![pattern.to_str()?.into()]),
+        LuaValue::Table(patterns) => patterns.sequence_values::<String>().collect(),
+        _ => Err(StringError::new("glob patterns must be a string or a list of strings").into()),
+    }
+}
+
 impl LuaUserData for Viewer {
     fn add_methods<'lua, T>(methods: &mut T)
     where
@@ -52,6 +184,35 @@ impl LuaUserData for Viewer {
 
             Ok(wrap_result(ctx, result))
         });
+
+        methods.add_method("header", |_, obj, _: ()| Ok(obj.header.clone()));
+
+        methods.add_method("stat", |ctx, obj, path: String| {
+            obj.archive
+                .stat(&path)
+                .map(|entry| entry_to_table(ctx, &entry))
+                .transpose()
+        });
+
+        methods.add_method_mut("recoverNames", |ctx, obj, listfiles: Option<Vec<String>>| {
+            let result = listfiles
+                .unwrap_or_default()
+                .iter()
+                .map(read_listfile)
+                .collect::<Result<Vec<_>, _>>()
+                .map(|names| recover_names(&mut obj.archive, &names.concat()))
+                .and_then(|report| Ok(report_to_table(ctx, &report)?));
+
+            Ok(wrap_result(ctx, result))
+        });
+
+        methods.add_method_mut("entries", |ctx, obj, _: ()| {
+            obj.archive
+                .entries()
+                .iter()
+                .map(|entry| entry_to_table(ctx, entry))
+                .collect::<Result<Vec<_>, _>>()
+        });
     }
 }
 
@@ -63,8 +224,8 @@ impl LuaUserData for Builder {
         methods.add_method_mut(
             "add",
             |ctx, obj, (path, contents, options): (LuaString, LuaString, Option<LuaTable>)| {
-                let options = fileoptions_from_table(options);
-                let result = writeflow_addbuf(obj, path, contents, options);
+                let rules = filerules_from_table(options)?;
+                let result = writeflow_addbuf(obj, path, contents, &rules);
 
                 Ok(wrap_result(ctx, result))
             },
@@ -73,8 +234,8 @@ impl LuaUserData for Builder {
         methods.add_method_mut(
             "addFromFile",
             |ctx, obj, (archive_path, fs_path, options): (LuaString, LuaString, Option<LuaTable>)| {
-                let options = fileoptions_from_table(options);
-                let result = writeflow_addfile(obj, archive_path, fs_path, options);
+                let rules = filerules_from_table(options)?;
+                let result = writeflow_addfile(obj, archive_path, fs_path, &rules);
 
                 Ok(wrap_result(ctx, result))
             },
@@ -82,9 +243,12 @@ impl LuaUserData for Builder {
 
         methods.add_method_mut(
             "addFromDir",
-            |ctx, obj, (dir_path, options): (LuaString, Option<LuaTable>)| {
-                let options = fileoptions_from_table(options);
-                let result = writeflow_adddir(obj, dir_path, options);
+            |ctx,
+             obj,
+             (dir_path, options, filter): (LuaString, Option<LuaTable>, Option<LuaValue>)| {
+                let rules = filerules_from_table(options)?;
+                let filter = FileFilter::from_lua_value(filter)?;
+                let result = writeflow_adddir(obj, dir_path, &rules, &filter);
 
                 Ok(wrap_result(ctx, result))
             },
@@ -92,45 +256,212 @@ impl LuaUserData for Builder {
 
         methods.add_method_mut(
             "addFromMpq",
-            |ctx, obj, (viewer, options): (LuaAnyUserData, Option<LuaTable>)| {
+            |ctx,
+             obj,
+             (viewer, options, filter): (LuaAnyUserData, Option<LuaTable>, Option<LuaValue>)| {
                 let mut viewer = viewer.borrow_mut::<Viewer>()?;
-                let options = fileoptions_from_table(options);
-                let result = writeflow_addmpq(obj, &mut viewer.archive, options);
+                let rules = filerules_from_table(options)?;
+                let filter = FileFilter::from_lua_value(filter)?;
+                let result = writeflow_addmpq(obj, &mut viewer.archive, &rules, &filter);
 
                 Ok(wrap_result(ctx, result))
             },
         );
 
-        methods.add_method_mut("write", |ctx, obj, path: LuaString| {
-            let result = writeflow_write(obj, path);
+        methods.add_method_mut(
+            "write",
+            |ctx, obj, (path, options): (LuaString, Option<LuaTable>)| {
+                let header = match &options {
+                    Some(options) => header_from_table(options)?,
+                    None => None,
+                };
+                *obj.creator.options_mut() = creatoroptions_from_table(options.as_ref())?;
+                let progress = progress_reporter(ctx, options.as_ref())?;
+                let result = writeflow_write(obj, path, header.as_ref(), progress);
 
-            Ok(wrap_result(ctx, result))
-        });
+                Ok(wrap_result(ctx, result))
+            },
+        );
     }
 }
 
-fn fileoptions_from_table(table: Option<LuaTable>) -> FileOptions {
-    if let Some(table) = table {
-        let mut options = FileOptions::default();
+/// Applies the `encrypt`, `compress` and `compression` fields
+/// of an options table on top of the given options.
+fn apply_fileoptions(table: &LuaTable, mut options: FileOptions) -> Result<FileOptions, LuaError> {
+    if let Ok(Some(encrypt)) = table.get::<_, Option<bool>>("encrypt") {
+        options.encrypt = encrypt;
+    }
 
-        if let Ok(Some(encrypt)) = table.get::<_, Option<bool>>("encrypt") {
-            options.encrypt = encrypt;
+    if let Ok(Some(compress)) = table.get::<_, Option<bool>>("compress") {
+        if !compress {
+            options.compression = Compression::None;
+        } else if options.compression == Compression::None {
+            options.compression = Compression::Zlib;
         }
+    }
 
-        if let Ok(Some(compress)) = table.get::<_, Option<bool>>("compress") {
-            options.compress = compress;
+    if let Some(compression) = table.get::<_, Option<String>>("compression")? {
+        options.compression = Compression::from_name(&compression).ok_or_else(|| {
+            StringError::new(format!(
+                "unknown compression method '{}', expected 'zlib', 'bzip2' or 'none'",
+                compression
+            ))
+        })?;
+    }
+
+    Ok(options)
+}
+
+/// Reads per-file options from a table. Besides the default options,
+/// the table can contain a list of `rules`, each with a glob `pattern`
+/// and options that apply to matching files, e.g.
+/// `{ compression = "bzip2", rules = { { pattern = "**/*.mp3", compression = "none" } } }`
+pub(crate) fn filerules_from_table(table: Option<LuaTable>) -> Result<FileRules, LuaError> {
+    let table = match table {
+        Some(table) => table,
+        None => return Ok(FileRules::new(FileOptions::default())),
+    };
+
+    let default = apply_fileoptions(&table, FileOptions::default())?;
+    let mut rules = FileRules::new(default);
+
+    if let Some(rule_list) = table.get::<_, Option<LuaTable>>("rules")? {
+        for rule in rule_list.sequence_values::<LuaTable>() {
+            let rule = rule?;
+            let pattern: String = rule.get("pattern")?;
+
+            rules.add_rule(&pattern, apply_fileoptions(&rule, default)?);
         }
+    }
+
+    Ok(rules)
+}
+
+/// Reads archive-wide options: `sectorSize` in bytes, `hashTableSize`,
+/// and `attributes`, which is either a boolean or a table with
+/// `crc32`, `md5` and `timestamps` flags.
+pub(crate) fn creatoroptions_from_table(
+    table: Option<&LuaTable>,
+) -> Result<CreatorOptions, LuaError> {
+    let mut options = CreatorOptions::default();
+
+    // https://reproducible-builds.org/specs/source-date-epoch/
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        let epoch: u64 = epoch
+            .trim()
+            .parse()
+            .map_err(|_| StringError::new("SOURCE_DATE_EPOCH must be a unix timestamp"))?;
+
+        options.timestamp = Some(UNIX_EPOCH + Duration::from_secs(epoch));
+    }
+
+    let table = match table {
+        Some(table) => table,
+        None => return Ok(options),
+    };
+
+    if let Some(sector_size) = table.get::<_, Option<u32>>("sectorSize")? {
+        if sector_size < 512 || !sector_size.is_power_of_two() || sector_size > (512 << 15) {
+            return Err(StringError::new(
+                "sector size must be a power of two between 512 and 16777216",
+            )
+            .into());
+        }
+
+        options.sector_size_shift = (sector_size / 512).trailing_zeros() as u16;
+    }
+
+    options.hash_table_size = table.get::<_, Option<u32>>("hashTableSize")?;
+    options.protect = table.get::<_, Option<bool>>("protect")?.unwrap_or(false);
+
+    if let Some(timestamp) = table.get::<_, Option<u64>>("timestamp")? {
+        options.timestamp = Some(UNIX_EPOCH + Duration::from_secs(timestamp));
+    }
 
-        options
-    } else {
-        let mut options = FileOptions::default();
-        options.encrypt = false;
-        options.compress = true;
+    options.attributes = match table.get::<_, LuaValue>("attributes")? {
+        LuaValue::Nil | LuaValue::Boolean(false) => AttributesOptions::default(),
+        LuaValue::Boolean(true) => AttributesOptions::all(),
+        LuaValue::Table(attributes) => AttributesOptions {
+            crc32:      attributes.get::<_, Option<bool>>("crc32")?.unwrap_or(false),
+            md5:        attributes.get::<_, Option<bool>>("md5")?.unwrap_or(false),
+            timestamps: attributes.get::<_, Option<bool>>("timestamps")?.unwrap_or(false),
+        },
+        _ => return Err(StringError::new("attributes must be a boolean or a table").into()),
+    };
+
+    Ok(options)
+}
+
+/// Turns the `progress` field of an options table into a callback for the
+/// MPQ writer. The Lua function receives a table with `file`, `filesWritten`,
+/// `filesTotal`, `bytesWritten` and `bytesTotal`.
+pub(crate) fn progress_reporter<'lua>(
+    ctx: LuaContext<'lua>,
+    table: Option<&LuaTable<'lua>>,
+) -> Result<impl FnMut(&Progress) + 'lua, LuaError> {
+    let callback: Option<LuaFunction> = match table {
+        Some(table) => table.get("progress")?,
+        None => None,
+    };
+
+    Ok(move |progress: &Progress| {
+        let callback = match &callback {
+            Some(callback) => callback,
+            None => return,
+        };
+
+        let result = ctx.create_table().and_then(|table| {
+            table.set("file", progress.name.replace("\\", "/"))?;
+            table.set("filesWritten", progress.files_written)?;
+            table.set("filesTotal", progress.files_total)?;
+            table.set("bytesWritten", progress.bytes_written)?;
+            table.set("bytesTotal", progress.bytes_total)?;
+
+            callback.call::<_, ()>(table)
+        });
+
+        if let Err(error) = result {
+            eprintln!("mpq: progress callback failed: {}", error);
+        }
+    })
+}
 
-        options
+/// Reads the `header` field of an options table, which must be a map header
+/// as returned by `viewer:header()` or `map.header`.
+pub(crate) fn header_from_table(table: &LuaTable) -> Result<Option<MapHeader>, LuaError> {
+    match table.get::<_, Option<LuaAnyUserData>>("header")? {
+        Some(header) => Ok(Some(header.borrow::<LuaMapHeader>()?.get())),
+        None => Ok(None),
     }
 }
 
+/// Describes a block of the archive, as returned by `stat` and `entries`.
+fn entry_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    entry: &FileEntry,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("name", entry.name.as_ref().map(|name| name.replace("\\", "/")))?;
+    table.set("size", entry.file_size)?;
+    table.set("compressedSize", entry.compressed_size)?;
+    table.set("position", entry.file_pos)?;
+    table.set("blockIndex", entry.block_index)?;
+    table.set("hashIndex", entry.hash_index)?;
+    table.set("hash", entry.hash_string())?;
+    table.set("locale", entry.locale)?;
+
+    let flags = ctx.create_table()?;
+    flags.set("encrypted", entry.is_encrypted())?;
+    flags.set("keyAdjusted", entry.is_key_adjusted())?;
+    flags.set("compressed", entry.is_compressed())?;
+    flags.set("singleUnit", entry.is_single_unit())?;
+    flags.set("deleted", entry.is_deleted())?;
+    table.set("flags", flags)?;
+
+    Ok(table)
+}
+
 fn readflow_readfile(archive: &mut FileArchive, path: LuaString) -> Result<Vec<u8>, anyhow::Error> {
     let path = path.to_str()?;
     Ok(archive.read_file(path)?)
@@ -172,25 +503,72 @@ fn readflow_extract(archive: &mut FileArchive, path: LuaString) -> Result<bool,
     Ok(true)
 }
 
-fn readflow_open(path: &str) -> Result<Viewer, anyhow::Error> {
+fn readflow_open(path: &str, options: Option<LuaTable>) -> Result<Viewer, anyhow::Error> {
     let file = fs::OpenOptions::new().read(true).open(path)?;
 
     let file = BufReader::new(file);
-    let archive = Archive::open(file)?;
+    let mut archive = Archive::open(file)?;
+    let header = read_map_header(path)?.map(LuaMapHeader::new);
+
+    let listfiles = listfiles_from_table(options.as_ref())?;
+    if !listfiles.is_empty() || needs_recovery(&mut archive) {
+        recover_names(&mut archive, &listfiles);
+    }
+
+    Ok(Viewer { archive, header })
+}
 
-    Ok(Viewer { archive })
+/// Reads the names from all listfiles given in the `listfiles` field.
+pub(crate) fn listfiles_from_table(table: Option<&LuaTable>) -> Result<Vec<String>, anyhow::Error> {
+    let paths = match table {
+        Some(table) => table.get::<_, Option<Vec<String>>>("listfiles")?,
+        None => None,
+    };
+
+    let mut names = Vec::new();
+    for path in paths.unwrap_or_default() {
+        names.extend(read_listfile(path)?);
+    }
+
+    Ok(names)
+}
+
+/// Describes the outcome of name recovery, as returned by `recoverNames`.
+pub(crate) fn report_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    report: &RecoveryReport,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    let recovered: Vec<String> = report
+        .recovered
+        .iter()
+        .map(|name| name.replace("\\", "/"))
+        .collect();
+    table.set("recovered", recovered)?;
+
+    let unnamed = report
+        .unnamed
+        .iter()
+        .map(|entry| entry_to_table(ctx, entry))
+        .collect::<Result<Vec<_>, _>>()?;
+    table.set("unnamed", unnamed)?;
+
+    Ok(table)
 }
 
 fn writeflow_addbuf(
     builder: &mut Builder,
     path: LuaString,
     contents: LuaString,
-    options: FileOptions,
+    rules: &FileRules,
 ) -> Result<bool, anyhow::Error> {
     let path = path.to_str()?;
     let contents = contents.as_bytes();
 
-    builder.creator.add_file(path, contents, options);
+    builder
+        .creator
+        .add_file(path, contents, rules.options_for(path));
 
     Ok(true)
 }
@@ -199,12 +577,17 @@ fn writeflow_addfile(
     builder: &mut Builder,
     archive_path: LuaString,
     fs_path: LuaString,
-    options: FileOptions,
+    rules: &FileRules,
 ) -> Result<bool, anyhow::Error> {
     let archive_path = archive_path.to_str()?;
     let fs_path = fs_path.to_str()?;
-    let contents = fs::read(fs_path)?;
-    builder.creator.add_file(archive_path, contents, options);
+
+    // the file is only read when the archive is written,
+    // but a missing file should still be reported right away
+    fs::metadata(fs_path).map_err(|cause| IoError::new(fs_path, cause))?;
+    builder
+        .creator
+        .add_file_from_disk(archive_path, fs_path, rules.options_for(archive_path));
 
     Ok(true)
 }
@@ -212,32 +595,31 @@ fn writeflow_addfile(
 fn writeflow_adddir(
     builder: &mut Builder,
     dir_path: LuaString,
-    options: FileOptions,
+    rules: &FileRules,
+    filter: &FileFilter,
 ) -> Result<bool, anyhow::Error> {
     let dir_path: PathBuf = dir_path.to_str()?.into();
 
     let entries = WalkDir::new(&dir_path)
         .follow_links(true)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
         .into_iter()
         .filter_map(|s| s.ok())
         .filter(|s| s.file_type().is_file());
 
     for entry in entries {
-        let contents = fs::read(entry.path());
+        let relative_path = entry.path().strip_prefix(&dir_path).unwrap();
+        let relative_path = relative_path.to_str().unwrap();
 
-        if let Err(error) = contents {
-            eprintln!(
-                "mpq.addFromDir(): could not add file {}: {}",
-                entry.path().display(),
-                error
-            );
+        if !filter.accepts(relative_path)? {
             continue;
         }
 
-        let relative_path = entry.path().strip_prefix(&dir_path).unwrap();
-        builder
-            .creator
-            .add_file(relative_path.to_str().unwrap(), contents.unwrap(), options);
+        builder.creator.add_file_from_disk(
+            relative_path,
+            entry.path(),
+            rules.options_for(relative_path),
+        );
     }
 
     Ok(true)
@@ -246,13 +628,18 @@ fn writeflow_adddir(
 fn writeflow_addmpq(
     builder: &mut Builder,
     archive: &mut FileArchive,
-    options: FileOptions,
+    rules: &FileRules,
+    filter: &FileFilter,
 ) -> Result<bool, anyhow::Error> {
     let files = archive
         .files()
         .ok_or_else(|| StringError::new("no listfile found"))?;
 
     for file in files {
+        if !filter.accepts(&file)? {
+            continue;
+        }
+
         let contents = archive.read_file(&file);
 
         if let Err(error) = contents {
@@ -260,27 +647,23 @@ fn writeflow_addmpq(
             continue;
         }
 
-        builder.creator.add_file(&file, contents.unwrap(), options);
+        builder
+            .creator
+            .add_file(&file, contents.unwrap(), rules.options_for(&file));
     }
 
     Ok(true)
 }
 
-fn writeflow_write(builder: &mut Builder, path: LuaString) -> Result<bool, anyhow::Error> {
+fn writeflow_write<F: FnMut(&Progress)>(
+    builder: &mut Builder,
+    path: LuaString,
+    header: Option<&MapHeader>,
+    progress: F,
+) -> Result<bool, anyhow::Error> {
     let path: PathBuf = path.to_str()?.into();
 
-    fs::create_dir_all(path.parent().unwrap())
-        .map_err(|cause| ContextError::new("could not create folder for map", cause))?;
-
-    let writer = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(path)?;
-
-    let mut writer = BufWriter::new(writer);
-    let creator = &mut builder.creator;
-    creator.write(&mut writer)?;
+    write_archive(&mut builder.creator, &path, header, progress)?;
 
     Ok(true)
 }
@@ -292,8 +675,8 @@ fn writeflow_new() -> Result<Builder, anyhow::Error> {
 }
 
 fn get_mpqopen_luafn(ctx: LuaContext) -> LuaFunction {
-    ctx.create_function(|ctx: LuaContext, path: String| {
-        let result = readflow_open(&path);
+    ctx.create_function(|ctx: LuaContext, (path, options): (String, Option<LuaTable>)| {
+        let result = readflow_open(&path, options);
 
         Ok(wrap_result(ctx, result))
     })
@@ -11,19 +11,36 @@ use ceres_formats::metadata::FieldDesc;
 use ceres_formats::object::{Object, Value};
 use ceres_formats::objectstore::ObjectStore;
 use ceres_formats::parser::w3obj;
+use ceres_formats::triggerstrings::parse_reference;
 
 use crate::error::StringError;
+use crate::lua::localization::Catalog;
+use crate::lua::strings::SharedStringTable;
 use crate::lua::util::*;
 
-fn get_field_for<C>(object: &Object, field_getter: C) -> Option<&Value>
+/// Looks up a field on the object or its prototype. String values which refer
+/// to the map's string table are resolved.
+fn get_field_for<C>(
+    object: &Object,
+    strings: Option<&SharedStringTable>,
+    field_getter: C,
+) -> Option<Value>
 where
     C: Fn(&Object) -> Option<&Value>,
 {
-    field_getter(object).or_else(|| {
+    let value = field_getter(object).or_else(|| {
         w3data::data()
             .object_prototype(&object)
             .and_then(|proto| field_getter(proto))
-    })
+    })?;
+
+    if let (Value::String(value), Some(strings)) = (value, strings) {
+        if let Some(resolved) = strings.borrow().strings().resolve(value) {
+            return Some(Value::String(resolved.into()));
+        }
+    }
+
+    Some(value.clone())
 }
 
 struct StaticMethodKeys {
@@ -190,7 +207,8 @@ impl<'lua> StaticMethods<'lua> {
 }
 
 struct LuaObjectWrapper {
-    inner: Rc<RefCell<Object>>,
+    inner:   Rc<RefCell<Object>>,
+    strings: Option<SharedStringTable>,
 }
 
 impl LuaObjectWrapper {
@@ -198,16 +216,17 @@ impl LuaObjectWrapper {
         _ctx: LuaContext<'lua>,
         object: LuaAnyUserData<'lua>,
     ) -> Result<impl ToLuaMulti<'lua>, LuaError> {
-        let object = object.borrow::<LuaObjectWrapper>()?;
+        let object_wrapper = object.borrow::<LuaObjectWrapper>()?;
 
-        let object = object.inner.borrow();
+        let object = object_wrapper.inner.borrow();
         let mut new_object = Object::new(object.id(), object.kind());
 
         new_object.set_parent_id(object.parent_id());
         new_object.add_from(&object);
 
         Ok(LuaObjectWrapper {
-            inner: Rc::new(RefCell::new(new_object)),
+            inner:   Rc::new(RefCell::new(new_object)),
+            strings: object_wrapper.strings.clone(),
         })
     }
 
@@ -263,17 +282,18 @@ impl LuaObjectWrapper {
         ctx: LuaContext<'lua>,
         (object, key): (&LuaObjectWrapper, LuaValue<'lua>),
     ) -> Result<impl ToLua<'lua>, LuaError> {
+        let strings = object.strings.as_ref();
         let object = object.inner.borrow();
 
         if let Some((field_desc, level)) = Self::translate_field_name(ctx, key, &object)? {
             let field = if let Some(level) = level {
-                get_field_for(&object, |o| o.leveled_field(field_desc.id, level))
+                get_field_for(&object, strings, |o| o.leveled_field(field_desc.id, level))
             } else {
-                get_field_for(&object, |o| o.simple_field(field_desc.id))
+                get_field_for(&object, strings, |o| o.simple_field(field_desc.id))
             };
 
             if let Some(field) = field {
-                return Ok(value_to_lvalue(ctx, field));
+                return Ok(value_to_lvalue(ctx, &field));
             }
         }
 
@@ -344,12 +364,132 @@ impl LuaObjectWrapper {
     }
 }
 
-struct LuaObjectStoreWrapper {
-    inner: ObjectStore,
-    kind:  ObjectKind,
+pub(crate) struct LuaObjectStoreWrapper {
+    inner:   ObjectStore,
+    kind:    ObjectKind,
+    /// The string table of the map the store belongs to, if any.
+    strings: Option<SharedStringTable>,
 }
 
 impl LuaObjectStoreWrapper {
+    pub(crate) fn new(kind: ObjectKind) -> LuaObjectStoreWrapper {
+        LuaObjectStoreWrapper {
+            inner: ObjectStore::default(),
+            kind,
+            strings: None,
+        }
+    }
+
+    pub(crate) fn set_strings(&mut self, strings: SharedStringTable) {
+        self.strings = Some(strings);
+    }
+
+    /// Moves text values of at least `min_length` bytes out of the modified
+    /// objects and into the string table, replacing them with references.
+    /// Other strings, like paths, stay in place, since the game only resolves
+    /// references in text fields. Returns the number of moved strings.
+    pub(crate) fn move_long_strings(&self, min_length: usize) -> usize {
+        let strings = match &self.strings {
+            Some(strings) => strings,
+            None => return 0,
+        };
+
+        let metadata = w3data::metadata();
+        let mut strings = strings.borrow_mut();
+        let mut moved = 0;
+
+        for object in self.inner.objects() {
+            let mut object = object.borrow_mut();
+            if !object.is_dirty() {
+                continue;
+            }
+
+            for value in object.text_values_mut(metadata) {
+                if value.len() >= min_length && parse_reference(value).is_none() {
+                    *value = strings.add(std::mem::take(value));
+                    moved += 1;
+                }
+            }
+        }
+
+        moved
+    }
+
+    /// The text values of all objects in the store, like names and tooltips.
+    /// References to the string table are left out, since the table is
+    /// translated as a whole.
+    pub(crate) fn text_strings(&self) -> Vec<String> {
+        let metadata = w3data::metadata();
+        let mut texts = Vec::new();
+
+        for object in self.inner.objects() {
+            let mut object = object.borrow_mut();
+
+            texts.extend(
+                object
+                    .text_values_mut(metadata)
+                    .filter(|value| parse_reference(value).is_none())
+                    .map(|value| value.clone()),
+            );
+        }
+
+        texts
+    }
+
+    /// Replaces text values which have a translation in `catalog`.
+    /// Returns the number of replaced values.
+    pub(crate) fn translate(&self, catalog: &Catalog) -> usize {
+        let metadata = w3data::metadata();
+        let mut translated = 0;
+
+        for object in self.inner.objects() {
+            let mut object = object.borrow_mut();
+            let mut changed = false;
+
+            for value in object.text_values_mut(metadata) {
+                if let Some(translation) = catalog.translation(value) {
+                    *value = translation.to_string();
+                    changed = true;
+                    translated += 1;
+                }
+            }
+
+            if changed {
+                object.set_dirty(true);
+            }
+        }
+
+        translated
+    }
+
+    pub(crate) fn store(&self) -> &ObjectStore {
+        &self.inner
+    }
+
+    pub(crate) fn kind(&self) -> ObjectKind {
+        self.kind
+    }
+
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+
+    /// Whether the store or the stock data has an object of this kind with this ID.
+    pub(crate) fn has_object(&self, id: ObjectId) -> bool {
+        self.inner.object(id).is_some()
+            || w3data::data()
+                .object(id)
+                .map(|object| self.kind.contains(object.kind()))
+                .unwrap_or(false)
+    }
+
+    pub(crate) fn write_to_vec(&self) -> Result<Vec<u8>, std::io::Error> {
+        let mut buf = Vec::new();
+        w3obj::write::write_object_file(&mut buf, w3data::metadata(), &self.inner, self.kind)?;
+
+        Ok(buf)
+    }
+
     fn read<'lua>(
         ctx: LuaContext<'lua>,
         (data, value): (LuaAnyUserData<'lua>, LuaValue<'lua>),
@@ -370,12 +510,7 @@ impl LuaObjectStoreWrapper {
         data: LuaAnyUserData<'lua>,
     ) -> Result<LuaValue<'lua>, LuaError> {
         let data = data.borrow::<LuaObjectStoreWrapper>()?;
-        let kind = data.kind;
-        let data = &data.inner;
-
-        let mut buf = Vec::new();
-        w3obj::write::write_object_file(&mut buf, w3data::metadata(), &data, kind)
-            .map_err(LuaError::external)?;
+        let buf = data.write_to_vec().map_err(LuaError::external)?;
 
         Ok(LuaValue::String(ctx.create_string(&buf)?))
     }
@@ -402,6 +537,7 @@ impl LuaObjectStoreWrapper {
         ctx: LuaContext<'lua>,
         data: &mut ObjectStore,
         kind: ObjectKind,
+        strings: &Option<SharedStringTable>,
     ) -> Result<LuaValue<'lua>, LuaError> {
         let table = ctx.create_table()?;
         let mut set: HashSet<ObjectId> = HashSet::new();
@@ -417,7 +553,13 @@ impl LuaObjectStoreWrapper {
 
         for id in set {
             let object = Self::object_or_new(data, kind, id).unwrap();
-            table.set(id, LuaObjectWrapper { inner: object })?;
+            table.set(
+                id,
+                LuaObjectWrapper {
+                    inner:   object,
+                    strings: strings.clone(),
+                },
+            )?;
         }
 
         Ok(LuaValue::Table(table))
@@ -428,11 +570,15 @@ impl LuaObjectStoreWrapper {
         (data, key): (&mut LuaObjectStoreWrapper, LuaValue<'lua>),
     ) -> Result<LuaValue<'lua>, LuaError> {
         let kind = data.kind;
+        let strings = &data.strings;
         let data = &mut data.inner;
 
         if let Ok(id) = ObjectId::from_lua(key, ctx) {
             return Self::object_or_new(data, kind, id)
-                .map(|object| LuaObjectWrapper { inner: object })
+                .map(|object| LuaObjectWrapper {
+                    inner:   object,
+                    strings: strings.clone(),
+                })
                 .to_lua(ctx);
         }
 
@@ -489,13 +635,14 @@ impl LuaObjectStoreWrapper {
         key: LuaValue<'lua>,
     ) -> Result<LuaValue<'lua>, LuaError> {
         let kind = data.kind;
+        let strings = &data.strings;
         let data_inner = &mut data.inner;
 
         if let Ok(key) = LuaString::from_lua(key.clone(), ctx) {
             let key = key.as_bytes();
 
             match key {
-                b"all" => return Self::objects(ctx, data_inner, kind),
+                b"all" => return Self::objects(ctx, data_inner, kind, strings),
                 b"readFromString" => return Ok(StaticMethods::objstore_read_fn(ctx)),
                 b"writeToString" => return Ok(StaticMethods::objstore_write_fn(ctx)),
                 b"getObject" => return Ok(StaticMethods::objstore_getobject_fn(ctx)),
@@ -539,9 +686,27 @@ impl LuaUserData for LuaObjectStoreWrapper {
     }
 }
 
+/// Whether one of the stores in `objects`, the table behind `map.objects`,
+/// has an object of one of the given kinds with this ID.
+pub(crate) fn objects_contain(
+    objects: &LuaTable,
+    kinds: &[ObjectKind],
+    id: ObjectId,
+) -> Result<bool, LuaError> {
+    for kind in kinds {
+        let store: LuaAnyUserData = objects.get(kind.to_typestr())?;
+
+        if store.borrow::<LuaObjectStoreWrapper>()?.has_object(id) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
 // standalone functions
 
-fn open_store_from_str(
+pub(crate) fn open_store_from_str(
     source: &[u8],
     kind: ObjectKind,
 ) -> Result<LuaObjectStoreWrapper, anyhow::Error> {
@@ -549,7 +714,11 @@ fn open_store_from_str(
     w3obj::read::read_object_file(source, &mut data, kind)?;
     data.reset_dirty();
 
-    Ok(LuaObjectStoreWrapper { inner: data, kind })
+    Ok(LuaObjectStoreWrapper {
+        inner: data,
+        kind,
+        strings: None,
+    })
 }
 
 fn get_open_store_from_str_luafn(ctx: LuaContext) -> LuaFunction {
@@ -584,10 +753,7 @@ fn get_open_store_blank_luafn(ctx: LuaContext) -> LuaFunction {
             .into());
         }
 
-        Ok(LuaObjectStoreWrapper {
-            inner: ObjectStore::default(),
-            kind,
-        })
+        Ok(LuaObjectStoreWrapper::new(kind))
     })
     .unwrap()
 }
@@ -605,3 +771,73 @@ pub fn get_object_module(ctx: LuaContext) -> LuaTable {
 
     table
 }
+
+#[cfg(test)]
+mod test {
+    use ceres_formats::triggerstrings::TriggerStrings;
+
+    use crate::lua::strings::StringTable;
+
+    use super::*;
+
+    fn unit(id: &[u8; 4], name: &str, model: &str) -> Object {
+        let mut object = Object::with_parent(
+            ObjectId::from_bytes(id).unwrap(),
+            ObjectId::from_bytes(b"hfoo").unwrap(),
+            ObjectKind::UNIT,
+        );
+        let name_id = ObjectId::from_bytes(b"unam").unwrap();
+        let model_id = ObjectId::from_bytes(b"umdl").unwrap();
+        object.set_simple_field(name_id, Value::String(name.into()));
+        object.set_simple_field(model_id, Value::String(model.into()));
+
+        object
+    }
+
+    fn name_and_model(store: &LuaObjectStoreWrapper, id: &[u8; 4]) -> (String, String) {
+        let object = store.inner.object(ObjectId::from_bytes(id).unwrap()).unwrap();
+        let object = object.borrow();
+        let value = |field: &[u8; 4]| match object.simple_field(ObjectId::from_bytes(field)?) {
+            Some(Value::String(value)) => Some(value.clone()),
+            _ => None,
+        };
+
+        (value(b"unam").unwrap(), value(b"umdl").unwrap())
+    }
+
+    #[test]
+    fn only_long_text_values_are_moved() {
+        let long_model = "units\\human\\Footman\\FootmanWithAVeryLongPath.mdl";
+        let strings = Rc::new(RefCell::new(StringTable::new(TriggerStrings::default())));
+        let mut store = LuaObjectStoreWrapper::new(ObjectKind::UNIT);
+        store.set_strings(Rc::clone(&strings));
+
+        store.inner.insert_object(unit(b"h000", "A footman with a long name", long_model));
+        store.inner.insert_object(unit(b"h001", "Short", long_model));
+        let mut clean = unit(b"h002", "A footman which was not modified", long_model);
+        clean.set_dirty(false);
+        store.inner.insert_object(clean);
+
+        // the limit is inclusive
+        assert_eq!(store.move_long_strings("A footman with a long name".len()), 1);
+
+        let (name, model) = name_and_model(&store, b"h000");
+        assert!(parse_reference(&name).is_some());
+        assert_eq!(
+            strings.borrow().strings().resolve(&name),
+            Some("A footman with a long name")
+        );
+        assert_eq!(model, long_model);
+
+        assert_eq!(name_and_model(&store, b"h001").0, "Short");
+        assert_eq!(
+            name_and_model(&store, b"h002").0,
+            "A footman which was not modified"
+        );
+        assert!(strings.borrow().is_dirty());
+
+        // references are not moved again
+        assert_eq!(store.move_long_strings(1), 1);
+        assert_eq!(name_and_model(&store, b"h000").0, name);
+    }
+}
@@ -0,0 +1,273 @@
+//! Conversion of the regions (`war3map.w3r`), cameras (`war3map.w3c`) and
+//! sounds (`war3map.w3s`) to and from plain Lua tables. Every entry also
+//! carries the name of the global the World Editor generates for it.
+
+use rlua::prelude::*;
+
+use ceres_formats::cameras::*;
+use ceres_formats::regions::*;
+use ceres_formats::sounds::*;
+
+use crate::lua::util::{array_to_table, get_array, get_or_default, list_to_table};
+
+fn region_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    region: &Region,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("name", region.name.as_str())?;
+    table.set("global", region.global_name())?;
+    table.set("minX", region.left)?;
+    table.set("minY", region.bottom)?;
+    table.set("maxX", region.right)?;
+    table.set("maxY", region.top)?;
+    table.set("creationNumber", region.creation_number)?;
+    table.set("weather", region.weather)?;
+    table.set("ambientSound", region.ambient_sound.as_str())?;
+    table.set("color", array_to_table(ctx, &region.color)?)?;
+
+    Ok(table)
+}
+
+fn region_from_table(table: LuaTable) -> Result<Region, LuaError> {
+    let mut region = Region {
+        name:            table.get("name")?,
+        left:            get_or_default(&table, "minX")?,
+        bottom:          get_or_default(&table, "minY")?,
+        right:           get_or_default(&table, "maxX")?,
+        top:             get_or_default(&table, "maxY")?,
+        // assigned by `regions_from_table` if missing
+        creation_number: table
+            .get::<_, Option<u32>>("creationNumber")?
+            .unwrap_or(u32::MAX),
+        weather:         table.get("weather")?,
+        ambient_sound:   get_or_default(&table, "ambientSound")?,
+        color:           [255, 255, 255],
+    };
+
+    get_array(&table, "color", &mut region.color)?;
+
+    Ok(region)
+}
+
+pub fn regions_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    file: &RegionsFile,
+) -> Result<LuaTable<'lua>, LuaError> {
+    list_to_table(ctx, &file.regions, |region| region_to_table(ctx, region))
+}
+
+/// Regions added by the build script get fresh creation numbers.
+pub fn regions_from_table(
+    table: &LuaTable,
+    original: &RegionsFile,
+) -> Result<RegionsFile, LuaError> {
+    let mut regions: Vec<Region> = table
+        .clone()
+        .sequence_values::<LuaTable>()
+        .map(|region| region_from_table(region?))
+        .collect::<Result<_, _>>()?;
+
+    let mut next = regions
+        .iter()
+        .filter(|region| region.creation_number != u32::MAX)
+        .map(|region| region.creation_number + 1)
+        .max()
+        .unwrap_or(0);
+
+    for region in regions.iter_mut() {
+        if region.creation_number == u32::MAX {
+            region.creation_number = next;
+            next += 1;
+        }
+    }
+
+    Ok(RegionsFile {
+        version: original.version,
+        regions,
+    })
+}
+
+fn camera_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    camera: &Camera,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("name", camera.name.as_str())?;
+    table.set("global", camera.global_name())?;
+    table.set("targetX", camera.target_x)?;
+    table.set("targetY", camera.target_y)?;
+    table.set("zOffset", camera.z_offset)?;
+    table.set("rotation", camera.rotation)?;
+    table.set("angleOfAttack", camera.angle_of_attack)?;
+    table.set("distance", camera.distance)?;
+    table.set("roll", camera.roll)?;
+    table.set("fieldOfView", camera.field_of_view)?;
+    table.set("farZ", camera.far_z)?;
+    table.set("nearZ", camera.near_z)?;
+    if let Some(local_rotation) = &camera.local_rotation {
+        table.set("localRotation", array_to_table(ctx, local_rotation)?)?;
+    }
+
+    Ok(table)
+}
+
+/// Builds a camera from a table. Only `name` is required, everything else
+/// defaults to what the World Editor uses.
+fn camera_from_table(table: LuaTable) -> Result<Camera, LuaError> {
+    let mut camera = Camera::new(table.get("name")?);
+
+    camera.target_x = get_or_default(&table, "targetX")?;
+    camera.target_y = get_or_default(&table, "targetY")?;
+    camera.z_offset = get_or_default(&table, "zOffset")?;
+    camera.roll = get_or_default(&table, "roll")?;
+
+    if let Some(rotation) = table.get("rotation")? {
+        camera.rotation = rotation;
+    }
+    if let Some(angle_of_attack) = table.get("angleOfAttack")? {
+        camera.angle_of_attack = angle_of_attack;
+    }
+    if let Some(distance) = table.get("distance")? {
+        camera.distance = distance;
+    }
+    if let Some(field_of_view) = table.get("fieldOfView")? {
+        camera.field_of_view = field_of_view;
+    }
+    if let Some(far_z) = table.get("farZ")? {
+        camera.far_z = far_z;
+    }
+    if let Some(near_z) = table.get("nearZ")? {
+        camera.near_z = near_z;
+    }
+    if table.get::<_, Option<LuaTable>>("localRotation")?.is_some() {
+        let mut local_rotation = [0.0; 3];
+        get_array(&table, "localRotation", &mut local_rotation)?;
+        camera.local_rotation = Some(local_rotation);
+    }
+
+    Ok(camera)
+}
+
+pub fn cameras_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    file: &CamerasFile,
+) -> Result<LuaTable<'lua>, LuaError> {
+    list_to_table(ctx, &file.cameras, |camera| camera_to_table(ctx, camera))
+}
+
+pub fn cameras_from_table(
+    table: &LuaTable,
+    original: &CamerasFile,
+) -> Result<CamerasFile, LuaError> {
+    Ok(CamerasFile {
+        version: original.version,
+        cameras: table
+            .clone()
+            .sequence_values::<LuaTable>()
+            .map(|camera| camera_from_table(camera?))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn sound_to_table<'lua>(ctx: LuaContext<'lua>, sound: &Sound) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("name", sound.name.as_str())?;
+    table.set("global", sound.name.as_str())?;
+    table.set("file", sound.file.as_str())?;
+    table.set("eaxEffect", sound.eax_effect.as_str())?;
+    table.set("flags", sound.flags)?;
+    table.set("fadeInRate", sound.fade_in_rate)?;
+    table.set("fadeOutRate", sound.fade_out_rate)?;
+    table.set("volume", sound.volume)?;
+    table.set("pitch", sound.pitch)?;
+    table.set("pitchVariance", sound.pitch_variance)?;
+    table.set("priority", sound.priority)?;
+    table.set("channel", sound.channel)?;
+    table.set("minDistance", sound.min_distance)?;
+    table.set("maxDistance", sound.max_distance)?;
+    table.set("distanceCutoff", sound.distance_cutoff)?;
+    table.set("coneInside", sound.cone_inside)?;
+    table.set("coneOutside", sound.cone_outside)?;
+    table.set("coneOutsideVolume", sound.cone_outside_volume)?;
+    table.set(
+        "coneOrientation",
+        array_to_table(ctx, &sound.cone_orientation)?,
+    )?;
+
+    Ok(table)
+}
+
+/// Builds a sound from a table. Only `name` and `file` are required, everything
+/// else defaults to what the sound editor uses. The fields Reforged added are
+/// not exposed, so they are taken from the original sound of the same name.
+fn sound_from_table(table: LuaTable, original: &SoundsFile) -> Result<Sound, LuaError> {
+    let mut sound = Sound::new(table.get("name")?, table.get("file")?);
+
+    sound.extension = original
+        .sounds
+        .iter()
+        .find(|original| original.name == sound.name)
+        .and_then(|original| original.extension.clone());
+
+    if let Some(eax_effect) = table.get("eaxEffect")? {
+        sound.eax_effect = eax_effect;
+    }
+    if let Some(flags) = table.get("flags")? {
+        sound.flags = flags;
+    }
+    if let Some(fade_in_rate) = table.get("fadeInRate")? {
+        sound.fade_in_rate = fade_in_rate;
+    }
+    if let Some(fade_out_rate) = table.get("fadeOutRate")? {
+        sound.fade_out_rate = fade_out_rate;
+    }
+    if let Some(volume) = table.get("volume")? {
+        sound.volume = volume;
+    }
+    if let Some(pitch) = table.get("pitch")? {
+        sound.pitch = pitch;
+    }
+    if let Some(priority) = table.get("priority")? {
+        sound.priority = priority;
+    }
+    if let Some(max_distance) = table.get("maxDistance")? {
+        sound.max_distance = max_distance;
+    }
+    if let Some(distance_cutoff) = table.get("distanceCutoff")? {
+        sound.distance_cutoff = distance_cutoff;
+    }
+    if let Some(cone_outside_volume) = table.get("coneOutsideVolume")? {
+        sound.cone_outside_volume = cone_outside_volume;
+    }
+
+    sound.pitch_variance = get_or_default(&table, "pitchVariance")?;
+    sound.channel = get_or_default(&table, "channel")?;
+    sound.min_distance = get_or_default(&table, "minDistance")?;
+    sound.cone_inside = get_or_default(&table, "coneInside")?;
+    sound.cone_outside = get_or_default(&table, "coneOutside")?;
+    get_array(&table, "coneOrientation", &mut sound.cone_orientation)?;
+
+    Ok(sound)
+}
+
+pub fn sounds_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    file: &SoundsFile,
+) -> Result<LuaTable<'lua>, LuaError> {
+    list_to_table(ctx, &file.sounds, |sound| sound_to_table(ctx, sound))
+}
+
+pub fn sounds_from_table(table: &LuaTable, original: &SoundsFile) -> Result<SoundsFile, LuaError> {
+    Ok(SoundsFile {
+        version: original.version,
+        sounds:  table
+            .clone()
+            .sequence_values::<LuaTable>()
+            .map(|sound| sound_from_table(sound?, original))
+            .collect::<Result<_, _>>()?,
+    })
+}
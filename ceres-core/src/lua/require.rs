@@ -0,0 +1,220 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rlua::prelude::*;
+
+use crate::error::IoError;
+
+const DEFAULT_BUILD_DIRECTORIES: &[&str] = &["build/", "lib/"];
+
+/// Reads `ceres.layout.buildDirectories` at lookup time, so that
+/// build scripts can change the layout before their first `require`.
+fn build_directories(ctx: LuaContext) -> Result<Vec<String>, LuaError> {
+    let ceres: Option<LuaTable> = ctx.globals().get("ceres")?;
+    let layout: Option<LuaTable> = match ceres {
+        Some(ceres) => ceres.get("layout")?,
+        None => None,
+    };
+    let directories: Option<Vec<String>> = match layout {
+        Some(layout) => layout.get("buildDirectories")?,
+        None => None,
+    };
+
+    Ok(directories.unwrap_or_else(|| {
+        DEFAULT_BUILD_DIRECTORIES
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect()
+    }))
+}
+
+/// Resolves a module name like `foo.bar` to `foo/bar.lua` or `foo/bar/init.lua`
+/// inside one of the build directories, returning the list of tried paths on failure.
+fn find_build_module(
+    project_dir: &Path,
+    directories: &[String],
+    module_name: &str,
+) -> Result<PathBuf, Vec<PathBuf>> {
+    let relative_path: PathBuf = module_name.split('.').collect();
+    let mut tried = Vec::new();
+
+    for dir in directories {
+        let module_path = project_dir.join(dir).join(&relative_path);
+        let candidates = vec![
+            module_path.with_extension("lua"),
+            module_path.join("init.lua"),
+        ];
+
+        for candidate in candidates {
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+
+            tried.push(candidate);
+        }
+    }
+
+    Err(tried)
+}
+
+fn get_build_searcher_luafn(ctx: LuaContext, project_dir: PathBuf) -> LuaFunction {
+    ctx.create_function(move |ctx, module_name: String| {
+        let directories = build_directories(ctx)?;
+
+        match find_build_module(&project_dir, &directories, &module_name) {
+            Ok(path) => {
+                let src = fs::read(&path)
+                    .map_err(|cause| LuaError::external(IoError::new(&path, cause)))?;
+                let path = path.to_string_lossy().into_owned();
+
+                // '@' marks the chunk name as a file path, so errors
+                // and tracebacks point at the actual module file
+                let loader = ctx
+                    .load(&src)
+                    .set_name(&format!("@{}", path))?
+                    .into_function()?;
+
+                Ok((
+                    LuaValue::Function(loader),
+                    LuaValue::String(ctx.create_string(&path)?),
+                ))
+            }
+            Err(tried) => {
+                let message: String = tried
+                    .iter()
+                    .map(|path| format!("\n\tno build module '{}'", path.display()))
+                    .collect();
+
+                Ok((
+                    LuaValue::String(ctx.create_string(&message)?),
+                    LuaValue::Nil,
+                ))
+            }
+        }
+    })
+    .unwrap()
+}
+
+/// Installs a `package.searchers` entry which resolves build-time modules
+/// relative to the project directory. Modules are cached in `package.loaded`
+/// like any other module loaded through `require`.
+pub fn install_build_searcher(ctx: LuaContext, project_dir: &Path) -> Result<(), LuaError> {
+    let globals = ctx.globals();
+    let package: LuaTable = globals.get("package")?;
+    let searchers: LuaTable = package.get("searchers")?;
+    let table_insert: LuaFunction = globals.get::<_, LuaTable>("table")?.get("insert")?;
+
+    // goes right after the preload searcher, so that project modules
+    // take precedence over anything found through package.path
+    table_insert.call::<_, ()>((
+        searchers,
+        2,
+        get_build_searcher_luafn(ctx, project_dir.into()),
+    ))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::process;
+
+    use rlua::Lua;
+
+    use super::*;
+
+    /// Lays out a throwaway project with the given files.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ceres-require-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    fn eval(project_dir: &Path, src: &str) -> Result<String, LuaError> {
+        let lua = Lua::new();
+
+        lua.context(|ctx| {
+            install_build_searcher(ctx, project_dir)?;
+
+            let package: LuaTable = ctx.globals().get("package")?;
+            let path = project_dir.join("path").join("?.lua");
+            package.set("path", path.to_string_lossy().into_owned())?;
+
+            ctx.load(src).eval()
+        })
+    }
+
+    #[test]
+    fn require_from_lib() {
+        let dir = project(
+            "lib",
+            &[
+                ("lib/util/strings.lua", "return { name = 'strings' }"),
+                ("lib/util/init.lua", "return { name = 'util' }"),
+            ],
+        );
+
+        let name = eval(
+            &dir,
+            "return require('util.strings').name .. require('util').name",
+        );
+        assert_eq!(name.unwrap(), "stringsutil");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn build_directories_take_precedence() {
+        let dir = project(
+            "precedence",
+            &[
+                ("path/config.lua", "return 'package.path'"),
+                ("lib/config.lua", "return 'lib'"),
+                ("build/config.lua", "return 'build'"),
+                ("path/other.lua", "return 'package.path'"),
+            ],
+        );
+
+        assert_eq!(eval(&dir, "return require('config')").unwrap(), "build");
+        assert_eq!(
+            eval(&dir, "return require('other')").unwrap(),
+            "package.path"
+        );
+
+        let layout = "ceres = { layout = { buildDirectories = { 'lib/' } } }\n";
+        let src = format!("{}return require('config')", layout);
+        assert_eq!(eval(&dir, &src).unwrap(), "lib");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_module_lists_tried_paths() {
+        let dir = project("missing", &[("lib/other.lua", "return 1")]);
+
+        let directories = vec!["build/".to_string(), "lib/".to_string()];
+        let tried = find_build_module(&dir, &directories, "a.b").unwrap_err();
+        assert_eq!(
+            tried,
+            vec![
+                dir.join("build/a/b.lua"),
+                dir.join("build/a/b/init.lua"),
+                dir.join("lib/a/b.lua"),
+                dir.join("lib/a/b/init.lua"),
+            ]
+        );
+
+        let message = eval(&dir, "return require('a.b')").unwrap_err().to_string();
+        assert!(message.contains("no build module"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+//! `map.strings`, the trigger strings of the map (`war3map.wts`).
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rlua::prelude::*;
+
+use ceres_formats::parser::wts;
+use ceres_formats::triggerstrings::*;
+
+use crate::error::StringError;
+use crate::lua::localization::Catalog;
+
+/// The string table of a map, shared between `map.strings` and the object
+/// stores, which resolve references through it.
+#[derive(Default)]
+pub(crate) struct StringTable {
+    strings: TriggerStrings,
+    dirty:   bool,
+}
+
+pub(crate) type SharedStringTable = Rc<RefCell<StringTable>>;
+
+impl StringTable {
+    pub(crate) fn new(strings: TriggerStrings) -> StringTable {
+        StringTable {
+            strings,
+            dirty: false,
+        }
+    }
+
+    pub(crate) fn strings(&self) -> &TriggerStrings {
+        &self.strings
+    }
+
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Adds a string, returning a reference to it.
+    pub(crate) fn add(&mut self, value: String) -> String {
+        self.dirty = true;
+
+        make_reference(self.strings.add(value))
+    }
+
+    /// Replaces strings which have a translation in `catalog`.
+    /// Returns the number of replaced strings.
+    pub(crate) fn translate(&mut self, catalog: &Catalog) -> usize {
+        let translations: Vec<_> = self
+            .strings
+            .strings()
+            .filter_map(|(id, string)| {
+                catalog
+                    .translation(&string.value)
+                    .map(|translation| (id, translation.to_string()))
+            })
+            .collect();
+
+        self.dirty |= !translations.is_empty();
+        for (id, translation) in &translations {
+            self.strings.set(*id, translation.clone());
+        }
+
+        translations.len()
+    }
+
+    pub(crate) fn write_to_vec(&mut self) -> Result<Vec<u8>, std::io::Error> {
+        let mut buf = Vec::new();
+        wts::write::write_string_table(&mut buf, &self.strings)?;
+        self.dirty = false;
+
+        Ok(buf)
+    }
+}
+
+pub(crate) struct LuaStringTable {
+    inner: SharedStringTable,
+}
+
+impl LuaStringTable {
+    pub(crate) fn new(inner: SharedStringTable) -> LuaStringTable {
+        LuaStringTable { inner }
+    }
+}
+
+/// Strings can be referred to by their ID, or by a reference like `TRIGSTR_012`.
+fn string_id<'lua>(ctx: LuaContext<'lua>, key: LuaValue<'lua>) -> Result<u32, LuaError> {
+    if let LuaValue::String(key) = &key {
+        if let Some(id) = parse_reference(key.to_str()?) {
+            return Ok(id);
+        }
+    }
+
+    u32::from_lua(key, ctx)
+        .map_err(|_| StringError::new("expected a string ID or a TRIGSTR_ reference").into())
+}
+
+impl LuaUserData for LuaStringTable {
+    fn add_methods<'lua, T>(methods: &mut T)
+    where
+        T: LuaUserDataMethods<'lua, Self>,
+    {
+        methods.add_method("get", |ctx, table, key: LuaValue| {
+            let id = string_id(ctx, key)?;
+
+            Ok(table.inner.borrow().strings.get(id).map(str::to_string))
+        });
+
+        methods.add_method("set", |ctx, table, (key, value): (LuaValue, String)| {
+            let id = string_id(ctx, key)?;
+            let mut table = table.inner.borrow_mut();
+            table.strings.set(id, value);
+            table.dirty = true;
+
+            Ok(make_reference(id))
+        });
+
+        methods.add_method("add", |_, table, value: String| {
+            Ok(table.inner.borrow_mut().add(value))
+        });
+
+        methods.add_method("remove", |ctx, table, key: LuaValue| {
+            let id = string_id(ctx, key)?;
+            let mut table = table.inner.borrow_mut();
+            let removed = table.strings.remove(id).is_some();
+            table.dirty |= removed;
+
+            Ok(removed)
+        });
+
+        // values which are no reference to a known string are returned as they are
+        methods.add_method("resolve", |_, table, value: String| {
+            let table = table.inner.borrow();
+
+            Ok(table
+                .strings
+                .resolve(&value)
+                .map(str::to_string)
+                .unwrap_or(value))
+        });
+
+        methods.add_method("all", |ctx, table, _: ()| {
+            let result = ctx.create_table()?;
+
+            for (id, string) in table.inner.borrow().strings.strings() {
+                result.set(id, string.value.as_str())?;
+            }
+
+            Ok(result)
+        });
+
+        methods.add_meta_method(LuaMetaMethod::Len, |_, table, _: ()| {
+            Ok(table.inner.borrow().strings.len())
+        });
+    }
+}
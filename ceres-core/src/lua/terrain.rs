@@ -0,0 +1,253 @@
+//! `map.terrain`, the terrain grid of the map (`war3map.w3e`).
+
+use rlua::prelude::*;
+
+use ceres_formats::parser::w3e;
+use ceres_formats::terrain::*;
+use ceres_formats::ObjectId;
+
+use crate::error::StringError;
+use crate::lua::util::array_to_table;
+
+/// Both tileset lists are indexed by 4 bits.
+const MAX_TILESETS: usize = 16;
+
+pub(crate) struct LuaTerrain {
+    terrain: Terrain,
+    dirty:   bool,
+}
+
+impl LuaTerrain {
+    pub(crate) fn new(terrain: Terrain) -> LuaTerrain {
+        LuaTerrain {
+            terrain,
+            dirty: false,
+        }
+    }
+
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub(crate) fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    pub(crate) fn write_to_vec(&mut self) -> Result<Vec<u8>, std::io::Error> {
+        let mut buf = Vec::new();
+        w3e::write::write_terrain(&mut buf, &self.terrain)?;
+        self.dirty = false;
+
+        Ok(buf)
+    }
+
+    fn point(&self, x: u32, y: u32) -> Result<&TilePoint, LuaError> {
+        let (width, height) = (self.terrain.width, self.terrain.height);
+
+        self.terrain
+            .point(x, y)
+            .ok_or_else(|| out_of_bounds(x, y, width, height))
+    }
+
+    /// Mutable access to a point, marking the terrain as modified.
+    fn point_mut(&mut self, x: u32, y: u32) -> Result<&mut TilePoint, LuaError> {
+        let (width, height) = (self.terrain.width, self.terrain.height);
+        self.dirty = true;
+
+        self.terrain
+            .point_mut(x, y)
+            .ok_or_else(|| out_of_bounds(x, y, width, height))
+    }
+
+    fn tile_to_table<'lua>(
+        &self,
+        ctx: LuaContext<'lua>,
+        x: u32,
+        y: u32,
+    ) -> Result<LuaTable<'lua>, LuaError> {
+        let point = self.point(x, y)?;
+        let table = ctx.create_table()?;
+
+        table.set(
+            "texture",
+            self.terrain.ground_tilesets.get(point.texture as usize).copied(),
+        )?;
+        table.set("variation", point.variation)?;
+        table.set(
+            "cliffTexture",
+            self.terrain.cliff_tilesets.get(point.cliff_texture as usize).copied(),
+        )?;
+        table.set("cliffVariation", point.cliff_variation)?;
+        table.set("layer", point.layer)?;
+        table.set("height", point.world_height())?;
+        table.set("water", point.water_height())?;
+        table.set("ramp", point.flags & flags::RAMP != 0)?;
+        table.set("blight", point.flags & flags::BLIGHT != 0)?;
+        table.set("boundary", point.flags & flags::BOUNDARY != 0)?;
+        table.set("mapEdge", point.map_edge)?;
+
+        Ok(table)
+    }
+
+    /// Updates the fields of a point present in `table`. Textures which the map
+    /// does not use yet are added to its tilesets.
+    fn set_tile(&mut self, x: u32, y: u32, table: LuaTable) -> Result<(), LuaError> {
+        // check the bounds first, so that no tileset is added for nothing
+        self.point(x, y)?;
+
+        let texture = match table.get::<_, Option<ObjectId>>("texture")? {
+            Some(id) => Some(tileset_index(&mut self.terrain.ground_tilesets, id, "ground")?),
+            None => None,
+        };
+        let cliff_texture = match table.get::<_, Option<ObjectId>>("cliffTexture")? {
+            Some(id) => Some(tileset_index(&mut self.terrain.cliff_tilesets, id, "cliff")?),
+            None => None,
+        };
+
+        let point = self.point_mut(x, y)?;
+
+        if let Some(texture) = texture {
+            point.texture = texture;
+        }
+        if let Some(cliff_texture) = cliff_texture {
+            point.cliff_texture = cliff_texture;
+        }
+        if let Some(variation) = table.get::<_, Option<u8>>("variation")? {
+            point.variation = variation & 0x1F;
+        }
+        if let Some(cliff_variation) = table.get::<_, Option<u8>>("cliffVariation")? {
+            point.cliff_variation = cliff_variation & 0x07;
+        }
+        if let Some(layer) = table.get::<_, Option<u8>>("layer")? {
+            point.layer = layer & 0x0F;
+        }
+        if let Some(height) = table.get("height")? {
+            point.set_world_height(height);
+        }
+        if let Some(map_edge) = table.get("mapEdge")? {
+            point.map_edge = map_edge;
+        }
+
+        for (key, flag) in &[
+            ("ramp", flags::RAMP),
+            ("blight", flags::BLIGHT),
+            ("boundary", flags::BOUNDARY),
+        ] {
+            match table.get::<_, Option<bool>>(*key)? {
+                Some(true) => point.flags |= flag,
+                Some(false) => point.flags &= !flag,
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), LuaError> {
+        // a map needs at least one tile
+        if width < 2 || height < 2 {
+            return Err(StringError::new(format!(
+                "cannot resize the terrain to {}x{} points, it needs at least 2x2",
+                width, height
+            ))
+            .into());
+        }
+
+        self.terrain.resize(width, height);
+        self.dirty = true;
+
+        Ok(())
+    }
+}
+
+fn out_of_bounds(x: u32, y: u32, width: u32, height: u32) -> LuaError {
+    StringError::new(format!(
+        "tile point ({}, {}) is outside of the {}x{} terrain",
+        x, y, width, height
+    ))
+    .into()
+}
+
+/// Finds `id` in a tileset list, adding it if there is room left.
+fn tileset_index(tilesets: &mut Vec<ObjectId>, id: ObjectId, kind: &str) -> Result<u8, LuaError> {
+    if let Some(index) = tilesets.iter().position(|tileset| *tileset == id) {
+        return Ok(index as u8);
+    }
+
+    if tilesets.len() >= MAX_TILESETS {
+        return Err(StringError::new(format!(
+            "cannot use {}, the map already uses {} {} tilesets",
+            id, MAX_TILESETS, kind
+        ))
+        .into());
+    }
+
+    tilesets.push(id);
+
+    Ok((tilesets.len() - 1) as u8)
+}
+
+impl LuaUserData for LuaTerrain {
+    fn add_methods<'lua, T>(methods: &mut T)
+    where
+        T: LuaUserDataMethods<'lua, Self>,
+    {
+        methods.add_method("getHeight", |_, terrain, (x, y): (u32, u32)| {
+            Ok(terrain.point(x, y)?.world_height())
+        });
+
+        methods.add_method_mut("setHeight", |_, terrain, (x, y, height): (u32, u32, f32)| {
+            terrain.point_mut(x, y)?.set_world_height(height);
+
+            Ok(())
+        });
+
+        methods.add_method("getWater", |_, terrain, (x, y): (u32, u32)| {
+            Ok(terrain.point(x, y)?.water_height())
+        });
+
+        methods.add_method_mut(
+            "setWater",
+            |_, terrain, (x, y, height): (u32, u32, Option<f32>)| {
+                terrain.point_mut(x, y)?.set_water_height(height);
+
+                Ok(())
+            },
+        );
+
+        methods.add_method("getTile", |ctx, terrain, (x, y): (u32, u32)| {
+            terrain.tile_to_table(ctx, x, y)
+        });
+
+        methods.add_method_mut("setTile", |_, terrain, (x, y, table): (u32, u32, LuaTable)| {
+            terrain.set_tile(x, y, table)
+        });
+
+        methods.add_method_mut("resize", |_, terrain, (width, height): (u32, u32)| {
+            terrain.resize(width, height)
+        });
+
+        methods.add_meta_method(LuaMetaMethod::Index, |ctx, terrain, key: LuaValue| {
+            let terrain = &terrain.terrain;
+
+            if let LuaValue::String(key) = key {
+                match key.as_bytes() {
+                    b"width" => return terrain.width.to_lua(ctx),
+                    b"height" => return terrain.height.to_lua(ctx),
+                    b"offsetX" => return terrain.offset_x.to_lua(ctx),
+                    b"offsetY" => return terrain.offset_y.to_lua(ctx),
+                    b"tileset" => return ctx.create_string(&[terrain.tileset])?.to_lua(ctx),
+                    b"groundTilesets" => {
+                        return array_to_table(ctx, &terrain.ground_tilesets)?.to_lua(ctx);
+                    }
+                    b"cliffTilesets" => {
+                        return array_to_table(ctx, &terrain.cliff_tilesets)?.to_lua(ctx);
+                    }
+                    _ => {}
+                }
+            }
+
+            Ok(LuaValue::Nil)
+        });
+    }
+}
@@ -0,0 +1,366 @@
+//! `map.units`, the units and items placed on the map (`war3mapUnits.doo`).
+
+use rlua::prelude::*;
+
+use ceres_formats::parser::doo;
+use ceres_formats::units::*;
+use ceres_formats::{ObjectId, ObjectKind};
+
+use crate::error::StringError;
+use crate::lua::object::objects_contain;
+use crate::lua::util::{array_to_table, get_array, get_list, get_or_default, list_to_table};
+
+/// Player start locations are placed like units, but are no objects.
+const START_LOCATION: &[u8] = b"sloc";
+
+pub(crate) struct LuaUnits {
+    file:    UnitsFile,
+    dirty:   bool,
+    /// The object stores of the map, against which new units are validated.
+    objects: LuaRegistryKey,
+}
+
+impl LuaUnits {
+    pub(crate) fn new(file: UnitsFile, objects: LuaRegistryKey) -> LuaUnits {
+        LuaUnits {
+            file,
+            dirty: false,
+            objects,
+        }
+    }
+
+    pub(crate) fn set_objects(&mut self, objects: LuaRegistryKey) {
+        self.objects = objects;
+    }
+
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub(crate) fn file(&self) -> &UnitsFile {
+        &self.file
+    }
+
+    pub(crate) fn write_to_vec(&mut self) -> Result<Vec<u8>, std::io::Error> {
+        let mut buf = Vec::new();
+        doo::write::write_units(&mut buf, &self.file)?;
+        self.dirty = false;
+
+        Ok(buf)
+    }
+
+    /// Whether `id` is a unit or item in the map's object stores,
+    /// including stock objects.
+    fn is_known_type(&self, ctx: LuaContext, id: ObjectId) -> Result<bool, LuaError> {
+        if ObjectId::from_bytes(START_LOCATION) == Some(id) {
+            return Ok(true);
+        }
+
+        let objects: LuaTable = ctx.registry_value(&self.objects)?;
+
+        objects_contain(&objects, &[ObjectKind::UNIT, ObjectKind::ITEM], id)
+    }
+
+    fn add<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+        table: LuaTable<'lua>,
+    ) -> Result<LuaTable<'lua>, LuaError> {
+        let unit = unit_from_table(&table)?;
+
+        if !self.is_known_type(ctx, unit.id)? {
+            return Err(StringError::new(format!(
+                "cannot place {}, there is no such unit or item",
+                unit.id
+            ))
+            .into());
+        }
+
+        let creation_number = self.file.add_unit(unit);
+        self.dirty = true;
+
+        unit_to_table(ctx, self.file.unit(creation_number).unwrap())
+    }
+
+    fn remove<'lua>(
+        &mut self,
+        ctx: LuaContext<'lua>,
+        unit: LuaValue<'lua>,
+    ) -> Result<bool, LuaError> {
+        let creation_number = match unit {
+            LuaValue::Table(unit) => unit.get("creationNumber")?,
+            value => u32::from_lua(value, ctx)?,
+        };
+
+        let removed = self.file.remove_unit(creation_number).is_some();
+        self.dirty |= removed;
+
+        Ok(removed)
+    }
+}
+
+fn dropped_items_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    items: &[DroppedItem],
+) -> Result<LuaTable<'lua>, LuaError> {
+    list_to_table(ctx, items, |item| {
+        let table = ctx.create_table()?;
+        table.set("id", item.id)?;
+        table.set("chance", item.chance)?;
+
+        Ok(table)
+    })
+}
+
+fn dropped_item_from_table(table: LuaTable) -> Result<DroppedItem, LuaError> {
+    Ok(DroppedItem {
+        id:     table.get("id")?,
+        chance: table.get::<_, Option<u32>>("chance")?.unwrap_or(100),
+    })
+}
+
+/// Item sets, which units and destructables drop when they die.
+pub(crate) fn item_sets_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    sets: &[Vec<DroppedItem>],
+) -> Result<LuaTable<'lua>, LuaError> {
+    list_to_table(ctx, sets, |set| dropped_items_to_table(ctx, set))
+}
+
+pub(crate) fn item_sets_from_table(
+    table: &LuaTable,
+    key: &str,
+) -> Result<Vec<Vec<DroppedItem>>, LuaError> {
+    get_list(table, key, |set| {
+        set.sequence_values::<LuaTable>()
+            .map(|item| dropped_item_from_table(item?))
+            .collect()
+    })
+}
+
+fn random_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    random: &RandomUnit,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    match random {
+        RandomUnit::Level { level, class } => {
+            table.set("kind", "level")?;
+            table.set("level", *level)?;
+            table.set("class", *class)?;
+        }
+        RandomUnit::Group { group, position } => {
+            table.set("kind", "group")?;
+            table.set("group", *group)?;
+            table.set("position", *position)?;
+        }
+        RandomUnit::Custom(units) => {
+            table.set("kind", "custom")?;
+            table.set("units", dropped_items_to_table(ctx, units)?)?;
+        }
+    }
+
+    Ok(table)
+}
+
+fn random_from_table(table: LuaTable) -> Result<RandomUnit, LuaError> {
+    let kind: String = table.get("kind")?;
+
+    match kind.as_str() {
+        "level" => Ok(RandomUnit::Level {
+            // the level is stored in 24 bits, all set meaning any level
+            level: get_or_default::<u32>(&table, "level")? & 0xFF_FFFF,
+            class: get_or_default(&table, "class")?,
+        }),
+        "group" => Ok(RandomUnit::Group {
+            group:    get_or_default(&table, "group")?,
+            position: get_or_default(&table, "position")?,
+        }),
+        "custom" => Ok(RandomUnit::Custom(get_list(
+            &table,
+            "units",
+            dropped_item_from_table,
+        )?)),
+        other => Err(StringError::new(format!(
+            "unknown random unit kind {}, expected \"level\", \"group\" or \"custom\"",
+            other
+        ))
+        .into()),
+    }
+}
+
+pub(crate) fn unit_to_table<'lua>(
+    ctx: LuaContext<'lua>,
+    unit: &PlacedUnit,
+) -> Result<LuaTable<'lua>, LuaError> {
+    let table = ctx.create_table()?;
+
+    table.set("type", unit.id)?;
+    table.set("creationNumber", unit.creation_number)?;
+    table.set("variation", unit.variation)?;
+    table.set("x", unit.x)?;
+    table.set("y", unit.y)?;
+    table.set("z", unit.z)?;
+    table.set("facing", unit.facing.to_degrees())?;
+    table.set("scale", array_to_table(ctx, &unit.scale)?)?;
+    table.set("skin", unit.skin)?;
+    table.set("flags", unit.flags)?;
+    table.set("owner", unit.owner)?;
+    table.set("hp", unit.hp)?;
+    table.set("mana", unit.mana)?;
+    table.set("itemTable", unit.item_table)?;
+    table.set("itemSets", item_sets_to_table(ctx, &unit.item_sets)?)?;
+    table.set("gold", unit.gold)?;
+    table.set("targetAcquisition", unit.target_acquisition)?;
+    table.set("level", unit.level)?;
+    table.set("strength", unit.strength)?;
+    table.set("agility", unit.agility)?;
+    table.set("intelligence", unit.intelligence)?;
+    table.set(
+        "items",
+        list_to_table(ctx, &unit.items, |item| {
+            let table = ctx.create_table()?;
+            table.set("slot", item.slot)?;
+            table.set("id", item.id)?;
+
+            Ok(table)
+        })?,
+    )?;
+    table.set(
+        "abilities",
+        list_to_table(ctx, &unit.abilities, |ability| {
+            let table = ctx.create_table()?;
+            table.set("id", ability.id)?;
+            table.set("autocast", ability.autocast)?;
+            table.set("level", ability.level)?;
+
+            Ok(table)
+        })?,
+    )?;
+    table.set("random", random_to_table(ctx, &unit.random)?)?;
+    table.set("color", unit.color)?;
+    table.set("waygate", unit.waygate)?;
+
+    Ok(table)
+}
+
+/// Builds a unit from a table passed to `units:add()`. Only `type`, `x` and `y`
+/// are required, everything else defaults to what the World Editor uses.
+fn unit_from_table(table: &LuaTable) -> Result<PlacedUnit, LuaError> {
+    let mut unit = PlacedUnit::new(table.get("type")?, table.get("x")?, table.get("y")?);
+
+    unit.skin = table.get::<_, Option<ObjectId>>("skin")?.unwrap_or(unit.id);
+    unit.variation = get_or_default(table, "variation")?;
+    unit.z = get_or_default(table, "z")?;
+    unit.facing = get_or_default::<f32>(table, "facing")?.to_radians();
+    get_array(table, "scale", &mut unit.scale)?;
+    unit.owner = get_or_default(table, "owner")?;
+
+    if let Some(flags) = table.get("flags")? {
+        unit.flags = flags;
+    }
+    if let Some(hp) = table.get("hp")? {
+        unit.hp = hp;
+    }
+    if let Some(mana) = table.get("mana")? {
+        unit.mana = mana;
+    }
+    if let Some(item_table) = table.get("itemTable")? {
+        unit.item_table = item_table;
+    }
+    if let Some(gold) = table.get("gold")? {
+        unit.gold = gold;
+    }
+    if let Some(target_acquisition) = table.get("targetAcquisition")? {
+        unit.target_acquisition = target_acquisition;
+    }
+    if let Some(level) = table.get("level")? {
+        unit.level = level;
+    }
+    if let Some(random) = table.get("random")? {
+        unit.random = random_from_table(random)?;
+    }
+    if let Some(color) = table.get("color")? {
+        unit.color = color;
+    }
+    if let Some(waygate) = table.get("waygate")? {
+        unit.waygate = waygate;
+    }
+
+    unit.strength = get_or_default(table, "strength")?;
+    unit.agility = get_or_default(table, "agility")?;
+    unit.intelligence = get_or_default(table, "intelligence")?;
+
+    unit.item_sets = item_sets_from_table(table, "itemSets")?;
+
+    // items and abilities can be given as plain IDs, or as tables
+    if let Some(items) = table.get::<_, Option<LuaTable>>("items")? {
+        for (slot, item) in items.sequence_values::<LuaValue>().enumerate() {
+            let item = match item? {
+                LuaValue::Table(item) => InventoryItem {
+                    slot: item.get::<_, Option<u32>>("slot")?.unwrap_or(slot as u32),
+                    id:   item.get("id")?,
+                },
+                LuaValue::String(id) => InventoryItem {
+                    slot: slot as u32,
+                    id:   ObjectId::from_bytes(id.as_bytes())
+                        .ok_or_else(|| StringError::new("invalid item id"))?,
+                },
+                _ => return Err(StringError::new("items must be IDs or tables").into()),
+            };
+
+            unit.items.push(item);
+        }
+    }
+
+    if let Some(abilities) = table.get::<_, Option<LuaTable>>("abilities")? {
+        for ability in abilities.sequence_values::<LuaValue>() {
+            let ability = match ability? {
+                LuaValue::Table(ability) => UnitAbility {
+                    id:       ability.get("id")?,
+                    autocast: get_or_default(&ability, "autocast")?,
+                    level:    ability.get::<_, Option<u32>>("level")?.unwrap_or(1),
+                },
+                LuaValue::String(id) => UnitAbility {
+                    id:       ObjectId::from_bytes(id.as_bytes())
+                        .ok_or_else(|| StringError::new("invalid ability id"))?,
+                    autocast: false,
+                    level:    1,
+                },
+                _ => return Err(StringError::new("abilities must be IDs or tables").into()),
+            };
+
+            unit.abilities.push(ability);
+        }
+    }
+
+    Ok(unit)
+}
+
+impl LuaUserData for LuaUnits {
+    fn add_methods<'lua, T>(methods: &mut T)
+    where
+        T: LuaUserDataMethods<'lua, Self>,
+    {
+        methods.add_method("all", |ctx, units, _: ()| {
+            list_to_table(ctx, &units.file.units, |unit| unit_to_table(ctx, unit))
+        });
+
+        methods.add_method("get", |ctx, units, creation_number: u32| {
+            units
+                .file
+                .unit(creation_number)
+                .map(|unit| unit_to_table(ctx, unit))
+                .transpose()
+        });
+
+        methods.add_method_mut("add", |ctx, units, table: LuaTable| units.add(ctx, table));
+
+        methods.add_method_mut("remove", |ctx, units, unit: LuaValue| units.remove(ctx, unit));
+
+        methods.add_meta_method(LuaMetaMethod::Len, |_, units, _: ()| {
+            Ok(units.file.units.len())
+        });
+    }
+}
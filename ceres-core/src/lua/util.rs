@@ -133,3 +133,78 @@ pub fn lvalue_to_value<'lua>(
         ValueType::Unreal => Value::Unreal(FromLua::from_lua(value, ctx)?),
     })
 }
+
+pub fn array_to_table<'lua, T>(
+    ctx: LuaContext<'lua>,
+    values: &[T],
+) -> Result<LuaTable<'lua>, LuaError>
+where
+    T: ToLua<'lua> + Copy,
+{
+    ctx.create_sequence_from(values.iter().copied())
+}
+
+/// Missing keys read as the default value, so that new entries
+/// only need to specify what they care about.
+pub fn get_or_default<'lua, T>(table: &LuaTable<'lua>, key: &str) -> Result<T, LuaError>
+where
+    T: FromLua<'lua> + Default,
+{
+    Ok(table.get::<_, Option<T>>(key)?.unwrap_or_default())
+}
+
+/// Reads a list of exactly `out.len()` values into `out`, if the key is set.
+pub fn get_array<'lua, T>(table: &LuaTable<'lua>, key: &str, out: &mut [T]) -> Result<(), LuaError>
+where
+    T: FromLua<'lua> + Copy,
+{
+    let values = match table.get::<_, Option<Vec<T>>>(key)? {
+        Some(values) => values,
+        None => return Ok(()),
+    };
+
+    if values.len() != out.len() {
+        return Err(StringError::new(format!(
+            "field {} must have {} values, but has {}",
+            key,
+            out.len(),
+            values.len()
+        ))
+        .into());
+    }
+
+    out.copy_from_slice(&values);
+
+    Ok(())
+}
+
+/// Reads a list of tables, which is empty if the key is not set.
+pub fn get_list<'lua, T, F>(table: &LuaTable<'lua>, key: &str, read: F) -> Result<Vec<T>, LuaError>
+where
+    F: Fn(LuaTable<'lua>) -> Result<T, LuaError>,
+{
+    match table.get::<_, Option<LuaTable>>(key)? {
+        Some(list) => list
+            .sequence_values::<LuaTable>()
+            .map(|entry| read(entry?))
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub fn list_to_table<'lua, T, F>(
+    ctx: LuaContext<'lua>,
+    values: &[T],
+    convert: F,
+) -> Result<LuaTable<'lua>, LuaError>
+where
+    F: Fn(&T) -> Result<LuaTable<'lua>, LuaError>,
+{
+    let table = ctx.create_table()?;
+
+    for (i, value) in values.iter().enumerate() {
+        table.set(i + 1, convert(value)?)?;
+    }
+
+    Ok(table)
+}
@@ -0,0 +1,406 @@
+//! Generation of the minimap, `war3mapMap.blp` and `war3map.mmp`.
+//!
+//! The image is drawn from the terrain: every pixel takes the color of the
+//! nearest tile point's ground texture, shaded by the slope of the ground,
+//! with water and blight drawn over it. Only the playable area is shown,
+//! scaled to fit the image, the rest stays black like in the editor.
+//!
+//! Icons are derived from the placed units: start locations in the color
+//! of their player, gold mines, and neutral buildings which are marked to
+//! show on the minimap.
+
+use std::collections::HashMap;
+
+use ceres_formats::image::Image;
+use ceres_formats::mapinfo::MapInfo;
+use ceres_formats::minimap::{icons, MinimapIcon, MinimapIcons, MINIMAP_SIZE};
+use ceres_formats::object::Value;
+use ceres_formats::objectstore::ObjectStore;
+use ceres_formats::terrain::{flags, Terrain, TILE_SIZE};
+use ceres_formats::units::PlacedUnit;
+use ceres_formats::ObjectId;
+
+/// Colors of ground textures, overriding the guessed ones.
+pub(crate) type TileColors = HashMap<ObjectId, [u8; 3]>;
+
+/// Colors of ground textures by the last three letters of their ID,
+/// which mean the same thing in most tilesets, e.g. `Ldrt` and `Bdrt`.
+const TEXTURE_COLORS: &[(&[u8], [u8; 3])] = &[
+    (b"grs", [80, 115, 45]),
+    (b"grd", [55, 85, 35]),
+    (b"grt", [70, 110, 40]),
+    (b"grr", [110, 110, 60]),
+    (b"gsb", [80, 115, 45]),
+    (b"hdg", [70, 105, 40]),
+    (b"drt", [120, 95, 65]),
+    (b"dro", [105, 85, 60]),
+    (b"drd", [105, 85, 60]),
+    (b"drh", [105, 85, 60]),
+    (b"drr", [105, 85, 60]),
+    (b"dtr", [105, 85, 60]),
+    (b"drg", [100, 100, 55]),
+    (b"smb", [140, 115, 85]),
+    (b"fst", [110, 85, 60]),
+    (b"rok", [105, 100, 95]),
+    (b"rck", [105, 100, 95]),
+    (b"flr", [105, 100, 95]),
+    (b"dkr", [70, 65, 60]),
+    (b"lgb", [125, 120, 110]),
+    (b"fsl", [125, 120, 110]),
+    (b"snw", [225, 225, 235]),
+    (b"sng", [170, 185, 170]),
+    (b"snr", [185, 185, 195]),
+    (b"ice", [160, 190, 210]),
+    (b"dki", [90, 115, 140]),
+    (b"dsr", [190, 160, 110]),
+    (b"dsd", [150, 120, 80]),
+    (b"san", [190, 170, 120]),
+    (b"vin", [60, 80, 35]),
+    (b"lvd", [110, 80, 40]),
+    (b"lvg", [110, 80, 40]),
+    (b"pos", [90, 110, 40]),
+    (b"lav", [200, 80, 20]),
+    (b"lvc", [120, 60, 35]),
+    (b"crp", [140, 130, 60]),
+    (b"blm", [50, 50, 55]),
+    (b"wmb", [200, 200, 195]),
+    (b"rds", [130, 70, 55]),
+    (b"aby", [10, 10, 20]),
+];
+
+/// Paths, bricks and tiles, which have many different IDs.
+const PAVED_COLOR: [u8; 3] = [120, 115, 105];
+const PAVED_TEXTURES: &[&[u8]] = &[
+    b"cbp", b"stp", b"brk", b"btl", b"bks", b"bkl", b"bkb", b"rbk", b"tbk", b"sqd", b"sqt", b"rtl",
+    b"til", b"bsq", b"dkt",
+];
+
+const DEFAULT_COLOR: [u8; 3] = [110, 100, 80];
+
+const SHALLOW_WATER: [u8; 3] = [60, 100, 160];
+const DEEP_WATER: [u8; 3] = [20, 40, 100];
+/// Depth in world units at which water is drawn fully deep.
+const DEEP_WATER_DEPTH: f32 = 128.0;
+const BLIGHT_COLOR: [u8; 3] = [80, 70, 90];
+
+const NEUTRAL_ICON_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// The standard player colors, red to snow.
+const PLAYER_COLORS: [[u8; 3]; 24] = [
+    [255, 3, 3],
+    [0, 66, 255],
+    [28, 230, 185],
+    [84, 0, 129],
+    [255, 252, 0],
+    [254, 138, 14],
+    [32, 192, 0],
+    [229, 91, 176],
+    [149, 150, 151],
+    [126, 191, 241],
+    [16, 98, 70],
+    [78, 42, 4],
+    [155, 0, 0],
+    [0, 0, 195],
+    [0, 234, 255],
+    [190, 0, 254],
+    [235, 205, 135],
+    [248, 164, 139],
+    [191, 255, 128],
+    [220, 185, 235],
+    [80, 79, 85],
+    [235, 240, 255],
+    [0, 120, 30],
+    [164, 111, 51],
+];
+
+const START_LOCATION: &[u8] = b"sloc";
+const GOLD_MINE: &[u8] = b"ngol";
+const GOLD_MINE_ABILITY: &str = "Agld";
+const ABILITIES_FIELD: &[u8] = b"uabi";
+const NEUTRAL_ICON_FIELD: &[u8] = b"unbm";
+
+/// Guesses the color of a ground texture from its ID.
+fn texture_color(id: ObjectId) -> [u8; 3] {
+    let bytes = id.to_u32().to_be_bytes();
+    let suffix = bytes[1..].to_ascii_lowercase();
+
+    if PAVED_TEXTURES.contains(&suffix.as_slice()) {
+        return PAVED_COLOR;
+    }
+
+    TEXTURE_COLORS
+        .iter()
+        .find(|(name, _)| *name == suffix.as_slice())
+        .map(|(_, color)| *color)
+        .unwrap_or(DEFAULT_COLOR)
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], amount: f32) -> [u8; 3] {
+    let mut out = a;
+    for (out, b) in out.iter_mut().zip(&b) {
+        *out = (f32::from(*out) * (1.0 - amount) + f32::from(*b) * amount).round() as u8;
+    }
+
+    out
+}
+
+fn shade(color: [u8; 3], amount: f32) -> [u8; 3] {
+    let mut out = color;
+    for out in &mut out {
+        *out = (f32::from(*out) * amount).round().clamp(0.0, 255.0) as u8;
+    }
+
+    out
+}
+
+/// The part of the map shown on the minimap, and how it maps to pixels.
+pub(crate) struct MinimapView {
+    left:   f32,
+    bottom: f32,
+    right:  f32,
+    top:    f32,
+    /// World units per pixel.
+    scale:  f32,
+    /// Pixel offsets centering the playable area on the image.
+    margin: [f32; 2],
+}
+
+impl MinimapView {
+    /// The playable area of the map, given by the camera bounds complements
+    /// of the map info, or the whole terrain if there is no info.
+    pub(crate) fn new(terrain: &Terrain, info: Option<&MapInfo>) -> MinimapView {
+        let complements = info.map(|info| info.camera_complements).unwrap_or_default();
+        let complement = |index: usize| complements[index].max(0) as f32 * TILE_SIZE;
+        let tiles_x = terrain.width.saturating_sub(1) as f32 * TILE_SIZE;
+        let tiles_y = terrain.height.saturating_sub(1) as f32 * TILE_SIZE;
+
+        let left = terrain.offset_x + complement(0);
+        let right = (terrain.offset_x + tiles_x - complement(1)).max(left + TILE_SIZE);
+        let bottom = terrain.offset_y + complement(2);
+        let top = (terrain.offset_y + tiles_y - complement(3)).max(bottom + TILE_SIZE);
+
+        let size = MINIMAP_SIZE as f32;
+        let scale = (right - left).max(top - bottom) / size;
+        let margin = [
+            (size - (right - left) / scale) / 2.0,
+            (size - (top - bottom) / scale) / 2.0,
+        ];
+
+        MinimapView {
+            left,
+            bottom,
+            right,
+            top,
+            scale,
+            margin,
+        }
+    }
+
+    /// World position at the center of a pixel, if it is inside the playable area.
+    fn to_world(&self, x: u32, y: u32) -> Option<(f32, f32)> {
+        let world_x = self.left + (x as f32 + 0.5 - self.margin[0]) * self.scale;
+        let world_y = self.top - (y as f32 + 0.5 - self.margin[1]) * self.scale;
+
+        if world_x < self.left
+            || world_x > self.right
+            || world_y < self.bottom
+            || world_y > self.top
+        {
+            None
+        } else {
+            Some((world_x, world_y))
+        }
+    }
+
+    /// Pixel of a world position, if it is inside the playable area.
+    fn to_minimap(&self, x: f32, y: f32) -> Option<(u32, u32)> {
+        if x < self.left || x > self.right || y < self.bottom || y > self.top {
+            return None;
+        }
+
+        let max = (MINIMAP_SIZE - 1) as f32;
+        let pixel_x = ((x - self.left) / self.scale + self.margin[0]).clamp(0.0, max);
+        let pixel_y = ((self.top - y) / self.scale + self.margin[1]).clamp(0.0, max);
+
+        Some((pixel_x as u32, pixel_y as u32))
+    }
+}
+
+/// Ground height at a position on the grid, interpolated between tile points.
+fn height_at(terrain: &Terrain, x: f32, y: f32) -> f32 {
+    let max_x = terrain.width.saturating_sub(1) as f32;
+    let max_y = terrain.height.saturating_sub(1) as f32;
+    let x = x.clamp(0.0, max_x);
+    let y = y.clamp(0.0, max_y);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (x1, y1) = ((x0 + 1.0).min(max_x), (y0 + 1.0).min(max_y));
+    let (fx, fy) = (x - x0, y - y0);
+
+    let height = |x: f32, y: f32| {
+        terrain
+            .point(x as u32, y as u32)
+            .map(|point| point.world_height())
+            .unwrap_or(0.0)
+    };
+
+    let bottom = height(x0, y0) * (1.0 - fx) + height(x1, y0) * fx;
+    let top = height(x0, y1) * (1.0 - fx) + height(x1, y1) * fx;
+
+    bottom * (1.0 - fy) + top * fy
+}
+
+/// Draws the playable area of the terrain.
+pub(crate) fn render_minimap(terrain: &Terrain, view: &MinimapView, colors: &TileColors) -> Image {
+    let mut image = Image::new(MINIMAP_SIZE, MINIMAP_SIZE);
+    if terrain.width == 0 || terrain.height == 0 {
+        return image;
+    }
+
+    for y in 0..MINIMAP_SIZE {
+        for x in 0..MINIMAP_SIZE {
+            let (world_x, world_y) = match view.to_world(x, y) {
+                Some(position) => position,
+                None => {
+                    image.set_pixel(x, y, [0, 0, 0, 255]);
+                    continue;
+                }
+            };
+
+            let grid_x = (world_x - terrain.offset_x) / TILE_SIZE;
+            let grid_y = (world_y - terrain.offset_y) / TILE_SIZE;
+            let point = terrain.point(
+                grid_x.round().max(0.0) as u32,
+                grid_y.round().max(0.0) as u32,
+            );
+            let point = match point {
+                Some(point) => point,
+                None => {
+                    image.set_pixel(x, y, [0, 0, 0, 255]);
+                    continue;
+                }
+            };
+
+            let mut color = terrain
+                .ground_tilesets
+                .get(point.texture as usize)
+                .map(|id| {
+                    colors
+                        .get(id)
+                        .copied()
+                        .unwrap_or_else(|| texture_color(*id))
+                })
+                .unwrap_or(DEFAULT_COLOR);
+
+            if point.flags & flags::BLIGHT != 0 {
+                color = mix(color, BLIGHT_COLOR, 0.5);
+            }
+
+            // lit from the north-west, so slopes rising to the south-east are brighter
+            let height = height_at(terrain, grid_x, grid_y);
+            let rise = height_at(terrain, grid_x + 0.5, grid_y - 0.5)
+                - height_at(terrain, grid_x - 0.5, grid_y + 0.5);
+            let light = (1.0 + rise / 128.0 + height / 2048.0).clamp(0.5, 1.5);
+            color = shade(color, light);
+
+            if let Some(water) = point.water_height() {
+                if water > height {
+                    let depth = ((water - height) / DEEP_WATER_DEPTH).min(1.0);
+                    color = mix(SHALLOW_WATER, DEEP_WATER, depth);
+                }
+            }
+
+            if point.flags & flags::BOUNDARY != 0 {
+                color = shade(color, 0.5);
+            }
+
+            image.set_pixel(x, y, [color[0], color[1], color[2], 255]);
+        }
+    }
+
+    image
+}
+
+/// Looks up a simple field of a unit type in the map's object data,
+/// falling back to the stock data.
+fn unit_field(stores: &[&ObjectStore], id: ObjectId, field: ObjectId) -> Option<Value> {
+    let data = w3data::data();
+
+    for store in stores {
+        if let Some(object) = store.object(id) {
+            let object = object.borrow();
+            if let Some(value) = object.simple_field(field) {
+                return Some(value.clone());
+            }
+
+            return data
+                .object_prototype(&object)
+                .and_then(|prototype| prototype.simple_field(field))
+                .cloned();
+        }
+    }
+
+    data.object(id)
+        .and_then(|object| object.simple_field(field))
+        .cloned()
+}
+
+fn is_gold_mine(stores: &[&ObjectStore], id: ObjectId) -> bool {
+    if ObjectId::from_bytes(GOLD_MINE) == Some(id) {
+        return true;
+    }
+
+    match unit_field(stores, id, ObjectId::from_bytes(ABILITIES_FIELD).unwrap()) {
+        Some(Value::String(abilities)) => abilities
+            .split(',')
+            .any(|ability| ability.trim() == GOLD_MINE_ABILITY),
+        _ => false,
+    }
+}
+
+fn shows_neutral_icon(stores: &[&ObjectStore], id: ObjectId) -> bool {
+    match unit_field(
+        stores,
+        id,
+        ObjectId::from_bytes(NEUTRAL_ICON_FIELD).unwrap(),
+    ) {
+        Some(Value::Int(value)) => value != 0,
+        _ => false,
+    }
+}
+
+/// Derives the minimap icons from the placed units. Neutral buildings only
+/// get an icon if they are not owned by one of the players of the map.
+pub(crate) fn minimap_icons(
+    units: &[PlacedUnit],
+    view: &MinimapView,
+    info: Option<&MapInfo>,
+    stores: &[&ObjectStore],
+) -> MinimapIcons {
+    let is_player = |owner: u32| {
+        info.map(|info| info.players.iter().any(|player| player.id == owner))
+            .unwrap_or(false)
+    };
+
+    let icons = units
+        .iter()
+        .filter_map(|unit| {
+            let (x, y) = view.to_minimap(unit.x, unit.y)?;
+
+            let (kind, color) = if ObjectId::from_bytes(START_LOCATION) == Some(unit.id) {
+                let color = PLAYER_COLORS[unit.owner as usize % PLAYER_COLORS.len()];
+
+                (icons::START_LOCATION, [color[0], color[1], color[2], 255])
+            } else if is_gold_mine(stores, unit.id) {
+                (icons::GOLD_MINE, NEUTRAL_ICON_COLOR)
+            } else if !is_player(unit.owner) && shows_neutral_icon(stores, unit.id) {
+                (icons::NEUTRAL_BUILDING, NEUTRAL_ICON_COLOR)
+            } else {
+                return None;
+            };
+
+            Some(MinimapIcon { kind, x, y, color })
+        })
+        .collect();
+
+    MinimapIcons { version: 0, icons }
+}
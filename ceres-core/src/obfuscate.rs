@@ -0,0 +1,467 @@
+//! Makes compiled Lua harder to read, without changing what it does.
+//!
+//! Local variables, parameters and local functions are renamed, and quoted
+//! or long strings are turned into decimal escapes. Globals and table fields
+//! keep their names, since the game and other scripts look them up by name.
+
+use std::collections::{HashMap, HashSet};
+
+use pest::iterators::Pair;
+use pest::Parser;
+
+use ceres_parsers::lua::{LuaParser, Rule};
+
+struct Obfuscator<'src> {
+    scopes:   Vec<HashMap<&'src str, String>>,
+    edits:    Vec<(usize, usize, String)>,
+    reserved: HashSet<&'src str>,
+    counter:  usize,
+}
+
+/// Obfuscates a Lua chunk. Fails if the chunk does not parse.
+pub(crate) fn obfuscate(src: &str) -> Result<String, pest::error::Error<Rule>> {
+    let chunk = LuaParser::parse(Rule::Chunk, src)?.next().unwrap();
+
+    let mut obfuscator = Obfuscator {
+        scopes:   Vec::new(),
+        edits:    Vec::new(),
+        // generated names must not capture any name used by the chunk
+        reserved: chunk
+            .clone()
+            .into_inner()
+            .flatten()
+            .filter(|pair| pair.as_rule() == Rule::Ident)
+            .map(|pair| pair.as_str())
+            .collect(),
+        counter:  0,
+    };
+
+    obfuscator.walk(chunk);
+    obfuscator.edits.sort_by_key(|(start, ..)| *start);
+
+    let mut out = String::with_capacity(src.len());
+    let mut emitted_index = 0;
+
+    for (start, end, replacement) in obfuscator.edits {
+        out += &src[emitted_index..start];
+        out += &replacement;
+        emitted_index = end;
+    }
+
+    out += &src[emitted_index..];
+
+    Ok(out)
+}
+
+impl<'src> Obfuscator<'src> {
+    fn walk(&mut self, pair: Pair<'src, Rule>) {
+        match pair.as_rule() {
+            Rule::Block => {
+                self.scopes.push(HashMap::new());
+                self.walk_children(pair);
+                self.scopes.pop();
+            }
+            Rule::StmtLocalDef => {
+                // `local x = x` refers to the outer `x` on the right-hand side
+                let mut inner = pair.into_inner();
+                let names = inner.next().unwrap();
+                inner.for_each(|pair| self.walk(pair));
+                names.into_inner().for_each(|name| self.declare(name));
+            }
+            Rule::StmtLocalFuncDef => {
+                // the function can call itself, so its name is declared first
+                let mut inner = pair.into_inner();
+                self.declare(inner.next().unwrap());
+                inner.for_each(|pair| self.walk(pair));
+            }
+            Rule::StmtForIndex | Rule::StmtForEach => {
+                let mut names = Vec::new();
+                let mut block = None;
+
+                for pair in pair.into_inner() {
+                    match pair.as_rule() {
+                        Rule::Ident => names.push(pair),
+                        Rule::IdentList => names.extend(pair.into_inner()),
+                        Rule::Block => block = Some(pair),
+                        _ => self.walk(pair),
+                    }
+                }
+
+                self.scopes.push(HashMap::new());
+                names.into_iter().for_each(|name| self.declare(name));
+                block.into_iter().for_each(|block| self.walk(block));
+                self.scopes.pop();
+            }
+            Rule::FuncBody => {
+                self.scopes.push(HashMap::new());
+
+                for pair in pair.into_inner() {
+                    if pair.as_rule() == Rule::ParList {
+                        pair.into_inner()
+                            .flat_map(|list| list.into_inner())
+                            .for_each(|name| self.declare(name));
+                    } else {
+                        self.walk(pair);
+                    }
+                }
+
+                self.scopes.pop();
+            }
+            Rule::StmtRepeat => {
+                // the condition can see the locals of the loop body
+                let mut inner = pair.into_inner();
+                let block = inner.next().unwrap();
+
+                self.scopes.push(HashMap::new());
+                self.walk_children(block);
+                inner.for_each(|pair| self.walk(pair));
+                self.scopes.pop();
+            }
+            Rule::AtomicExp | Rule::FuncName => {
+                let mut inner = pair.into_inner();
+
+                if let Some(first) = inner.next() {
+                    if first.as_rule() == Rule::Ident {
+                        self.resolve(first);
+                    } else {
+                        self.walk(first);
+                    }
+                }
+
+                // the rest of a function name are field names
+                inner
+                    .filter(|pair| pair.as_rule() != Rule::Ident)
+                    .for_each(|pair| self.walk(pair));
+            }
+            Rule::Index | Rule::MethodCall | Rule::Field => {
+                pair.into_inner()
+                    .filter(|pair| pair.as_rule() != Rule::Ident)
+                    .for_each(|pair| self.walk(pair));
+            }
+            Rule::StmtLabel | Rule::StmtGoto => {}
+            Rule::LiteralString => {
+                if let Some(contents) = string_value(pair.as_str()) {
+                    let span = pair.as_span();
+                    self.edits
+                        .push((span.start(), span.end(), encode_string(&contents)));
+                }
+            }
+            _ => self.walk_children(pair),
+        }
+    }
+
+    fn walk_children(&mut self, pair: Pair<'src, Rule>) {
+        pair.into_inner().for_each(|pair| self.walk(pair));
+    }
+
+    fn declare(&mut self, ident: Pair<'src, Rule>) {
+        let name = self.next_name();
+        let span = ident.as_span();
+
+        self.edits.push((span.start(), span.end(), name.clone()));
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(ident.as_str(), name);
+        }
+    }
+
+    fn resolve(&mut self, ident: Pair<'src, Rule>) {
+        let name = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(ident.as_str()));
+
+        // anything that is not a local is a global, and keeps its name
+        if let Some(name) = name {
+            let span = ident.as_span();
+            self.edits.push((span.start(), span.end(), name.clone()));
+        }
+    }
+
+    /// Every declaration gets a name of its own, so that
+    /// renaming can never change which variable a name refers to.
+    fn next_name(&mut self) -> String {
+        const ALPHABET: &[u8] = b"lI1";
+
+        loop {
+            let mut n = self.counter;
+            let mut name = String::from("I");
+            self.counter += 1;
+
+            loop {
+                name.push(ALPHABET[n % ALPHABET.len()] as char);
+                n /= ALPHABET.len();
+
+                if n == 0 {
+                    break;
+                }
+            }
+
+            if !self.reserved.contains(name.as_str()) {
+                return name;
+            }
+        }
+    }
+}
+
+fn encode_string(contents: &[u8]) -> String {
+    let mut out = String::with_capacity(contents.len() * 4 + 2);
+
+    out.push('"');
+    for byte in contents {
+        out += &format!("\\{}", byte);
+    }
+    out.push('"');
+
+    out
+}
+
+/// The value of a string literal. Returns `None` for escapes this does not
+/// understand, in which case the literal is left as it is.
+fn string_value(literal: &str) -> Option<Vec<u8>> {
+    let bytes = literal.as_bytes();
+
+    if bytes[0] == b'[' {
+        let level = bytes[1..].iter().take_while(|b| **b == b'=').count();
+        let mut contents = &bytes[level + 2..bytes.len() - level - 2];
+
+        // a newline right after the opening bracket is skipped
+        if contents.starts_with(b"\r\n") || contents.starts_with(b"\n\r") {
+            contents = &contents[2..];
+        } else if contents.starts_with(b"\n") || contents.starts_with(b"\r") {
+            contents = &contents[1..];
+        }
+
+        return Some(normalize_newlines(contents));
+    }
+
+    let contents = &bytes[1..bytes.len() - 1];
+    let mut out = Vec::with_capacity(contents.len());
+    let mut i = 0;
+
+    while i < contents.len() {
+        if contents[i] != b'\\' {
+            out.push(contents[i]);
+            i += 1;
+            continue;
+        }
+
+        let escape = *contents.get(i + 1)?;
+        i += 2;
+
+        match escape {
+            b'a' => out.push(0x07),
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0C),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'v' => out.push(0x0B),
+            b'\\' | b'"' | b'\'' => out.push(escape),
+            b'\n' | b'\r' => {
+                out.push(b'\n');
+
+                let next = contents.get(i).copied();
+                if (escape == b'\n' && next == Some(b'\r'))
+                    || (escape == b'\r' && next == Some(b'\n'))
+                {
+                    i += 1;
+                }
+            }
+            b'z' => {
+                while matches!(contents.get(i), Some(b) if b.is_ascii_whitespace()) {
+                    i += 1;
+                }
+            }
+            b'x' => {
+                let digits = std::str::from_utf8(contents.get(i..i + 2)?).ok()?;
+                out.push(u8::from_str_radix(digits, 16).ok()?);
+                i += 2;
+            }
+            b'0'..=b'9' => {
+                let start = i - 1;
+                while i < contents.len() && i - start < 3 && contents[i].is_ascii_digit() {
+                    i += 1;
+                }
+
+                let digits = std::str::from_utf8(&contents[start..i]).ok()?;
+                let value: u16 = digits.parse().ok()?;
+                if value > 255 {
+                    return None;
+                }
+
+                out.push(value as u8);
+            }
+            b'u' => {
+                if contents.get(i) != Some(&b'{') {
+                    return None;
+                }
+
+                let end = i + contents[i..].iter().position(|b| *b == b'}')?;
+                let digits = std::str::from_utf8(&contents[i + 1..end]).ok()?;
+                let code_point = std::char::from_u32(u32::from_str_radix(digits, 16).ok()?)?;
+                let mut buf = [0; 4];
+
+                out.extend_from_slice(code_point.encode_utf8(&mut buf).as_bytes());
+                i = end + 1;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(out)
+}
+
+/// Lua reads every kind of line break in a long string as `\n`.
+fn normalize_newlines(contents: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(contents.len());
+    let mut i = 0;
+
+    while i < contents.len() {
+        match (contents[i], contents.get(i + 1)) {
+            (b'\r', Some(b'\n')) | (b'\n', Some(b'\r')) => {
+                out.push(b'\n');
+                i += 2;
+            }
+            (b'\r', _) => {
+                out.push(b'\n');
+                i += 1;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use rlua::Lua;
+
+    use super::*;
+
+    fn eval(src: &str) -> String {
+        Lua::new().context(|ctx| ctx.load(src).eval::<String>().unwrap())
+    }
+
+    /// Obfuscates a chunk, and checks that it still returns the same string.
+    fn obfuscate_same(src: &str) -> String {
+        let obfuscated = obfuscate(src).unwrap();
+        assert_eq!(eval(src), eval(&obfuscated), "{}", obfuscated);
+
+        obfuscated
+    }
+
+    #[test]
+    fn shadowing() {
+        let obfuscated = obfuscate_same(
+            r#"
+            local x = 1
+            local r = {}
+            do
+                local x = 2
+                r[#r + 1] = x
+            end
+            for x = 3, 4 do r[#r + 1] = x end
+            local function f(x) return x * 10 end
+            r[#r + 1] = f(5)
+            r[#r + 1] = x
+            return table.concat(r, ",")
+            "#,
+        );
+
+        assert!(!obfuscated.contains("local x"));
+        assert!(obfuscated.contains("table.concat"));
+    }
+
+    #[test]
+    fn local_initialized_from_outer_name() {
+        let obfuscated = obfuscate_same(
+            r#"
+            x = "global"
+            local x = x .. "!"
+            do
+                local x = x .. "?"
+                return x
+            end
+            "#,
+        );
+
+        assert!(obfuscated.contains("x = "));
+        assert!(!obfuscated.contains("local x"));
+    }
+
+    #[test]
+    fn repeat_condition_sees_body_locals() {
+        obfuscate_same(
+            r#"
+            local i = 0
+            repeat
+                local done = i >= 3
+                i = i + 1
+            until done
+            return tostring(i)
+            "#,
+        );
+    }
+
+    #[test]
+    fn methods_and_fields_keep_their_names() {
+        let obfuscated = obfuscate_same(
+            r#"
+            local name = "local"
+            local t = { name = "field", ["key"] = 1 }
+            function t:get() return self.name end
+            function t.static(name) return name end
+            return t:get() .. t.name .. t.static(name) .. t["key"]
+            "#,
+        );
+
+        assert!(obfuscated.contains("{ name = "));
+        assert!(obfuscated.contains(":get()"));
+        assert!(obfuscated.contains("self.name"));
+        assert!(obfuscated.contains(".static("));
+        assert!(!obfuscated.contains("local name"));
+    }
+
+    #[test]
+    fn labels_and_goto() {
+        let obfuscated = obfuscate_same(
+            r#"
+            local n = 0
+            ::top::
+            n = n + 1
+            if n < 3 then goto top end
+            for i = 1, 3 do
+                if i == 2 then goto continue end
+                n = n + i
+                ::continue::
+            end
+            return tostring(n)
+            "#,
+        );
+
+        assert!(obfuscated.contains("::top::"));
+        assert!(obfuscated.contains("goto continue"));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let obfuscated = obfuscate_same(
+            "return \"a\\tb\\65\\x41\\u{48}\\z   c\\\n\" .. 'q\\'' .. [[\nlong\r\nstring]] \
+             .. [==[]]]==]",
+        );
+
+        assert!(!obfuscated.contains("long"));
+        assert!(obfuscated.starts_with("return \"\\97\\9\\98\\65\\65\\72\\99\\10\""));
+    }
+
+    #[test]
+    fn unparseable_chunks_fail() {
+        assert!(obfuscate("local = 1").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Checks whether building a project twice produces identical artifacts.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use mpq::Archive;
+use rlua::prelude::*;
+use walkdir::WalkDir;
+
+use crate::error::{ContextError, IoError};
+
+/// Restores the working directory it was created in when dropped, since
+/// both builds run with their project directory as the working directory.
+pub(crate) struct WorkingDir {
+    previous: PathBuf,
+}
+
+impl WorkingDir {
+    pub(crate) fn remember() -> Result<WorkingDir, anyhow::Error> {
+        let previous = std::env::current_dir()
+            .map_err(|cause| ContextError::new("could not read the working directory", cause))?;
+
+        Ok(WorkingDir { previous })
+    }
+}
+
+impl Drop for WorkingDir {
+    fn drop(&mut self) {
+        std::env::set_current_dir(&self.previous).ok();
+    }
+}
+
+/// A temporary directory, removed with everything in it when dropped.
+pub(crate) struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub(crate) fn new(path: PathBuf) -> ScratchDir {
+        ScratchDir { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+/// Reads `ceres.layout.targetDirectory` after the build script has run,
+/// since build scripts are free to change it.
+pub(crate) fn target_directory(ctx: LuaContext) -> Result<PathBuf, LuaError> {
+    let ceres: LuaTable = ctx.globals().get("ceres")?;
+    let layout: LuaTable = ceres.get("layout")?;
+    let directory: String = layout.get("targetDirectory")?;
+
+    Ok(directory.into())
+}
+
+/// Files inside a directory, relative to it and sorted.
+fn list_files(dir: &Path) -> BTreeSet<PathBuf> {
+    WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|s| s.ok())
+        .filter(|s| s.file_type().is_file())
+        .map(|s| s.path().strip_prefix(dir).unwrap().to_path_buf())
+        .collect()
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+    Ok(fs::read(path).map_err(|cause| IoError::new(path, cause))?)
+}
+
+/// Copies files from one directory into a fresh one, keeping their relative paths.
+fn copy_files<I>(from: &Path, to: &Path, files: I) -> Result<(), anyhow::Error>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    if to.exists() {
+        fs::remove_dir_all(to)
+            .map_err(|cause| ContextError::new("could not clear the temporary folder", cause))?;
+    }
+
+    for file in files {
+        let file_to = to.join(&file);
+
+        fs::create_dir_all(file_to.parent().unwrap())
+            .map_err(|cause| IoError::new(file_to.parent().unwrap(), cause))?;
+        fs::copy(from.join(&file), &file_to).map_err(|cause| IoError::new(&file_to, cause))?;
+    }
+
+    Ok(())
+}
+
+/// Copies the artifacts of a build, so that they survive the next build.
+pub(crate) fn snapshot(target_dir: &Path, snapshot_dir: &Path) -> Result<(), anyhow::Error> {
+    copy_files(target_dir, snapshot_dir, list_files(target_dir))
+}
+
+/// Copies a project for a second build, leaving out the artifacts of the first one.
+pub(crate) fn copy_project(
+    project_dir: &Path,
+    copy_dir: &Path,
+    target_dir: &Path,
+) -> Result<(), anyhow::Error> {
+    let target_dir = target_dir.canonicalize().ok();
+    let files = WalkDir::new(project_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|s| {
+            !s.file_type().is_dir() || s.path().canonicalize().ok() != target_dir
+        })
+        .filter_map(|s| s.ok())
+        .filter(|s| s.file_type().is_file())
+        .map(|s| s.path().strip_prefix(project_dir).unwrap().to_path_buf());
+
+    copy_files(project_dir, copy_dir, files)
+}
+
+/// Describes how two versions of an artifact differ. Archives are compared
+/// file by file, everything else byte by byte.
+fn describe_difference(name: &str, first: &[u8], second: &[u8]) -> Vec<String> {
+    let archives = (
+        Archive::open(Cursor::new(first)),
+        Archive::open(Cursor::new(second)),
+    );
+
+    if let (Ok(mut first), Ok(mut second)) = archives {
+        let first_files: BTreeSet<String> = first.files().unwrap_or_default().into_iter().collect();
+        let second_files: BTreeSet<String> =
+            second.files().unwrap_or_default().into_iter().collect();
+        let mut differences = Vec::new();
+
+        for file in first_files.symmetric_difference(&second_files) {
+            differences.push(format!("{}: {} is only in one of the builds", name, file));
+        }
+
+        for file in first_files.intersection(&second_files) {
+            if first.read_file(file).ok() != second.read_file(file).ok() {
+                differences.push(format!("{}: {} differs", name, file));
+            }
+        }
+
+        if differences.is_empty() {
+            differences.push(format!(
+                "{}: all files are identical, but the archives are not \
+                 (header, compression or timestamp attributes differ)",
+                name
+            ));
+        }
+
+        return differences;
+    }
+
+    let offset = first
+        .iter()
+        .zip(second)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| first.len().min(second.len()));
+
+    vec![format!(
+        "{}: {} and {} bytes, first difference at offset {}",
+        name,
+        first.len(),
+        second.len(),
+        offset
+    )]
+}
+
+/// Compares the artifacts of two builds, returning a description
+/// of every difference.
+pub(crate) fn compare_dirs(first: &Path, second: &Path) -> Result<Vec<String>, anyhow::Error> {
+    let first_files = list_files(first);
+    let second_files = list_files(second);
+    let mut differences = Vec::new();
+
+    for file in first_files.symmetric_difference(&second_files) {
+        differences.push(format!(
+            "{}: only produced by one of the builds",
+            file.display()
+        ));
+    }
+
+    for file in first_files.intersection(&second_files) {
+        let first = read(&first.join(file))?;
+        let second = read(&second.join(file))?;
+
+        if first != second {
+            let name = file.display().to_string();
+            differences.extend(describe_difference(&name, &first, &second));
+        }
+    }
+
+    Ok(differences)
+}
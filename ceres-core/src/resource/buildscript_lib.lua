@@ -39,6 +39,67 @@ end)
 function macro_define() end
 function compiletime() end
 
+-- localization
+
+-- TRANS("...") expands to the translation of a string for the locale being built,
+-- and remembers it for ceres.extractStrings().
+local translations = {}
+local markedStrings = {}
+
+ceres.registerMacro("TRANS", function(source)
+    markedStrings[source] = true
+
+    local translation = translations[source]
+    if translation == nil or translation == "" then
+        translation = source
+    end
+
+    return string.format("%q", translation)
+end)
+
+function TRANS(source)
+    return source
+end
+
+-- Reads the catalog of `locale` from `directory`, trying <locale>.po and then
+-- <locale>.json. Returns the catalog, or nil and an error message.
+function ceres.loadCatalog(directory, locale)
+    local catalog, errorMsg = ceres.readCatalog(directory .. locale .. ".po")
+    if not catalog then
+        catalog = ceres.readCatalog(directory .. locale .. ".json")
+    end
+
+    if not catalog then
+        return nil, errorMsg
+    end
+
+    return catalog
+end
+
+-- Writes the strings marked with TRANS() and those of `map` (if not nil)
+-- to the .po or .json catalog at `path`, keeping existing translations.
+function ceres.extractStrings(path, map)
+    local existing = ceres.readCatalog(path) or {}
+    local catalog = {}
+
+    for source in pairs(markedStrings) do
+        catalog[source] = existing[source] or ""
+    end
+
+    if map then
+        local strings, errorMsg = map:extractStrings()
+        if not strings then
+            return false, errorMsg
+        end
+
+        for _, source in ipairs(strings) do
+            catalog[source] = existing[source] or ""
+        end
+    end
+
+    return ceres.writeCatalog(path, catalog)
+end
+
 -- hooks
 
 local preScriptBuildHooks = {}
@@ -83,198 +144,101 @@ end
 
 -- map library
 
-local mapMeta = {}
-mapMeta.__index = mapMeta
-
--- Reads a file from the map and returns its contents as a string if successful
-function mapMeta:readFile(path)
-    local added = self.added[path]
-
-    if added then
-        if added.kind == "string" then
-            return added.contents
-        elseif added.kind == "file" then
-            return fs.readFile(added.path)
-        end
-    end
-
-    if self.kind == "mpq" then
-        return self.archive:readFile(path)
-    elseif self.kind == "dir" then
-        return fs.readFile(self.path .. path)
-    end
-end
-
-local addDirRecursive
-local function addDirRecursive(self, path, basePath)
-    local files, dirs = fs.readDir(path)
-
-    for _, file in pairs(files) do
-        local relativePath = file:sub(#basePath + 2)
-        self:addFileDisk(relativePath, file)
-    end
+-- Maps are opened via ceres.openMap(path) and implemented natively (see lua/map.rs),
+-- their API is described in docs/build-scripts.md.
 
-    for _, dir in pairs(dirs) do
-        addDirRecursive(self, dir, basePath)
-    end
-end
+-- Switches a JASS map to Lua if `script` (the compiled war3map.lua) provides main()
+-- and config(). Returns true, false if it is a Lua map already, or nil and an error.
+function ceres.convertToLua(map, script, options)
+    options = options or {}
 
-function mapMeta:addDir(path)
-    addDirRecursive(self, path, fs.absolutize(path))
-end
-
--- Adds a file to the map, as a lua string
--- This doesn't modify the map in any way, it only adds the file to be written when either
--- map:writeToDir() or map:writeToMpq() is called
-function mapMeta:addFileString(path, contents)
-    self.added[path] = {
-        kind = "string",
-        contents = contents
-    }
-end
-
--- Adds a file to the map, reading the contents from another file on the disk
--- This doesn't modify the map in any way, it only adds the file to be written when either
--- map:writeToDir() or map:writeToMpq() is called
-function mapMeta:addFileDisk(archivePath, filePath)
-    self.added[archivePath] = {
-        kind = "file",
-        path = filePath
-    }
-end
-
--- Writes the map to a directory
--- Any files added to the map via map:addFileString() or map:addFileDisk() will be
--- written at this stage
-function mapMeta:writeToDir(path)
-    if self.kind == "dir" then
-        fs.copyDir(self.path, path)
-    elseif self.kind == "mpq" then
-        self.archive:extractTo(path)
+    local info = map.info
+    if info == nil or info.scriptLanguage == "lua" then
+        return false
     end
 
-    for _, v in ipairs(self.addedDirs) do
-        fs.copyDir(v, path)
-    end
+    for _, name in ipairs({ "main", "config" }) do
+        local defined = script and (script:find("ceres.modules[\"" .. name .. "\"] =", 1, true)
+            or script:find("function%s+" .. name .. "%s*%(")
+            or script:find("%f[%w_]" .. name .. "%s*=%s*function%s*%("))
 
-    for k, v in pairs(self.added) do
-        if v.kind == "string" then
-            fs.writeFile(path .. k:gsub("%\\", "/"), v.contents)
-        elseif v.kind == "file" then
-            fs.copyFile(v.path, path .. k:gsub("%\\", "/"))
+        if not defined then
+            return nil, "neither a module nor the map script defines " .. name .. "(), "
+                .. "which the game calls in place of the JASS one"
         end
     end
-end
-
--- Writes the map to an mpq archive
--- Any files added to the map via map:addFileString() or map:addFileDisk() will be
--- written at this stage
-function mapMeta:writeToMpq(path)
-    local creator = mpq.create()
 
-    if self.kind == "dir" then
-        local success, errorMsg = creator:addFromDir(self.path)
-        if not success then
-            log("Couldn't add directory " .. self.path .. " to archive: " .. errorMsg)
-        end
-    elseif self.kind == "mpq" then
-        local success, errorMsg = creator:addFromMpq(self.archive)
-        if not success then
-            log("Couldn't add files from another archive: " .. errorMsg)
-        end
+    info.scriptLanguage = "lua"
+    if info.version < 28 then
+        log("Upgrading the map info from version " .. info.version .. " to 28, "
+            .. "the first version with a script language")
+        info.version = 28
     end
 
-    for _, v in ipairs(self.addedDirs) do
-        creator:addFromDir(v)
-    end
+    map:removeFile("war3map.j")
+    map:removeFile("scripts/war3map.j")
 
-    for k, v in pairs(self.added) do
-        if v.kind == "string" then
-            creator:add(k, v.contents)
-        elseif v.kind == "file" then
-            local success, errorMsg = creator:addFromFile(k, v.path)
-            if not success then
-                log("Couldn't add file " .. k .. " to archive: " .. errorMsg)
-            end
-        end
+    if options.removeTriggers then
+        map:removeFile("war3map.wtg")
+        map:removeFile("war3map.wct")
     end
 
-    return creator:write(path)
+    return true
 end
 
-local objectExtensions = {
-    "w3a", "w3t", "w3u", "w3b", "w3d", "w3h", "w3q"
-}
-
-function mapMeta:initObjectStorage(ext)
-    local result = self:readFile("war3map." .. ext)
-    local storage = objdata.newStore(ext)
-
-    if result then
-        storage:readFromString(result)
-    end
+-- images
 
-    return storage
-end
+-- image.load(path) and image.fromString(data) are implemented natively,
+-- see docs/build-scripts.md.
 
--- Initializes object storages for the map
-function mapMeta:initObjects()
-    local objects = {}
-    self.objects = objects
+-- Adds the image at `path` as the icon BTN<name>.blp (PASBTN<name>.blp with
+-- `options.passive`) and its disabled variant. Returns the path of the icon.
+function ceres.addIcon(map, path, name, options)
+    options = options or {}
 
-    for _, v in pairs(objectExtensions) do
-        local data = self:initObjectStorage(v)
-        objects[data.typestr] = data
+    local icon, errorMsg = image.load(path)
+    if not icon then
+        return nil, errorMsg
     end
-end
 
-function mapMeta:commitObjectStorage(storage)
-    if storage.isDirty then
-        local data = storage:writeToString()
-        self:addFileString("war3map." .. storage.ext, data)
+    if icon.width ~= 64 or icon.height ~= 64 then
+        icon = icon:resize(64, 64)
     end
-end
 
-function mapMeta:commitObjects()
-    for _, v in pairs(self.objects) do
-        self:commitObjectStorage(v)
+    local iconPath, disabledPath
+    if options.passive then
+        icon = icon:passive()
+        iconPath = "ReplaceableTextures\\PassiveButtons\\PASBTN" .. name .. ".blp"
+        disabledPath = "ReplaceableTextures\\CommandButtonsDisabled\\DISPASBTN" .. name .. ".blp"
+    else
+        iconPath = "ReplaceableTextures\\CommandButtons\\BTN" .. name .. ".blp"
+        disabledPath = "ReplaceableTextures\\CommandButtonsDisabled\\DISBTN" .. name .. ".blp"
     end
-end
 
-function ceres.openMap(name)
-    local map = {
-        added = {},
-        addedDirs = {}
-    }
-    local mapPath = name
+    map:addFileString(iconPath, icon:toBlp(options))
+    map:addFileString(disabledPath, icon:disabled():toBlp(options))
 
-    if not fs.exists(mapPath) then
-        return false, "map does not exist"
-    end
+    return iconPath
+end
 
-    if fs.isDir(mapPath) then
-        map.kind = "dir"
+-- models
 
-        map.path = mapPath .. "/"
-    elseif fs.isFile(mapPath) then
-        map.kind = "mpq"
+-- model.load(path) and model.fromString(data) are implemented natively,
+-- see docs/build-scripts.md.
 
-        local archive, errorMsg = mpq.open(mapPath)
+-- Maps constant names to the sequence names of `model`, e.g. ATTACK_2 to "Attack - 2",
+-- for use with SetUnitAnimation at compiletime.
+function ceres.animationNames(model)
+    local names = {}
 
-        if not archive then
-            return false, errorMsg
+    for _, sequence in ipairs(model.sequences) do
+        local name = sequence.name:upper():gsub("[^%w]+", "_"):gsub("^_", ""):gsub("_$", "")
+        if name ~= "" and names[name] == nil then
+            names[name] = sequence.name
         end
-
-        map.archive = archive
-    else
-        return false, "map path is not a file or directory"
     end
 
-    setmetatable(map, mapMeta)
-
-    map:initObjects()
-
-    return map
+    return names
 end
 
 -- default build functionality
@@ -284,13 +248,21 @@ end
 ceres.layout = {
     mapsDirectory = "maps/",
     srcDirectories = {"src/", "lib/"},
+    -- directories searched by `require` inside build.lua,
+    -- relative to the project directory
+    buildDirectories = {"build/", "lib/"},
     targetDirectory = "target/"
 }
 
 -- This is the default map build procedure
 -- Takes a single "build command" specifying
 -- what and how to build.
+-- The fields of build commands are described in docs/build-scripts.md.
 function ceres.buildMap(buildCommand)
+    if buildCommand.locales and not buildCommand.locale then
+        return ceres.buildLocalizedMaps(buildCommand)
+    end
+
     _G.lastBuildCommand = buildCommand
 
     local map, mapScript
@@ -311,6 +283,12 @@ function ceres.buildMap(buildCommand)
     log("    Input: " .. tostring(mapName))
     log("    Retain map script: " .. tostring(buildCommand.retainMapScript))
     log("    Output type: " .. buildCommand.output)
+    if buildCommand.exclude then
+        log("    Excluded files: " .. table.concat(buildCommand.exclude, ", "))
+    end
+    if buildCommand.protect then
+        log("    Protected: true")
+    end
 
     if mapName ~= nil then
         local loadedMap, errorMsg = ceres.openMap(ceres.layout.mapsDirectory .. mapName)
@@ -344,11 +322,29 @@ function ceres.buildMap(buildCommand)
 
     _G.currentMap = map
 
+    local locale = buildCommand.locale
+    translations = {}
+    markedStrings = {}
+    if locale then
+        local catalogDirectory = buildCommand.catalogDirectory or "locales/"
+        local catalog, errorMsg = ceres.loadCatalog(catalogDirectory, locale)
+        if catalog then
+            translations = catalog
+        else
+            log("WARN: No catalog for locale " .. locale .. ", it is left untranslated")
+            log(errorMsg)
+        end
+    end
+
     mapScript = callHooks(preScriptBuildHooks, map, mapScript) or mapScript
 
+    local protect = buildCommand.protect
+    local obfuscate = type(protect) == "table" and protect.obfuscate or false
+
     local script, errorMsg = ceres.compileScript {
         srcDirectories = ceres.layout.srcDirectories,
-        mapScript = mapScript or ""
+        mapScript = mapScript or "",
+        obfuscate = obfuscate
     }
 
     if errorMsg ~= nil then
@@ -359,9 +355,85 @@ function ceres.buildMap(buildCommand)
 
     script = callHooks(postScriptBuildHooks, map, script) or script
 
+    local catalogPath = buildCommand.extractStrings
+    if catalogPath then
+        local _, errorMsg = ceres.extractStrings(catalogPath, map)
+        if errorMsg then
+            log("WARN: Could not write the strings to " .. catalogPath .. ": " .. errorMsg)
+        else
+            log("Wrote the strings to " .. catalogPath)
+        end
+    end
+
+    if map ~= nil and locale then
+        local translated = map:translate(translations)
+        log("Translated " .. tostring(translated) .. " strings of the map to " .. locale)
+    end
+
     if map ~= nil then
         map:addFileString("war3map.lua", script)
-        map:commitObjects()
+        map:commitObjects { moveLongStrings = buildCommand.moveLongStrings }
+        map:commitUnits()
+        map:commitDoodads()
+        map:commitTerrain()
+        map:commitRegions()
+        map:commitCameras()
+        map:commitSounds()
+
+        local convertToLua = buildCommand.convertToLua
+        if convertToLua then
+            local options = type(convertToLua) == "table" and convertToLua or {}
+            local converted, errorMsg = ceres.convertToLua(map, script, options)
+            if errorMsg then
+                log("ERR: Could not switch the map's script language to Lua: " .. errorMsg)
+                return false
+            elseif converted then
+                log("Switched the map's script language from JASS to Lua")
+            end
+        elseif map.info and map.info.scriptLanguage ~= "lua" then
+            log("WARN: The map is saved in JASS mode, the game will not run war3map.lua")
+        end
+
+        local _, infoError = map:commitInfo()
+        if infoError then
+            log("ERR: Writing the map info failed: " .. infoError)
+            return false
+        end
+
+        map:commitStrings()
+
+        if buildCommand.updateMinimap then
+            local options = type(buildCommand.updateMinimap) == "table"
+                and buildCommand.updateMinimap or {}
+            local updated, errorMsg = map:updateMinimap(options)
+            if errorMsg then
+                log("WARN: Could not update the minimap: " .. errorMsg)
+            elseif not updated then
+                log("WARN: Could not update the minimap, the map has no terrain")
+            end
+        end
+
+        if buildCommand.pruneImports then
+            local _, removed = map:syncImports { prune = true }
+            if type(removed) == "number" and removed > 0 then
+                log("Removed " .. removed .. " missing files from the import list")
+            elseif type(removed) == "string" then
+                log("WARN: Could not update the import list: " .. removed)
+            end
+        end
+
+        if buildCommand.exclude then
+            map:exclude(buildCommand.exclude)
+        end
+
+        if buildCommand.validateAssets then
+            local options = type(buildCommand.validateAssets) == "table"
+                and buildCommand.validateAssets or {}
+            if not ceres.reportAssets(map, options.listfiles) and options.strict then
+                log("ERR: The map refers to missing assets")
+                return false
+            end
+        end
     end
 
     callHooks(postMapBuildHooks, map)
@@ -369,22 +441,35 @@ function ceres.buildMap(buildCommand)
     log("Successfuly built the map")
 
     local artifact = {}
+    local targetDirectory = ceres.layout.targetDirectory
+    if locale then
+        artifact.locale = locale
+        targetDirectory = targetDirectory .. locale .. "/"
+    end
 
     local result, errorMsg
     if outputType == "script" then
-        log("Writing artifact [script] to " .. ceres.layout.targetDirectory .. "war3map.lua")
+        log("Writing artifact [script] to " .. targetDirectory .. "war3map.lua")
         artifact.type = "script"
-        artifact.path = ceres.layout.targetDirectory .. "war3map.lua"
+        artifact.path = targetDirectory .. "war3map.lua"
         artifact.content = script
-        result, errorMsg = fs.writeFile(ceres.layout.targetDirectory .. "war3map.lua", script)
+        result, errorMsg = fs.writeFile(targetDirectory .. "war3map.lua", script)
     elseif outputType == "mpq" then
         artifact.type = "mpq"
-        artifact.path = ceres.layout.targetDirectory .. mapName
+        artifact.path = targetDirectory .. mapName
         log("Writing artifact [mpq] to " .. artifact.path)
-        result, errorMsg = map:writeToMpq(artifact.path)
+        -- copied, since build commands are often reused for several builds
+        local mpqOptions = {}
+        for key, value in pairs(buildCommand.mpqOptions or {}) do
+            mpqOptions[key] = value
+        end
+        if protect then
+            mpqOptions.protect = true
+        end
+        result, errorMsg = map:writeToMpq(artifact.path, mpqOptions)
     elseif outputType == "dir" then
         artifact.type = "dir"
-        artifact.path = ceres.layout.targetDirectory .. mapName .. ".dir/"
+        artifact.path = targetDirectory .. mapName .. ".dir/"
         log("Writing artifact [dir] to " .. artifact.path)
         result, errorMsg = map:writeToDir(artifact.path)
     end
@@ -398,6 +483,74 @@ function ceres.buildMap(buildCommand)
     end
 end
 
+-- Logs the missing assets and unused imports of `map` (see map:validateAssets()).
+-- Returns false if assets are missing.
+function ceres.reportAssets(map, listfiles)
+    local report, errorMsg = map:validateAssets(listfiles)
+    if not report then
+        log("WARN: Could not validate the assets: " .. errorMsg)
+        return true
+    end
+
+    for _, missing in ipairs(report.missing) do
+        local source
+        if missing.object then
+            source = "object " .. missing.object .. ", field " .. missing.field
+            if missing.level then
+                source = source .. ", level " .. missing.level
+            end
+        elseif missing.sound then
+            source = "sound " .. missing.sound
+        elseif missing.model then
+            source = "model " .. missing.model
+        else
+            source = missing.script
+        end
+
+        log("WARN: Missing asset " .. missing.path .. " (" .. source .. ")")
+    end
+
+    for _, path in ipairs(report.unused) do
+        log("WARN: Unused import " .. path)
+    end
+
+    return #report.missing == 0
+end
+
+-- Runs ceres.buildMap() once for every locale in `buildCommand.locales`.
+-- Strings are only extracted in the first build, since the others see the same ones.
+function ceres.buildLocalizedMaps(buildCommand)
+    local first
+    local artifacts = {}
+
+    for i, locale in ipairs(buildCommand.locales) do
+        local command = {}
+        for k, v in pairs(buildCommand) do
+            command[k] = v
+        end
+        command.locale = locale
+        if i > 1 then
+            command.extractStrings = nil
+        end
+
+        log("Building locale " .. locale)
+        local artifact = ceres.buildMap(command)
+        if not artifact then
+            log("ERR: Building locale " .. locale .. " failed")
+            return false
+        end
+
+        artifacts[locale] = artifact
+        first = first or artifact
+    end
+
+    if first then
+        first.locales = artifacts
+    end
+
+    return first or false
+end
+
 -- arg parsing
 local args = ceres.getScriptArgs()
 
@@ -443,15 +596,34 @@ function ceres.defaultHandler()
     local mapArg = arg.value("--map") or arg.value("-m")
     local outputType = arg.value("--output") or arg.value("-o") or "mpq"
     local noKeepScript = arg.exists("--no-map-script") or false
+    -- reusing blocks skips the compression rules for unchanged files, so both are opt-in
+    local update = arg.exists("--reuse-blocks")
+    local patch = ceres.runMode() == "run" and arg.exists("--patch")
+    local protect = arg.exists("--protect") and { obfuscate = arg.exists("--obfuscate") }
+    local convertToLua = arg.exists("--convert-to-lua")
+        and { removeTriggers = arg.exists("--remove-triggers") }
+    local locales
+    if arg.value("--locales") then
+        locales = {}
+        for locale in arg.value("--locales"):gmatch("[^,]+") do
+            table.insert(locales, locale)
+        end
+    end
 
-    for _, v in pairs(ceres.layout.srcDirectories) do
+    for _, v in ipairs(ceres.layout.srcDirectories) do
         package.path = package.path .. ";./" .. v .. "/?.lua"
     end
 
     local artifact = ceres.buildMap {
         input = mapArg,
         output = outputType,
-        retainMapScript = not noKeepScript
+        retainMapScript = not noKeepScript,
+        protect = protect,
+        convertToLua = convertToLua,
+        locales = locales,
+        validateAssets = arg.exists("--validate-assets"),
+        extractStrings = arg.value("--extract-strings"),
+        mpqOptions = { update = update, patch = patch }
     }
 
     if ceres.runMode() == "run" then
@@ -0,0 +1,1103 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use ceres_formats::imports::{Import, ImportsFile};
+use ceres_formats::mapheader::MapHeader;
+use ceres_formats::parser::{imp, w3x};
+use mpq::{Archive, Creator, CreatorOptions, FileOptions, Progress, RawFile};
+use walkdir::WalkDir;
+
+use crate::error::{ContextError, IoError, StringError};
+use crate::listfile::{needs_recovery, recover_names, RecoveryReport};
+
+pub type FileArchive = Archive<BufReader<fs::File>>;
+
+/// Files which are maintained by the MPQ writer itself,
+/// and are never carried over from a base archive.
+const MPQ_SPECIAL_FILES: &[&str] = &["(listfile)", "(attributes)", "(signature)"];
+
+/// Files which only the World Editor reads. Protected maps are written without them.
+const EDITOR_ONLY_FILES: &[&str] = &["war3map.wtg", "war3map.wct", "war3map.imp"];
+
+/// The import list, see `Vfs::sync_imports`.
+const IMPORTS_FILE: &str = "war3map.imp";
+
+/// Whether a file is part of the map itself, like its script or object data,
+/// as opposed to an imported file.
+pub(crate) fn is_map_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path).to_ascii_lowercase();
+
+    name.starts_with("war3map") || MPQ_SPECIAL_FILES.contains(&name.as_str())
+}
+
+/// Normalizes a path inside a map to use forward slashes,
+/// without empty or `.` components.
+pub fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// MPQ lookups are case-insensitive, so the VFS is as well.
+fn path_key(path: &str) -> String {
+    normalize_path(path).to_ascii_lowercase()
+}
+
+/// WC3 expects backslashes as path separators inside archives.
+pub fn to_archive_path(path: &str) -> String {
+    normalize_path(path).replace('/', "\\")
+}
+
+fn glob_matches_bytes(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+
+            // `**/` may also match no directories at all
+            if rest.first() == Some(&b'/') && glob_matches_bytes(&rest[1..], path) {
+                return true;
+            }
+
+            (0..=path.len()).any(|i| glob_matches_bytes(rest, &path[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+
+            for i in 0..=path.len() {
+                if glob_matches_bytes(rest, &path[i..]) {
+                    return true;
+                }
+
+                if i < path.len() && path[i] == b'/' {
+                    break;
+                }
+            }
+
+            false
+        }
+        Some(b'?') => {
+            !path.is_empty() && path[0] != b'/' && glob_matches_bytes(&pattern[1..], &path[1..])
+        }
+        Some(c) => {
+            !path.is_empty() && path[0] == *c && glob_matches_bytes(&pattern[1..], &path[1..])
+        }
+    }
+}
+
+/// Matches a map path against a glob pattern. `*` and `?` do not cross
+/// directory boundaries, while `**` does. Matching is case-insensitive
+/// and does not care about the kind of slashes used.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    glob_matches_bytes(path_key(pattern).as_bytes(), path_key(path).as_bytes())
+}
+
+/// A set of include and exclude globs used to select files.
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl PathFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> PathFilter {
+        PathFilter { include, exclude }
+    }
+
+    /// A path is accepted if it matches any of the include patterns
+    /// (or there are none), and none of the exclude patterns.
+    pub fn accepts(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_matches(p, path)))
+            && !self.exclude.iter().any(|p| glob_matches(p, path))
+    }
+}
+
+/// Reads the `HM3W` header and the signature footer of a map file,
+/// if it has them.
+pub fn read_map_header<P: AsRef<Path>>(path: P) -> Result<Option<MapHeader>, anyhow::Error> {
+    let path = path.as_ref();
+    let mut file = fs::File::open(path).map_err(|cause| IoError::new(path, cause))?;
+
+    let mut header = Vec::with_capacity(w3x::HEADER_SIZE);
+    (&mut file)
+        .take(w3x::HEADER_SIZE as u64)
+        .read_to_end(&mut header)
+        .map_err(|cause| IoError::new(path, cause))?;
+
+    let mut header = match w3x::read::read_header(&header)? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let len = file
+        .seek(SeekFrom::End(0))
+        .map_err(|cause| IoError::new(path, cause))?;
+
+    if len >= (w3x::HEADER_SIZE + w3x::FOOTER_SIZE) as u64 {
+        let mut footer = vec![0; w3x::FOOTER_SIZE];
+        file.seek(SeekFrom::End(-(w3x::FOOTER_SIZE as i64)))
+            .and_then(|_| file.read_exact(&mut footer))
+            .map_err(|cause| IoError::new(path, cause))?;
+
+        header.signature = w3x::read::read_footer(&footer);
+    }
+
+    Ok(Some(header))
+}
+
+/// Shifts all absolute seeks by a fixed offset, so that the MPQ writer
+/// can write its archive after the map header as if it started at 0.
+struct OffsetWriter<W> {
+    inner:  W,
+    offset: u64,
+}
+
+impl<W: Write> Write for OffsetWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for OffsetWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => SeekFrom::Start(pos + self.offset),
+            pos => pos,
+        };
+
+        let pos = self.inner.seek(pos)?;
+
+        Ok(pos.saturating_sub(self.offset))
+    }
+}
+
+/// Writes an archive to disk, preceded by the map header
+/// and followed by the signature footer if one is given.
+pub fn write_archive<P, F>(
+    creator: &mut Creator,
+    out_path: P,
+    header: Option<&MapHeader>,
+    progress: F,
+) -> Result<(), anyhow::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Progress),
+{
+    let out_path = out_path.as_ref();
+
+    fs::create_dir_all(out_path.parent().unwrap())
+        .map_err(|cause| ContextError::new("could not create folder for map", cause))?;
+
+    let writer = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(out_path)
+        .map_err(|cause| IoError::new(out_path, cause))?;
+
+    let mut writer = BufWriter::new(writer);
+
+    if let Some(header) = header {
+        w3x::write::write_header(&mut writer, header)
+            .map_err(|cause| IoError::new(out_path, cause))?;
+
+        let mut offset_writer = OffsetWriter {
+            inner:  &mut writer,
+            offset: w3x::HEADER_SIZE as u64,
+        };
+        creator.write_with_progress(&mut offset_writer, progress)?;
+
+        writer
+            .seek(SeekFrom::End(0))
+            .and_then(|_| w3x::write::write_footer(&mut writer, header))
+            .map_err(|cause| IoError::new(out_path, cause))?;
+    } else {
+        creator.write_with_progress(&mut writer, progress)?;
+    }
+
+    writer.flush().map_err(|cause| IoError::new(out_path, cause))?;
+
+    Ok(())
+}
+
+/// How `Vfs::write_to_mpq` produces the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Every file is compressed from scratch.
+    Rebuild,
+    /// Files which come unchanged from a base archive are copied
+    /// as they are, without decompressing and recompressing them.
+    Update,
+    /// Only files which differ from the ones in an existing output archive
+    /// are appended to it. Files which come unchanged from a base archive
+    /// are compared by their MD5 attribute and copied like in `Update`.
+    /// Falls back to `Update` if there is no usable output archive yet.
+    Patch,
+}
+
+impl Default for WriteMode {
+    fn default() -> WriteMode {
+        WriteMode::Rebuild
+    }
+}
+
+/// Patched archives are rewritten once they are this many times
+/// larger than the data they actually contain.
+const PATCH_MAX_OVERHEAD: u64 = 2;
+
+/// MPQ file options, with glob patterns overriding the defaults
+/// for matching files. The first matching rule wins.
+#[derive(Debug, Default, Clone)]
+pub struct FileRules {
+    default: FileOptions,
+    rules:   Vec<(String, FileOptions)>,
+}
+
+impl FileRules {
+    pub fn new(default: FileOptions) -> FileRules {
+        FileRules {
+            default,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn default_options(&self) -> FileOptions {
+        self.default
+    }
+
+    pub fn add_rule(&mut self, pattern: &str, options: FileOptions) {
+        self.rules.push((pattern.into(), options));
+    }
+
+    pub fn options_for(&self, path: &str) -> FileOptions {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| glob_matches(pattern, path))
+            .map(|(_, options)| *options)
+            .unwrap_or(self.default)
+    }
+}
+
+pub enum Base {
+    Mpq(FileArchive),
+    Dir(PathBuf),
+}
+
+enum Layer {
+    Contents(Vec<u8>),
+    Disk(PathBuf),
+    // a file from the base under a different path, i.e. a renamed file
+    Base(String),
+    Removed,
+}
+
+struct OverlayEntry {
+    path:  String,
+    layer: Layer,
+}
+
+/// A virtual file system over a map, consisting of a base (either an MPQ archive
+/// or a directory) and an overlay of added, replaced and removed files.
+///
+/// Nothing is written to the base. The merged view can be written out
+/// to a directory or a new archive.
+pub struct Vfs {
+    base:       Base,
+    base_files: Option<BTreeMap<String, String>>,
+    overlay:    BTreeMap<String, OverlayEntry>,
+    excluded:   Vec<String>,
+    /// Candidate names for files of the base archive, see `Vfs::base_files`.
+    listfiles:  Vec<String>,
+    /// Whether names of unnamed files in the base archive were looked for already.
+    recovered:  bool,
+}
+
+impl Vfs {
+    pub fn new(base: Base) -> Vfs {
+        Vfs {
+            base,
+            base_files: None,
+            overlay: Default::default(),
+            excluded: Default::default(),
+            listfiles: Default::default(),
+            recovered: false,
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Vfs, anyhow::Error> {
+        Vfs::open_with_listfiles(path, Vec::new())
+    }
+
+    /// Opens a map, using the given names as candidates when looking for the names
+    /// of unnamed files. This only happens once the files of the map are listed.
+    pub fn open_with_listfiles<P: AsRef<Path>>(
+        path: P,
+        listfiles: Vec<String>,
+    ) -> Result<Vfs, anyhow::Error> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Err(StringError::new("map does not exist").into());
+        }
+
+        let base = if path.is_dir() {
+            Base::Dir(path.into())
+        } else if path.is_file() {
+            let file = fs::File::open(path).map_err(|cause| IoError::new(path, cause))?;
+            let archive = Archive::open(BufReader::new(file))?;
+
+            Base::Mpq(archive)
+        } else {
+            return Err(StringError::new("map path is not a file or directory").into());
+        };
+
+        let mut vfs = Vfs::new(base);
+        vfs.listfiles = listfiles;
+
+        Ok(vfs)
+    }
+
+    pub fn base(&self) -> &Base {
+        &self.base
+    }
+
+    /// Looks for names of unnamed files in the base archive,
+    /// additionally using the given names as candidates.
+    /// Returns `None` if the base is a directory.
+    pub fn recover_names(&mut self, listfiles: &[String]) -> Option<RecoveryReport> {
+        match &mut self.base {
+            Base::Mpq(archive) => {
+                let report = recover_names(archive, listfiles);
+                self.base_files = None;
+                self.recovered = true;
+
+                Some(report)
+            }
+            Base::Dir(_) => None,
+        }
+    }
+
+    /// Lists the files of the base, keyed by their case-insensitive path.
+    /// The listing is cached, since reading it from an archive is not free.
+    ///
+    /// The first listing of an archive looks for the names of unnamed files,
+    /// since protected maps often come without a (complete) listfile.
+    fn base_files(&mut self) -> &BTreeMap<String, String> {
+        if self.base_files.is_none() {
+            let mut files = BTreeMap::new();
+
+            match &mut self.base {
+                Base::Mpq(archive) => {
+                    if !self.recovered && (!self.listfiles.is_empty() || needs_recovery(archive)) {
+                        let report = recover_names(archive, &self.listfiles);
+
+                        eprintln!(
+                            "map: recovered {} file names, {} files remain unnamed",
+                            report.recovered.len(),
+                            report.unnamed.len()
+                        );
+                    }
+                    self.recovered = true;
+
+                    for path in archive.files().unwrap_or_default() {
+                        let path = normalize_path(&path);
+
+                        if !MPQ_SPECIAL_FILES.contains(&path.as_str()) {
+                            files.insert(path_key(&path), path);
+                        }
+                    }
+                }
+                Base::Dir(dir) => {
+                    let entries = WalkDir::new(&dir)
+                        .follow_links(true)
+                        .into_iter()
+                        .filter_map(|s| s.ok())
+                        .filter(|s| s.file_type().is_file());
+
+                    for entry in entries {
+                        let relative_path = entry.path().strip_prefix(&dir).unwrap();
+
+                        if let Some(relative_path) = relative_path.to_str() {
+                            let path = normalize_path(relative_path);
+                            files.insert(path_key(&path), path);
+                        } else {
+                            eprintln!(
+                                "map: skipping file with a non UTF-8 path {}",
+                                entry.path().display()
+                            );
+                        }
+                    }
+                }
+            }
+
+            self.base_files = Some(files);
+        }
+
+        self.base_files.as_ref().unwrap()
+    }
+
+    /// The file on disk which holds the contents of a file, if there is one.
+    /// Such files are streamed into archives instead of being read up front.
+    fn disk_path(&self, path: &str) -> Option<PathBuf> {
+        let base_path = match self.overlay.get(&path_key(path)).map(|entry| &entry.layer) {
+            Some(Layer::Disk(file_path)) => return Some(file_path.clone()),
+            Some(Layer::Base(base_path)) => base_path.clone(),
+            Some(_) => return None,
+            None => path.to_string(),
+        };
+
+        match &self.base {
+            Base::Dir(dir) => Some(dir.join(normalize_path(&base_path))),
+            Base::Mpq(_) => None,
+        }
+    }
+
+    /// Adds a file to an archive, either streamed from disk or read up front.
+    fn add_to_creator(&mut self, creator: &mut Creator, path: &str, options: FileOptions) {
+        let archive_path = to_archive_path(path);
+
+        if let Some(file_path) = self.disk_path(path) {
+            if file_path.is_file() {
+                creator.add_file_from_disk(&archive_path, file_path, options);
+            } else {
+                eprintln!(
+                    "map.writeToMpq(): could not read file {}: {} does not exist",
+                    path,
+                    file_path.display()
+                );
+            }
+
+            return;
+        }
+
+        match self.read_file(path) {
+            Ok(contents) => creator.add_file(&archive_path, contents, options),
+            Err(error) => {
+                eprintln!("map.writeToMpq(): could not read file {}: {}", path, error);
+            }
+        }
+    }
+
+    /// Reads the stored data of a file which comes unchanged from a base archive.
+    fn read_base_raw_file(&mut self, path: &str) -> Option<RawFile> {
+        let base_path = match self.overlay.get(&path_key(path)).map(|entry| &entry.layer) {
+            Some(Layer::Base(base_path)) => base_path.clone(),
+            Some(_) => return None,
+            None => path.to_string(),
+        };
+
+        match &mut self.base {
+            Base::Mpq(archive) => archive.read_raw_file(&to_archive_path(&base_path)).ok(),
+            Base::Dir(_) => None,
+        }
+    }
+
+    fn read_base_file(&mut self, path: &str) -> Result<Vec<u8>, anyhow::Error> {
+        match &mut self.base {
+            Base::Mpq(archive) => Ok(archive.read_file(&to_archive_path(path))?),
+            Base::Dir(dir) => {
+                let file_path = dir.join(normalize_path(path));
+
+                Ok(fs::read(&file_path).map_err(|cause| IoError::new(&file_path, cause))?)
+            }
+        }
+    }
+
+    /// Returns the merged list of files in the map, sorted by path.
+    pub fn list_files(&mut self) -> Vec<String> {
+        let mut files = self.base_files().clone();
+
+        for (key, entry) in &self.overlay {
+            if let Layer::Removed = entry.layer {
+                files.remove(key);
+            } else {
+                files.insert(key.clone(), entry.path.clone());
+            }
+        }
+
+        files
+            .into_iter()
+            .map(|(_, path)| path)
+            .filter(|path| !self.is_excluded(path))
+            .collect()
+    }
+
+    pub fn has_file(&mut self, path: &str) -> bool {
+        if self.is_excluded(path) {
+            return false;
+        }
+
+        let key = path_key(path);
+
+        if let Some(entry) = self.overlay.get(&key) {
+            if let Layer::Removed = entry.layer {
+                return false;
+            }
+
+            return true;
+        }
+
+        if self.base_files().contains_key(&key) {
+            return true;
+        }
+
+        // archives without a complete listfile can still contain the file
+        match &self.base {
+            Base::Mpq(archive) => match archive.stat(&to_archive_path(path)) {
+                Some(entry) => !entry.is_deleted(),
+                None => false,
+            },
+            Base::Dir(_) => false,
+        }
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, anyhow::Error> {
+        if self.is_excluded(path) {
+            return Err(StringError::new(format!("file {} is excluded from the map", path)).into());
+        }
+
+        let base_path = match self.overlay.get(&path_key(path)).map(|entry| &entry.layer) {
+            Some(Layer::Contents(contents)) => return Ok(contents.clone()),
+            Some(Layer::Disk(file_path)) => {
+                return Ok(fs::read(file_path).map_err(|cause| IoError::new(file_path, cause))?)
+            }
+            Some(Layer::Removed) => {
+                return Err(
+                    StringError::new(format!("file {} was removed from the map", path)).into(),
+                )
+            }
+            Some(Layer::Base(base_path)) => base_path.clone(),
+            None => path.to_string(),
+        };
+
+        self.read_base_file(&base_path)
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.excluded.iter().any(|pattern| glob_matches(pattern, path))
+    }
+
+    fn set_layer(&mut self, path: &str, layer: Layer) {
+        self.overlay.insert(
+            path_key(path),
+            OverlayEntry {
+                path: normalize_path(path),
+                layer,
+            },
+        );
+    }
+
+    pub fn add_file_contents(&mut self, path: &str, contents: Vec<u8>) {
+        self.set_layer(path, Layer::Contents(contents));
+    }
+
+    pub fn add_file_disk<P: AsRef<Path>>(&mut self, path: &str, file_path: P) {
+        self.set_layer(path, Layer::Disk(file_path.as_ref().into()));
+    }
+
+    /// Adds every file inside the given directory to the map,
+    /// using its path relative to the directory.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), anyhow::Error> {
+        let dir = dir.as_ref();
+
+        if !dir.is_dir() {
+            return Err(StringError::new(format!("{} is not a directory", dir.display())).into());
+        }
+
+        let entries = WalkDir::new(dir)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|s| s.ok())
+            .filter(|s| s.file_type().is_file());
+
+        for entry in entries {
+            let relative_path = entry.path().strip_prefix(dir).unwrap();
+
+            if let Some(relative_path) = relative_path.to_str() {
+                self.add_file_disk(relative_path, entry.path());
+            } else {
+                eprintln!(
+                    "map.addDir(): skipping file with a non UTF-8 path {}",
+                    entry.path().display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, path: &str) {
+        self.set_layer(path, Layer::Removed);
+    }
+
+    /// Moves a file to a new path, keeping its contents.
+    pub fn rename_file(&mut self, from: &str, to: &str) -> Result<(), anyhow::Error> {
+        if !self.has_file(from) {
+            return Err(StringError::new(format!("file {} does not exist in the map", from)).into());
+        }
+
+        let layer = match self.overlay.remove(&path_key(from)) {
+            Some(entry) => entry.layer,
+            None => Layer::Base(normalize_path(from)),
+        };
+
+        self.set_layer(to, layer);
+
+        // renaming may only change the case of the path
+        if path_key(from) != path_key(to) {
+            self.remove_file(from);
+        }
+
+        Ok(())
+    }
+
+    /// Excludes all files matching the glob pattern from the map,
+    /// regardless of whether they come from the base or were added later.
+    pub fn exclude(&mut self, pattern: &str) {
+        self.excluded.push(pattern.into());
+    }
+
+    /// Brings the import list in line with the map: files added to the overlay
+    /// are listed, and files removed from it are no longer listed. With `prune`,
+    /// entries of files which are missing from the map are dropped as well.
+    /// Returns the number of added and removed entries.
+    ///
+    /// Files belonging to the map itself, like `war3map.lua`, are never listed.
+    /// Nothing is done if the import list was removed from the map.
+    pub fn sync_imports(&mut self, prune: bool) -> Result<(usize, usize), anyhow::Error> {
+        if let Some(Layer::Removed) = self.overlay.get(&path_key(IMPORTS_FILE)).map(|e| &e.layer) {
+            return Ok((0, 0));
+        }
+
+        let mut imports = if self.has_file(IMPORTS_FILE) {
+            imp::read::read_imports(&self.read_file(IMPORTS_FILE)?)?
+        } else {
+            ImportsFile::default()
+        };
+
+        let files: HashSet<String> = self.list_files().iter().map(|path| path_key(path)).collect();
+        let overlay = &self.overlay;
+
+        let count = imports.imports.len();
+        imports.imports.retain(|import| {
+            let key = path_key(&import.archive_path());
+
+            match overlay.get(&key).map(|entry| &entry.layer) {
+                Some(Layer::Removed) => false,
+                _ => !prune || files.contains(&key),
+            }
+        });
+        let removed = count - imports.imports.len();
+
+        let mut listed: HashSet<String> = imports
+            .imports
+            .iter()
+            .map(|import| path_key(&import.archive_path()))
+            .collect();
+        let mut added = 0;
+
+        for (key, entry) in overlay {
+            if files.contains(key) && !is_map_file(&entry.path) && listed.insert(key.clone()) {
+                imports.imports.push(Import::new(to_archive_path(&entry.path)));
+                added += 1;
+            }
+        }
+
+        if added > 0 || removed > 0 {
+            let mut buf = Vec::new();
+            imp::write::write_imports(&mut buf, &imports)?;
+            self.add_file_contents(IMPORTS_FILE, buf);
+        }
+
+        Ok((added, removed))
+    }
+
+    /// Called before the map is written, so that the World Editor keeps
+    /// the files added by the build. The import list is left alone if no
+    /// imported file was added or removed, which are the only changes
+    /// `sync_imports` makes without `prune`.
+    fn sync_imports_for_write(&mut self) {
+        if !self.overlay.values().any(|entry| !is_map_file(&entry.path)) {
+            return;
+        }
+
+        if let Err(error) = self.sync_imports(false) {
+            eprintln!("map: could not update {}: {}", IMPORTS_FILE, error);
+        }
+    }
+
+    pub fn write_to_dir<P: AsRef<Path>>(&mut self, out_dir: P) -> Result<(), anyhow::Error> {
+        let out_dir = out_dir.as_ref();
+        self.sync_imports_for_write();
+
+        for path in self.list_files() {
+            let contents = match self.read_file(&path) {
+                Ok(contents) => contents,
+                Err(error) => {
+                    eprintln!("map.writeToDir(): could not read file {}: {}", path, error);
+                    continue;
+                }
+            };
+
+            let out_path = out_dir.join(&path);
+            fs::create_dir_all(out_path.parent().unwrap())
+                .map_err(|cause| IoError::new(out_path.parent().unwrap(), cause))?;
+            fs::write(&out_path, contents).map_err(|cause| IoError::new(&out_path, cause))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the map to an archive, reporting progress after every file.
+    ///
+    /// In `Patch` mode, MD5 attributes are always written, since they are
+    /// used to find out which files changed since the last write.
+    /// Progress is not reported for patches.
+    ///
+    /// Protected maps are written without editor-only files,
+    /// and are never patched.
+    pub fn write_to_mpq<P, F>(
+        &mut self,
+        out_path: P,
+        rules: &FileRules,
+        mut options: CreatorOptions,
+        header: Option<&MapHeader>,
+        mode: WriteMode,
+        progress: F,
+    ) -> Result<(), anyhow::Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Progress),
+    {
+        let out_path = out_path.as_ref();
+        self.sync_imports_for_write();
+
+        let stripped = if options.protect {
+            self.editor_only_files()
+        } else {
+            HashSet::new()
+        };
+
+        // without a listfile, the next patch could not tell which files to remove
+        let mode = if options.protect && mode == WriteMode::Patch {
+            WriteMode::Update
+        } else {
+            mode
+        };
+
+        if mode == WriteMode::Patch {
+            options.attributes.md5 = true;
+
+            if self.patch_mpq(out_path, rules, options.clone(), header)? {
+                return Ok(());
+            }
+        }
+
+        let mut creator = Creator::new(options);
+
+        for path in self.list_files() {
+            if stripped.contains(&path_key(&path)) {
+                continue;
+            }
+
+            if mode != WriteMode::Rebuild {
+                if let Some(raw) = self.read_base_raw_file(&path) {
+                    creator.add_raw_file(&to_archive_path(&path), raw);
+                    continue;
+                }
+            }
+
+            self.add_to_creator(&mut creator, &path, rules.options_for(&path));
+        }
+
+        write_archive(&mut creator, out_path, header, progress)
+    }
+
+    /// Keys of the files left out of protected maps. Regions (`war3map.w3r`)
+    /// are only kept if the map script refers to them.
+    fn editor_only_files(&mut self) -> HashSet<String> {
+        let mut files: HashSet<String> = EDITOR_ONLY_FILES.iter().map(|s| path_key(s)).collect();
+
+        let uses_regions = ["war3map.lua", "war3map.j"].iter().any(|script| {
+            self.read_file(script)
+                .map(|contents| contents.windows(7).any(|w| w == b"gg_rct_"))
+                .unwrap_or(false)
+        });
+
+        if !uses_regions {
+            files.insert(path_key("war3map.w3r"));
+        }
+
+        files
+    }
+
+    /// Appends changed files to an existing output archive.
+    /// Returns `false` if the archive has to be written from scratch instead.
+    fn patch_mpq(
+        &mut self,
+        out_path: &Path,
+        rules: &FileRules,
+        options: CreatorOptions,
+        header: Option<&MapHeader>,
+    ) -> Result<bool, anyhow::Error> {
+        if !out_path.is_file() {
+            return Ok(false);
+        }
+
+        // the header has a fixed size and is simply overwritten,
+        // but it can not be added to or removed from an existing file
+        let old_header = read_map_header(out_path)?;
+        if old_header.is_some() != header.is_some() {
+            return Ok(false);
+        }
+
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(out_path)
+            .map_err(|cause| IoError::new(out_path, cause))?;
+        let file_len = file
+            .metadata()
+            .map_err(|cause| IoError::new(out_path, cause))?
+            .len();
+
+        let mut archive = match Archive::open(file) {
+            Ok(archive) => archive,
+            Err(_) => return Ok(false),
+        };
+
+        let entries = archive.entries();
+        let used_size: u64 = entries
+            .iter()
+            .filter(|entry| !entry.is_deleted())
+            .map(|entry| u64::from(entry.compressed_size))
+            .sum();
+
+        if file_len > used_size * PATCH_MAX_OVERHEAD + (1 << 20) {
+            return Ok(false);
+        }
+
+        let mut creator = Creator::new(options);
+        let mut keep = HashSet::new();
+
+        for path in self.list_files() {
+            keep.insert(to_archive_path(&path).to_ascii_lowercase());
+
+            if let Some(raw) = self.read_base_raw_file(&path) {
+                creator.add_raw_file(&to_archive_path(&path), raw);
+            } else {
+                self.add_to_creator(&mut creator, &path, rules.options_for(&path));
+            }
+        }
+
+        let removed: Vec<String> = archive
+            .files()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|name| !MPQ_SPECIAL_FILES.contains(&name.as_str()))
+            .filter(|name| !keep.contains(&to_archive_path(name).to_ascii_lowercase()))
+            .collect();
+
+        let result = creator.patch(&mut archive, &removed);
+        let mut file = archive.into_inner();
+
+        let end = match result {
+            Ok(stats) => stats.end,
+            Err(error) => {
+                restore_patched_file(&mut file, file_len, old_header.as_ref())
+                    .map_err(|cause| IoError::new(out_path, cause))?;
+
+                match error {
+                    mpq::Error::HashTableTooSmall { .. }
+                    | mpq::Error::UnsupportedVersion { .. } => {
+                        eprintln!(
+                            "map.writeToMpq(): rewriting the map, patching failed: {}",
+                            error
+                        );
+
+                        return Ok(false);
+                    }
+                    error => return Err(error.into()),
+                }
+            }
+        };
+
+        file.set_len(end)
+            .map_err(|cause| IoError::new(out_path, cause))?;
+
+        if let Some(header) = header {
+            file.seek(SeekFrom::Start(0))
+                .and_then(|_| w3x::write::write_header(&mut file, header))
+                .and_then(|_| file.seek(SeekFrom::End(0)))
+                .and_then(|_| w3x::write::write_footer(&mut file, header))
+                .map_err(|cause| IoError::new(out_path, cause))?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// Undoes a failed patch: cuts off the appended data, and puts back
+/// the signature footer which `Creator::patch` wrote over.
+fn restore_patched_file(
+    file: &mut fs::File,
+    len: u64,
+    header: Option<&MapHeader>,
+) -> Result<(), io::Error> {
+    file.set_len(len)?;
+
+    if let Some(header) = header.filter(|header| header.signature.is_some()) {
+        file.seek(SeekFrom::Start(len - w3x::FOOTER_SIZE as u64))?;
+        w3x::write::write_footer(&mut *file, header)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::process;
+
+    use super::*;
+
+    /// A map stored as a directory, with a script and an imported file.
+    fn map_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ceres-vfs-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        fs::create_dir_all(dir.join("map").join("UI")).unwrap();
+        fs::write(dir.join("map").join("war3map.lua"), "print('hi')").unwrap();
+        fs::write(dir.join("map").join("UI").join("icon.blp"), vec![7; 5000]).unwrap();
+
+        dir
+    }
+
+    fn signed_header() -> MapHeader {
+        MapHeader {
+            unknown:     0,
+            name:        "Roundtrip".into(),
+            flags:       0x8000,
+            max_players: 4,
+            signature:   Some((0..w3x::SIGNATURE_SIZE).map(|i| i as u8).collect()),
+        }
+    }
+
+    fn write(vfs: &mut Vfs, out_path: &Path, header: Option<&MapHeader>) {
+        vfs.write_to_mpq(
+            out_path,
+            &FileRules::default(),
+            CreatorOptions::default(),
+            header,
+            WriteMode::Rebuild,
+            |_| {},
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn header_and_footer_roundtrip() {
+        let dir = map_dir("header");
+        let header = signed_header();
+
+        let mut vfs = Vfs::open(dir.join("map")).unwrap();
+        write(&mut vfs, &dir.join("out.w3x"), Some(&header));
+
+        assert_eq!(read_map_header(dir.join("out.w3x")).unwrap(), Some(header.clone()));
+
+        // writing the map again keeps both header and footer in place
+        let mut vfs = Vfs::open(dir.join("out.w3x")).unwrap();
+        write(&mut vfs, &dir.join("copy.w3x"), Some(&header));
+
+        assert_eq!(read_map_header(dir.join("copy.w3x")).unwrap(), Some(header));
+
+        let mut vfs = Vfs::open(dir.join("copy.w3x")).unwrap();
+        assert_eq!(vfs.read_file("war3map.lua").unwrap(), b"print('hi')");
+        assert_eq!(vfs.read_file("UI/icon.blp").unwrap(), vec![7; 5000]);
+    }
+
+    #[test]
+    fn maps_without_header_stay_bare() {
+        let dir = map_dir("bare");
+
+        let mut vfs = Vfs::open(dir.join("map")).unwrap();
+        write(&mut vfs, &dir.join("out.w3x"), None);
+
+        let data = fs::read(dir.join("out.w3x")).unwrap();
+        assert!(data.starts_with(b"MPQ\x1a"));
+        assert_eq!(read_map_header(dir.join("out.w3x")).unwrap(), None);
+
+        let mut vfs = Vfs::open(dir.join("out.w3x")).unwrap();
+        assert_eq!(vfs.read_file("war3map.lua").unwrap(), b"print('hi')");
+    }
+
+    #[test]
+    fn patched_maps_keep_their_signature() {
+        let dir = map_dir("patch");
+        let header = signed_header();
+        let out_path = dir.join("out.w3x");
+
+        let mut vfs = Vfs::open(dir.join("map")).unwrap();
+        write(&mut vfs, &out_path, Some(&header));
+
+        vfs.add_file_contents("war3map.lua", b"print('patched')".to_vec());
+        vfs.write_to_mpq(
+            &out_path,
+            &FileRules::default(),
+            CreatorOptions::default(),
+            Some(&header),
+            WriteMode::Patch,
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(read_map_header(&out_path).unwrap(), Some(header));
+
+        let mut vfs = Vfs::open(&out_path).unwrap();
+        assert_eq!(vfs.read_file("war3map.lua").unwrap(), b"print('patched')");
+        assert_eq!(vfs.read_file("UI/icon.blp").unwrap(), vec![7; 5000]);
+        assert!(vfs.has_file("ui\\ICON.blp"));
+        assert!(!vfs.has_file("missing.txt"));
+    }
+
+    #[test]
+    fn failed_patches_leave_the_map_intact() {
+        let dir = map_dir("failed-patch");
+        let header = signed_header();
+        let out_path = dir.join("out.w3x");
+        let options = CreatorOptions {
+            hash_table_size: Some(4),
+            ..Default::default()
+        };
+
+        let mut vfs = Vfs::open(dir.join("map")).unwrap();
+        vfs.write_to_mpq(
+            &out_path,
+            &FileRules::default(),
+            options.clone(),
+            Some(&header),
+            WriteMode::Rebuild,
+            |_| {},
+        )
+        .unwrap();
+        let before = fs::read(&out_path).unwrap();
+
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            vfs.add_file_contents(name, b"does not fit".to_vec());
+        }
+
+        let patched = vfs
+            .patch_mpq(&out_path, &FileRules::default(), options, Some(&header))
+            .unwrap();
+
+        assert!(!patched);
+        assert_eq!(fs::read(&out_path).unwrap(), before);
+    }
+}
@@ -1,10 +1,37 @@
+use std::collections::BTreeSet;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use ceres_formats::metadata;
 use ceres_formats::objectstore;
 
+fn is_path_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"_-\\/.()!&+#$~".contains(&c)
+}
+
+/// Collects every backslash-separated path in the files of a data directory,
+/// such as `Units\Human\Footman\Footman` or `Sound\Music\mp3Music\Human1.mp3`.
+fn collect_stock_paths(dir: &Path, paths: &mut BTreeSet<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+
+        if path.is_dir() {
+            collect_stock_paths(&path, paths);
+            continue;
+        }
+
+        let contents = fs::read(&path).unwrap();
+        paths.extend(
+            contents
+                .split(|c| !is_path_byte(*c))
+                .filter(|run| run.contains(&b'\\'))
+                .filter(|run| run[0].is_ascii_alphanumeric() || run[0] == b'_')
+                .map(|run| String::from_utf8_lossy(run).replace("\\\\", "\\")),
+        );
+    }
+}
+
 fn main() {
     let out_dir: PathBuf = env::var("OUT_DIR").unwrap().into();
     let crate_dir: PathBuf = env::var("CARGO_MANIFEST_DIR").unwrap().into();
@@ -20,4 +47,10 @@ fn main() {
         .unwrap();
 
     bincode::serialize_into(&mut file, &(&data, &meta)).unwrap();
+
+    let mut stock_paths = BTreeSet::new();
+    collect_stock_paths(&crate_dir.join("data"), &mut stock_paths);
+
+    let stock_paths: Vec<String> = stock_paths.into_iter().collect();
+    fs::write(out_dir.join("stock_paths.txt"), stock_paths.join("\n")).unwrap();
 }
@@ -3,6 +3,7 @@ use ceres_formats::objectstore::ObjectStoreStock;
 use lazy_static::lazy_static;
 
 const BUNDLED_DATA_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/data.bin"));
+const STOCK_PATHS: &str = include_str!(concat!(env!("OUT_DIR"), "/stock_paths.txt"));
 
 lazy_static! {
     static ref BUNDLED_DATA: (ObjectStoreStock, MetadataStore) = {
@@ -20,3 +21,12 @@ pub fn metadata() -> &'static MetadataStore {
 pub fn data() -> &'static ObjectStoreStock {
     &BUNDLED_DATA.0
 }
+
+/// Paths of the game's files which the bundled game data refers to,
+/// like models, icons and sounds. Models are often named without an extension.
+///
+/// This is not a listing of every file of the game: files which only the game
+/// itself refers to, like most UI textures and unit sound sets, are not in it.
+pub fn stock_paths() -> impl Iterator<Item = &'static str> {
+    STOCK_PATHS.lines()
+}
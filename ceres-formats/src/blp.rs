@@ -0,0 +1,51 @@
+use crate::image::Image;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Every mipmap is a JPEG image, with BGRA as its four components.
+    Jpeg,
+    /// Every mipmap indexes a shared palette of 256 colors,
+    /// with alpha stored separately.
+    Palette,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A BLP1 texture, the format of icons and most other textures in WC3.
+pub struct Blp {
+    pub compression: Compression,
+    /// 0, 1, 4 or 8. Textures without alpha bits are opaque.
+    pub alpha_bits:  u8,
+    /// The full size image followed by its mipmaps, if any.
+    pub mipmaps:     Vec<Image>,
+}
+
+impl Blp {
+    /// A texture of the image, with 8 alpha bits if it has any transparency.
+    pub fn new(image: Image, compression: Compression, with_mipmaps: bool) -> Blp {
+        let alpha_bits = if image.has_alpha() { 8 } else { 0 };
+        let mipmaps = if with_mipmaps {
+            image.mipmaps()
+        } else {
+            vec![image]
+        };
+
+        Blp {
+            compression,
+            alpha_bits,
+            mipmaps,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.mipmaps.first().map(|image| image.width).unwrap_or(0)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.mipmaps.first().map(|image| image.height).unwrap_or(0)
+    }
+
+    /// The full size image.
+    pub fn image(&self) -> Option<&Image> {
+        self.mipmaps.first()
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use crate::regions::global_name;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A camera setup placed in the World Editor. Angles are in degrees.
+pub struct Camera {
+    pub name:            String,
+    pub target_x:        f32,
+    pub target_y:        f32,
+    pub z_offset:        f32,
+    pub rotation:        f32,
+    pub angle_of_attack: f32,
+    pub distance:        f32,
+    pub roll:            f32,
+    pub field_of_view:   f32,
+    pub far_z:           f32,
+    pub near_z:          f32,
+    /// Pitch, yaw and roll of the camera itself. 1.31 and later only.
+    pub local_rotation:  Option<[f32; 3]>,
+}
+
+impl Camera {
+    /// A camera with the defaults the World Editor uses for new cameras.
+    pub fn new(name: String) -> Camera {
+        Camera {
+            name,
+            target_x: 0.0,
+            target_y: 0.0,
+            z_offset: 0.0,
+            rotation: 90.0,
+            angle_of_attack: 304.0,
+            distance: 1650.0,
+            roll: 0.0,
+            field_of_view: 70.0,
+            far_z: 5000.0,
+            near_z: 100.0,
+            local_rotation: None,
+        }
+    }
+
+    /// The camera's global, e.g. `gg_cam_Intro`.
+    pub fn global_name(&self) -> String {
+        global_name("gg_cam_", &self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+/// The contents of `war3map.w3c`.
+pub struct CamerasFile {
+    pub version: u32,
+    pub cameras: Vec<Camera>,
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use crate::units::DroppedItem;
+use crate::ObjectId;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A doodad or destructable placed on the map.
+pub struct PlacedDoodad {
+    pub id:              ObjectId,
+    pub variation:       u32,
+    pub x:               f32,
+    pub y:               f32,
+    pub z:               f32,
+    /// In radians.
+    pub facing:          f32,
+    pub scale:           [f32; 3],
+    /// Reforged only, the doodad ID itself unless a skin is applied.
+    pub skin:            ObjectId,
+    /// 0 = invisible and non-solid, 1 = visible and non-solid, 2 = normal
+    pub flags:           u8,
+    /// In percent of the maximum life.
+    pub life:            u8,
+    /// Index of a random item table in the map info, or -1. TFT only.
+    pub item_table:      i32,
+    /// Every set drops one of its items when the destructable dies. TFT only.
+    pub item_sets:       Vec<Vec<DroppedItem>>,
+    /// Unique among all placed doodads. The map script refers to them by it.
+    pub creation_number: u32,
+}
+
+impl PlacedDoodad {
+    /// A doodad with the defaults the World Editor uses for new doodads.
+    pub fn new(id: ObjectId, x: f32, y: f32) -> PlacedDoodad {
+        PlacedDoodad {
+            id,
+            variation: 0,
+            x,
+            y,
+            z: 0.0,
+            facing: 0.0,
+            scale: [1.0; 3],
+            skin: id,
+            flags: 2,
+            life: 100,
+            item_table: -1,
+            item_sets: Vec::new(),
+            creation_number: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// A doodad which is part of the terrain, e.g. a cliff or a ramp,
+/// and which the map script cannot refer to.
+pub struct SpecialDoodad {
+    pub id:        ObjectId,
+    pub variation: u32,
+    /// In tiles.
+    pub x:         u32,
+    pub y:         u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3map.doo`.
+pub struct DoodadsFile {
+    /// 7 (RoC and TFT) or 8 (Reforged).
+    pub version:         u32,
+    /// 9 (RoC) or 11 (TFT and later).
+    pub subversion:      u32,
+    pub doodads:         Vec<PlacedDoodad>,
+    pub special_version: u32,
+    pub special_doodads: Vec<SpecialDoodad>,
+}
+
+impl Default for DoodadsFile {
+    fn default() -> DoodadsFile {
+        DoodadsFile {
+            version:         8,
+            subversion:      11,
+            doodads:         Vec::new(),
+            special_version: 0,
+            special_doodads: Vec::new(),
+        }
+    }
+}
+
+impl DoodadsFile {
+    pub fn doodad(&self, creation_number: u32) -> Option<&PlacedDoodad> {
+        self.doodads
+            .iter()
+            .find(|doodad| doodad.creation_number == creation_number)
+    }
+
+    /// Adds a doodad with a fresh creation number, which is returned.
+    pub fn add_doodad(&mut self, mut doodad: PlacedDoodad) -> u32 {
+        let creation_number = self
+            .doodads
+            .iter()
+            .map(|doodad| doodad.creation_number + 1)
+            .max()
+            .unwrap_or(0);
+
+        doodad.creation_number = creation_number;
+        self.doodads.push(doodad);
+
+        creation_number
+    }
+
+    pub fn remove_doodad(&mut self, creation_number: u32) -> Option<PlacedDoodad> {
+        let index = self
+            .doodads
+            .iter()
+            .position(|doodad| doodad.creation_number == creation_number)?;
+
+        Some(self.doodads.remove(index))
+    }
+}
@@ -26,3 +26,32 @@ impl ObjParseError {
         ObjParseError::UnterminatedString
     }
 }
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("IO error: {source}")]
+    Io {
+        #[from]
+        source: IoError,
+    },
+    #[error("C String is unterminated")]
+    UnterminatedString,
+    #[error("Invalid file magic, expected {expected}")]
+    InvalidMagic { expected: &'static str },
+    #[error("Unsupported {format} version {version}")]
+    UnsupportedVersion { format: &'static str, version: u32 },
+}
+
+impl FormatError {
+    pub fn unterminated_string() -> FormatError {
+        FormatError::UnterminatedString
+    }
+
+    pub fn invalid_magic(expected: &'static str) -> FormatError {
+        FormatError::InvalidMagic { expected }
+    }
+
+    pub fn unsupported_version(format: &'static str, version: u32) -> FormatError {
+        FormatError::UnsupportedVersion { format, version }
+    }
+}
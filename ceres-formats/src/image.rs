@@ -0,0 +1,190 @@
+/// An image with 8-bit RGBA pixels, stored row by row from the top left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width:  u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// The source pixels covering the destination pixels along one axis,
+/// with their weights. Shrinking averages over all covered pixels.
+fn axis_weights(from: u32, to: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = from as f32 / to as f32;
+
+    (0..to)
+        .map(|i| {
+            if scale <= 1.0 {
+                let source = ((i as f32 + 0.5) * scale) as usize;
+                return vec![(source.min(from as usize - 1), 1.0)];
+            }
+
+            let start = i as f32 * scale;
+            let end = start + scale;
+            let mut weights = Vec::new();
+            let mut source = start.floor();
+
+            while source < end {
+                let weight = (source + 1.0).min(end) - source.max(start);
+                if weight > 0.0 && (source as u32) < from {
+                    weights.push((source as usize, weight / scale));
+                }
+                source += 1.0;
+            }
+
+            weights
+        })
+        .collect()
+}
+
+impl Image {
+    /// A fully transparent image.
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    /// Returns `None` if the amount of pixels does not match the size.
+    pub fn from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Option<Image> {
+        if pixels.len() != (width * height * 4) as usize {
+            return None;
+        }
+
+        Some(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.pixels[i..i + 4]);
+
+        pixel
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: [u8; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&pixel);
+    }
+
+    /// Whether any pixel is not fully opaque.
+    pub fn has_alpha(&self) -> bool {
+        self.pixels.chunks(4).any(|pixel| pixel[3] != 255)
+    }
+
+    /// Scales the image to the given size. Colors are weighted by their alpha,
+    /// so that transparent pixels do not darken the edges.
+    pub fn resized(&self, width: u32, height: u32) -> Image {
+        let width = width.max(1);
+        let height = height.max(1);
+        let weights_x = axis_weights(self.width, width);
+        let weights_y = axis_weights(self.height, height);
+        let mut result = Image::new(width, height);
+
+        for (y, weights_y) in weights_y.iter().enumerate() {
+            for (x, weights_x) in weights_x.iter().enumerate() {
+                let mut sum = [0.0f32; 4];
+
+                for (sy, wy) in weights_y {
+                    for (sx, wx) in weights_x {
+                        let pixel = self.pixel(*sx as u32, *sy as u32);
+                        let weight = wx * wy;
+                        let alpha = f32::from(pixel[3]) * weight;
+
+                        for (total, channel) in sum.iter_mut().zip(&pixel[..3]) {
+                            *total += f32::from(*channel) * alpha;
+                        }
+                        sum[3] += alpha;
+                    }
+                }
+
+                let mut pixel = [0; 4];
+                if sum[3] > 0.0 {
+                    for (channel, total) in pixel.iter_mut().zip(&sum[..3]) {
+                        *channel = (total / sum[3]).round().min(255.0) as u8;
+                    }
+                    pixel[3] = sum[3].round().min(255.0) as u8;
+                }
+
+                result.set_pixel(x as u32, y as u32, pixel);
+            }
+        }
+
+        result
+    }
+
+    /// The image followed by its mipmaps, each half the size of the previous one,
+    /// down to a single pixel.
+    pub fn mipmaps(&self) -> Vec<Image> {
+        let mut mipmaps = vec![self.clone()];
+
+        loop {
+            let last = mipmaps.last().unwrap();
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+
+            let next = last.resized((last.width / 2).max(1), (last.height / 2).max(1));
+            mipmaps.push(next);
+        }
+
+        mipmaps
+    }
+
+    /// The image in grayscale at half brightness, which is how the game
+    /// shows disabled command buttons (`DISBTN` icons).
+    pub fn disabled(&self) -> Image {
+        let mut result = self.clone();
+
+        for pixel in result.pixels.chunks_mut(4) {
+            let gray = 0.299 * f32::from(pixel[0])
+                + 0.587 * f32::from(pixel[1])
+                + 0.114 * f32::from(pixel[2]);
+            let gray = (gray * 0.5).round() as u8;
+
+            pixel[0] = gray;
+            pixel[1] = gray;
+            pixel[2] = gray;
+        }
+
+        result
+    }
+
+    /// The image inside the dark frame which sets passive abilities
+    /// (`PASBTN` icons) apart from ones which can be cast.
+    pub fn with_passive_frame(&self) -> Image {
+        let mut result = self.clone();
+        let size = self.width.min(self.height);
+        // 3 and 2 pixels for the usual 64x64 icons
+        let outer = (size * 3 / 64).max(1);
+        let inner = (size * 2 / 64).max(1);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let distance = x.min(y).min(self.width - 1 - x).min(self.height - 1 - y);
+                let brightness = if distance < outer {
+                    0.15
+                } else if distance < outer + inner {
+                    0.5
+                } else {
+                    continue;
+                };
+
+                let mut pixel = self.pixel(x, y);
+                for c in pixel.iter_mut().take(3) {
+                    *c = (f32::from(*c) * brightness).round() as u8;
+                }
+                pixel[3] = 255;
+
+                result.set_pixel(x, y, pixel);
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Directory which imports with a standard path are placed in.
+pub const IMPORT_DIRECTORY: &str = "war3mapImported\\";
+
+/// Flag of imports whose path is the full path inside the archive,
+/// as written by current versions of the World Editor.
+pub const FLAG_CUSTOM_PATH: u8 = 13;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Import {
+    /// 5 or 8 if `path` is relative to `war3mapImported\`,
+    /// 10 or 13 if it is the full path.
+    pub flag: u8,
+    pub path: String,
+}
+
+impl Import {
+    /// An import of the file at `path` inside the archive.
+    pub fn new(path: String) -> Import {
+        Import {
+            flag: FLAG_CUSTOM_PATH,
+            path,
+        }
+    }
+
+    pub fn has_custom_path(&self) -> bool {
+        self.flag == 10 || self.flag == 13
+    }
+
+    /// The path of the imported file inside the archive.
+    pub fn archive_path(&self) -> String {
+        if self.has_custom_path() {
+            self.path.clone()
+        } else {
+            format!("{}{}", IMPORT_DIRECTORY, self.path)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3map.imp`, the files the World Editor lists in the
+/// import manager. It drops files which are not listed when the map is saved.
+pub struct ImportsFile {
+    pub version: u32,
+    pub imports: Vec<Import>,
+}
+
+impl Default for ImportsFile {
+    fn default() -> ImportsFile {
+        ImportsFile {
+            version: 1,
+            imports: Vec::new(),
+        }
+    }
+}
@@ -9,12 +9,42 @@ pub mod parser {
     pub mod crlf;
     pub mod profile;
     pub mod w3obj;
+    pub mod binary;
+    pub mod w3x;
+    pub mod w3i;
+    pub mod doo;
+    pub mod w3e;
+    pub mod w3r;
+    pub mod w3c;
+    pub mod w3s;
+    pub mod wts;
+    pub mod imp;
+    pub mod blp;
+    pub mod tga;
+    pub mod mdx;
+    pub mod mdl;
+    pub mod mmp;
+    pub(crate) mod jpeg;
 }
 
 pub mod error;
 pub mod metadata;
 pub mod object;
 pub mod objectstore;
+pub mod mapheader;
+pub mod mapinfo;
+pub mod units;
+pub mod doodads;
+pub mod terrain;
+pub mod regions;
+pub mod cameras;
+pub mod sounds;
+pub mod triggerstrings;
+pub mod imports;
+pub mod image;
+pub mod blp;
+pub mod model;
+pub mod minimap;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 /// A WC3 object id, which is conceptually a simple 32-bit integer,
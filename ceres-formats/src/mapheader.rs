@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The 512-byte `HM3W` header which precedes the MPQ archive
+/// in a WC3 map file, as well as the optional `NGIS` footer
+/// which holds the map's authentication signature.
+///
+/// WC3 itself uses `war3map.w3i` for all of this information,
+/// but the header is what shows up in the map list before the map is loaded.
+pub struct MapHeader {
+    pub unknown:     u32,
+    pub name:        String,
+    pub flags:       u32,
+    pub max_players: u32,
+    pub signature:   Option<Vec<u8>>,
+}
+
+impl Default for MapHeader {
+    fn default() -> MapHeader {
+        MapHeader {
+            unknown:     0,
+            name:        "Just another Warcraft III map".into(),
+            flags:       0,
+            max_players: 1,
+            signature:   None,
+        }
+    }
+}
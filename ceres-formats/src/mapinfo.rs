@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ObjectId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptLanguage {
+    Jass,
+    Lua,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoadingScreen {
+    /// Index of a preset loading screen, or -1 if there is none.
+    pub number:   i32,
+    /// Path of a custom loading screen model. TFT and later only.
+    pub model:    String,
+    pub text:     String,
+    pub title:    String,
+    pub subtitle: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Prologue {
+    /// Path of a custom prologue screen model. TFT and later only.
+    pub model:    String,
+    pub text:     String,
+    pub title:    String,
+    pub subtitle: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// Terrain fog settings. TFT and later only.
+pub struct Fog {
+    /// 0 if the map does not use fog, otherwise the fog style.
+    pub style:   u32,
+    pub start_z: f32,
+    pub end_z:   f32,
+    pub density: f32,
+    /// RGBA
+    pub color:   [u8; 4],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub id:                    u32,
+    /// 1 = human, 2 = computer, 3 = neutral, 4 = rescuable
+    pub kind:                  u32,
+    /// 1 = human, 2 = orc, 3 = undead, 4 = night elf, 5 = selectable
+    pub race:                  u32,
+    pub fixed_start_position:  bool,
+    pub name:                  String,
+    pub start_x:               f32,
+    pub start_y:               f32,
+    /// Bit `x` is set for every player `x` with low/high ally priority.
+    pub ally_low_priorities:   u32,
+    pub ally_high_priorities:  u32,
+    /// Reforged (version 31) only.
+    pub enemy_low_priorities:  u32,
+    pub enemy_high_priorities: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ForceInfo {
+    /// 0x1 allied, 0x2 allied victory, 0x4 share vision,
+    /// 0x10 share unit control, 0x20 share advanced unit control
+    pub flags:   u32,
+    /// Bit `x` is set for every player `x` in the force.
+    pub players: u32,
+    pub name:    String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpgradeAvailability {
+    /// Bit `x` is set for every player `x` this applies to.
+    pub players:      u32,
+    pub id:           ObjectId,
+    /// Zero-based upgrade level.
+    pub level:        u32,
+    /// 0 = unavailable, 1 = available, 2 = researched
+    pub availability: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A unit, item or ability which is made unavailable.
+pub struct TechAvailability {
+    pub players: u32,
+    pub id:      ObjectId,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RandomUnitRow {
+    pub chance: u32,
+    /// One ID per column.
+    pub ids:    Vec<ObjectId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RandomUnitTable {
+    pub id:      u32,
+    pub name:    String,
+    /// 0 = unit, 1 = building, 2 = item
+    pub columns: Vec<u32>,
+    pub rows:    Vec<RandomUnitRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RandomItem {
+    pub chance: u32,
+    pub id:     ObjectId,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RandomItemTable {
+    pub id:   u32,
+    pub name: String,
+    /// Every set drops one of its items.
+    pub sets: Vec<Vec<RandomItem>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3map.w3i`, the map info file.
+///
+/// Fields which do not exist in the format version of the map
+/// are kept at their defaults, and are not written.
+pub struct MapInfo {
+    /// 18 (RoC), 25 (TFT), 28 (1.31) or 31 (Reforged).
+    pub version:             u32,
+    /// Number of times the map was saved.
+    pub map_version:         u32,
+    pub editor_version:      u32,
+    /// Major, minor, patch and build of the game which saved the map.
+    /// Version 27 and later only.
+    pub game_version:        [u32; 4],
+    pub name:                String,
+    pub author:              String,
+    pub description:         String,
+    pub suggested_players:   String,
+    pub camera_bounds:       [f32; 8],
+    pub camera_complements:  [i32; 4],
+    pub playable_width:      u32,
+    pub playable_height:     u32,
+    pub flags:               u32,
+    pub tileset:             u8,
+    pub loading_screen:      LoadingScreen,
+    /// RoC only.
+    pub campaign_background: i32,
+    /// TFT and later only.
+    pub game_data_set:       u32,
+    pub prologue:            Prologue,
+    pub fog:                 Fog,
+    /// Weather ID, or 0 if there is none. TFT and later only.
+    pub weather:             u32,
+    pub sound_environment:   String,
+    /// Tileset of the custom light environment, or 0 if there is none.
+    pub light_tileset:       u8,
+    /// RGBA
+    pub water_color:         [u8; 4],
+    /// Version 28 and later only, older maps always use JASS.
+    pub script_language:     ScriptLanguage,
+    /// 1 = SD, 2 = HD, 3 = both. Reforged only.
+    pub supported_modes:     u32,
+    /// 0 = RoC, 1 = TFT. Reforged only.
+    pub game_data_version:   u32,
+    pub players:             Vec<PlayerInfo>,
+    pub forces:              Vec<ForceInfo>,
+    pub upgrades:            Vec<UpgradeAvailability>,
+    pub techs:               Vec<TechAvailability>,
+    pub random_unit_tables:  Vec<RandomUnitTable>,
+    /// TFT and later only.
+    pub random_item_tables:  Vec<RandomItemTable>,
+}
+
+impl MapInfo {
+    pub fn is_tft(&self) -> bool {
+        self.version >= 25
+    }
+}
@@ -56,6 +56,32 @@ pub struct FieldDesc {
     pub is_profile:   bool,
 }
 
+/// Field types whose values are paths to game or imported files.
+/// List types hold several comma-separated paths.
+const PATH_VALUE_TYPES: &[&str] = &[
+    "model",
+    "modelList",
+    "icon",
+    "texture",
+    "pathingTexture",
+    "shadowImage",
+    "shadowTexture",
+];
+
+impl FieldDesc {
+    /// Whether the value of this field refers to files,
+    /// e.g. models or icons.
+    pub fn is_path(&self) -> bool {
+        PATH_VALUE_TYPES.contains(&self.value_ty_raw.as_str())
+    }
+
+    /// Whether this field holds text shown to players, e.g. names and tooltips,
+    /// which is what gets translated when localizing a map.
+    pub fn is_text(&self) -> bool {
+        self.is_profile && self.value_ty_raw == "string"
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum FieldVariant {
     Normal { name: String },
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// Size of the minimap image, `war3mapMap.blp`, in pixels.
+pub const MINIMAP_SIZE: u32 = 256;
+
+/// Kinds of minimap icons.
+pub mod icons {
+    pub const GOLD_MINE: u32 = 0;
+    pub const NEUTRAL_BUILDING: u32 = 1;
+    pub const START_LOCATION: u32 = 2;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinimapIcon {
+    /// See `icons`.
+    pub kind:  u32,
+    /// Position on the minimap image, from the top left.
+    pub x:     u32,
+    pub y:     u32,
+    /// RGBA.
+    pub color: [u8; 4],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3map.mmp`, the icons drawn on the minimap
+/// of the map preview and the loading screen.
+pub struct MinimapIcons {
+    pub version: u32,
+    pub icons:   Vec<MinimapIcon>,
+}
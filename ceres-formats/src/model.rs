@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// A bounding box and the radius of the bounding sphere.
+pub struct Extent {
+    pub min:    [f32; 3],
+    pub max:    [f32; 3],
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Texture {
+    /// Non-zero for team colors and other textures chosen by the game,
+    /// e.g. 1 for the team color and 2 for the team glow.
+    pub replaceable_id: u32,
+    /// Empty for replaceable textures.
+    pub path:           String,
+    /// 1 to wrap horizontally, 2 to wrap vertically.
+    pub flags:          u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// An animation, e.g. `Stand` or `Attack - 2`.
+pub struct Sequence {
+    pub name:        String,
+    /// Start and end, in milliseconds.
+    pub interval:    [u32; 2],
+    pub move_speed:  f32,
+    pub non_looping: bool,
+    pub rarity:      f32,
+    pub extent:      Extent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A point effects can be attached to, e.g. `Overhead Ref`.
+pub struct Attachment {
+    pub name:          String,
+    pub object_id:     u32,
+    pub attachment_id: u32,
+    /// Model shown at the attachment point, usually empty.
+    pub path:          String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// The parts of an MDX or MDL model which refer to other files or are
+/// referred to by the map, like animation names. Geometry is not read.
+pub struct Model {
+    pub version:     u32,
+    pub name:        String,
+    pub extent:      Extent,
+    pub textures:    Vec<Texture>,
+    pub sequences:   Vec<Sequence>,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Model {
+    /// Paths of the textures the model loads, without replaceable textures.
+    pub fn texture_paths(&self) -> impl Iterator<Item = &str> {
+        self.textures
+            .iter()
+            .filter(|texture| texture.replaceable_id == 0 && !texture.path.is_empty())
+            .map(|texture| texture.path.as_str())
+    }
+}
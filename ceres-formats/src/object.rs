@@ -2,6 +2,7 @@ use std::collections::BTreeMap;
 
 use serde::{Deserialize, Serialize};
 
+use crate::metadata::FieldDesc;
 use crate::metadata::FieldVariant;
 use crate::metadata::MetadataStore;
 use crate::ObjectId;
@@ -55,6 +56,18 @@ pub struct Field {
     pub kind: FieldKind,
 }
 
+fn string_values_of(field: &mut Field) -> impl Iterator<Item = &mut String> {
+    let values = match &mut field.kind {
+        FieldKind::Simple { value } => vec![value],
+        FieldKind::Leveled { values } => values.iter_mut().map(|v| &mut v.value).collect(),
+    };
+
+    values.into_iter().filter_map(|value| match value {
+        Value::String(value) => Some(value),
+        _ => None,
+    })
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Object {
     kind:      ObjectKind,
@@ -117,6 +130,27 @@ impl Object {
         self.fields.iter()
     }
 
+    /// All string values of the object, including every level of leveled fields.
+    pub fn string_values_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        self.fields.values_mut().flat_map(string_values_of)
+    }
+
+    /// The string values of fields which are shown to players, like names and tooltips.
+    pub fn text_values_mut<'a>(
+        &'a mut self,
+        metadata: &'a MetadataStore,
+    ) -> impl Iterator<Item = &'a mut String> + 'a {
+        self.fields
+            .values_mut()
+            .filter(move |field| {
+                metadata
+                    .field_by_id(field.id)
+                    .map(FieldDesc::is_text)
+                    .unwrap_or(false)
+            })
+            .flat_map(string_values_of)
+    }
+
     pub fn field(&self, id: ObjectId) -> Option<&Field> {
         self.fields.get(&id)
     }
@@ -0,0 +1,46 @@
+//! Helpers shared by the binary WC3 file format parsers.
+
+use std::io::{Error as IoError, ErrorKind, Write};
+
+use byteorder::WriteBytesExt;
+
+use crate::error::FormatError;
+
+/// Reads a null-terminated string, replacing invalid UTF-8 sequences.
+pub fn read_cstring(source: &mut &[u8]) -> Result<String, FormatError> {
+    let end = source
+        .iter()
+        .position(|c| *c == 0)
+        .ok_or_else(FormatError::unterminated_string)?;
+    let result = String::from_utf8_lossy(&source[..end]).into_owned();
+    *source = &source[end + 1..];
+
+    Ok(result)
+}
+
+/// Writes a null-terminated string. Anything after an embedded
+/// null byte is dropped, since it could not be read back.
+pub fn write_cstring<W: Write>(mut writer: W, string: &str) -> Result<(), IoError> {
+    for c in string.as_bytes() {
+        if *c == 0 {
+            break;
+        }
+
+        writer.write_u8(*c)?;
+    }
+    writer.write_u8(0)?;
+
+    Ok(())
+}
+
+/// Reads a fixed amount of bytes.
+pub fn read_bytes<'src>(source: &mut &'src [u8], count: usize) -> Result<&'src [u8], FormatError> {
+    if source.len() < count {
+        return Err(IoError::new(ErrorKind::UnexpectedEof, "unexpected end of file").into());
+    }
+
+    let result = &source[..count];
+    *source = &source[count..];
+
+    Ok(result)
+}
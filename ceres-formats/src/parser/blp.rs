@@ -0,0 +1,464 @@
+//! Reading and writing of BLP1 textures.
+
+const MAGIC: &[u8] = b"BLP1";
+const MAX_MIPMAPS: usize = 16;
+
+const COMPRESSION_JPEG: u32 = 0;
+const COMPRESSION_PALETTE: u32 = 1;
+
+/// Size of the header, which is followed by the mipmap offsets and sizes.
+const HEADER_SIZE: usize = 4 + 6 * 4 + MAX_MIPMAPS * 4 * 2;
+
+/// Size of the mipmap at `level`, given the size of the full image.
+fn mipmap_size(width: u32, height: u32, level: usize) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use byteorder::{ReadBytesExt, LE};
+
+    use crate::blp::*;
+    use crate::error::FormatError;
+    use crate::image::Image;
+    use crate::parser::binary::read_bytes;
+    use crate::parser::jpeg;
+
+    use super::*;
+
+    fn invalid(message: &str) -> FormatError {
+        IoError::new(ErrorKind::InvalidData, message.to_string()).into()
+    }
+
+    fn read_jpeg_mipmap(header: &[u8], data: &[u8], alpha_bits: u8) -> Result<Image, FormatError> {
+        let mut source = header.to_vec();
+        source.extend_from_slice(data);
+
+        let decoded = jpeg::decode(&source)?;
+        if decoded.components < 3 {
+            return Err(invalid("BLP JPEG mipmaps must have at least 3 components"));
+        }
+
+        let pixels = decoded
+            .data
+            .chunks(decoded.components)
+            .flat_map(|bgra| {
+                let alpha = if alpha_bits > 0 && bgra.len() > 3 {
+                    bgra[3]
+                } else {
+                    255
+                };
+
+                vec![bgra[2], bgra[1], bgra[0], alpha]
+            })
+            .collect();
+
+        Ok(Image::from_rgba(decoded.width, decoded.height, pixels).unwrap())
+    }
+
+    fn read_palette_mipmap(
+        palette: &[[u8; 4]],
+        mut data: &[u8],
+        width: u32,
+        height: u32,
+        alpha_bits: u8,
+    ) -> Result<Image, FormatError> {
+        let count = (width * height) as usize;
+        let indices = read_bytes(&mut data, count)?;
+        let alpha: Vec<u8> = match alpha_bits {
+            0 => vec![255; count],
+            1 => {
+                let bits = read_bytes(&mut data, count.div_ceil(8))?;
+                (0..count)
+                    .map(|i| ((bits[i / 8] >> (i % 8)) & 1) * 255)
+                    .collect()
+            }
+            4 => {
+                let nibbles = read_bytes(&mut data, count.div_ceil(2))?;
+                (0..count)
+                    .map(|i| ((nibbles[i / 2] >> ((i % 2) * 4)) & 0x0F) * 17)
+                    .collect()
+            }
+            8 => read_bytes(&mut data, count)?.to_vec(),
+            _ => return Err(invalid("unsupported amount of BLP alpha bits")),
+        };
+
+        let pixels = indices
+            .iter()
+            .zip(alpha)
+            .flat_map(|(index, alpha)| {
+                let bgra = palette[usize::from(*index)];
+
+                vec![bgra[2], bgra[1], bgra[0], alpha]
+            })
+            .collect();
+
+        Ok(Image::from_rgba(width, height, pixels).unwrap())
+    }
+
+    pub fn read_blp(mut source: &[u8]) -> Result<Blp, FormatError> {
+        let file = source;
+        let source = &mut source;
+
+        if read_bytes(source, 4)? != MAGIC {
+            return Err(FormatError::invalid_magic("BLP1"));
+        }
+
+        let compression = source.read_u32::<LE>()?;
+        let alpha_bits = source.read_u32::<LE>()? as u8;
+        let width = source.read_u32::<LE>()?;
+        let height = source.read_u32::<LE>()?;
+        let _extra = source.read_u32::<LE>()?;
+        let has_mipmaps = source.read_u32::<LE>()? != 0;
+
+        let mut offsets = [0; MAX_MIPMAPS];
+        source.read_u32_into::<LE>(&mut offsets)?;
+        let mut sizes = [0; MAX_MIPMAPS];
+        source.read_u32_into::<LE>(&mut sizes)?;
+
+        if width == 0 || height == 0 {
+            return Err(invalid("BLP has no pixels"));
+        }
+
+        let count = if has_mipmaps {
+            sizes.iter().take_while(|size| **size != 0).count().max(1)
+        } else {
+            1
+        };
+
+        let mipmap_data = |level: usize| -> Result<&[u8], FormatError> {
+            let start = offsets[level] as usize;
+            let end = start + sizes[level] as usize;
+
+            file.get(start..end)
+                .ok_or_else(|| invalid("BLP mipmap out of bounds"))
+        };
+
+        let (compression, mipmaps) = match compression {
+            COMPRESSION_JPEG => {
+                let header_size = source.read_u32::<LE>()? as usize;
+                let header = read_bytes(source, header_size)?;
+
+                let mipmaps = (0..count)
+                    .map(|level| read_jpeg_mipmap(header, mipmap_data(level)?, alpha_bits))
+                    .collect::<Result<Vec<_>, FormatError>>()?;
+
+                (Compression::Jpeg, mipmaps)
+            }
+            COMPRESSION_PALETTE => {
+                let mut palette = [[0; 4]; 256];
+                for color in palette.iter_mut() {
+                    *color = source.read_u32::<LE>()?.to_le_bytes();
+                }
+
+                let mipmaps = (0..count)
+                    .map(|level| {
+                        let (width, height) = mipmap_size(width, height, level);
+
+                        read_palette_mipmap(
+                            &palette,
+                            mipmap_data(level)?,
+                            width,
+                            height,
+                            alpha_bits,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, FormatError>>()?;
+
+                (Compression::Palette, mipmaps)
+            }
+            _ => return Err(invalid("unknown BLP compression")),
+        };
+
+        Ok(Blp {
+            compression,
+            alpha_bits,
+            mipmaps,
+        })
+    }
+}
+
+pub mod write {
+    use std::collections::HashMap;
+    use std::io::{Error as IoError, ErrorKind, Write};
+
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::blp::*;
+    use crate::image::Image;
+    use crate::parser::jpeg;
+
+    use super::*;
+
+    type Color = [u8; 3];
+
+    /// Splits the colors of an image into up to 256 boxes by median cut,
+    /// and returns the average color of every box.
+    fn quantize(image: &Image) -> Vec<Color> {
+        let mut counts: HashMap<Color, u32> = HashMap::new();
+        for pixel in image.pixels.chunks(4) {
+            *counts.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+        }
+
+        let mut boxes: Vec<Vec<(Color, u32)>> = vec![counts.into_iter().collect()];
+
+        while boxes.len() < 256 {
+            let channel_range = |colors: &[(Color, u32)], channel: usize| {
+                let min = colors.iter().map(|(c, _)| c[channel]).min().unwrap_or(0);
+                let max = colors.iter().map(|(c, _)| c[channel]).max().unwrap_or(0);
+
+                max - min
+            };
+
+            // the box with the widest channel is split along it
+            let widest = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, colors)| colors.len() > 1)
+                .flat_map(|(i, colors)| (0..3).map(move |c| (i, c, channel_range(colors, c))))
+                .max_by_key(|(_, _, range)| *range);
+
+            let (index, channel) = match widest {
+                Some((index, channel, _)) => (index, channel),
+                None => break,
+            };
+
+            let mut colors = boxes.swap_remove(index);
+            colors.sort_by_key(|(color, _)| color[channel]);
+
+            let total: u32 = colors.iter().map(|(_, count)| count).sum();
+            let mut sum = 0;
+            let median = colors
+                .iter()
+                .position(|(_, count)| {
+                    sum += count;
+                    sum * 2 >= total
+                })
+                .unwrap_or(0);
+
+            let upper = colors.split_off((median + 1).max(1).min(colors.len() - 1));
+            boxes.push(colors);
+            boxes.push(upper);
+        }
+
+        boxes
+            .iter()
+            .map(|colors| {
+                let total: u64 = colors.iter().map(|(_, count)| u64::from(*count)).sum();
+                let mut color = [0; 3];
+
+                for (channel, value) in color.iter_mut().enumerate() {
+                    let sum: u64 = colors
+                        .iter()
+                        .map(|(c, count)| u64::from(c[channel]) * u64::from(*count))
+                        .sum();
+                    *value = ((sum + total / 2) / total.max(1)) as u8;
+                }
+
+                color
+            })
+            .collect()
+    }
+
+    fn nearest(palette: &[Color], color: Color) -> u8 {
+        let distance = |other: &Color| -> i32 {
+            (0..3)
+                .map(|c| (i32::from(other[c]) - i32::from(color[c])).pow(2))
+                .sum()
+        };
+
+        palette
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, other)| distance(other))
+            .map(|(index, _)| index as u8)
+            .unwrap_or(0)
+    }
+
+    fn palette_mipmap(
+        image: &Image,
+        palette: &[Color],
+        cache: &mut HashMap<Color, u8>,
+        alpha_bits: u8,
+    ) -> Vec<u8> {
+        let mut data: Vec<u8> = image
+            .pixels
+            .chunks(4)
+            .map(|pixel| {
+                let color = [pixel[0], pixel[1], pixel[2]];
+
+                *cache
+                    .entry(color)
+                    .or_insert_with(|| nearest(palette, color))
+            })
+            .collect();
+
+        let alpha = image.pixels.chunks(4).map(|pixel| pixel[3]);
+        match alpha_bits {
+            1 => {
+                let mut bits = vec![0; data.len().div_ceil(8)];
+                for (i, alpha) in alpha.enumerate() {
+                    bits[i / 8] |= u8::from(alpha >= 128) << (i % 8);
+                }
+                data.extend(bits);
+            }
+            4 => {
+                let mut nibbles = vec![0; data.len().div_ceil(2)];
+                for (i, alpha) in alpha.enumerate() {
+                    nibbles[i / 2] |= ((alpha + 8) / 17) << ((i % 2) * 4);
+                }
+                data.extend(nibbles);
+            }
+            8 => data.extend(alpha),
+            _ => {}
+        }
+
+        data
+    }
+
+    /// Writes the texture. `quality` is the JPEG quality from 1 to 100,
+    /// and is ignored for palette textures.
+    pub fn write_blp<W: Write>(mut writer: W, blp: &Blp, quality: u8) -> Result<(), IoError> {
+        if blp.mipmaps.is_empty() || blp.mipmaps.len() > MAX_MIPMAPS {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "BLP textures need between 1 and 16 mipmaps",
+            ));
+        }
+
+        if ![0, 1, 4, 8].contains(&blp.alpha_bits) {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "BLP textures have 0, 1, 4 or 8 alpha bits",
+            ));
+        }
+
+        let (compression, preamble, mipmaps) = match blp.compression {
+            Compression::Jpeg => {
+                let tables = jpeg::encode_tables(quality);
+                let mut preamble = Vec::new();
+                preamble.write_u32::<LE>(tables.len() as u32)?;
+                preamble.extend(tables);
+
+                let mipmaps = blp
+                    .mipmaps
+                    .iter()
+                    .map(|image| {
+                        let bgra: Vec<u8> = image
+                            .pixels
+                            .chunks(4)
+                            .flat_map(|rgba| {
+                                let alpha = if blp.alpha_bits > 0 { rgba[3] } else { 255 };
+
+                                vec![rgba[2], rgba[1], rgba[0], alpha]
+                            })
+                            .collect();
+
+                        jpeg::encode_image(image.width, image.height, 4, &bgra, quality)
+                    })
+                    .collect::<Vec<_>>();
+
+                (COMPRESSION_JPEG, preamble, mipmaps)
+            }
+            Compression::Palette => {
+                let palette = quantize(&blp.mipmaps[0]);
+                let mut preamble = Vec::new();
+                for index in 0..256 {
+                    let [r, g, b] = palette.get(index).copied().unwrap_or([0; 3]);
+                    preamble.extend_from_slice(&[b, g, r, 0]);
+                }
+
+                let mut cache = HashMap::new();
+                let mipmaps = blp
+                    .mipmaps
+                    .iter()
+                    .map(|image| palette_mipmap(image, &palette, &mut cache, blp.alpha_bits))
+                    .collect::<Vec<_>>();
+
+                (COMPRESSION_PALETTE, preamble, mipmaps)
+            }
+        };
+
+        let extra = if blp.compression == Compression::Palette && blp.alpha_bits > 0 {
+            4
+        } else {
+            5
+        };
+
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LE>(compression)?;
+        writer.write_u32::<LE>(u32::from(blp.alpha_bits))?;
+        writer.write_u32::<LE>(blp.mipmaps[0].width)?;
+        writer.write_u32::<LE>(blp.mipmaps[0].height)?;
+        writer.write_u32::<LE>(extra)?;
+        writer.write_u32::<LE>((blp.mipmaps.len() > 1) as u32)?;
+
+        let mut offset = HEADER_SIZE + preamble.len();
+        for index in 0..MAX_MIPMAPS {
+            let size = mipmaps.get(index).map(Vec::len).unwrap_or(0);
+            writer.write_u32::<LE>(if size > 0 { offset as u32 } else { 0 })?;
+            offset += size;
+        }
+        for index in 0..MAX_MIPMAPS {
+            let size = mipmaps.get(index).map(Vec::len).unwrap_or(0);
+            writer.write_u32::<LE>(size as u32)?;
+        }
+
+        writer.write_all(&preamble)?;
+        for mipmap in &mipmaps {
+            writer.write_all(mipmap)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::blp::*;
+    use crate::image::Image;
+
+    use super::*;
+
+    fn test_image() -> Image {
+        let mut image = Image::new(16, 8);
+        for y in 0..8 {
+            for x in 0..16 {
+                let alpha = if x < 2 { 0 } else { 255 };
+                image.set_pixel(x, y, [(x * 16) as u8, (y * 32) as u8, 96, alpha]);
+            }
+        }
+
+        image
+    }
+
+    #[test]
+    fn blp_roundtrip() {
+        // palette textures are lossless as long as the image has at most 256 colors
+        let blp = Blp::new(test_image(), Compression::Palette, true);
+        assert_eq!(blp.mipmaps.len(), 5);
+        assert_eq!(blp.alpha_bits, 8);
+
+        let mut buf = Vec::new();
+        write::write_blp(&mut buf, &blp, 80).unwrap();
+        let read = read::read_blp(&buf).unwrap();
+        assert_eq!(read.mipmaps.len(), 5);
+        assert_eq!(read.mipmaps[0], blp.mipmaps[0]);
+        assert_eq!(read.mipmaps[4].width, 1);
+
+        let blp = Blp::new(test_image(), Compression::Jpeg, false);
+        let mut buf = Vec::new();
+        write::write_blp(&mut buf, &blp, 100).unwrap();
+        let read = read::read_blp(&buf).unwrap();
+
+        assert_eq!(read.compression, Compression::Jpeg);
+        assert_eq!((read.width(), read.height()), (16, 8));
+
+        let original = &blp.mipmaps[0].pixels;
+        let decoded = &read.mipmaps[0].pixels;
+        for (a, b) in original.iter().zip(decoded) {
+            assert!((i32::from(*a) - i32::from(*b)).abs() <= 8);
+        }
+    }
+}
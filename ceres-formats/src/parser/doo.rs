@@ -0,0 +1,645 @@
+//! Reading and writing of the placement files `war3map.doo` (doodads
+//! and destructables) and `war3mapUnits.doo` (units and items).
+
+const MAGIC: &[u8] = b"W3do";
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use byteorder::{ReadBytesExt, BE, LE};
+
+    use crate::error::FormatError;
+    use crate::doodads::*;
+    use crate::parser::binary::read_bytes;
+    use crate::units::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn read_id(source: &mut &[u8]) -> Result<ObjectId, FormatError> {
+        Ok(ObjectId::new(source.read_u32::<BE>()?))
+    }
+
+    fn read_header(source: &mut &[u8]) -> Result<(u32, u32), FormatError> {
+        if read_bytes(source, MAGIC.len())? != MAGIC {
+            return Err(FormatError::invalid_magic("W3do"));
+        }
+
+        let version = source.read_u32::<LE>()?;
+        if version != 7 && version != 8 {
+            return Err(FormatError::unsupported_version("doo", version));
+        }
+
+        let subversion = source.read_u32::<LE>()?;
+
+        Ok((version, subversion))
+    }
+
+    fn read_item_sets(source: &mut &[u8]) -> Result<Vec<Vec<DroppedItem>>, FormatError> {
+        let set_count = source.read_u32::<LE>()?;
+        let mut sets = Vec::new();
+
+        for _ in 0..set_count {
+            let item_count = source.read_u32::<LE>()?;
+            let mut items = Vec::new();
+
+            for _ in 0..item_count {
+                items.push(DroppedItem {
+                    id:     read_id(source)?,
+                    chance: source.read_u32::<LE>()?,
+                });
+            }
+
+            sets.push(items);
+        }
+
+        Ok(sets)
+    }
+
+    fn read_random(source: &mut &[u8]) -> Result<RandomUnit, FormatError> {
+        let random = match source.read_u32::<LE>()? {
+            0 => RandomUnit::Level {
+                level: source.read_u24::<LE>()?,
+                class: source.read_u8()?,
+            },
+            1 => RandomUnit::Group {
+                group:    source.read_u32::<LE>()?,
+                position: source.read_u32::<LE>()?,
+            },
+            2 => {
+                let count = source.read_u32::<LE>()?;
+                let mut units = Vec::new();
+
+                for _ in 0..count {
+                    units.push(DroppedItem {
+                        id:     read_id(source)?,
+                        chance: source.read_u32::<LE>()?,
+                    });
+                }
+
+                RandomUnit::Custom(units)
+            }
+            kind => {
+                let message = format!("unknown random unit kind {}", kind);
+
+                return Err(IoError::new(ErrorKind::InvalidData, message).into());
+            }
+        };
+
+        Ok(random)
+    }
+
+    fn read_unit(
+        source: &mut &[u8],
+        version: u32,
+        subversion: u32,
+    ) -> Result<PlacedUnit, FormatError> {
+        let id = read_id(source)?;
+        let mut unit = PlacedUnit::new(id, 0.0, 0.0);
+
+        unit.variation = source.read_u32::<LE>()?;
+        unit.x = source.read_f32::<LE>()?;
+        unit.y = source.read_f32::<LE>()?;
+        unit.z = source.read_f32::<LE>()?;
+        unit.facing = source.read_f32::<LE>()?;
+        for scale in &mut unit.scale {
+            *scale = source.read_f32::<LE>()?;
+        }
+
+        if version >= 8 {
+            unit.skin = read_id(source)?;
+        }
+
+        unit.flags = source.read_u8()?;
+        unit.owner = source.read_u32::<LE>()?;
+        unit.unknown = [source.read_u8()?, source.read_u8()?];
+        unit.hp = source.read_i32::<LE>()?;
+        unit.mana = source.read_i32::<LE>()?;
+
+        if subversion >= 11 {
+            unit.item_table = source.read_i32::<LE>()?;
+        }
+
+        unit.item_sets = read_item_sets(source)?;
+        unit.gold = source.read_u32::<LE>()?;
+        unit.target_acquisition = source.read_f32::<LE>()?;
+        unit.level = source.read_u32::<LE>()?;
+
+        if subversion >= 11 {
+            unit.strength = source.read_u32::<LE>()?;
+            unit.agility = source.read_u32::<LE>()?;
+            unit.intelligence = source.read_u32::<LE>()?;
+        }
+
+        let item_count = source.read_u32::<LE>()?;
+        for _ in 0..item_count {
+            unit.items.push(InventoryItem {
+                slot: source.read_u32::<LE>()?,
+                id:   read_id(source)?,
+            });
+        }
+
+        let ability_count = source.read_u32::<LE>()?;
+        for _ in 0..ability_count {
+            unit.abilities.push(UnitAbility {
+                id:       read_id(source)?,
+                autocast: source.read_u32::<LE>()? != 0,
+                level:    source.read_u32::<LE>()?,
+            });
+        }
+
+        unit.random = read_random(source)?;
+        unit.color = source.read_i32::<LE>()?;
+        unit.waygate = source.read_i32::<LE>()?;
+        unit.creation_number = source.read_u32::<LE>()?;
+
+        Ok(unit)
+    }
+
+    pub fn read_units(mut source: &[u8]) -> Result<UnitsFile, FormatError> {
+        let source = &mut source;
+        let (version, subversion) = read_header(source)?;
+
+        let count = source.read_u32::<LE>()?;
+        let units = (0..count)
+            .map(|_| read_unit(source, version, subversion))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(UnitsFile {
+            version,
+            subversion,
+            units,
+        })
+    }
+
+    fn read_doodad(
+        source: &mut &[u8],
+        version: u32,
+        subversion: u32,
+    ) -> Result<PlacedDoodad, FormatError> {
+        let id = read_id(source)?;
+        let mut doodad = PlacedDoodad::new(id, 0.0, 0.0);
+
+        doodad.variation = source.read_u32::<LE>()?;
+        doodad.x = source.read_f32::<LE>()?;
+        doodad.y = source.read_f32::<LE>()?;
+        doodad.z = source.read_f32::<LE>()?;
+        doodad.facing = source.read_f32::<LE>()?;
+        for scale in &mut doodad.scale {
+            *scale = source.read_f32::<LE>()?;
+        }
+
+        if version >= 8 {
+            doodad.skin = read_id(source)?;
+        }
+
+        doodad.flags = source.read_u8()?;
+        doodad.life = source.read_u8()?;
+
+        if subversion >= 11 {
+            doodad.item_table = source.read_i32::<LE>()?;
+            doodad.item_sets = read_item_sets(source)?;
+        }
+
+        doodad.creation_number = source.read_u32::<LE>()?;
+
+        Ok(doodad)
+    }
+
+    pub fn read_doodads(mut source: &[u8]) -> Result<DoodadsFile, FormatError> {
+        let source = &mut source;
+        let (version, subversion) = read_header(source)?;
+
+        let count = source.read_u32::<LE>()?;
+        let doodads = (0..count)
+            .map(|_| read_doodad(source, version, subversion))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let special_version = source.read_u32::<LE>()?;
+        let special_count = source.read_u32::<LE>()?;
+        let mut special_doodads = Vec::new();
+
+        for _ in 0..special_count {
+            special_doodads.push(SpecialDoodad {
+                id:        read_id(source)?,
+                variation: source.read_u32::<LE>()?,
+                x:         source.read_u32::<LE>()?,
+                y:         source.read_u32::<LE>()?,
+            });
+        }
+
+        Ok(DoodadsFile {
+            version,
+            subversion,
+            doodads,
+            special_version,
+            special_doodads,
+        })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use byteorder::{WriteBytesExt, BE, LE};
+
+    use crate::doodads::*;
+    use crate::units::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn write_id<W: Write>(mut writer: W, id: ObjectId) -> Result<(), IoError> {
+        writer.write_u32::<BE>(id.to_u32())
+    }
+
+    fn write_item_sets<W: Write>(mut writer: W, sets: &[Vec<DroppedItem>]) -> Result<(), IoError> {
+        writer.write_u32::<LE>(sets.len() as u32)?;
+
+        for set in sets {
+            writer.write_u32::<LE>(set.len() as u32)?;
+
+            for item in set {
+                write_id(&mut writer, item.id)?;
+                writer.write_u32::<LE>(item.chance)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_random<W: Write>(mut writer: W, random: &RandomUnit) -> Result<(), IoError> {
+        match random {
+            RandomUnit::Level { level, class } => {
+                writer.write_u32::<LE>(0)?;
+                writer.write_u24::<LE>(*level)?;
+                writer.write_u8(*class)?;
+            }
+            RandomUnit::Group { group, position } => {
+                writer.write_u32::<LE>(1)?;
+                writer.write_u32::<LE>(*group)?;
+                writer.write_u32::<LE>(*position)?;
+            }
+            RandomUnit::Custom(units) => {
+                writer.write_u32::<LE>(2)?;
+                writer.write_u32::<LE>(units.len() as u32)?;
+
+                for unit in units {
+                    write_id(&mut writer, unit.id)?;
+                    writer.write_u32::<LE>(unit.chance)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_unit<W: Write>(
+        mut writer: W,
+        unit: &PlacedUnit,
+        version: u32,
+        subversion: u32,
+    ) -> Result<(), IoError> {
+        write_id(&mut writer, unit.id)?;
+        writer.write_u32::<LE>(unit.variation)?;
+        writer.write_f32::<LE>(unit.x)?;
+        writer.write_f32::<LE>(unit.y)?;
+        writer.write_f32::<LE>(unit.z)?;
+        writer.write_f32::<LE>(unit.facing)?;
+        for scale in &unit.scale {
+            writer.write_f32::<LE>(*scale)?;
+        }
+
+        if version >= 8 {
+            write_id(&mut writer, unit.skin)?;
+        }
+
+        writer.write_u8(unit.flags)?;
+        writer.write_u32::<LE>(unit.owner)?;
+        writer.write_all(&unit.unknown)?;
+        writer.write_i32::<LE>(unit.hp)?;
+        writer.write_i32::<LE>(unit.mana)?;
+
+        if subversion >= 11 {
+            writer.write_i32::<LE>(unit.item_table)?;
+        }
+
+        write_item_sets(&mut writer, &unit.item_sets)?;
+        writer.write_u32::<LE>(unit.gold)?;
+        writer.write_f32::<LE>(unit.target_acquisition)?;
+        writer.write_u32::<LE>(unit.level)?;
+
+        if subversion >= 11 {
+            writer.write_u32::<LE>(unit.strength)?;
+            writer.write_u32::<LE>(unit.agility)?;
+            writer.write_u32::<LE>(unit.intelligence)?;
+        }
+
+        writer.write_u32::<LE>(unit.items.len() as u32)?;
+        for item in &unit.items {
+            writer.write_u32::<LE>(item.slot)?;
+            write_id(&mut writer, item.id)?;
+        }
+
+        writer.write_u32::<LE>(unit.abilities.len() as u32)?;
+        for ability in &unit.abilities {
+            write_id(&mut writer, ability.id)?;
+            writer.write_u32::<LE>(ability.autocast as u32)?;
+            writer.write_u32::<LE>(ability.level)?;
+        }
+
+        write_random(&mut writer, &unit.random)?;
+        writer.write_i32::<LE>(unit.color)?;
+        writer.write_i32::<LE>(unit.waygate)?;
+        writer.write_u32::<LE>(unit.creation_number)?;
+
+        Ok(())
+    }
+
+    pub fn write_units<W: Write>(mut writer: W, file: &UnitsFile) -> Result<(), IoError> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LE>(file.version)?;
+        writer.write_u32::<LE>(file.subversion)?;
+        writer.write_u32::<LE>(file.units.len() as u32)?;
+
+        for unit in &file.units {
+            write_unit(&mut writer, unit, file.version, file.subversion)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_doodad<W: Write>(
+        mut writer: W,
+        doodad: &PlacedDoodad,
+        version: u32,
+        subversion: u32,
+    ) -> Result<(), IoError> {
+        write_id(&mut writer, doodad.id)?;
+        writer.write_u32::<LE>(doodad.variation)?;
+        writer.write_f32::<LE>(doodad.x)?;
+        writer.write_f32::<LE>(doodad.y)?;
+        writer.write_f32::<LE>(doodad.z)?;
+        writer.write_f32::<LE>(doodad.facing)?;
+        for scale in &doodad.scale {
+            writer.write_f32::<LE>(*scale)?;
+        }
+
+        if version >= 8 {
+            write_id(&mut writer, doodad.skin)?;
+        }
+
+        writer.write_u8(doodad.flags)?;
+        writer.write_u8(doodad.life)?;
+
+        if subversion >= 11 {
+            writer.write_i32::<LE>(doodad.item_table)?;
+            write_item_sets(&mut writer, &doodad.item_sets)?;
+        }
+
+        writer.write_u32::<LE>(doodad.creation_number)?;
+
+        Ok(())
+    }
+
+    pub fn write_doodads<W: Write>(mut writer: W, file: &DoodadsFile) -> Result<(), IoError> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LE>(file.version)?;
+        writer.write_u32::<LE>(file.subversion)?;
+        writer.write_u32::<LE>(file.doodads.len() as u32)?;
+
+        for doodad in &file.doodads {
+            write_doodad(&mut writer, doodad, file.version, file.subversion)?;
+        }
+
+        writer.write_u32::<LE>(file.special_version)?;
+        writer.write_u32::<LE>(file.special_doodads.len() as u32)?;
+
+        for special in &file.special_doodads {
+            write_id(&mut writer, special.id)?;
+            writer.write_u32::<LE>(special.variation)?;
+            writer.write_u32::<LE>(special.x)?;
+            writer.write_u32::<LE>(special.y)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::doodads::*;
+    use crate::units::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn id(id: &[u8]) -> ObjectId {
+        ObjectId::from_bytes(id).unwrap()
+    }
+
+    #[test]
+    fn units_read() {
+        let mut buf = MAGIC.to_vec();
+        buf.write_u32::<LE>(7).unwrap();
+        buf.write_u32::<LE>(11).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+
+        buf.extend_from_slice(b"Hpal");
+        buf.write_u32::<LE>(0).unwrap();
+        for value in &[1920.0, 1664.0, 0.0, 4.712, 1.0, 1.0, 1.0] {
+            buf.write_f32::<LE>(*value).unwrap();
+        }
+        buf.write_u8(2).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.extend_from_slice(&[0, 0]);
+        buf.write_i32::<LE>(-1).unwrap();
+        buf.write_i32::<LE>(-1).unwrap();
+        buf.write_i32::<LE>(-1).unwrap();
+        // one item set with one item
+        buf.write_u32::<LE>(1).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.extend_from_slice(b"pman");
+        buf.write_u32::<LE>(100).unwrap();
+        buf.write_u32::<LE>(12500).unwrap();
+        buf.write_f32::<LE>(-1.0).unwrap();
+        buf.write_u32::<LE>(3).unwrap();
+        for attribute in &[22, 13, 17] {
+            buf.write_u32::<LE>(*attribute).unwrap();
+        }
+        buf.write_u32::<LE>(1).unwrap();
+        buf.write_u32::<LE>(5).unwrap();
+        buf.extend_from_slice(b"pghe");
+        buf.write_u32::<LE>(1).unwrap();
+        buf.extend_from_slice(b"AHhb");
+        buf.write_u32::<LE>(1).unwrap();
+        buf.write_u32::<LE>(2).unwrap();
+        // random unit of level 2, stored as a 24 bit level and a class
+        buf.write_u32::<LE>(0).unwrap();
+        buf.extend_from_slice(&[2, 0, 0, 0]);
+        buf.write_i32::<LE>(-1).unwrap();
+        buf.write_i32::<LE>(-1).unwrap();
+        buf.write_u32::<LE>(3).unwrap();
+
+        let file = read::read_units(&buf).unwrap();
+        assert_eq!((file.version, file.subversion), (7, 11));
+
+        let hero = &file.units[0];
+        assert_eq!(hero.id, id(b"Hpal"));
+        // files without skins default to the unit itself
+        assert_eq!(hero.skin, id(b"Hpal"));
+        assert_eq!((hero.x, hero.y, hero.owner), (1920.0, 1664.0, 1));
+        assert_eq!(hero.item_sets[0][0].id, id(b"pman"));
+        assert_eq!((hero.gold, hero.level, hero.strength), (12500, 3, 22));
+        assert_eq!((hero.items[0].slot, hero.items[0].id), (5, id(b"pghe")));
+        assert!(hero.abilities[0].autocast);
+        assert_eq!(hero.abilities[0].level, 2);
+        assert_eq!(hero.random, RandomUnit::Level { level: 2, class: 0 });
+        assert_eq!(hero.creation_number, 3);
+    }
+
+    #[test]
+    fn doodads_read() {
+        let mut buf = MAGIC.to_vec();
+        buf.write_u32::<LE>(8).unwrap();
+        buf.write_u32::<LE>(11).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+
+        buf.extend_from_slice(b"LTlt");
+        buf.write_u32::<LE>(3).unwrap();
+        for value in &[256.0, 192.0, 12.5, 0.0, 1.1, 1.1, 1.1] {
+            buf.write_f32::<LE>(*value).unwrap();
+        }
+        buf.extend_from_slice(b"LTlt");
+        buf.write_u8(2).unwrap();
+        buf.write_u8(50).unwrap();
+        buf.write_i32::<LE>(-1).unwrap();
+        // one item set with one item
+        buf.write_u32::<LE>(1).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.extend_from_slice(b"ratc");
+        buf.write_u32::<LE>(25).unwrap();
+        buf.write_u32::<LE>(7).unwrap();
+
+        buf.write_u32::<LE>(0).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.extend_from_slice(b"DTrx");
+        buf.write_u32::<LE>(0).unwrap();
+        buf.write_u32::<LE>(30).unwrap();
+        buf.write_u32::<LE>(4).unwrap();
+
+        let file = read::read_doodads(&buf).unwrap();
+        assert_eq!((file.version, file.subversion), (8, 11));
+
+        let tree = &file.doodads[0];
+        assert_eq!((tree.id, tree.skin), (id(b"LTlt"), id(b"LTlt")));
+        assert_eq!((tree.variation, tree.x, tree.y, tree.z), (3, 256.0, 192.0, 12.5));
+        assert_eq!(tree.scale, [1.1; 3]);
+        assert_eq!((tree.flags, tree.life), (2, 50));
+        assert_eq!(tree.item_sets[0][0].id, id(b"ratc"));
+        assert_eq!(tree.creation_number, 7);
+
+        let special = &file.special_doodads[0];
+        assert_eq!((special.id, special.x, special.y), (id(b"DTrx"), 30, 4));
+    }
+
+    #[test]
+    fn units_roundtrip() {
+        let mut hero = PlacedUnit::new(id(b"Hpal"), 128.0, -64.0);
+        hero.facing = 4.712;
+        hero.owner = 1;
+        hero.level = 5;
+        hero.strength = 30;
+        hero.item_sets = vec![vec![DroppedItem {
+            id:     id(b"ratc"),
+            chance: 100,
+        }]];
+        hero.items = vec![InventoryItem {
+            slot: 0,
+            id:   id(b"bspd"),
+        }];
+        hero.abilities = vec![UnitAbility {
+            id:       id(b"AHhb"),
+            autocast: true,
+            level:    2,
+        }];
+
+        let mut creep = PlacedUnit::new(id(b"nDNR"), 0.0, 0.0);
+        creep.random = RandomUnit::Custom(vec![DroppedItem {
+            id:     id(b"nfsh"),
+            chance: 50,
+        }]);
+
+        let mut camp = PlacedUnit::new(id(b"nfsp"), 512.0, 512.0);
+        camp.random = RandomUnit::Group {
+            group:    0,
+            position: 1,
+        };
+
+        for (version, subversion) in &[(7, 9), (7, 11), (8, 11)] {
+            let mut file = UnitsFile {
+                version:    *version,
+                subversion: *subversion,
+                units:      Vec::new(),
+            };
+
+            for unit in &[&hero, &creep, &camp] {
+                let mut unit = (*unit).clone();
+                if *subversion < 11 {
+                    unit.item_table = -1;
+                    unit.strength = 0;
+                }
+
+                file.add_unit(unit);
+            }
+
+            let mut buf = Vec::new();
+            write::write_units(&mut buf, &file).unwrap();
+
+            assert_eq!(read::read_units(&buf).unwrap(), file);
+        }
+    }
+
+    #[test]
+    fn doodads_roundtrip() {
+        let mut tree = PlacedDoodad::new(id(b"LTlt"), 256.0, 192.0);
+        tree.variation = 3;
+        tree.life = 50;
+        tree.item_sets = vec![vec![DroppedItem {
+            id:     id(b"ratc"),
+            chance: 25,
+        }]];
+
+        let rock = PlacedDoodad::new(id(b"LOrk"), -32.0, 64.0);
+
+        for (version, subversion) in &[(7, 9), (7, 11), (8, 11)] {
+            let mut file = DoodadsFile {
+                version: *version,
+                subversion: *subversion,
+                special_doodads: vec![SpecialDoodad {
+                    id:        id(b"DTg1"),
+                    variation: 0,
+                    x:         12,
+                    y:         20,
+                }],
+                ..Default::default()
+            };
+
+            for doodad in &[&tree, &rock] {
+                let mut doodad = (*doodad).clone();
+                if *subversion < 11 {
+                    doodad.item_sets.clear();
+                }
+
+                file.add_doodad(doodad);
+            }
+
+            let mut buf = Vec::new();
+            write::write_doodads(&mut buf, &file).unwrap();
+
+            assert_eq!(read::read_doodads(&buf).unwrap(), file);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Reading and writing of `war3map.imp`, the import list.
+
+const VERSION: u32 = 1;
+
+pub mod read {
+    use byteorder::{ReadBytesExt, LE};
+
+    use crate::error::FormatError;
+    use crate::imports::*;
+    use crate::parser::binary::read_cstring;
+
+    use super::*;
+
+    pub fn read_imports(mut source: &[u8]) -> Result<ImportsFile, FormatError> {
+        let source = &mut source;
+
+        let version = source.read_u32::<LE>()?;
+        if version != VERSION {
+            return Err(FormatError::unsupported_version("imp", version));
+        }
+
+        let count = source.read_u32::<LE>()?;
+        let imports = (0..count)
+            .map(|_| {
+                let flag = source.read_u8()?;
+                let path = read_cstring(source)?;
+
+                Ok(Import { flag, path })
+            })
+            .collect::<Result<Vec<_>, FormatError>>()?;
+
+        Ok(ImportsFile { version, imports })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::imports::*;
+    use crate::parser::binary::write_cstring;
+
+    use super::*;
+
+    pub fn write_imports<W: Write>(mut writer: W, file: &ImportsFile) -> Result<(), IoError> {
+        writer.write_u32::<LE>(VERSION)?;
+        writer.write_u32::<LE>(file.imports.len() as u32)?;
+
+        for import in &file.imports {
+            writer.write_u8(import.flag)?;
+            write_cstring(&mut writer, &import.path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::imports::*;
+
+    use super::*;
+
+    #[test]
+    fn imports_read() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LE>(VERSION).unwrap();
+        buf.write_u32::<LE>(3).unwrap();
+        buf.push(5);
+        buf.extend_from_slice(b"Footman.mdx\0");
+        buf.push(8);
+        buf.extend_from_slice(b"Sounds\\Theme.mp3\0");
+        buf.push(10);
+        buf.extend_from_slice(b"UI\\Widgets\\Console\\Human\\human-console.blp\0");
+
+        let file = read::read_imports(&buf).unwrap();
+        assert_eq!(file.version, VERSION);
+
+        let paths: Vec<_> = file.imports.iter().map(Import::archive_path).collect();
+        assert_eq!(
+            paths,
+            [
+                "war3mapImported\\Footman.mdx",
+                "war3mapImported\\Sounds\\Theme.mp3",
+                "UI\\Widgets\\Console\\Human\\human-console.blp",
+            ]
+        );
+        assert_eq!(file.imports[2].flag, 10);
+    }
+
+    #[test]
+    fn imports_roundtrip() {
+        let file = ImportsFile {
+            version: VERSION,
+            imports: vec![
+                Import {
+                    flag: 8,
+                    path: "Footman.mdx".into(),
+                },
+                Import::new("UI\\Widgets\\Console\\Human\\human-console.blp".into()),
+            ],
+        };
+
+        assert_eq!(
+            file.imports[0].archive_path(),
+            "war3mapImported\\Footman.mdx"
+        );
+        assert_eq!(file.imports[1].archive_path(), file.imports[1].path);
+
+        let mut buf = Vec::new();
+        write::write_imports(&mut buf, &file).unwrap();
+
+        assert_eq!(read::read_imports(&buf).unwrap(), file);
+    }
+}
@@ -0,0 +1,800 @@
+//! A baseline JPEG codec for the JPEG content of BLP textures.
+//!
+//! BLP stores BGRA pixels as the four components of a JPEG image, without
+//! any color conversion, which general purpose decoders either refuse or
+//! convert from CMYK. Components are therefore read and written as they are.
+//! Only sequential, Huffman coded images are supported, which is what the
+//! game and the usual tools produce.
+
+use std::f32::consts::PI;
+use std::io::{Error as IoError, ErrorKind};
+
+use crate::error::FormatError;
+
+/// Natural (row-major) index of each coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// The example luminance quantization table of the JPEG standard, in natural order.
+const QUANTIZATION: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// The example luminance Huffman tables of the JPEG standard,
+/// as the number of codes of each length followed by the symbols.
+const DC_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const AC_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+fn invalid(message: &str) -> FormatError {
+    IoError::new(ErrorKind::InvalidData, format!("invalid JPEG: {}", message)).into()
+}
+
+/// `COSINES[x][u]` is the basis function of frequency `u` at position `x`,
+/// scaled so that the 2D transform is orthonormal.
+fn cosines() -> [[f32; 8]; 8] {
+    let mut table = [[0.0; 8]; 8];
+
+    for (x, row) in table.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { 1.0 / 2.0f32.sqrt() } else { 1.0 };
+            *value = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos() / 2.0;
+        }
+    }
+
+    table
+}
+
+/// Scales the quantization table like libjpeg does. Returns it in zigzag order.
+fn quantization_table(quality: u8) -> [u16; 64] {
+    let quality = u32::from(quality.clamp(1, 100));
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+
+    let mut table = [0; 64];
+    for (value, index) in table.iter_mut().zip(ZIGZAG.iter()) {
+        let scaled = (u32::from(QUANTIZATION[*index]) * scale + 50) / 100;
+        *value = scaled.clamp(1, 255) as u16;
+    }
+
+    table
+}
+
+// decoding
+
+struct HuffmanTable {
+    /// Largest code of each length, or -1 if there is none.
+    max_code: [i32; 17],
+    /// Index into `values` of the first code of each length, minus that code.
+    offset:   [i32; 17],
+    values:   Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(bits: &[u8], values: &[u8]) -> HuffmanTable {
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+        let mut code = 0;
+        let mut index = 0;
+
+        for length in 1..=16 {
+            let count = i32::from(bits[length - 1]);
+            if count > 0 {
+                offset[length] = index - code;
+                code += count;
+                index += count;
+                max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+
+        HuffmanTable {
+            max_code,
+            offset,
+            values: values.to_vec(),
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, FormatError> {
+        let mut code = 0;
+
+        for length in 1..=16 {
+            code = (code << 1) | reader.bit() as i32;
+
+            if code <= self.max_code[length] {
+                return self
+                    .values
+                    .get((self.offset[length] + code) as usize)
+                    .copied()
+                    .ok_or_else(|| invalid("bad Huffman code"));
+            }
+        }
+
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+/// Reads the bits of an entropy coded segment, after byte stuffing was removed.
+/// Missing bits at the end are read as zeros.
+struct BitReader<'a> {
+    data:     &'a [u8],
+    position: usize,
+    bit:      u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+            bit: 0,
+        }
+    }
+
+    fn bit(&mut self) -> u32 {
+        let byte = self.data.get(self.position).copied().unwrap_or(0);
+        let bit = (byte >> (7 - self.bit)) & 1;
+
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+
+        u32::from(bit)
+    }
+
+    fn bits(&mut self, count: u8) -> u32 {
+        (0..count).fold(0, |value, _| (value << 1) | self.bit())
+    }
+
+    /// Reads a coefficient of `size` bits, where values with a leading zero are negative.
+    fn value(&mut self, size: u8) -> i32 {
+        if size == 0 {
+            return 0;
+        }
+
+        let value = self.bits(size) as i32;
+        if value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        }
+    }
+}
+
+struct Component {
+    id:           u8,
+    h:            usize,
+    v:            usize,
+    quantization: usize,
+    dc_table:     usize,
+    ac_table:     usize,
+    /// Samples of the component, covering whole blocks.
+    plane:        Vec<u8>,
+    plane_width:  usize,
+}
+
+struct Frame {
+    width:      usize,
+    height:     usize,
+    components: Vec<Component>,
+    max_h:      usize,
+    max_v:      usize,
+    mcus_x:     usize,
+    mcus_y:     usize,
+}
+
+/// A decoded image, with the components of each pixel next to each other.
+pub struct Decoded {
+    pub width:      u32,
+    pub height:     u32,
+    pub components: usize,
+    pub data:       Vec<u8>,
+}
+
+struct Decoder {
+    quantization: [[u16; 64]; 4],
+    dc_tables:    [Option<HuffmanTable>; 4],
+    ac_tables:    [Option<HuffmanTable>; 4],
+    restart:      usize,
+    frame:        Option<Frame>,
+    cosines:      [[f32; 8]; 8],
+}
+
+fn read_u16(data: &[u8], position: usize) -> Result<usize, FormatError> {
+    match data.get(position..position + 2) {
+        Some(bytes) => Ok(usize::from(bytes[0]) << 8 | usize::from(bytes[1])),
+        None => Err(invalid("unexpected end of data")),
+    }
+}
+
+/// Splits the entropy coded data following a scan header into the segments
+/// between restart markers, with byte stuffing removed.
+/// Returns the segments and the position of the marker ending the scan.
+fn scan_segments(data: &[u8], mut position: usize) -> (Vec<Vec<u8>>, usize) {
+    let mut segments = vec![Vec::new()];
+
+    while position < data.len() {
+        let byte = data[position];
+        if byte != 0xFF {
+            segments.last_mut().unwrap().push(byte);
+            position += 1;
+            continue;
+        }
+
+        match data.get(position + 1) {
+            Some(0x00) => {
+                segments.last_mut().unwrap().push(0xFF);
+                position += 2;
+            }
+            Some(0xD0..=0xD7) => {
+                segments.push(Vec::new());
+                position += 2;
+            }
+            // fill bytes before a marker
+            Some(0xFF) => position += 1,
+            _ => break,
+        }
+    }
+
+    (segments, position)
+}
+
+impl Decoder {
+    fn new() -> Decoder {
+        Decoder {
+            quantization: [[1; 64]; 4],
+            dc_tables:    [None, None, None, None],
+            ac_tables:    [None, None, None, None],
+            restart:      0,
+            frame:        None,
+            cosines:      cosines(),
+        }
+    }
+
+    fn read_quantization(&mut self, mut segment: &[u8]) -> Result<(), FormatError> {
+        while !segment.is_empty() {
+            let precision = segment[0] >> 4;
+            let id = usize::from(segment[0] & 0x0F);
+            let size = if precision == 0 { 64 } else { 128 };
+            if id > 3 || segment.len() < 1 + size {
+                return Err(invalid("bad quantization table"));
+            }
+
+            for (i, value) in self.quantization[id].iter_mut().enumerate() {
+                *value = if precision == 0 {
+                    u16::from(segment[1 + i])
+                } else {
+                    u16::from(segment[1 + i * 2]) << 8 | u16::from(segment[2 + i * 2])
+                };
+            }
+
+            segment = &segment[1 + size..];
+        }
+
+        Ok(())
+    }
+
+    fn read_huffman(&mut self, mut segment: &[u8]) -> Result<(), FormatError> {
+        while !segment.is_empty() {
+            if segment.len() < 17 {
+                return Err(invalid("bad Huffman table"));
+            }
+
+            let class = segment[0] >> 4;
+            let id = usize::from(segment[0] & 0x0F);
+            let bits = &segment[1..17];
+            let count: usize = bits.iter().map(|count| usize::from(*count)).sum();
+            if id > 3 || class > 1 || segment.len() < 17 + count {
+                return Err(invalid("bad Huffman table"));
+            }
+
+            let table = HuffmanTable::new(bits, &segment[17..17 + count]);
+            if class == 0 {
+                self.dc_tables[id] = Some(table);
+            } else {
+                self.ac_tables[id] = Some(table);
+            }
+
+            segment = &segment[17 + count..];
+        }
+
+        Ok(())
+    }
+
+    fn read_frame(&mut self, segment: &[u8]) -> Result<(), FormatError> {
+        if segment.len() < 6 || segment[0] != 8 {
+            return Err(invalid("only 8-bit samples are supported"));
+        }
+
+        let height = read_u16(segment, 1)?;
+        let width = read_u16(segment, 3)?;
+        let count = usize::from(segment[5]);
+        if width == 0 || height == 0 || count == 0 || segment.len() < 6 + count * 3 {
+            return Err(invalid("bad frame header"));
+        }
+
+        let mut components: Vec<Component> = segment[6..6 + count * 3]
+            .chunks(3)
+            .map(|component| Component {
+                id:           component[0],
+                h:            usize::from(component[1] >> 4).max(1),
+                v:            usize::from(component[1] & 0x0F).max(1),
+                quantization: usize::from(component[2] & 3),
+                dc_table:     0,
+                ac_table:     0,
+                plane:        Vec::new(),
+                plane_width:  0,
+            })
+            .collect();
+
+        let max_h = components.iter().map(|c| c.h).max().unwrap();
+        let max_v = components.iter().map(|c| c.v).max().unwrap();
+        let mcus_x = width.div_ceil(8 * max_h);
+        let mcus_y = height.div_ceil(8 * max_v);
+
+        for component in &mut components {
+            component.plane_width = mcus_x * component.h * 8;
+            component.plane = vec![0; component.plane_width * mcus_y * component.v * 8];
+        }
+
+        self.frame = Some(Frame {
+            width,
+            height,
+            components,
+            max_h,
+            max_v,
+            mcus_x,
+            mcus_y,
+        });
+
+        Ok(())
+    }
+
+    fn decode_block(
+        &self,
+        reader: &mut BitReader,
+        component: &mut Component,
+        prediction: &mut i32,
+        block_x: usize,
+        block_y: usize,
+    ) -> Result<(), FormatError> {
+        let dc_table = self.dc_tables[component.dc_table]
+            .as_ref()
+            .ok_or_else(|| invalid("missing Huffman table"))?;
+        let ac_table = self.ac_tables[component.ac_table]
+            .as_ref()
+            .ok_or_else(|| invalid("missing Huffman table"))?;
+        let quantization = &self.quantization[component.quantization];
+
+        let mut coefficients = [0.0f32; 64];
+
+        let size = dc_table.decode(reader)?;
+        *prediction += reader.value(size);
+        coefficients[0] = (*prediction * i32::from(quantization[0])) as f32;
+
+        let mut k = 1;
+        while k < 64 {
+            let symbol = ac_table.decode(reader)?;
+            let run = usize::from(symbol >> 4);
+            let size = symbol & 0x0F;
+
+            if size == 0 {
+                if run == 15 {
+                    k += 16;
+                    continue;
+                }
+                break;
+            }
+
+            k += run;
+            if k > 63 {
+                return Err(invalid("coefficient out of range"));
+            }
+
+            let value = reader.value(size) * i32::from(quantization[k]);
+            coefficients[ZIGZAG[k]] = value as f32;
+            k += 1;
+        }
+
+        // inverse DCT, first along the rows, then along the columns
+        let cosines = &self.cosines;
+        let mut rows = [0.0f32; 64];
+        for v in 0..8 {
+            for x in 0..8 {
+                rows[v * 8 + x] = (0..8)
+                    .map(|u| cosines[x][u] * coefficients[v * 8 + u])
+                    .sum();
+            }
+        }
+
+        for (y, basis) in cosines.iter().enumerate() {
+            let line = (block_y * 8 + y) * component.plane_width + block_x * 8;
+
+            for x in 0..8 {
+                let value: f32 = (0..8).map(|v| basis[v] * rows[v * 8 + x]).sum();
+                component.plane[line + x] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_scan(&mut self, data: &[u8], position: usize) -> Result<usize, FormatError> {
+        let length = read_u16(data, position)?;
+        let header = data
+            .get(position + 2..position + length)
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+
+        let mut frame = self
+            .frame
+            .take()
+            .ok_or_else(|| invalid("scan before frame"))?;
+
+        let count = usize::from(*header.first().unwrap_or(&0));
+        if count == 0 || header.len() < 1 + count * 2 {
+            return Err(invalid("bad scan header"));
+        }
+
+        let mut scan_components = Vec::new();
+        for selector in header[1..1 + count * 2].chunks(2) {
+            let index = frame
+                .components
+                .iter()
+                .position(|c| c.id == selector[0])
+                .ok_or_else(|| invalid("scan refers to an unknown component"))?;
+
+            let component = &mut frame.components[index];
+            component.dc_table = usize::from(selector[1] >> 4) & 3;
+            component.ac_table = usize::from(selector[1] & 0x0F) & 3;
+            scan_components.push(index);
+        }
+
+        let (segments, end) = scan_segments(data, position + length);
+        let restart = if self.restart == 0 {
+            usize::MAX
+        } else {
+            self.restart
+        };
+
+        // a single component is not interleaved, and coded block by block
+        let units: Vec<Vec<(usize, usize, usize)>> = if scan_components.len() == 1 {
+            let index = scan_components[0];
+            let c = &frame.components[index];
+            let width = (frame.width * c.h).div_ceil(frame.max_h);
+            let height = (frame.height * c.v).div_ceil(frame.max_v);
+            let blocks_x = width.div_ceil(8);
+            let blocks_y = height.div_ceil(8);
+
+            (0..blocks_y)
+                .flat_map(|y| (0..blocks_x).map(move |x| vec![(index, x, y)]))
+                .collect()
+        } else {
+            let components = &frame.components;
+            let scan_components = &scan_components;
+
+            (0..frame.mcus_y)
+                .flat_map(|mcu_y| {
+                    (0..frame.mcus_x).map(move |mcu_x| {
+                        let mut blocks = Vec::new();
+                        for index in scan_components {
+                            let c = &components[*index];
+                            for v in 0..c.v {
+                                for h in 0..c.h {
+                                    blocks.push((*index, mcu_x * c.h + h, mcu_y * c.v + v));
+                                }
+                            }
+                        }
+                        blocks
+                    })
+                })
+                .collect()
+        };
+
+        for (segment, units) in segments.iter().zip(units.chunks(restart)) {
+            let mut reader = BitReader::new(segment);
+            let mut predictions = vec![0; frame.components.len()];
+
+            for blocks in units {
+                for (index, x, y) in blocks {
+                    let component = &mut frame.components[*index];
+                    self.decode_block(&mut reader, component, &mut predictions[*index], *x, *y)?;
+                }
+            }
+        }
+
+        self.frame = Some(frame);
+
+        Ok(end)
+    }
+}
+
+/// Decodes a baseline JPEG image, without converting colors.
+pub fn decode(data: &[u8]) -> Result<Decoded, FormatError> {
+    if data.get(0..2) != Some(&[0xFF, 0xD8]) {
+        return Err(FormatError::invalid_magic("JPEG SOI marker"));
+    }
+
+    let mut decoder = Decoder::new();
+    let mut position = 2;
+
+    loop {
+        while data.get(position) == Some(&0xFF) && data.get(position + 1) == Some(&0xFF) {
+            position += 1;
+        }
+
+        if data.get(position) != Some(&0xFF) {
+            return Err(invalid("expected a marker"));
+        }
+        let marker = *data
+            .get(position + 1)
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+        position += 2;
+
+        match marker {
+            0xD9 => break,
+            0xD8 | 0x01 | 0xD0..=0xD7 => continue,
+            0xDA => {
+                position = decoder.read_scan(data, position)?;
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = read_u16(data, position)?;
+        let segment = data
+            .get(position + 2..position + length)
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+        position += length;
+
+        match marker {
+            0xC0 | 0xC1 => decoder.read_frame(segment)?,
+            0xC2 => return Err(invalid("progressive images are not supported")),
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                return Err(invalid("only baseline images are supported"))
+            }
+            0xC4 => decoder.read_huffman(segment)?,
+            0xDB => decoder.read_quantization(segment)?,
+            0xDD => decoder.restart = read_u16(segment, 0)?,
+            _ => {}
+        }
+    }
+
+    let frame = decoder.frame.ok_or_else(|| invalid("missing frame"))?;
+    let count = frame.components.len();
+    let mut result = vec![0; frame.width * frame.height * count];
+
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            for (i, c) in frame.components.iter().enumerate() {
+                let sx = x * c.h / frame.max_h;
+                let sy = y * c.v / frame.max_v;
+                result[(y * frame.width + x) * count + i] = c.plane[sy * c.plane_width + sx];
+            }
+        }
+    }
+
+    Ok(Decoded {
+        width:      frame.width as u32,
+        height:     frame.height as u32,
+        components: count,
+        data:       result,
+    })
+}
+
+// encoding
+
+struct BitWriter {
+    data:  Vec<u8>,
+    value: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.value = (self.value << 1) | ((value >> i) & 1);
+            self.count += 1;
+
+            if self.count == 8 {
+                let byte = self.value as u8;
+                self.data.push(byte);
+                if byte == 0xFF {
+                    self.data.push(0x00);
+                }
+
+                self.value = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    /// Pads the last byte with ones.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.write(0xFF, 8 - self.count);
+        }
+
+        self.data
+    }
+}
+
+/// Code and length of every symbol of a Huffman table.
+fn huffman_codes(bits: &[u8], values: &[u8]) -> [(u32, u32); 256] {
+    let mut codes = [(0, 0); 256];
+    let mut code = 0;
+    let mut values = values.iter();
+
+    for length in 1..=16 {
+        for _ in 0..bits[length - 1] {
+            let symbol = *values.next().unwrap();
+            codes[usize::from(symbol)] = (code, length as u32);
+            code += 1;
+        }
+        code <<= 1;
+    }
+
+    codes
+}
+
+/// The number of bits needed for a coefficient, and the bits themselves.
+fn coefficient_bits(value: i32) -> (u32, u32) {
+    let size = 32 - value.abs().leading_zeros();
+    let bits = if value < 0 { value - 1 } else { value };
+
+    (size, (bits as u32) & ((1 << size) - 1))
+}
+
+fn push_segment(out: &mut Vec<u8>, marker: u8, contents: &[u8]) {
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&((contents.len() + 2) as u16).to_be_bytes());
+    out.extend_from_slice(contents);
+}
+
+/// The start of an image and its quantization and Huffman tables, which
+/// can be shared between several images encoded with the same quality.
+pub fn encode_tables(quality: u8) -> Vec<u8> {
+    let mut out = vec![0xFF, 0xD8];
+
+    let mut dqt = vec![0];
+    dqt.extend(quantization_table(quality).iter().map(|value| *value as u8));
+    push_segment(&mut out, 0xDB, &dqt);
+
+    let mut dht = vec![0x00];
+    dht.extend_from_slice(&DC_BITS);
+    dht.extend_from_slice(&DC_VALUES);
+    dht.push(0x10);
+    dht.extend_from_slice(&AC_BITS);
+    dht.extend_from_slice(&AC_VALUES);
+    push_segment(&mut out, 0xC4, &dht);
+
+    out
+}
+
+/// Encodes an image whose components are next to each other in `data`,
+/// without converting colors. The result follows the output of
+/// `encode_tables` with the same quality.
+pub fn encode_image(
+    width: u32,
+    height: u32,
+    components: usize,
+    data: &[u8],
+    quality: u8,
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut out = Vec::new();
+
+    let mut sof = vec![8];
+    sof.extend_from_slice(&(height as u16).to_be_bytes());
+    sof.extend_from_slice(&(width as u16).to_be_bytes());
+    sof.push(components as u8);
+    for id in 0..components {
+        sof.extend_from_slice(&[id as u8, 0x11, 0]);
+    }
+    push_segment(&mut out, 0xC0, &sof);
+
+    let mut sos = vec![components as u8];
+    for id in 0..components {
+        sos.extend_from_slice(&[id as u8, 0x00]);
+    }
+    sos.extend_from_slice(&[0, 63, 0]);
+    push_segment(&mut out, 0xDA, &sos);
+
+    let quantization = quantization_table(quality);
+    let dc_codes = huffman_codes(&DC_BITS, &DC_VALUES);
+    let ac_codes = huffman_codes(&AC_BITS, &AC_VALUES);
+    let cosines = cosines();
+
+    let mut writer = BitWriter {
+        data:  Vec::new(),
+        value: 0,
+        count: 0,
+    };
+    let mut predictions = vec![0; components];
+
+    for block_y in 0..height.div_ceil(8) {
+        for block_x in 0..width.div_ceil(8) {
+            for (component, prediction) in predictions.iter_mut().enumerate() {
+                // edges are padded by repeating the last row and column
+                let mut samples = [0.0f32; 64];
+                for (i, sample) in samples.iter_mut().enumerate() {
+                    let x = (block_x * 8 + i % 8).min(width - 1);
+                    let y = (block_y * 8 + i / 8).min(height - 1);
+                    *sample = f32::from(data[(y * width + x) * components + component]) - 128.0;
+                }
+
+                let mut rows = [0.0f32; 64];
+                for y in 0..8 {
+                    for u in 0..8 {
+                        rows[y * 8 + u] = (0..8).map(|x| cosines[x][u] * samples[y * 8 + x]).sum();
+                    }
+                }
+
+                let mut coefficients = [0i32; 64];
+                for (k, coefficient) in coefficients.iter_mut().enumerate() {
+                    let (v, u) = (ZIGZAG[k] / 8, ZIGZAG[k] % 8);
+                    let value: f32 = (0..8).map(|y| cosines[y][v] * rows[y * 8 + u]).sum();
+                    *coefficient = (value / f32::from(quantization[k])).round() as i32;
+                }
+
+                let (size, bits) = coefficient_bits(coefficients[0] - *prediction);
+                *prediction = coefficients[0];
+                let (code, length) = dc_codes[size as usize];
+                writer.write(code, length);
+                writer.write(bits, size);
+
+                let mut run = 0;
+                for coefficient in &coefficients[1..] {
+                    if *coefficient == 0 {
+                        run += 1;
+                        continue;
+                    }
+
+                    while run > 15 {
+                        let (code, length) = ac_codes[0xF0];
+                        writer.write(code, length);
+                        run -= 16;
+                    }
+
+                    let (size, bits) = coefficient_bits(*coefficient);
+                    let (code, length) = ac_codes[(run << 4 | size) as usize];
+                    writer.write(code, length);
+                    writer.write(bits, size);
+                    run = 0;
+                }
+
+                if run > 0 {
+                    let (code, length) = ac_codes[0x00];
+                    writer.write(code, length);
+                }
+            }
+        }
+    }
+
+    out.extend(writer.finish());
+    out.extend_from_slice(&[0xFF, 0xD9]);
+
+    out
+}
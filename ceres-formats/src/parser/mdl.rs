@@ -0,0 +1,334 @@
+//! Reading of MDL models, the text model format.
+//!
+//! MDL files are nested blocks of comma-separated entries, like
+//! `Anim "Stand" { Interval { 0, 1000 }, NonLooping, }`. The whole file is
+//! parsed into a tree of entries, which is then searched for the parts
+//! of the model we are interested in.
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use crate::error::FormatError;
+    use crate::model::*;
+
+    fn invalid(message: &str) -> FormatError {
+        IoError::new(ErrorKind::InvalidData, format!("invalid MDL: {}", message)).into()
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Token<'src> {
+        Value(&'src str),
+        Open,
+        Close,
+        Comma,
+    }
+
+    fn tokenize(source: &str) -> Result<Vec<Token<'_>>, FormatError> {
+        let mut tokens = Vec::new();
+        let mut rest = source;
+
+        loop {
+            rest = rest.trim_start();
+            if rest.starts_with("//") {
+                rest = rest.find('\n').map(|end| &rest[end..]).unwrap_or("");
+                continue;
+            }
+
+            let c = match rest.chars().next() {
+                Some(c) => c,
+                None => break,
+            };
+
+            let length = match c {
+                '{' | '}' | ',' => {
+                    tokens.push(match c {
+                        '{' => Token::Open,
+                        '}' => Token::Close,
+                        _ => Token::Comma,
+                    });
+                    1
+                }
+                '"' => {
+                    let end = rest[1..]
+                        .find('"')
+                        .ok_or_else(|| invalid("unterminated string"))?;
+                    tokens.push(Token::Value(&rest[1..=end]));
+                    end + 2
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || "{},\"".contains(c))
+                        .unwrap_or(rest.len());
+                    tokens.push(Token::Value(&rest[..end]));
+                    end
+                }
+            };
+
+            rest = &rest[length..];
+        }
+
+        Ok(tokens)
+    }
+
+    /// An entry like `BoundsRadius 52.5` or `Anim "Stand" { ... }`.
+    #[derive(Debug, Default)]
+    struct Entry<'src> {
+        values:   Vec<&'src str>,
+        children: Vec<Entry<'src>>,
+    }
+
+    impl<'src> Entry<'src> {
+        fn value(&self, index: usize) -> &'src str {
+            self.values.get(index).copied().unwrap_or("")
+        }
+
+        fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Entry<'src>> {
+            self.children
+                .iter()
+                .filter(move |child| child.value(0) == name)
+        }
+
+        fn child(&self, name: &str) -> Option<&Entry<'src>> {
+            self.children.iter().find(|child| child.value(0) == name)
+        }
+
+        fn has(&self, name: &str) -> bool {
+            self.child(name).is_some()
+        }
+
+        fn number<T: std::str::FromStr + Default>(&self, name: &str) -> T {
+            self.child(name)
+                .and_then(|child| child.value(1).parse().ok())
+                .unwrap_or_default()
+        }
+
+        fn string(&self, name: &str) -> String {
+            self.child(name)
+                .map(|child| unescape(child.value(1)))
+                .unwrap_or_default()
+        }
+
+        /// The values of a block like `{ 1, 2, 3 }`.
+        fn numbers<T: std::str::FromStr + Default + Copy>(&self, name: &str, out: &mut [T]) {
+            if let Some(child) = self.child(name) {
+                for (value, entry) in out.iter_mut().zip(&child.children) {
+                    *value = entry.value(0).parse().unwrap_or_default();
+                }
+            }
+        }
+
+        fn extent(&self) -> Extent {
+            let mut extent = Extent {
+                radius: self.number("BoundsRadius"),
+                ..Extent::default()
+            };
+            self.numbers("MinimumExtent", &mut extent.min);
+            self.numbers("MaximumExtent", &mut extent.max);
+
+            extent
+        }
+    }
+
+    /// Paths are sometimes written with doubled backslashes.
+    fn unescape(value: &str) -> String {
+        value.replace("\\\\", "\\")
+    }
+
+    fn parse_block<'src>(
+        tokens: &[Token<'src>],
+        position: &mut usize,
+        nested: bool,
+    ) -> Result<Vec<Entry<'src>>, FormatError> {
+        let mut entries = Vec::new();
+
+        loop {
+            match tokens.get(*position) {
+                None if nested => return Err(invalid("unterminated block")),
+                None => return Ok(entries),
+                Some(Token::Close) if nested => {
+                    *position += 1;
+                    return Ok(entries);
+                }
+                Some(Token::Close) => return Err(invalid("unexpected }")),
+                Some(Token::Comma) => {
+                    *position += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut entry = Entry::default();
+            while let Some(Token::Value(value)) = tokens.get(*position) {
+                entry.values.push(value);
+                *position += 1;
+            }
+
+            if tokens.get(*position) == Some(&Token::Open) {
+                *position += 1;
+                entry.children = parse_block(tokens, position, true)?;
+            }
+
+            entries.push(entry);
+        }
+    }
+
+    fn read_sequence(entry: &Entry) -> Sequence {
+        let mut interval = [0; 2];
+        entry.numbers("Interval", &mut interval);
+
+        Sequence {
+            name: unescape(entry.value(1)),
+            interval,
+            move_speed: entry.number("MoveSpeed"),
+            non_looping: entry.has("NonLooping"),
+            rarity: entry.number("Rarity"),
+            extent: entry.extent(),
+        }
+    }
+
+    fn read_texture(entry: &Entry) -> Texture {
+        let mut flags = 0;
+        if entry.has("WrapWidth") {
+            flags |= 1;
+        }
+        if entry.has("WrapHeight") {
+            flags |= 2;
+        }
+
+        Texture {
+            replaceable_id: entry.number("ReplaceableId"),
+            path: entry.string("Image"),
+            flags,
+        }
+    }
+
+    fn read_attachment(entry: &Entry) -> Attachment {
+        Attachment {
+            name:          unescape(entry.value(1)),
+            object_id:     entry.number("ObjectId"),
+            attachment_id: entry.number("AttachmentID"),
+            path:          entry.string("Path"),
+        }
+    }
+
+    pub fn read_mdl(source: &[u8]) -> Result<Model, FormatError> {
+        let source = String::from_utf8_lossy(source);
+        let tokens = tokenize(&source)?;
+        let root = Entry {
+            values:   Vec::new(),
+            children: parse_block(&tokens, &mut 0, false)?,
+        };
+
+        let version = root
+            .child("Version")
+            .map(|version| version.number("FormatVersion"))
+            .ok_or_else(|| invalid("missing Version block"))?;
+
+        let mut model = Model {
+            version,
+            ..Model::default()
+        };
+
+        if let Some(entry) = root.child("Model") {
+            model.name = unescape(entry.value(1));
+            model.extent = entry.extent();
+        }
+
+        if let Some(sequences) = root.child("Sequences") {
+            model.sequences = sequences.children("Anim").map(read_sequence).collect();
+        }
+
+        if let Some(textures) = root.child("Textures") {
+            model.textures = textures.children("Bitmap").map(read_texture).collect();
+        }
+
+        model.attachments = root.children("Attachment").map(read_attachment).collect();
+
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = r#"// Exported by hand
+Version {
+	FormatVersion 800,
+}
+Model "Footman" {
+	BlendTime 150,
+	MinimumExtent { -1, -2, 0 },
+	MaximumExtent { 1, 2, 3 },
+	BoundsRadius 50,
+}
+Sequences 2 {
+	Anim "Stand" {
+		Interval { 0, 1000 },
+		MinimumExtent { -1, -1, -1 },
+		MaximumExtent { 1, 1, 1 },
+		BoundsRadius 10,
+	}
+	Anim "Death" {
+		Interval { 1000, 2500 },
+		NonLooping,
+		MoveSpeed 270,
+	}
+}
+Textures 2 {
+	Bitmap {
+		Image "",
+		ReplaceableId 1,
+	}
+	Bitmap {
+		Image "Textures\\Footman.blp",
+		WrapWidth,
+		WrapHeight,
+	}
+}
+Bone "Root" {
+	ObjectId 0,
+	Translation 1 {
+		Linear,
+		0: { 0, 0, 0 },
+	}
+}
+Attachment "Overhead Ref" {
+	ObjectId 7,
+	Parent 0,
+	AttachmentID 2,
+	Path "Effects\Overhead.mdl",
+}
+"#;
+
+    #[test]
+    fn mdl_read() {
+        let model = read::read_mdl(SOURCE.as_bytes()).unwrap();
+
+        assert_eq!(model.version, 800);
+        assert_eq!(model.name, "Footman");
+        assert_eq!(model.extent.min, [-1.0, -2.0, 0.0]);
+        assert_eq!(model.extent.radius, 50.0);
+
+        assert_eq!(model.sequences.len(), 2);
+        assert_eq!(model.sequences[0].extent.radius, 10.0);
+        assert_eq!(model.sequences[1].name, "Death");
+        assert_eq!(model.sequences[1].interval, [1000, 2500]);
+        assert!(model.sequences[1].non_looping);
+        assert_eq!(model.sequences[1].move_speed, 270.0);
+
+        assert_eq!(model.textures.len(), 2);
+        assert_eq!(model.textures[0].replaceable_id, 1);
+        assert_eq!(model.textures[1].flags, 3);
+        assert_eq!(
+            model.texture_paths().collect::<Vec<_>>(),
+            vec!["Textures\\Footman.blp"]
+        );
+
+        assert_eq!(model.attachments.len(), 1);
+        assert_eq!(model.attachments[0].name, "Overhead Ref");
+        assert_eq!(model.attachments[0].object_id, 7);
+        assert_eq!(model.attachments[0].attachment_id, 2);
+        assert_eq!(model.attachments[0].path, "Effects\\Overhead.mdl");
+    }
+}
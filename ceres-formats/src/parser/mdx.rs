@@ -0,0 +1,246 @@
+//! Reading of MDX models, the binary model format.
+//!
+//! Only the chunks describing the model, its sequences, textures and
+//! attachment points are read, everything else is skipped.
+
+const MAGIC: &[u8] = b"MDLX";
+
+const NAME_SIZE: usize = 80;
+const FILE_NAME_SIZE: usize = 260;
+const ATTACHMENT_PATH_SIZE: usize = 256;
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use byteorder::{ReadBytesExt, LE};
+
+    use crate::error::FormatError;
+    use crate::model::*;
+    use crate::parser::binary::read_bytes;
+
+    use super::*;
+
+    fn invalid(message: &str) -> FormatError {
+        IoError::new(ErrorKind::InvalidData, message.to_string()).into()
+    }
+
+    /// Reads a null-padded string of a fixed size.
+    fn read_fixed_string(source: &mut &[u8], size: usize) -> Result<String, FormatError> {
+        let bytes = read_bytes(source, size)?;
+        let end = bytes.iter().position(|c| *c == 0).unwrap_or(size);
+
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+
+    fn read_extent(source: &mut &[u8]) -> Result<Extent, FormatError> {
+        let mut extent = Extent {
+            radius: source.read_f32::<LE>()?,
+            ..Extent::default()
+        };
+        source.read_f32_into::<LE>(&mut extent.min)?;
+        source.read_f32_into::<LE>(&mut extent.max)?;
+
+        Ok(extent)
+    }
+
+    fn read_sequence(source: &mut &[u8]) -> Result<Sequence, FormatError> {
+        let name = read_fixed_string(source, NAME_SIZE)?;
+        let mut interval = [0; 2];
+        source.read_u32_into::<LE>(&mut interval)?;
+        let move_speed = source.read_f32::<LE>()?;
+        let non_looping = source.read_u32::<LE>()? != 0;
+        let rarity = source.read_f32::<LE>()?;
+        let _sync_point = source.read_u32::<LE>()?;
+        let extent = read_extent(source)?;
+
+        Ok(Sequence {
+            name,
+            interval,
+            move_speed,
+            non_looping,
+            rarity,
+            extent,
+        })
+    }
+
+    fn read_texture(source: &mut &[u8]) -> Result<Texture, FormatError> {
+        Ok(Texture {
+            replaceable_id: source.read_u32::<LE>()?,
+            path:           read_fixed_string(source, FILE_NAME_SIZE)?,
+            flags:          source.read_u32::<LE>()?,
+        })
+    }
+
+    /// Reads an attachment, whose size includes the size field itself.
+    fn read_attachment(source: &mut &[u8]) -> Result<Attachment, FormatError> {
+        let size = source.read_u32::<LE>()? as usize;
+        let mut attachment = read_bytes(source, size.saturating_sub(4))?;
+        let attachment = &mut attachment;
+
+        // the node comes first, and its size includes its own size field as well
+        let node_size = attachment.read_u32::<LE>()? as usize;
+        let mut node = read_bytes(attachment, node_size.saturating_sub(4))?;
+        let node = &mut node;
+        let name = read_fixed_string(node, NAME_SIZE)?;
+        let object_id = node.read_u32::<LE>()?;
+
+        let path = read_fixed_string(attachment, ATTACHMENT_PATH_SIZE)?;
+        let attachment_id = attachment.read_u32::<LE>()?;
+
+        Ok(Attachment {
+            name,
+            object_id,
+            attachment_id,
+            path,
+        })
+    }
+
+    /// Reads fixed-size entries until the chunk is empty.
+    fn read_entries<T, F>(mut chunk: &[u8], read: F) -> Result<Vec<T>, FormatError>
+    where
+        F: Fn(&mut &[u8]) -> Result<T, FormatError>,
+    {
+        let mut entries = Vec::new();
+        while !chunk.is_empty() {
+            entries.push(read(&mut chunk)?);
+        }
+
+        Ok(entries)
+    }
+
+    pub fn read_mdx(mut source: &[u8]) -> Result<Model, FormatError> {
+        let source = &mut source;
+
+        if read_bytes(source, 4)? != MAGIC {
+            return Err(FormatError::invalid_magic("MDLX"));
+        }
+
+        let mut model = Model::default();
+
+        while !source.is_empty() {
+            let tag = read_bytes(source, 4)?;
+            let size = source.read_u32::<LE>()? as usize;
+            let mut chunk = read_bytes(source, size)?;
+            let chunk = &mut chunk;
+
+            match tag {
+                b"VERS" => model.version = chunk.read_u32::<LE>()?,
+                b"MODL" => {
+                    model.name = read_fixed_string(chunk, NAME_SIZE)?;
+                    let _animation_file = read_fixed_string(chunk, FILE_NAME_SIZE)?;
+                    model.extent = read_extent(chunk)?;
+                }
+                b"SEQS" => model.sequences = read_entries(chunk, read_sequence)?,
+                b"TEXS" => model.textures = read_entries(chunk, read_texture)?,
+                b"ATCH" => model.attachments = read_entries(chunk, read_attachment)?,
+                _ => {}
+            }
+        }
+
+        if model.version == 0 {
+            return Err(invalid("MDX model without a VERS chunk"));
+        }
+
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{WriteBytesExt, LE};
+
+    use super::*;
+
+    fn fixed_string(buf: &mut Vec<u8>, value: &str, size: usize) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize(size, 0);
+        buf.extend(bytes);
+    }
+
+    fn chunk(buf: &mut Vec<u8>, tag: &[u8], contents: &[u8]) {
+        buf.extend_from_slice(tag);
+        buf.write_u32::<LE>(contents.len() as u32).unwrap();
+        buf.extend_from_slice(contents);
+    }
+
+    fn extent(buf: &mut Vec<u8>, radius: f32) {
+        buf.write_f32::<LE>(radius).unwrap();
+        for value in &[-1.0, -2.0, 0.0, 1.0, 2.0, 3.0] {
+            buf.write_f32::<LE>(*value).unwrap();
+        }
+    }
+
+    #[test]
+    fn mdx_read() {
+        let mut buf = MAGIC.to_vec();
+        chunk(&mut buf, b"VERS", &800u32.to_le_bytes());
+
+        let mut modl = Vec::new();
+        fixed_string(&mut modl, "Footman", NAME_SIZE);
+        fixed_string(&mut modl, "", FILE_NAME_SIZE);
+        extent(&mut modl, 50.0);
+        modl.write_u32::<LE>(150).unwrap();
+        chunk(&mut buf, b"MODL", &modl);
+
+        let mut seqs = Vec::new();
+        for (name, non_looping) in &[("Stand", 0), ("Death", 1)] {
+            fixed_string(&mut seqs, name, NAME_SIZE);
+            seqs.write_u32::<LE>(0).unwrap();
+            seqs.write_u32::<LE>(1000).unwrap();
+            seqs.write_f32::<LE>(270.0).unwrap();
+            seqs.write_u32::<LE>(*non_looping).unwrap();
+            seqs.write_f32::<LE>(0.0).unwrap();
+            seqs.write_u32::<LE>(0).unwrap();
+            extent(&mut seqs, 10.0);
+        }
+        chunk(&mut buf, b"SEQS", &seqs);
+
+        // unknown chunks are skipped
+        chunk(&mut buf, b"GLBS", &[0; 8]);
+
+        let mut texs = Vec::new();
+        texs.write_u32::<LE>(1).unwrap();
+        fixed_string(&mut texs, "", FILE_NAME_SIZE);
+        texs.write_u32::<LE>(0).unwrap();
+        texs.write_u32::<LE>(0).unwrap();
+        fixed_string(&mut texs, "Textures\\Footman.blp", FILE_NAME_SIZE);
+        texs.write_u32::<LE>(3).unwrap();
+        chunk(&mut buf, b"TEXS", &texs);
+
+        let mut node = Vec::new();
+        fixed_string(&mut node, "Overhead Ref", NAME_SIZE);
+        node.write_u32::<LE>(7).unwrap();
+        node.write_i32::<LE>(-1).unwrap();
+        node.write_u32::<LE>(0x800).unwrap();
+        let mut attachment = Vec::new();
+        attachment.write_u32::<LE>(node.len() as u32 + 4).unwrap();
+        attachment.extend(node);
+        fixed_string(&mut attachment, "", ATTACHMENT_PATH_SIZE);
+        attachment.write_u32::<LE>(2).unwrap();
+        // tracks after the ID are skipped
+        attachment.extend_from_slice(b"KATV");
+        attachment.extend_from_slice(&[0; 12]);
+        let mut atch = Vec::new();
+        atch.write_u32::<LE>(attachment.len() as u32 + 4).unwrap();
+        atch.extend(attachment);
+        chunk(&mut buf, b"ATCH", &atch);
+
+        let model = read::read_mdx(&buf).unwrap();
+
+        assert_eq!(model.version, 800);
+        assert_eq!(model.name, "Footman");
+        assert_eq!(model.extent.max, [1.0, 2.0, 3.0]);
+        assert_eq!(model.extent.radius, 50.0);
+        assert_eq!(model.sequences.len(), 2);
+        assert_eq!(model.sequences[1].name, "Death");
+        assert!(model.sequences[1].non_looping);
+        assert_eq!(model.sequences[0].interval, [0, 1000]);
+        assert_eq!(
+            model.texture_paths().collect::<Vec<_>>(),
+            vec!["Textures\\Footman.blp"]
+        );
+        assert_eq!(model.attachments[0].name, "Overhead Ref");
+        assert_eq!(model.attachments[0].object_id, 7);
+        assert_eq!(model.attachments[0].attachment_id, 2);
+    }
+}
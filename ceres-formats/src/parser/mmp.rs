@@ -0,0 +1,125 @@
+//! Reading and writing of `war3map.mmp`, the minimap icons.
+
+const VERSION: u32 = 0;
+
+pub mod read {
+    use byteorder::{ReadBytesExt, LE};
+
+    use crate::error::FormatError;
+    use crate::minimap::*;
+    use crate::parser::binary::read_bytes;
+
+    use super::*;
+
+    pub fn read_minimap_icons(mut source: &[u8]) -> Result<MinimapIcons, FormatError> {
+        let source = &mut source;
+
+        let version = source.read_u32::<LE>()?;
+        if version != VERSION {
+            return Err(FormatError::unsupported_version("mmp", version));
+        }
+
+        let count = source.read_u32::<LE>()?;
+        let icons = (0..count)
+            .map(|_| {
+                let kind = source.read_u32::<LE>()?;
+                let x = source.read_u32::<LE>()?;
+                let y = source.read_u32::<LE>()?;
+                let bgra = read_bytes(source, 4)?;
+
+                Ok(MinimapIcon {
+                    kind,
+                    x,
+                    y,
+                    color: [bgra[2], bgra[1], bgra[0], bgra[3]],
+                })
+            })
+            .collect::<Result<Vec<_>, FormatError>>()?;
+
+        Ok(MinimapIcons { version, icons })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::minimap::*;
+
+    use super::*;
+
+    pub fn write_minimap_icons<W: Write>(
+        mut writer: W,
+        file: &MinimapIcons,
+    ) -> Result<(), IoError> {
+        writer.write_u32::<LE>(VERSION)?;
+        writer.write_u32::<LE>(file.icons.len() as u32)?;
+
+        for icon in &file.icons {
+            let [r, g, b, a] = icon.color;
+
+            writer.write_u32::<LE>(icon.kind)?;
+            writer.write_u32::<LE>(icon.x)?;
+            writer.write_u32::<LE>(icon.y)?;
+            writer.write_all(&[b, g, r, a])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::minimap::*;
+
+    use super::*;
+
+    #[test]
+    fn minimap_icons_read() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LE>(VERSION).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.write_u32::<LE>(icons::START_LOCATION).unwrap();
+        buf.write_u32::<LE>(216).unwrap();
+        buf.write_u32::<LE>(40).unwrap();
+        buf.extend_from_slice(&[255, 66, 0, 255]);
+
+        let file = read::read_minimap_icons(&buf).unwrap();
+        let blue = &file.icons[0];
+
+        assert_eq!(blue.kind, icons::START_LOCATION);
+        assert_eq!((blue.x, blue.y), (216, 40));
+        // player colors are stored as BGRA
+        assert_eq!(blue.color, [0, 66, 255, 255]);
+    }
+
+    #[test]
+    fn minimap_icons_roundtrip() {
+        let file = MinimapIcons {
+            version: VERSION,
+            icons:   vec![
+                MinimapIcon {
+                    kind:  icons::START_LOCATION,
+                    x:     32,
+                    y:     224,
+                    color: [255, 3, 3, 255],
+                },
+                MinimapIcon {
+                    kind:  icons::GOLD_MINE,
+                    x:     64,
+                    y:     200,
+                    color: [255, 255, 255, 255],
+                },
+            ],
+        };
+
+        let mut buf = Vec::new();
+        write::write_minimap_icons(&mut buf, &file).unwrap();
+        assert_eq!(buf.len(), 8 + 16 * 2);
+
+        assert_eq!(read::read_minimap_icons(&buf).unwrap(), file);
+    }
+}
@@ -0,0 +1,171 @@
+//! Reading and writing of TGA images.
+
+const TYPE_COLOR_MAPPED: u8 = 1;
+const TYPE_TRUE_COLOR: u8 = 2;
+const TYPE_GRAYSCALE: u8 = 3;
+const TYPE_RLE: u8 = 8;
+
+/// Set in the descriptor if rows are stored from the top.
+const TOP_TO_BOTTOM: u8 = 0x20;
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use byteorder::{ReadBytesExt, LE};
+
+    use crate::error::FormatError;
+    use crate::image::Image;
+    use crate::parser::binary::read_bytes;
+
+    use super::*;
+
+    fn invalid(message: &str) -> FormatError {
+        IoError::new(ErrorKind::InvalidData, message.to_string()).into()
+    }
+
+    /// Reads uncompressed and RLE compressed true color and grayscale images.
+    pub fn read_tga(mut source: &[u8]) -> Result<Image, FormatError> {
+        let source = &mut source;
+
+        let id_length = source.read_u8()?;
+        let color_map_type = source.read_u8()?;
+        let image_type = source.read_u8()?;
+        let _color_map_start = source.read_u16::<LE>()?;
+        let color_map_length = source.read_u16::<LE>()?;
+        let color_map_depth = source.read_u8()?;
+        let _x_origin = source.read_u16::<LE>()?;
+        let _y_origin = source.read_u16::<LE>()?;
+        let width = u32::from(source.read_u16::<LE>()?);
+        let height = u32::from(source.read_u16::<LE>()?);
+        let depth = source.read_u8()?;
+        let descriptor = source.read_u8()?;
+
+        read_bytes(source, usize::from(id_length))?;
+        if color_map_type != 0 {
+            let size = usize::from(color_map_length) * usize::from(color_map_depth).div_ceil(8);
+            read_bytes(source, size)?;
+        }
+
+        let base_type = image_type & !TYPE_RLE;
+        if base_type == TYPE_COLOR_MAPPED {
+            return Err(invalid("color mapped TGA images are not supported"));
+        }
+
+        let bytes_per_pixel = match (base_type, depth) {
+            (TYPE_TRUE_COLOR, 24) => 3,
+            (TYPE_TRUE_COLOR, 32) => 4,
+            (TYPE_GRAYSCALE, 8) => 1,
+            _ => return Err(invalid("unsupported TGA image type or depth")),
+        };
+
+        let count = (width * height) as usize;
+        let data = if image_type & TYPE_RLE != 0 {
+            let mut data = Vec::with_capacity(count * bytes_per_pixel);
+
+            while data.len() < count * bytes_per_pixel {
+                let header = source.read_u8()?;
+                let length = usize::from(header & 0x7F) + 1;
+
+                if header & 0x80 != 0 {
+                    let pixel = read_bytes(source, bytes_per_pixel)?;
+                    for _ in 0..length {
+                        data.extend_from_slice(pixel);
+                    }
+                } else {
+                    data.extend_from_slice(read_bytes(source, length * bytes_per_pixel)?);
+                }
+            }
+
+            data.truncate(count * bytes_per_pixel);
+            data
+        } else {
+            read_bytes(source, count * bytes_per_pixel)?.to_vec()
+        };
+
+        let mut image = Image::new(width, height);
+        for (i, pixel) in data.chunks(bytes_per_pixel).enumerate() {
+            let x = i as u32 % width;
+            let row = i as u32 / width;
+            let y = if descriptor & TOP_TO_BOTTOM != 0 {
+                row
+            } else {
+                height - 1 - row
+            };
+
+            let rgba = match pixel {
+                [b, g, r, a] => [*r, *g, *b, *a],
+                [b, g, r] => [*r, *g, *b, 255],
+                [gray] => [*gray, *gray, *gray, 255],
+                _ => unreachable!(),
+            };
+
+            image.set_pixel(x, y, rgba);
+        }
+
+        Ok(image)
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, ErrorKind, Write};
+
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::image::Image;
+
+    use super::*;
+
+    /// Writes an uncompressed 32-bit image, stored from the top.
+    pub fn write_tga<W: Write>(mut writer: W, image: &Image) -> Result<(), IoError> {
+        if image.width > u32::from(u16::MAX) || image.height > u32::from(u16::MAX) {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "TGA images are at most 65535 pixels wide and high",
+            ));
+        }
+
+        writer.write_u8(0)?;
+        writer.write_u8(0)?;
+        writer.write_u8(TYPE_TRUE_COLOR)?;
+        writer.write_all(&[0; 5])?;
+        writer.write_u16::<LE>(0)?;
+        writer.write_u16::<LE>(0)?;
+        writer.write_u16::<LE>(image.width as u16)?;
+        writer.write_u16::<LE>(image.height as u16)?;
+        writer.write_u8(32)?;
+        // 8 alpha bits
+        writer.write_u8(TOP_TO_BOTTOM | 8)?;
+
+        for pixel in image.pixels.chunks(4) {
+            writer.write_all(&[pixel[2], pixel[1], pixel[0], pixel[3]])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::image::Image;
+
+    use super::*;
+
+    #[test]
+    fn tga_roundtrip() {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(0, 0, [255, 0, 0, 255]);
+        image.set_pixel(2, 1, [10, 20, 30, 40]);
+
+        let mut buf = Vec::new();
+        write::write_tga(&mut buf, &image).unwrap();
+        assert_eq!(read::read_tga(&buf).unwrap(), image);
+
+        // RLE compressed 24-bit, stored from the bottom
+        let mut rle = vec![0, 0, TYPE_TRUE_COLOR | TYPE_RLE, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rle.extend_from_slice(&[2, 0, 1, 0, 24, 0]);
+        rle.extend_from_slice(&[0x81, 1, 2, 3]);
+
+        let image = read::read_tga(&rle).unwrap();
+        assert_eq!(image.pixel(1, 0), [3, 2, 1, 255]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Reading and writing of `war3map.w3c`, the cameras.
+//!
+//! Since 1.31, every camera has three more fields for its local rotation,
+//! but the version of the file stayed the same. The reader therefore tries
+//! both layouts, and keeps the one which covers the file exactly.
+
+const VERSION: u32 = 0;
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use byteorder::{ReadBytesExt, LE};
+
+    use crate::cameras::*;
+    use crate::error::FormatError;
+    use crate::parser::binary::read_cstring;
+
+    use super::*;
+
+    fn read_camera(source: &mut &[u8], local_rotation: bool) -> Result<Camera, FormatError> {
+        let target_x = source.read_f32::<LE>()?;
+        let target_y = source.read_f32::<LE>()?;
+        let z_offset = source.read_f32::<LE>()?;
+        let rotation = source.read_f32::<LE>()?;
+        let angle_of_attack = source.read_f32::<LE>()?;
+        let distance = source.read_f32::<LE>()?;
+        let roll = source.read_f32::<LE>()?;
+        let field_of_view = source.read_f32::<LE>()?;
+        let far_z = source.read_f32::<LE>()?;
+        let near_z = source.read_f32::<LE>()?;
+
+        let local_rotation = if local_rotation {
+            Some([
+                source.read_f32::<LE>()?,
+                source.read_f32::<LE>()?,
+                source.read_f32::<LE>()?,
+            ])
+        } else {
+            None
+        };
+
+        let name = read_cstring(source)?;
+
+        Ok(Camera {
+            name,
+            target_x,
+            target_y,
+            z_offset,
+            rotation,
+            angle_of_attack,
+            distance,
+            roll,
+            field_of_view,
+            far_z,
+            near_z,
+            local_rotation,
+        })
+    }
+
+    /// Reads all cameras, failing unless they end exactly where the file does.
+    fn read_cameras_exact(
+        mut source: &[u8],
+        count: u32,
+        local_rotation: bool,
+    ) -> Result<Vec<Camera>, FormatError> {
+        let source = &mut source;
+        let cameras = (0..count)
+            .map(|_| read_camera(source, local_rotation))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !source.is_empty() {
+            let message = format!("{} bytes left after the last camera", source.len());
+
+            return Err(IoError::new(ErrorKind::InvalidData, message).into());
+        }
+
+        Ok(cameras)
+    }
+
+    pub fn read_cameras(mut source: &[u8]) -> Result<CamerasFile, FormatError> {
+        let version = source.read_u32::<LE>()?;
+        if version != VERSION {
+            return Err(FormatError::unsupported_version("w3c", version));
+        }
+
+        let count = source.read_u32::<LE>()?;
+        let cameras = read_cameras_exact(source, count, false)
+            .or_else(|_| read_cameras_exact(source, count, true))?;
+
+        Ok(CamerasFile { version, cameras })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::cameras::*;
+    use crate::parser::binary::write_cstring;
+
+    use super::*;
+
+    fn write_camera<W: Write>(
+        mut writer: W,
+        camera: &Camera,
+        local_rotation: bool,
+    ) -> Result<(), IoError> {
+        writer.write_f32::<LE>(camera.target_x)?;
+        writer.write_f32::<LE>(camera.target_y)?;
+        writer.write_f32::<LE>(camera.z_offset)?;
+        writer.write_f32::<LE>(camera.rotation)?;
+        writer.write_f32::<LE>(camera.angle_of_attack)?;
+        writer.write_f32::<LE>(camera.distance)?;
+        writer.write_f32::<LE>(camera.roll)?;
+        writer.write_f32::<LE>(camera.field_of_view)?;
+        writer.write_f32::<LE>(camera.far_z)?;
+        writer.write_f32::<LE>(camera.near_z)?;
+
+        if local_rotation {
+            for angle in &camera.local_rotation.unwrap_or_default() {
+                writer.write_f32::<LE>(*angle)?;
+            }
+        }
+
+        write_cstring(&mut writer, &camera.name)?;
+
+        Ok(())
+    }
+
+    /// Writes the 1.31 layout if any camera has a local rotation.
+    pub fn write_cameras<W: Write>(mut writer: W, file: &CamerasFile) -> Result<(), IoError> {
+        let local_rotation = file
+            .cameras
+            .iter()
+            .any(|camera| camera.local_rotation.is_some());
+
+        writer.write_u32::<LE>(VERSION)?;
+        writer.write_u32::<LE>(file.cameras.len() as u32)?;
+
+        for camera in &file.cameras {
+            write_camera(&mut writer, camera, local_rotation)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cameras::*;
+
+    use super::*;
+
+    fn camera(name: &str, local_rotation: Option<[f32; 3]>) -> Camera {
+        let mut camera = Camera::new(name.into());
+        camera.target_x = 128.0;
+        camera.target_y = -64.0;
+        camera.local_rotation = local_rotation;
+
+        camera
+    }
+
+    #[test]
+    fn cameras_roundtrip() {
+        let classic = CamerasFile {
+            version: VERSION,
+            cameras: vec![camera("Intro", None), camera("Boss Fight", None)],
+        };
+        let reforged = CamerasFile {
+            version: VERSION,
+            cameras: vec![camera("Intro", Some([0.0, 10.0, 0.0]))],
+        };
+
+        assert_eq!(classic.cameras[1].global_name(), "gg_cam_Boss_Fight");
+
+        for file in &[classic, reforged] {
+            let mut buf = Vec::new();
+            write::write_cameras(&mut buf, file).unwrap();
+
+            assert_eq!(&read::read_cameras(&buf).unwrap(), file);
+        }
+    }
+}
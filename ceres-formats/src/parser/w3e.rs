@@ -0,0 +1,252 @@
+//! Reading and writing of `war3map.w3e`, the terrain.
+
+const MAGIC: &[u8] = b"W3E!";
+const VERSION: u32 = 11;
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use byteorder::{ReadBytesExt, BE, LE};
+
+    use crate::error::FormatError;
+    use crate::parser::binary::read_bytes;
+    use crate::terrain::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn read_ids(source: &mut &[u8]) -> Result<Vec<ObjectId>, FormatError> {
+        let count = source.read_u32::<LE>()?;
+
+        (0..count)
+            .map(|_| Ok(ObjectId::new(source.read_u32::<BE>()?)))
+            .collect()
+    }
+
+    fn read_point(source: &mut &[u8]) -> Result<TilePoint, FormatError> {
+        let height = source.read_i16::<LE>()?;
+        let water = source.read_u16::<LE>()?;
+        let texture = source.read_u8()?;
+        let variation = source.read_u8()?;
+        let cliff = source.read_u8()?;
+
+        Ok(TilePoint {
+            height,
+            water: water & 0x3FFF,
+            map_edge: water & 0x4000 != 0,
+            texture: texture & 0x0F,
+            flags: texture >> 4,
+            variation: variation & 0x1F,
+            cliff_variation: variation >> 5,
+            cliff_texture: cliff >> 4,
+            layer: cliff & 0x0F,
+        })
+    }
+
+    pub fn read_terrain(mut source: &[u8]) -> Result<Terrain, FormatError> {
+        let source = &mut source;
+
+        if read_bytes(source, MAGIC.len())? != MAGIC {
+            return Err(FormatError::invalid_magic("W3E!"));
+        }
+
+        let version = source.read_u32::<LE>()?;
+        if version != VERSION {
+            return Err(FormatError::unsupported_version("w3e", version));
+        }
+
+        let tileset = source.read_u8()?;
+        let custom_tilesets = source.read_u32::<LE>()? != 0;
+        let ground_tilesets = read_ids(source)?;
+        let cliff_tilesets = read_ids(source)?;
+        let width = source.read_u32::<LE>()?;
+        let height = source.read_u32::<LE>()?;
+        let offset_x = source.read_f32::<LE>()?;
+        let offset_y = source.read_f32::<LE>()?;
+
+        let count = u64::from(width) * u64::from(height);
+        if count * 7 > source.len() as u64 {
+            let message = format!("terrain of {}x{} points is truncated", width, height);
+
+            return Err(IoError::new(ErrorKind::UnexpectedEof, message).into());
+        }
+
+        let points = (0..count)
+            .map(|_| read_point(source))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Terrain {
+            version,
+            tileset,
+            custom_tilesets,
+            ground_tilesets,
+            cliff_tilesets,
+            width,
+            height,
+            offset_x,
+            offset_y,
+            points,
+        })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, ErrorKind, Write};
+
+    use byteorder::{WriteBytesExt, BE, LE};
+
+    use crate::terrain::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn write_ids<W: Write>(mut writer: W, ids: &[ObjectId]) -> Result<(), IoError> {
+        writer.write_u32::<LE>(ids.len() as u32)?;
+
+        for id in ids {
+            writer.write_u32::<BE>(id.to_u32())?;
+        }
+
+        Ok(())
+    }
+
+    fn write_point<W: Write>(mut writer: W, point: &TilePoint) -> Result<(), IoError> {
+        let edge = if point.map_edge { 0x4000 } else { 0 };
+
+        writer.write_i16::<LE>(point.height)?;
+        writer.write_u16::<LE>((point.water & 0x3FFF) | edge)?;
+        writer.write_u8((point.flags << 4) | (point.texture & 0x0F))?;
+        writer.write_u8((point.cliff_variation << 5) | (point.variation & 0x1F))?;
+        writer.write_u8((point.cliff_texture << 4) | (point.layer & 0x0F))?;
+
+        Ok(())
+    }
+
+    pub fn write_terrain<W: Write>(mut writer: W, terrain: &Terrain) -> Result<(), IoError> {
+        if terrain.points.len() as u64 != u64::from(terrain.width) * u64::from(terrain.height) {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "terrain has {} points, but is {}x{}",
+                    terrain.points.len(),
+                    terrain.width,
+                    terrain.height
+                ),
+            ));
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LE>(VERSION)?;
+        writer.write_u8(terrain.tileset)?;
+        writer.write_u32::<LE>(terrain.custom_tilesets as u32)?;
+        write_ids(&mut writer, &terrain.ground_tilesets)?;
+        write_ids(&mut writer, &terrain.cliff_tilesets)?;
+        writer.write_u32::<LE>(terrain.width)?;
+        writer.write_u32::<LE>(terrain.height)?;
+        writer.write_f32::<LE>(terrain.offset_x)?;
+        writer.write_f32::<LE>(terrain.offset_y)?;
+
+        for point in &terrain.points {
+            write_point(&mut writer, point)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::terrain::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    #[test]
+    fn terrain_read() {
+        let mut buf = MAGIC.to_vec();
+        buf.write_u32::<LE>(VERSION).unwrap();
+        buf.push(b'L');
+        buf.write_u32::<LE>(0).unwrap();
+        buf.write_u32::<LE>(2).unwrap();
+        buf.extend_from_slice(b"LdrtLgrs");
+        buf.write_u32::<LE>(1).unwrap();
+        buf.extend_from_slice(b"CLgr");
+        buf.write_u32::<LE>(2).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.write_f32::<LE>(-2048.0).unwrap();
+        buf.write_f32::<LE>(-1024.0).unwrap();
+
+        // a boundary at the map edge, on layer 2 and at zero height
+        buf.write_i16::<LE>(ZERO_HEIGHT).unwrap();
+        buf.write_u16::<LE>(0x4000 | 0x2000).unwrap();
+        buf.extend_from_slice(&[0x80, 0x00, 0x02]);
+        // water on layer 1, with texture 1 and variations 17 and 5
+        buf.write_i16::<LE>(ZERO_HEIGHT - 58).unwrap();
+        buf.write_u16::<LE>(0x2000 - 512).unwrap();
+        buf.extend_from_slice(&[0x41, 0xB1, 0x31]);
+
+        let terrain = read::read_terrain(&buf).unwrap();
+
+        assert_eq!(terrain.tileset, b'L');
+        assert!(!terrain.custom_tilesets);
+        assert_eq!(terrain.ground_tilesets[1], ObjectId::from_bytes(b"Lgrs").unwrap());
+        assert_eq!(terrain.cliff_tilesets[0], ObjectId::from_bytes(b"CLgr").unwrap());
+        assert_eq!((terrain.width, terrain.height), (2, 1));
+        assert_eq!((terrain.offset_x, terrain.offset_y), (-2048.0, -1024.0));
+
+        let corner = terrain.point(0, 0).unwrap();
+        assert!(corner.map_edge);
+        assert_eq!(corner.flags, flags::BOUNDARY);
+        assert_eq!(corner.water_height(), None);
+        assert_eq!(corner.world_height(), 0.0);
+
+        let pond = terrain.point(1, 0).unwrap();
+        assert!(!pond.map_edge);
+        assert_eq!((pond.texture, pond.flags), (1, flags::WATER));
+        assert_eq!((pond.variation, pond.cliff_variation), (17, 5));
+        assert_eq!((pond.cliff_texture, pond.layer), (3, 1));
+        assert_eq!(pond.water_height(), Some(-128.0));
+        assert_eq!(pond.world_height(), -14.5 - 128.0);
+    }
+
+    #[test]
+    fn terrain_roundtrip() {
+        let mut terrain = Terrain {
+            version:         VERSION,
+            tileset:         b'L',
+            custom_tilesets: false,
+            ground_tilesets: vec![
+                ObjectId::from_bytes(b"Ldrt").unwrap(),
+                ObjectId::from_bytes(b"Lgrs").unwrap(),
+            ],
+            cliff_tilesets:  vec![ObjectId::from_bytes(b"CLdi").unwrap()],
+            width:           0,
+            height:          0,
+            offset_x:        -256.0,
+            offset_y:        -256.0,
+            points:          Vec::new(),
+        };
+
+        terrain.resize(5, 4);
+
+        let point = terrain.point_mut(2, 3).unwrap();
+        point.texture = 1;
+        point.flags = flags::BLIGHT | flags::BOUNDARY;
+        point.variation = 17;
+        point.cliff_variation = 5;
+        point.layer = 4;
+        point.map_edge = true;
+        point.set_world_height(-300.0);
+        point.set_water_height(Some(-64.0));
+
+        assert!((point.world_height() + 300.0).abs() < 0.01);
+        assert!((point.water_height().unwrap() + 64.0).abs() < 0.01);
+
+        let mut buf = Vec::new();
+        write::write_terrain(&mut buf, &terrain).unwrap();
+
+        assert_eq!(read::read_terrain(&buf).unwrap(), terrain);
+    }
+}
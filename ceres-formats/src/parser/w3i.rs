@@ -0,0 +1,660 @@
+//! Reading and writing of `war3map.w3i`, the map info file.
+//!
+//! Supports the RoC (18) and TFT (25) formats, as well as the
+//! formats written by later patches up to Reforged (31).
+
+/// Oldest and newest supported format versions.
+const MIN_VERSION: u32 = 18;
+const MAX_VERSION: u32 = 31;
+
+fn is_supported(version: u32) -> bool {
+    version == MIN_VERSION || (25..=MAX_VERSION).contains(&version)
+}
+
+pub mod read {
+    use byteorder::{ReadBytesExt, BE, LE};
+
+    use crate::error::FormatError;
+    use crate::mapinfo::*;
+    use crate::parser::binary::{read_bytes, read_cstring};
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn read_id(source: &mut &[u8]) -> Result<ObjectId, FormatError> {
+        Ok(ObjectId::new(source.read_u32::<BE>()?))
+    }
+
+    fn read_color(source: &mut &[u8]) -> Result<[u8; 4], FormatError> {
+        let mut color = [0; 4];
+        color.copy_from_slice(read_bytes(source, 4)?);
+
+        Ok(color)
+    }
+
+    fn read_player(source: &mut &[u8], version: u32) -> Result<PlayerInfo, FormatError> {
+        let mut player = PlayerInfo {
+            id: source.read_u32::<LE>()?,
+            kind: source.read_u32::<LE>()?,
+            race: source.read_u32::<LE>()?,
+            fixed_start_position: source.read_u32::<LE>()? != 0,
+            name: read_cstring(source)?,
+            start_x: source.read_f32::<LE>()?,
+            start_y: source.read_f32::<LE>()?,
+            ally_low_priorities: source.read_u32::<LE>()?,
+            ally_high_priorities: source.read_u32::<LE>()?,
+            ..Default::default()
+        };
+
+        if version >= 31 {
+            player.enemy_low_priorities = source.read_u32::<LE>()?;
+            player.enemy_high_priorities = source.read_u32::<LE>()?;
+        }
+
+        Ok(player)
+    }
+
+    fn read_force(source: &mut &[u8]) -> Result<ForceInfo, FormatError> {
+        Ok(ForceInfo {
+            flags:   source.read_u32::<LE>()?,
+            players: source.read_u32::<LE>()?,
+            name:    read_cstring(source)?,
+        })
+    }
+
+    fn read_random_unit_table(source: &mut &[u8]) -> Result<RandomUnitTable, FormatError> {
+        let id = source.read_u32::<LE>()?;
+        let name = read_cstring(source)?;
+
+        let column_count = source.read_u32::<LE>()?;
+        let columns = (0..column_count)
+            .map(|_| source.read_u32::<LE>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let row_count = source.read_u32::<LE>()?;
+        let mut rows = Vec::new();
+        for _ in 0..row_count {
+            let chance = source.read_u32::<LE>()?;
+            let ids = (0..column_count)
+                .map(|_| read_id(source))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            rows.push(RandomUnitRow { chance, ids });
+        }
+
+        Ok(RandomUnitTable {
+            id,
+            name,
+            columns,
+            rows,
+        })
+    }
+
+    fn read_random_item_table(source: &mut &[u8]) -> Result<RandomItemTable, FormatError> {
+        let id = source.read_u32::<LE>()?;
+        let name = read_cstring(source)?;
+
+        let set_count = source.read_u32::<LE>()?;
+        let mut sets = Vec::new();
+        for _ in 0..set_count {
+            let item_count = source.read_u32::<LE>()?;
+            let mut items = Vec::new();
+
+            for _ in 0..item_count {
+                items.push(RandomItem {
+                    chance: source.read_u32::<LE>()?,
+                    id:     read_id(source)?,
+                });
+            }
+
+            sets.push(items);
+        }
+
+        Ok(RandomItemTable { id, name, sets })
+    }
+
+    fn read_list<T, F>(source: &mut &[u8], mut read: F) -> Result<Vec<T>, FormatError>
+    where
+        F: FnMut(&mut &[u8]) -> Result<T, FormatError>,
+    {
+        let count = source.read_u32::<LE>()?;
+
+        (0..count).map(|_| read(source)).collect()
+    }
+
+    pub fn read_map_info(mut source: &[u8]) -> Result<MapInfo, FormatError> {
+        let source = &mut source;
+
+        let version = source.read_u32::<LE>()?;
+        if !is_supported(version) {
+            return Err(FormatError::unsupported_version("w3i", version));
+        }
+
+        let tft = version >= 25;
+        let map_version = source.read_u32::<LE>()?;
+        let editor_version = source.read_u32::<LE>()?;
+
+        let mut game_version = [0; 4];
+        if version >= 27 {
+            for part in &mut game_version {
+                *part = source.read_u32::<LE>()?;
+            }
+        }
+
+        let name = read_cstring(source)?;
+        let author = read_cstring(source)?;
+        let description = read_cstring(source)?;
+        let suggested_players = read_cstring(source)?;
+
+        let mut camera_bounds = [0.0; 8];
+        for bound in &mut camera_bounds {
+            *bound = source.read_f32::<LE>()?;
+        }
+
+        let mut camera_complements = [0; 4];
+        for complement in &mut camera_complements {
+            *complement = source.read_i32::<LE>()?;
+        }
+
+        let playable_width = source.read_u32::<LE>()?;
+        let playable_height = source.read_u32::<LE>()?;
+        let flags = source.read_u32::<LE>()?;
+        let tileset = source.read_u8()?;
+
+        let mut loading_screen = LoadingScreen::default();
+        let mut prologue = Prologue::default();
+        let mut campaign_background = -1;
+        let mut game_data_set = 0;
+
+        if tft {
+            loading_screen.number = source.read_i32::<LE>()?;
+            loading_screen.model = read_cstring(source)?;
+        } else {
+            campaign_background = source.read_i32::<LE>()?;
+        }
+
+        loading_screen.text = read_cstring(source)?;
+        loading_screen.title = read_cstring(source)?;
+        loading_screen.subtitle = read_cstring(source)?;
+
+        if tft {
+            game_data_set = source.read_u32::<LE>()?;
+            prologue.model = read_cstring(source)?;
+        } else {
+            loading_screen.number = source.read_i32::<LE>()?;
+        }
+
+        prologue.text = read_cstring(source)?;
+        prologue.title = read_cstring(source)?;
+        prologue.subtitle = read_cstring(source)?;
+
+        let mut fog = Fog::default();
+        let mut weather = 0;
+        let mut sound_environment = String::new();
+        let mut light_tileset = 0;
+        let mut water_color = [255; 4];
+
+        if tft {
+            fog = Fog {
+                style:   source.read_u32::<LE>()?,
+                start_z: source.read_f32::<LE>()?,
+                end_z:   source.read_f32::<LE>()?,
+                density: source.read_f32::<LE>()?,
+                color:   read_color(source)?,
+            };
+            weather = source.read_u32::<LE>()?;
+            sound_environment = read_cstring(source)?;
+            light_tileset = source.read_u8()?;
+            water_color = read_color(source)?;
+        }
+
+        let script_language = if version >= 28 && source.read_u32::<LE>()? == 1 {
+            ScriptLanguage::Lua
+        } else {
+            ScriptLanguage::Jass
+        };
+
+        let mut supported_modes = 0;
+        let mut game_data_version = 0;
+        if version >= 31 {
+            supported_modes = source.read_u32::<LE>()?;
+            game_data_version = source.read_u32::<LE>()?;
+        }
+
+        let players = read_list(source, |source| read_player(source, version))?;
+        let forces = read_list(source, read_force)?;
+        let upgrades = read_list(source, |source| {
+            Ok(UpgradeAvailability {
+                players:      source.read_u32::<LE>()?,
+                id:           read_id(source)?,
+                level:        source.read_u32::<LE>()?,
+                availability: source.read_u32::<LE>()?,
+            })
+        })?;
+        let techs = read_list(source, |source| {
+            Ok(TechAvailability {
+                players: source.read_u32::<LE>()?,
+                id:      read_id(source)?,
+            })
+        })?;
+        let random_unit_tables = read_list(source, read_random_unit_table)?;
+
+        // some editors leave the item tables out of TFT maps without any items
+        let random_item_tables = if tft && !source.is_empty() {
+            read_list(source, read_random_item_table)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(MapInfo {
+            version,
+            map_version,
+            editor_version,
+            game_version,
+            name,
+            author,
+            description,
+            suggested_players,
+            camera_bounds,
+            camera_complements,
+            playable_width,
+            playable_height,
+            flags,
+            tileset,
+            loading_screen,
+            campaign_background,
+            game_data_set,
+            prologue,
+            fog,
+            weather,
+            sound_environment,
+            light_tileset,
+            water_color,
+            script_language,
+            supported_modes,
+            game_data_version,
+            players,
+            forces,
+            upgrades,
+            techs,
+            random_unit_tables,
+            random_item_tables,
+        })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, ErrorKind, Write};
+
+    use byteorder::{WriteBytesExt, BE, LE};
+
+    use crate::mapinfo::*;
+    use crate::parser::binary::write_cstring;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn write_id<W: Write>(mut writer: W, id: ObjectId) -> Result<(), IoError> {
+        writer.write_u32::<BE>(id.to_u32())
+    }
+
+    fn write_count<W: Write, T>(mut writer: W, list: &[T]) -> Result<(), IoError> {
+        writer.write_u32::<LE>(list.len() as u32)
+    }
+
+    fn write_player<W: Write>(
+        mut writer: W,
+        player: &PlayerInfo,
+        version: u32,
+    ) -> Result<(), IoError> {
+        writer.write_u32::<LE>(player.id)?;
+        writer.write_u32::<LE>(player.kind)?;
+        writer.write_u32::<LE>(player.race)?;
+        writer.write_u32::<LE>(player.fixed_start_position as u32)?;
+        write_cstring(&mut writer, &player.name)?;
+        writer.write_f32::<LE>(player.start_x)?;
+        writer.write_f32::<LE>(player.start_y)?;
+        writer.write_u32::<LE>(player.ally_low_priorities)?;
+        writer.write_u32::<LE>(player.ally_high_priorities)?;
+
+        if version >= 31 {
+            writer.write_u32::<LE>(player.enemy_low_priorities)?;
+            writer.write_u32::<LE>(player.enemy_high_priorities)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_random_unit_table<W: Write>(
+        mut writer: W,
+        table: &RandomUnitTable,
+    ) -> Result<(), IoError> {
+        writer.write_u32::<LE>(table.id)?;
+        write_cstring(&mut writer, &table.name)?;
+
+        write_count(&mut writer, &table.columns)?;
+        for column in &table.columns {
+            writer.write_u32::<LE>(*column)?;
+        }
+
+        write_count(&mut writer, &table.rows)?;
+        for row in &table.rows {
+            if row.ids.len() != table.columns.len() {
+                return Err(IoError::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "random unit table {} has {} columns, but a row with {} IDs",
+                        table.name,
+                        table.columns.len(),
+                        row.ids.len()
+                    ),
+                ));
+            }
+
+            writer.write_u32::<LE>(row.chance)?;
+            for id in &row.ids {
+                write_id(&mut writer, *id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_random_item_table<W: Write>(
+        mut writer: W,
+        table: &RandomItemTable,
+    ) -> Result<(), IoError> {
+        writer.write_u32::<LE>(table.id)?;
+        write_cstring(&mut writer, &table.name)?;
+
+        write_count(&mut writer, &table.sets)?;
+        for set in &table.sets {
+            write_count(&mut writer, set)?;
+
+            for item in set {
+                writer.write_u32::<LE>(item.chance)?;
+                write_id(&mut writer, item.id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the map info in the format version given by `info.version`.
+    pub fn write_map_info<W: Write>(mut writer: W, info: &MapInfo) -> Result<(), IoError> {
+        let version = info.version;
+        if !is_supported(version) {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!("unsupported w3i version {}", version),
+            ));
+        }
+
+        let tft = version >= 25;
+
+        writer.write_u32::<LE>(version)?;
+        writer.write_u32::<LE>(info.map_version)?;
+        writer.write_u32::<LE>(info.editor_version)?;
+
+        if version >= 27 {
+            for part in &info.game_version {
+                writer.write_u32::<LE>(*part)?;
+            }
+        }
+
+        write_cstring(&mut writer, &info.name)?;
+        write_cstring(&mut writer, &info.author)?;
+        write_cstring(&mut writer, &info.description)?;
+        write_cstring(&mut writer, &info.suggested_players)?;
+
+        for bound in &info.camera_bounds {
+            writer.write_f32::<LE>(*bound)?;
+        }
+
+        for complement in &info.camera_complements {
+            writer.write_i32::<LE>(*complement)?;
+        }
+
+        writer.write_u32::<LE>(info.playable_width)?;
+        writer.write_u32::<LE>(info.playable_height)?;
+        writer.write_u32::<LE>(info.flags)?;
+        writer.write_u8(info.tileset)?;
+
+        let loading_screen = &info.loading_screen;
+        let prologue = &info.prologue;
+
+        if tft {
+            writer.write_i32::<LE>(loading_screen.number)?;
+            write_cstring(&mut writer, &loading_screen.model)?;
+        } else {
+            writer.write_i32::<LE>(info.campaign_background)?;
+        }
+
+        write_cstring(&mut writer, &loading_screen.text)?;
+        write_cstring(&mut writer, &loading_screen.title)?;
+        write_cstring(&mut writer, &loading_screen.subtitle)?;
+
+        if tft {
+            writer.write_u32::<LE>(info.game_data_set)?;
+            write_cstring(&mut writer, &prologue.model)?;
+        } else {
+            writer.write_i32::<LE>(loading_screen.number)?;
+        }
+
+        write_cstring(&mut writer, &prologue.text)?;
+        write_cstring(&mut writer, &prologue.title)?;
+        write_cstring(&mut writer, &prologue.subtitle)?;
+
+        if tft {
+            writer.write_u32::<LE>(info.fog.style)?;
+            writer.write_f32::<LE>(info.fog.start_z)?;
+            writer.write_f32::<LE>(info.fog.end_z)?;
+            writer.write_f32::<LE>(info.fog.density)?;
+            writer.write_all(&info.fog.color)?;
+            writer.write_u32::<LE>(info.weather)?;
+            write_cstring(&mut writer, &info.sound_environment)?;
+            writer.write_u8(info.light_tileset)?;
+            writer.write_all(&info.water_color)?;
+        }
+
+        if version >= 28 {
+            let lua = info.script_language == ScriptLanguage::Lua;
+            writer.write_u32::<LE>(lua as u32)?;
+        }
+
+        if version >= 31 {
+            writer.write_u32::<LE>(info.supported_modes)?;
+            writer.write_u32::<LE>(info.game_data_version)?;
+        }
+
+        write_count(&mut writer, &info.players)?;
+        for player in &info.players {
+            write_player(&mut writer, player, version)?;
+        }
+
+        write_count(&mut writer, &info.forces)?;
+        for force in &info.forces {
+            writer.write_u32::<LE>(force.flags)?;
+            writer.write_u32::<LE>(force.players)?;
+            write_cstring(&mut writer, &force.name)?;
+        }
+
+        write_count(&mut writer, &info.upgrades)?;
+        for upgrade in &info.upgrades {
+            writer.write_u32::<LE>(upgrade.players)?;
+            write_id(&mut writer, upgrade.id)?;
+            writer.write_u32::<LE>(upgrade.level)?;
+            writer.write_u32::<LE>(upgrade.availability)?;
+        }
+
+        write_count(&mut writer, &info.techs)?;
+        for tech in &info.techs {
+            writer.write_u32::<LE>(tech.players)?;
+            write_id(&mut writer, tech.id)?;
+        }
+
+        write_count(&mut writer, &info.random_unit_tables)?;
+        for table in &info.random_unit_tables {
+            write_random_unit_table(&mut writer, table)?;
+        }
+
+        if tft {
+            write_count(&mut writer, &info.random_item_tables)?;
+            for table in &info.random_item_tables {
+                write_random_item_table(&mut writer, table)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mapinfo::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn map_info(version: u32) -> MapInfo {
+        MapInfo {
+            version,
+            map_version: 12,
+            editor_version: 6072,
+            game_version: if version >= 27 {
+                [1, 32, 10, 18820]
+            } else {
+                [0; 4]
+            },
+            name: "TRIGSTR_001".into(),
+            author: "mori".into(),
+            description: "A map".into(),
+            suggested_players: "Any".into(),
+            camera_bounds: [-1.0, -2.0, 3.0, 4.0, -5.0, 6.0, 7.0, -8.0],
+            camera_complements: [6, 6, 4, 8],
+            playable_width: 52,
+            playable_height: 52,
+            flags: 0x8000,
+            tileset: b'L',
+            loading_screen: LoadingScreen {
+                number:   -1,
+                model:    if version >= 25 {
+                    "Loading.mdx".into()
+                } else {
+                    String::new()
+                },
+                text:     "text".into(),
+                title:    "title".into(),
+                subtitle: "subtitle".into(),
+            },
+            campaign_background: if version >= 25 { -1 } else { 2 },
+            game_data_set: if version >= 25 { 1 } else { 0 },
+            prologue: Prologue {
+                model:    String::new(),
+                text:     "prologue".into(),
+                title:    String::new(),
+                subtitle: String::new(),
+            },
+            fog: if version >= 25 {
+                Fog {
+                    style:   1,
+                    start_z: 3000.0,
+                    end_z:   5000.0,
+                    density: 0.5,
+                    color:   [1, 2, 3, 255],
+                }
+            } else {
+                Fog::default()
+            },
+            weather: 0,
+            sound_environment: String::new(),
+            light_tileset: 0,
+            water_color: [255; 4],
+            script_language: if version >= 28 {
+                ScriptLanguage::Lua
+            } else {
+                ScriptLanguage::Jass
+            },
+            supported_modes: if version >= 31 { 3 } else { 0 },
+            game_data_version: if version >= 31 { 1 } else { 0 },
+            players: vec![PlayerInfo {
+                id:                    0,
+                kind:                  1,
+                race:                  1,
+                fixed_start_position:  true,
+                name:                  "Player 1".into(),
+                start_x:               128.0,
+                start_y:               -256.0,
+                ally_low_priorities:   0,
+                ally_high_priorities:  0,
+                enemy_low_priorities:  if version >= 31 { 2 } else { 0 },
+                enemy_high_priorities: 0,
+            }],
+            forces: vec![ForceInfo {
+                flags:   0x1,
+                players: 0xFFFF_FFFF,
+                name:    "Force 1".into(),
+            }],
+            upgrades: vec![UpgradeAvailability {
+                players:      1,
+                id:           ObjectId::from_bytes(b"Rhme").unwrap(),
+                level:        0,
+                availability: 2,
+            }],
+            techs: vec![TechAvailability {
+                players: 1,
+                id:      ObjectId::from_bytes(b"hfoo").unwrap(),
+            }],
+            random_unit_tables: vec![RandomUnitTable {
+                id:      0,
+                name:    "Creeps".into(),
+                columns: vec![0, 2],
+                rows:    vec![RandomUnitRow {
+                    chance: 100,
+                    ids:    vec![
+                        ObjectId::from_bytes(b"nfsh").unwrap(),
+                        ObjectId::from_bytes(b"ratf").unwrap(),
+                    ],
+                }],
+            }],
+            random_item_tables: if version >= 25 {
+                vec![RandomItemTable {
+                    id:   0,
+                    name: "Drops".into(),
+                    sets: vec![vec![RandomItem {
+                        chance: 50,
+                        id:     ObjectId::from_bytes(b"ratc").unwrap(),
+                    }]],
+                }]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    #[test]
+    fn missing_item_tables() {
+        let mut info = map_info(25);
+        info.random_item_tables.clear();
+
+        // some TFT maps end before the count of item tables
+        let mut buf = Vec::new();
+        write::write_map_info(&mut buf, &info).unwrap();
+        assert_eq!(&buf[buf.len() - 4..], &[0; 4]);
+        buf.truncate(buf.len() - 4);
+
+        assert_eq!(read::read_map_info(&buf).unwrap(), info);
+    }
+
+    #[test]
+    fn map_info_roundtrip() {
+        for version in &[18, 25, 26, 27, 28, 29, 30, 31] {
+            let info = map_info(*version);
+
+            let mut buf = Vec::new();
+            write::write_map_info(&mut buf, &info).unwrap();
+
+            assert_eq!(read::read_map_info(&buf).unwrap(), info);
+        }
+    }
+}
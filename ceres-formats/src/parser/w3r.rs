@@ -0,0 +1,178 @@
+//! Reading and writing of `war3map.w3r`, the regions.
+
+const VERSION: u32 = 5;
+
+pub mod read {
+    use byteorder::{ReadBytesExt, BE, LE};
+
+    use crate::error::FormatError;
+    use crate::parser::binary::read_cstring;
+    use crate::regions::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    fn read_region(source: &mut &[u8]) -> Result<Region, FormatError> {
+        let left = source.read_f32::<LE>()?;
+        let bottom = source.read_f32::<LE>()?;
+        let right = source.read_f32::<LE>()?;
+        let top = source.read_f32::<LE>()?;
+        let name = read_cstring(source)?;
+        let creation_number = source.read_u32::<LE>()?;
+        let weather = match source.read_u32::<BE>()? {
+            0 => None,
+            id => Some(ObjectId::new(id)),
+        };
+        let ambient_sound = read_cstring(source)?;
+
+        // stored as BGR, followed by an unused byte
+        let mut color = [0; 3];
+        for channel in color.iter_mut().rev() {
+            *channel = source.read_u8()?;
+        }
+        source.read_u8()?;
+
+        Ok(Region {
+            name,
+            left,
+            bottom,
+            right,
+            top,
+            creation_number,
+            weather,
+            ambient_sound,
+            color,
+        })
+    }
+
+    pub fn read_regions(mut source: &[u8]) -> Result<RegionsFile, FormatError> {
+        let source = &mut source;
+
+        let version = source.read_u32::<LE>()?;
+        if version != VERSION {
+            return Err(FormatError::unsupported_version("w3r", version));
+        }
+
+        let count = source.read_u32::<LE>()?;
+        let regions = (0..count)
+            .map(|_| read_region(source))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(RegionsFile { version, regions })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use byteorder::{WriteBytesExt, BE, LE};
+
+    use crate::parser::binary::write_cstring;
+    use crate::regions::*;
+
+    use super::*;
+
+    fn write_region<W: Write>(mut writer: W, region: &Region) -> Result<(), IoError> {
+        writer.write_f32::<LE>(region.left)?;
+        writer.write_f32::<LE>(region.bottom)?;
+        writer.write_f32::<LE>(region.right)?;
+        writer.write_f32::<LE>(region.top)?;
+        write_cstring(&mut writer, &region.name)?;
+        writer.write_u32::<LE>(region.creation_number)?;
+        writer.write_u32::<BE>(region.weather.map(|id| id.to_u32()).unwrap_or(0))?;
+        write_cstring(&mut writer, &region.ambient_sound)?;
+
+        for channel in region.color.iter().rev() {
+            writer.write_u8(*channel)?;
+        }
+        writer.write_u8(0xFF)?;
+
+        Ok(())
+    }
+
+    pub fn write_regions<W: Write>(mut writer: W, file: &RegionsFile) -> Result<(), IoError> {
+        writer.write_u32::<LE>(VERSION)?;
+        writer.write_u32::<LE>(file.regions.len() as u32)?;
+
+        for region in &file.regions {
+            write_region(&mut writer, region)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::regions::*;
+    use crate::ObjectId;
+
+    use super::*;
+
+    #[test]
+    fn regions_read() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LE>(VERSION).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        for value in &[-512.0, -256.0, 512.0, 256.0] {
+            buf.write_f32::<LE>(*value).unwrap();
+        }
+        buf.extend_from_slice(b"Spawn Area\0");
+        buf.write_u32::<LE>(2).unwrap();
+        buf.extend_from_slice(b"RAhr");
+        buf.extend_from_slice(b"gg_snd_RainAmbience\0");
+        buf.extend_from_slice(&[0, 128, 255, 0xFF]);
+
+        let file = read::read_regions(&buf).unwrap();
+        let spawn = &file.regions[0];
+
+        assert_eq!(spawn.name, "Spawn Area");
+        assert_eq!((spawn.left, spawn.bottom), (-512.0, -256.0));
+        assert_eq!((spawn.right, spawn.top), (512.0, 256.0));
+        assert_eq!(spawn.creation_number, 2);
+        assert_eq!(spawn.weather, ObjectId::from_bytes(b"RAhr"));
+        assert_eq!(spawn.ambient_sound, "gg_snd_RainAmbience");
+        // stored as BGR
+        assert_eq!(spawn.color, [255, 128, 0]);
+    }
+
+    #[test]
+    fn regions_roundtrip() {
+        let file = RegionsFile {
+            version: VERSION,
+            regions: vec![
+                Region {
+                    name:            "Spawn Area".into(),
+                    left:            -512.0,
+                    bottom:          -256.0,
+                    right:           512.0,
+                    top:             256.0,
+                    creation_number: 0,
+                    weather:         ObjectId::from_bytes(b"RAhr"),
+                    ambient_sound:   "gg_snd_Rain".into(),
+                    color:           [255, 128, 0],
+                },
+                Region {
+                    name:            "Exit".into(),
+                    left:            0.0,
+                    bottom:          0.0,
+                    right:           128.0,
+                    top:             128.0,
+                    creation_number: 1,
+                    weather:         None,
+                    ambient_sound:   String::new(),
+                    color:           [0, 0, 255],
+                },
+            ],
+        };
+
+        assert_eq!(file.regions[0].global_name(), "gg_rct_Spawn_Area");
+
+        let mut buf = Vec::new();
+        write::write_regions(&mut buf, &file).unwrap();
+
+        assert_eq!(read::read_regions(&buf).unwrap(), file);
+    }
+}
@@ -0,0 +1,247 @@
+//! Reading and writing of `war3map.w3s`, the sounds.
+
+pub mod read {
+    use byteorder::{ReadBytesExt, LE};
+
+    use crate::error::FormatError;
+    use crate::parser::binary::read_cstring;
+    use crate::sounds::*;
+
+    fn read_extension(source: &mut &[u8], version: u32) -> Result<SoundExtension, FormatError> {
+        let strings = [
+            read_cstring(source)?,
+            read_cstring(source)?,
+            read_cstring(source)?,
+        ];
+        let unknown1 = source.read_u32::<LE>()?;
+        let text = read_cstring(source)?;
+        let unknown2 = source.read_u32::<LE>()?;
+        let has_optional = source.read_u32::<LE>()?;
+        let optional_text = if has_optional != 0 {
+            read_cstring(source)?
+        } else {
+            String::new()
+        };
+        let text2 = read_cstring(source)?;
+        let unknown3 = source.read_u32::<LE>()?;
+        let unknown4 = if version >= 3 {
+            source.read_u32::<LE>()?
+        } else {
+            0
+        };
+
+        Ok(SoundExtension {
+            strings,
+            unknown1,
+            text,
+            unknown2,
+            has_optional,
+            optional_text,
+            text2,
+            unknown3,
+            unknown4,
+        })
+    }
+
+    fn read_sound(source: &mut &[u8], version: u32) -> Result<Sound, FormatError> {
+        Ok(Sound {
+            name:                read_cstring(source)?,
+            file:                read_cstring(source)?,
+            eax_effect:          read_cstring(source)?,
+            flags:               source.read_u32::<LE>()?,
+            fade_in_rate:        source.read_u32::<LE>()?,
+            fade_out_rate:       source.read_u32::<LE>()?,
+            volume:              source.read_i32::<LE>()?,
+            pitch:               source.read_f32::<LE>()?,
+            pitch_variance:      source.read_f32::<LE>()?,
+            priority:            source.read_i32::<LE>()?,
+            channel:             source.read_i32::<LE>()?,
+            min_distance:        source.read_f32::<LE>()?,
+            max_distance:        source.read_f32::<LE>()?,
+            distance_cutoff:     source.read_f32::<LE>()?,
+            cone_inside:         source.read_f32::<LE>()?,
+            cone_outside:        source.read_f32::<LE>()?,
+            cone_outside_volume: source.read_i32::<LE>()?,
+            cone_orientation:    [
+                source.read_f32::<LE>()?,
+                source.read_f32::<LE>()?,
+                source.read_f32::<LE>()?,
+            ],
+            extension:           if version >= 2 {
+                Some(read_extension(source, version)?)
+            } else {
+                None
+            },
+        })
+    }
+
+    pub fn read_sounds(mut source: &[u8]) -> Result<SoundsFile, FormatError> {
+        let source = &mut source;
+
+        let version = source.read_u32::<LE>()?;
+        if !(1..=3).contains(&version) {
+            return Err(FormatError::unsupported_version("w3s", version));
+        }
+
+        let count = source.read_u32::<LE>()?;
+        let sounds = (0..count)
+            .map(|_| read_sound(source, version))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SoundsFile { version, sounds })
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::parser::binary::write_cstring;
+    use crate::sounds::*;
+
+    fn write_extension<W: Write>(
+        mut writer: W,
+        extension: &SoundExtension,
+        version: u32,
+    ) -> Result<(), IoError> {
+        for string in &extension.strings {
+            write_cstring(&mut writer, string)?;
+        }
+        writer.write_u32::<LE>(extension.unknown1)?;
+        write_cstring(&mut writer, &extension.text)?;
+        writer.write_u32::<LE>(extension.unknown2)?;
+        writer.write_u32::<LE>(extension.has_optional)?;
+        if extension.has_optional != 0 {
+            write_cstring(&mut writer, &extension.optional_text)?;
+        }
+        write_cstring(&mut writer, &extension.text2)?;
+        writer.write_u32::<LE>(extension.unknown3)?;
+        if version >= 3 {
+            writer.write_u32::<LE>(extension.unknown4)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_sound<W: Write>(mut writer: W, sound: &Sound, version: u32) -> Result<(), IoError> {
+        write_cstring(&mut writer, &sound.name)?;
+        write_cstring(&mut writer, &sound.file)?;
+        write_cstring(&mut writer, &sound.eax_effect)?;
+        writer.write_u32::<LE>(sound.flags)?;
+        writer.write_u32::<LE>(sound.fade_in_rate)?;
+        writer.write_u32::<LE>(sound.fade_out_rate)?;
+        writer.write_i32::<LE>(sound.volume)?;
+        writer.write_f32::<LE>(sound.pitch)?;
+        writer.write_f32::<LE>(sound.pitch_variance)?;
+        writer.write_i32::<LE>(sound.priority)?;
+        writer.write_i32::<LE>(sound.channel)?;
+        writer.write_f32::<LE>(sound.min_distance)?;
+        writer.write_f32::<LE>(sound.max_distance)?;
+        writer.write_f32::<LE>(sound.distance_cutoff)?;
+        writer.write_f32::<LE>(sound.cone_inside)?;
+        writer.write_f32::<LE>(sound.cone_outside)?;
+        writer.write_i32::<LE>(sound.cone_outside_volume)?;
+        for value in &sound.cone_orientation {
+            writer.write_f32::<LE>(*value)?;
+        }
+
+        if version >= 2 {
+            let extension = sound.extension.clone().unwrap_or_default();
+            write_extension(&mut writer, &extension, version)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_sounds<W: Write>(mut writer: W, file: &SoundsFile) -> Result<(), IoError> {
+        writer.write_u32::<LE>(file.version)?;
+        writer.write_u32::<LE>(file.sounds.len() as u32)?;
+
+        for sound in &file.sounds {
+            write_sound(&mut writer, sound, file.version)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{WriteBytesExt, LE};
+
+    use crate::sounds::*;
+
+    use super::*;
+
+    #[test]
+    fn sounds_read() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.write_u32::<LE>(1).unwrap();
+        buf.extend_from_slice(b"gg_snd_RainAmbience\0");
+        buf.extend_from_slice(b"Sound\\Ambient\\RainAmbience.wav\0");
+        buf.extend_from_slice(b"DefaultEAXON\0");
+        buf.write_u32::<LE>(flags::LOOPING | flags::IS_3D).unwrap();
+        buf.write_u32::<LE>(10).unwrap();
+        buf.write_u32::<LE>(20).unwrap();
+        buf.write_i32::<LE>(80).unwrap();
+        buf.write_f32::<LE>(1.0).unwrap();
+        buf.write_f32::<LE>(0.0).unwrap();
+        buf.write_i32::<LE>(-1).unwrap();
+        buf.write_i32::<LE>(5).unwrap();
+        for value in &[600.0, 10000.0, 3000.0, 360.0, 360.0] {
+            buf.write_f32::<LE>(*value).unwrap();
+        }
+        buf.write_i32::<LE>(127).unwrap();
+        for value in &[0.0, 0.0, 1.0] {
+            buf.write_f32::<LE>(*value).unwrap();
+        }
+
+        let file = read::read_sounds(&buf).unwrap();
+        let rain = &file.sounds[0];
+
+        assert_eq!(rain.name, "gg_snd_RainAmbience");
+        assert_eq!(rain.file, "Sound\\Ambient\\RainAmbience.wav");
+        assert_eq!(rain.eax_effect, "DefaultEAXON");
+        assert_eq!(rain.flags, flags::LOOPING | flags::IS_3D);
+        assert_eq!((rain.fade_in_rate, rain.fade_out_rate), (10, 20));
+        assert_eq!((rain.volume, rain.channel), (80, 5));
+        assert_eq!((rain.min_distance, rain.max_distance), (600.0, 10000.0));
+        assert_eq!((rain.cone_outside_volume, rain.cone_orientation), (127, [0.0, 0.0, 1.0]));
+        assert!(rain.extension.is_none());
+    }
+
+    #[test]
+    fn sounds_roundtrip() {
+        let mut rain = Sound::new("gg_snd_Rain".into(), "Sound\\Ambient\\Rain.wav".into());
+        rain.flags |= flags::LOOPING | flags::IS_3D;
+        rain.volume = 80;
+
+        let classic = SoundsFile {
+            version: 1,
+            sounds:  vec![rain.clone()],
+        };
+
+        rain.extension = Some(SoundExtension {
+            strings: ["Rain".into(), String::new(), String::new()],
+            unknown1: 0xFFFF_FFFF,
+            has_optional: 1,
+            optional_text: "Rain".into(),
+            unknown4: 1,
+            ..Default::default()
+        });
+
+        let reforged = SoundsFile {
+            version: 3,
+            sounds:  vec![rain],
+        };
+
+        for file in &[classic, reforged] {
+            let mut buf = Vec::new();
+            write::write_sounds(&mut buf, file).unwrap();
+
+            assert_eq!(&read::read_sounds(&buf).unwrap(), file);
+        }
+    }
+}
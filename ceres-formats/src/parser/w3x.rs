@@ -0,0 +1,131 @@
+/// Size of the `HM3W` header. The MPQ archive starts right after it.
+pub const HEADER_SIZE: usize = 512;
+/// Size of the `NGIS` footer, which holds a 256-byte signature.
+pub const FOOTER_SIZE: usize = 260;
+pub const SIGNATURE_SIZE: usize = FOOTER_SIZE - 4;
+
+const HEADER_MAGIC: &[u8] = b"HM3W";
+const FOOTER_MAGIC: &[u8] = b"NGIS";
+
+pub mod read {
+    use byteorder::{LE, ReadBytesExt};
+
+    use crate::error::FormatError;
+    use crate::mapheader::MapHeader;
+    use crate::parser::binary::{read_bytes, read_cstring};
+
+    use super::*;
+
+    /// Reads the `HM3W` header from the start of a map file.
+    /// Returns `None` if the file is a plain MPQ archive without a header.
+    pub fn read_header(mut source: &[u8]) -> Result<Option<MapHeader>, FormatError> {
+        if !source.starts_with(HEADER_MAGIC) {
+            return Ok(None);
+        }
+
+        read_bytes(&mut source, HEADER_MAGIC.len())?;
+
+        let unknown = source.read_u32::<LE>()?;
+        let name = read_cstring(&mut source)?;
+        let flags = source.read_u32::<LE>()?;
+        let max_players = source.read_u32::<LE>()?;
+
+        Ok(Some(MapHeader {
+            unknown,
+            name,
+            flags,
+            max_players,
+            signature: None,
+        }))
+    }
+
+    /// Reads the signature out of the last `FOOTER_SIZE` bytes of a map file,
+    /// if they contain an `NGIS` footer.
+    pub fn read_footer(source: &[u8]) -> Option<Vec<u8>> {
+        if source.len() == FOOTER_SIZE && source.starts_with(FOOTER_MAGIC) {
+            Some(source[FOOTER_MAGIC.len()..].to_vec())
+        } else {
+            None
+        }
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use byteorder::{LE, WriteBytesExt};
+
+    use crate::mapheader::MapHeader;
+    use crate::parser::binary::write_cstring;
+
+    use super::*;
+
+    /// Writes exactly `HEADER_SIZE` bytes. Map names which do not fit
+    /// into the header are truncated.
+    pub fn write_header<W: Write>(mut writer: W, header: &MapHeader) -> Result<(), IoError> {
+        // magic, unknown, flags, max players and the name terminator
+        const MAX_NAME_LEN: usize = HEADER_SIZE - 4 * 4 - 1;
+
+        let mut name = header.name.as_str();
+        if name.len() > MAX_NAME_LEN {
+            let mut end = MAX_NAME_LEN;
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            name = &name[..end];
+        }
+
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        buf.write_all(HEADER_MAGIC)?;
+        buf.write_u32::<LE>(header.unknown)?;
+        write_cstring(&mut buf, name)?;
+        buf.write_u32::<LE>(header.flags)?;
+        buf.write_u32::<LE>(header.max_players)?;
+        buf.resize(HEADER_SIZE, 0);
+
+        writer.write_all(&buf)
+    }
+
+    /// Writes the `NGIS` footer if the header carries a signature.
+    /// Signatures are padded or truncated to `SIGNATURE_SIZE` bytes.
+    pub fn write_footer<W: Write>(mut writer: W, header: &MapHeader) -> Result<(), IoError> {
+        if let Some(signature) = &header.signature {
+            let mut signature = signature.clone();
+            signature.resize(SIGNATURE_SIZE, 0);
+
+            writer.write_all(FOOTER_MAGIC)?;
+            writer.write_all(&signature)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mapheader::MapHeader;
+
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let header = MapHeader {
+            unknown:     0,
+            name:        "Test Map".into(),
+            flags:       0x4,
+            max_players: 8,
+            signature:   Some(vec![1; SIGNATURE_SIZE]),
+        };
+
+        let mut buf = Vec::new();
+        write::write_header(&mut buf, &header).unwrap();
+        assert_eq!(buf.len(), HEADER_SIZE);
+        write::write_footer(&mut buf, &header).unwrap();
+        assert_eq!(buf.len(), HEADER_SIZE + FOOTER_SIZE);
+
+        let mut read = read::read_header(&buf).unwrap().unwrap();
+        read.signature = read::read_footer(&buf[HEADER_SIZE..]);
+
+        assert_eq!(read, header);
+    }
+}
@@ -0,0 +1,170 @@
+//! Reading and writing of `war3map.wts`, the trigger strings.
+//!
+//! The file is text, made of blocks like
+//!
+//! ```text
+//! STRING 12
+//! // Units: hfoo (Footman), Name
+//! {
+//! Footman
+//! }
+//! ```
+//!
+//! where the comment is optional, and the value may span multiple lines.
+
+const BOM: &str = "\u{feff}";
+
+pub mod read {
+    use std::io::{Error as IoError, ErrorKind};
+    use std::str::from_utf8;
+
+    use crate::error::FormatError;
+    use crate::triggerstrings::*;
+
+    use super::*;
+
+    fn invalid(line: usize, message: &str) -> FormatError {
+        let message = format!("line {}: {}", line + 1, message);
+
+        IoError::new(ErrorKind::InvalidData, message).into()
+    }
+
+    pub fn read_string_table(source: &[u8]) -> Result<TriggerStrings, FormatError> {
+        let source =
+            from_utf8(source).map_err(|error| IoError::new(ErrorKind::InvalidData, error))?;
+        let source = source.strip_prefix(BOM).unwrap_or(source);
+
+        let mut strings = TriggerStrings::default();
+        let mut lines = source.lines().enumerate();
+
+        while let Some((number, line)) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let id = line
+                .strip_prefix("STRING ")
+                .and_then(|id| id.trim().parse::<u32>().ok())
+                .ok_or_else(|| invalid(number, "expected STRING <id>"))?;
+
+            let mut comment: Option<String> = None;
+            loop {
+                match lines.next() {
+                    Some((_, line)) if line.trim().is_empty() => {}
+                    Some((_, line)) if line.starts_with("//") => {
+                        let comment = comment.get_or_insert_with(String::new);
+                        if !comment.is_empty() {
+                            comment.push('\n');
+                        }
+                        comment.push_str(line);
+                    }
+                    Some((_, line)) if line.trim() == "{" => break,
+                    Some((number, _)) => return Err(invalid(number, "expected {")),
+                    None => return Err(invalid(number, "string has no value")),
+                }
+            }
+
+            let mut value_lines = Vec::new();
+            loop {
+                match lines.next() {
+                    Some((_, "}")) => break,
+                    Some((_, line)) => value_lines.push(line),
+                    None => return Err(invalid(number, "string is not terminated by }")),
+                }
+            }
+
+            strings.insert(
+                id,
+                TriggerString {
+                    comment,
+                    value: value_lines.join("\r\n"),
+                },
+            );
+        }
+
+        Ok(strings)
+    }
+}
+
+pub mod write {
+    use std::io::{Error as IoError, Write};
+
+    use crate::triggerstrings::*;
+
+    use super::*;
+
+    pub fn write_string_table<W: Write>(
+        mut writer: W,
+        strings: &TriggerStrings,
+    ) -> Result<(), IoError> {
+        write!(writer, "{}", BOM)?;
+
+        for (id, string) in strings.strings() {
+            write!(writer, "STRING {}\r\n", id)?;
+            if let Some(comment) = &string.comment {
+                for line in comment.lines() {
+                    write!(writer, "{}\r\n", line)?;
+                }
+            }
+            write!(writer, "{{\r\n{}\r\n}}\r\n\r\n", string.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::triggerstrings::*;
+
+    use super::*;
+
+    #[test]
+    fn string_table_read() {
+        let data = "\u{feff}STRING 1\r\n{\r\nPlayer 1\r\n}\r\n\r\n\
+                    STRING 4\r\n// Units: h000 (Captain), Tooltip - Extended (Ubertip)\r\n\
+                    {\r\nA seasoned officer.\r\n\r\n|cffffcc00Level 2|r\r\n}\r\n\r\n\
+                    STRING 7\r\n{\r\nRune des Königs\r\n}\r\n\r\n";
+        let strings = read::read_string_table(data.as_bytes()).unwrap();
+
+        assert_eq!(strings.len(), 3);
+        assert_eq!(strings.get(1), Some("Player 1"));
+        assert_eq!(
+            strings.resolve("TRIGSTR_004"),
+            Some("A seasoned officer.\r\n\r\n|cffffcc00Level 2|r")
+        );
+        assert_eq!(strings.get(5), None);
+        assert_eq!(strings.get(7), Some("Rune des Königs"));
+
+        let (_, tooltip) = strings.strings().nth(1).unwrap();
+        assert_eq!(
+            tooltip.comment.as_deref(),
+            Some("// Units: h000 (Captain), Tooltip - Extended (Ubertip)")
+        );
+    }
+
+    #[test]
+    fn string_table_roundtrip() {
+        let mut strings = TriggerStrings::default();
+        strings.insert(
+            1,
+            TriggerString {
+                comment: Some("// Units: hfoo (Footman), Name".into()),
+                value:   "Footman".into(),
+            },
+        );
+        strings.set(7, "A tooltip\r\n\r\nwith |cffffcc00colors|r".into());
+
+        let id = strings.add("Just me".into());
+        assert_eq!(id, 8);
+        assert_eq!(make_reference(id), "TRIGSTR_008");
+        assert_eq!(strings.resolve("TRIGSTR_007"), strings.get(7));
+        assert_eq!(strings.resolve("TRIGSTR_7x"), None);
+
+        let mut buf = Vec::new();
+        write::write_string_table(&mut buf, &strings).unwrap();
+
+        assert_eq!(read::read_string_table(&buf).unwrap(), strings);
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ObjectId;
+
+/// The name of the global variable the World Editor generates for a region,
+/// camera or sound, e.g. `gg_rct_Spawn_Area` for the region `Spawn Area`.
+pub fn global_name(prefix: &str, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("{}{}", prefix, name)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A rectangular region, in world coordinates.
+pub struct Region {
+    pub name:            String,
+    pub left:            f32,
+    pub bottom:          f32,
+    pub right:           f32,
+    pub top:             f32,
+    /// Unique among all regions.
+    pub creation_number: u32,
+    pub weather:         Option<ObjectId>,
+    /// Name of the sound variable, e.g. `gg_snd_Rain`, or empty.
+    pub ambient_sound:   String,
+    /// Color in the World Editor, as RGB.
+    pub color:           [u8; 3],
+}
+
+impl Region {
+    /// The region's global, e.g. `gg_rct_Spawn`.
+    pub fn global_name(&self) -> String {
+        global_name("gg_rct_", &self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3map.w3r`.
+pub struct RegionsFile {
+    pub version: u32,
+    pub regions: Vec<Region>,
+}
+
+impl Default for RegionsFile {
+    fn default() -> RegionsFile {
+        RegionsFile {
+            version: 5,
+            regions: Vec::new(),
+        }
+    }
+}
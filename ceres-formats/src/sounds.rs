@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+/// Flags of a sound.
+pub mod flags {
+    pub const LOOPING: u32 = 0x1;
+    pub const IS_3D: u32 = 0x2;
+    pub const STOP_OUT_OF_RANGE: u32 = 0x4;
+    pub const MUSIC: u32 = 0x8;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+/// Fields added in version 2 (Reforged). Their meaning is mostly unknown,
+/// they are only kept so that the file can be written back unchanged.
+pub struct SoundExtension {
+    pub strings:       [String; 3],
+    pub unknown1:      u32,
+    pub text:          String,
+    pub unknown2:      u32,
+    /// `optional_text` is only stored if this is not 0.
+    pub has_optional:  u32,
+    pub optional_text: String,
+    pub text2:         String,
+    pub unknown3:      u32,
+    /// Version 3 only.
+    pub unknown4:      u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A sound defined in the sound editor.
+pub struct Sound {
+    /// Name of the global variable, e.g. `gg_snd_Rain`.
+    pub name:                String,
+    pub file:                String,
+    pub eax_effect:          String,
+    /// See `flags`.
+    pub flags:               u32,
+    pub fade_in_rate:        u32,
+    pub fade_out_rate:       u32,
+    /// -1 for the default volume.
+    pub volume:              i32,
+    pub pitch:               f32,
+    pub pitch_variance:      f32,
+    pub priority:            i32,
+    pub channel:             i32,
+    pub min_distance:        f32,
+    pub max_distance:        f32,
+    pub distance_cutoff:     f32,
+    pub cone_inside:         f32,
+    pub cone_outside:        f32,
+    pub cone_outside_volume: i32,
+    pub cone_orientation:    [f32; 3],
+    pub extension:           Option<SoundExtension>,
+}
+
+impl Sound {
+    /// A sound with the defaults the sound editor uses.
+    pub fn new(name: String, file: String) -> Sound {
+        Sound {
+            name,
+            file,
+            eax_effect: "DefaultEAXON".into(),
+            flags: flags::STOP_OUT_OF_RANGE,
+            fade_in_rate: 10,
+            fade_out_rate: 10,
+            volume: -1,
+            pitch: 1.0,
+            pitch_variance: 0.0,
+            priority: -1,
+            channel: 0,
+            min_distance: 0.0,
+            max_distance: 10000.0,
+            distance_cutoff: 3000.0,
+            cone_inside: 0.0,
+            cone_outside: 0.0,
+            cone_outside_volume: 127,
+            cone_orientation: [0.0; 3],
+            extension: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3map.w3s`.
+pub struct SoundsFile {
+    /// 1 (RoC and TFT), 2 or 3 (Reforged).
+    pub version: u32,
+    pub sounds:  Vec<Sound>,
+}
+
+impl Default for SoundsFile {
+    fn default() -> SoundsFile {
+        SoundsFile {
+            version: 1,
+            sounds:  Vec::new(),
+        }
+    }
+}
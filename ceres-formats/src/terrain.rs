@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ObjectId;
+
+/// Raw height of a tile point at zero height.
+pub const ZERO_HEIGHT: i16 = 0x2000;
+/// Cliff layer of a tile point at zero height.
+pub const ZERO_LAYER: u8 = 2;
+/// Height of a single cliff layer, in world units.
+pub const LAYER_HEIGHT: f32 = 128.0;
+/// Size of a tile, in world units.
+pub const TILE_SIZE: f32 = 128.0;
+
+/// Flags stored in the upper half of a tile point's texture byte.
+pub mod flags {
+    pub const RAMP: u8 = 0x1;
+    pub const BLIGHT: u8 = 0x2;
+    pub const WATER: u8 = 0x4;
+    pub const BOUNDARY: u8 = 0x8;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// A corner of the terrain grid.
+pub struct TilePoint {
+    /// Ground height, where `ZERO_HEIGHT` is 0 and one unit is 1/4 world unit.
+    pub height:          i16,
+    /// Water level, in the same units as `height`. 14 bits.
+    pub water:           u16,
+    pub map_edge:        bool,
+    /// Index into the ground tilesets. 4 bits.
+    pub texture:         u8,
+    /// See `flags`. 4 bits.
+    pub flags:           u8,
+    /// 5 bits.
+    pub variation:       u8,
+    /// 3 bits.
+    pub cliff_variation: u8,
+    /// Index into the cliff tilesets. 4 bits.
+    pub cliff_texture:   u8,
+    /// Cliff layer, where `ZERO_LAYER` is level ground. 4 bits.
+    pub layer:           u8,
+}
+
+impl Default for TilePoint {
+    fn default() -> TilePoint {
+        TilePoint {
+            height:          ZERO_HEIGHT,
+            water:           ZERO_HEIGHT as u16,
+            map_edge:        false,
+            texture:         0,
+            flags:           0,
+            variation:       0,
+            cliff_variation: 0,
+            cliff_texture:   0,
+            layer:           ZERO_LAYER,
+        }
+    }
+}
+
+fn layer_offset(layer: u8) -> f32 {
+    (i32::from(layer) - i32::from(ZERO_LAYER)) as f32 * LAYER_HEIGHT
+}
+
+fn from_raw_height(raw: i32) -> f32 {
+    (raw - i32::from(ZERO_HEIGHT)) as f32 / 4.0
+}
+
+fn to_raw_height(height: f32) -> f32 {
+    (height * 4.0).round() + f32::from(ZERO_HEIGHT)
+}
+
+impl TilePoint {
+    /// Ground height in world units, including the cliff layer.
+    pub fn world_height(&self) -> f32 {
+        from_raw_height(i32::from(self.height)) + layer_offset(self.layer)
+    }
+
+    /// Sets the ground height in world units, relative to the cliff layer.
+    pub fn set_world_height(&mut self, height: f32) {
+        let raw = to_raw_height(height - layer_offset(self.layer));
+
+        self.height = raw.max(f32::from(i16::MIN)).min(f32::from(i16::MAX)) as i16;
+    }
+
+    /// Water level in world units, if the point has water.
+    pub fn water_height(&self) -> Option<f32> {
+        if self.flags & flags::WATER != 0 {
+            Some(from_raw_height(i32::from(self.water)))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_water_height(&mut self, height: Option<f32>) {
+        match height {
+            Some(height) => {
+                let raw = to_raw_height(height);
+
+                self.water = raw.max(0.0).min(f32::from(0x3FFF_u16)) as u16;
+                self.flags |= flags::WATER;
+            }
+            None => self.flags &= !flags::WATER,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3map.w3e`, the terrain of the map.
+///
+/// Tile points are stored row by row, starting in the south-west corner.
+pub struct Terrain {
+    pub version:         u32,
+    /// Main tileset, e.g. `L` for Lordaeron Summer.
+    pub tileset:         u8,
+    pub custom_tilesets: bool,
+    /// At most 16 ground textures, e.g. `Ldrt`.
+    pub ground_tilesets: Vec<ObjectId>,
+    /// At most 16 cliff textures, e.g. `CLdi`.
+    pub cliff_tilesets:  Vec<ObjectId>,
+    /// Number of tile points on the X axis, one more than the number of tiles.
+    pub width:           u32,
+    pub height:          u32,
+    /// World coordinates of the south-west corner.
+    pub offset_x:        f32,
+    pub offset_y:        f32,
+    pub points:          Vec<TilePoint>,
+}
+
+impl Terrain {
+    pub fn point(&self, x: u32, y: u32) -> Option<&TilePoint> {
+        if x < self.width && y < self.height {
+            self.points.get((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn point_mut(&mut self, x: u32, y: u32) -> Option<&mut TilePoint> {
+        if x < self.width && y < self.height {
+            self.points.get_mut((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Resizes the grid, keeping the south-west corner in place.
+    /// New points are flat, and use the first ground texture.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let mut points = Vec::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                points.push(self.point(x, y).copied().unwrap_or_default());
+            }
+        }
+
+        self.width = width;
+        self.height = height;
+        self.points = points;
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+const REFERENCE_PREFIX: &str = "TRIGSTR_";
+
+/// Parses a reference into the string table, e.g. `TRIGSTR_012`.
+pub fn parse_reference(value: &str) -> Option<u32> {
+    let digits = value.strip_prefix(REFERENCE_PREFIX)?;
+
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    digits.parse().ok()
+}
+
+/// A reference to a string, formatted like the World Editor does.
+pub fn make_reference(id: u32) -> String {
+    format!("{}{:03}", REFERENCE_PREFIX, id)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TriggerString {
+    /// The World Editor notes where the string is used, e.g. `// Units: hfoo (Footman)`.
+    pub comment: Option<String>,
+    pub value:   String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+/// The contents of `war3map.wts`, the strings which object data, the map info
+/// and triggers refer to as `TRIGSTR_<id>`.
+pub struct TriggerStrings {
+    strings: BTreeMap<u32, TriggerString>,
+}
+
+impl TriggerStrings {
+    pub fn strings(&self) -> impl Iterator<Item = (u32, &TriggerString)> {
+        self.strings.iter().map(|(id, string)| (*id, string))
+    }
+
+    pub fn get(&self, id: u32) -> Option<&str> {
+        self.strings.get(&id).map(|string| string.value.as_str())
+    }
+
+    /// Sets the value of a string, keeping its comment if it already exists.
+    pub fn set(&mut self, id: u32, value: String) {
+        self.strings
+            .entry(id)
+            .and_modify(|string| string.value = value.clone())
+            .or_insert(TriggerString {
+                comment: None,
+                value,
+            });
+    }
+
+    pub fn insert(&mut self, id: u32, string: TriggerString) {
+        self.strings.insert(id, string);
+    }
+
+    /// Adds a string with a fresh ID, which is returned.
+    pub fn add(&mut self, value: String) -> u32 {
+        let id = self
+            .strings
+            .keys()
+            .next_back()
+            .map(|id| id + 1)
+            .unwrap_or(0);
+
+        self.set(id, value);
+
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<TriggerString> {
+        self.strings.remove(&id)
+    }
+
+    /// The string `value` refers to, if it is a reference to a known string.
+    pub fn resolve(&self, value: &str) -> Option<&str> {
+        parse_reference(value).and_then(|id| self.get(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
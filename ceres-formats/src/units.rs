@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ObjectId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DroppedItem {
+    pub id:     ObjectId,
+    /// In percent.
+    pub chance: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InventoryItem {
+    /// Zero-based inventory slot.
+    pub slot: u32,
+    pub id:   ObjectId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// An ability whose level or autocast state differs from the default.
+pub struct UnitAbility {
+    pub id:       ObjectId,
+    pub autocast: bool,
+    pub level:    u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// How a random unit or item (`uDNR`, `bDNR`, `iDNR`) is picked.
+/// Non-random units store a `Level` as well.
+pub enum RandomUnit {
+    /// Any unit or item of the given level (24 bits), and item class.
+    Level { level: u32, class: u8 },
+    /// A column of a random group from the map info.
+    Group { group: u32, position: u32 },
+    /// One of the given units or items, with chances in percent.
+    Custom(Vec<DroppedItem>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A unit or item placed on the map.
+pub struct PlacedUnit {
+    pub id:                 ObjectId,
+    pub variation:          u32,
+    pub x:                  f32,
+    pub y:                  f32,
+    pub z:                  f32,
+    /// In radians.
+    pub facing:             f32,
+    pub scale:              [f32; 3],
+    /// Reforged only, the unit ID itself unless a skin is applied.
+    pub skin:               ObjectId,
+    pub flags:              u8,
+    pub owner:              u32,
+    pub unknown:            [u8; 2],
+    /// -1 for the default.
+    pub hp:                 i32,
+    /// -1 for the default.
+    pub mana:               i32,
+    /// Index of a random item table in the map info, or -1. TFT only.
+    pub item_table:         i32,
+    /// Every set drops one of its items when the unit dies.
+    pub item_sets:          Vec<Vec<DroppedItem>>,
+    /// Gold mines only.
+    pub gold:               u32,
+    /// -1 for normal, -2 for camp.
+    pub target_acquisition: f32,
+    /// Heroes only.
+    pub level:              u32,
+    /// Heroes only, 0 for the default. TFT only.
+    pub strength:           u32,
+    pub agility:            u32,
+    pub intelligence:       u32,
+    pub items:              Vec<InventoryItem>,
+    pub abilities:          Vec<UnitAbility>,
+    pub random:             RandomUnit,
+    /// Player color, or -1 to use the owner's.
+    pub color:              i32,
+    /// Creation number of the target region of a waygate, or -1.
+    pub waygate:            i32,
+    /// Unique among all placed units. The map script refers to units by it.
+    pub creation_number:    u32,
+}
+
+impl PlacedUnit {
+    /// A unit with the defaults the World Editor uses for new units.
+    pub fn new(id: ObjectId, x: f32, y: f32) -> PlacedUnit {
+        PlacedUnit {
+            id,
+            variation: 0,
+            x,
+            y,
+            z: 0.0,
+            facing: 0.0,
+            scale: [1.0; 3],
+            skin: id,
+            flags: 2,
+            owner: 0,
+            unknown: [0; 2],
+            hp: -1,
+            mana: -1,
+            item_table: -1,
+            item_sets: Vec::new(),
+            gold: 12500,
+            target_acquisition: -1.0,
+            level: 1,
+            strength: 0,
+            agility: 0,
+            intelligence: 0,
+            items: Vec::new(),
+            abilities: Vec::new(),
+            random: RandomUnit::Level { level: 1, class: 0 },
+            color: -1,
+            waygate: -1,
+            creation_number: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The contents of `war3mapUnits.doo`.
+pub struct UnitsFile {
+    /// 7 (RoC and TFT) or 8 (Reforged).
+    pub version:    u32,
+    /// 9 (RoC) or 11 (TFT and later).
+    pub subversion: u32,
+    pub units:      Vec<PlacedUnit>,
+}
+
+impl Default for UnitsFile {
+    fn default() -> UnitsFile {
+        UnitsFile {
+            version:    8,
+            subversion: 11,
+            units:      Vec::new(),
+        }
+    }
+}
+
+impl UnitsFile {
+    pub fn unit(&self, creation_number: u32) -> Option<&PlacedUnit> {
+        self.units
+            .iter()
+            .find(|unit| unit.creation_number == creation_number)
+    }
+
+    /// Adds a unit with a fresh creation number, which is returned.
+    pub fn add_unit(&mut self, mut unit: PlacedUnit) -> u32 {
+        let creation_number = self
+            .units
+            .iter()
+            .map(|unit| unit.creation_number + 1)
+            .max()
+            .unwrap_or(0);
+
+        unit.creation_number = creation_number;
+        self.units.push(unit);
+
+        creation_number
+    }
+
+    pub fn remove_unit(&mut self, creation_number: u32) -> Option<PlacedUnit> {
+        let index = self
+            .units
+            .iter()
+            .position(|unit| unit.creation_number == creation_number)?;
+
+        Some(self.units.remove(index))
+    }
+}
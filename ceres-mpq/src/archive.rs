@@ -0,0 +1,553 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use std::collections::{HashMap, HashSet};
+
+use byteorder::{ByteOrder, LE};
+
+use crate::attributes::{read_attributes, AttributesOptions, FileAttributes};
+use crate::compression::{decompress_sector, sector_compression, Compression};
+use crate::consts::*;
+use crate::crypto::*;
+use crate::entry::FileEntry;
+use crate::error::Error;
+use crate::header::Header;
+use crate::table::*;
+
+/// A read-only view of an MPQ archive.
+pub struct Archive<R: Read + Seek> {
+    pub(crate) reader:      R,
+    pub(crate) offset:      u64,
+    pub(crate) header:      Header,
+    pub(crate) hash_table:  Vec<HashEntry>,
+    pub(crate) block_table: Vec<BlockEntry>,
+    /// Names found by other means than the `(listfile)`.
+    extra_names:            Vec<String>,
+    /// Parsed `(attributes)`, read on first use.
+    pub(crate) attributes:  Option<Option<(AttributesOptions, Vec<FileAttributes>)>>,
+}
+
+/// The stored data of a file, exactly as it is in the archive.
+/// Adding it to a `Creator` copies the file without recompressing it.
+pub struct RawFile {
+    pub(crate) name:        String,
+    pub(crate) data:        Vec<u8>,
+    pub(crate) block:       BlockEntry,
+    pub(crate) sector_size: usize,
+    pub(crate) attributes:  Option<(AttributesOptions, FileAttributes)>,
+}
+
+impl RawFile {
+    pub fn file_size(&self) -> u32 {
+        self.block.file_size
+    }
+
+    pub fn compressed_size(&self) -> u32 {
+        self.block.compressed_size
+    }
+
+    /// Whether the data can be stored as-is under the given name,
+    /// in an archive with the given sector size.
+    ///
+    /// Encrypted files are bound to their name, and position-adjusted keys
+    /// to their position as well. Files split into sectors need the same
+    /// sector size.
+    pub(crate) fn is_portable(&self, name: &str, sector_size: usize) -> bool {
+        let flags = self.block.flags;
+
+        if flags & MPQ_FILE_ENCRYPTED != 0
+            && (flags & MPQ_FILE_ADJUST_KEY != 0
+                || file_key(name, 0, 0, false) != file_key(&self.name, 0, 0, false))
+        {
+            return false;
+        }
+
+        flags & MPQ_FILE_SINGLE_UNIT != 0
+            || self.block.file_size as usize <= sector_size.min(self.sector_size)
+            || sector_size == self.sector_size
+    }
+
+    /// The compression method of the file, going by its first compressed
+    /// sector. Files whose sectors all ended up stored as-is count as zlib
+    /// compressed, since that is what they were written with in most cases.
+    pub(crate) fn compression(&self) -> Compression {
+        let flags = self.block.flags;
+        let file_size = self.block.file_size as usize;
+
+        if flags & MPQ_FILE_COMPRESS == 0 {
+            return Compression::None;
+        }
+
+        let key = if flags & MPQ_FILE_ENCRYPTED != 0 {
+            Some(file_key(
+                &self.name,
+                self.block.file_pos,
+                self.block.file_size,
+                flags & MPQ_FILE_ADJUST_KEY != 0,
+            ))
+        } else {
+            None
+        };
+
+        if flags & MPQ_FILE_SINGLE_UNIT != 0 {
+            if self.data.len() >= file_size {
+                return Compression::Zlib;
+            }
+
+            let mut data = self.data.clone();
+            if let Some(key) = key {
+                decrypt_mpq_block(&mut data, key);
+            }
+
+            return sector_compression(&data);
+        }
+
+        let sector_count = file_size.div_ceil(self.sector_size);
+        let table_size = (sector_count + 1) * 4;
+        if self.data.len() < table_size {
+            return Compression::Zlib;
+        }
+
+        let mut offset_table = self.data[..table_size].to_vec();
+        if let Some(key) = key {
+            decrypt_mpq_block(&mut offset_table, key.wrapping_sub(1));
+        }
+
+        for i in 0..sector_count {
+            let start = LE::read_u32(&offset_table[i * 4..]) as usize;
+            let end = LE::read_u32(&offset_table[(i + 1) * 4..]) as usize;
+            let expected_size = self.sector_size.min(file_size - i * self.sector_size);
+
+            if start > end || end > self.data.len() {
+                break;
+            }
+
+            if end - start < expected_size {
+                let mut sector = self.data[start..end].to_vec();
+                if let Some(key) = key {
+                    decrypt_mpq_block(&mut sector, key.wrapping_add(i as u32));
+                }
+
+                return sector_compression(&sector);
+            }
+        }
+
+        Compression::Zlib
+    }
+
+    /// Decrypts and decompresses the data.
+    pub(crate) fn decode(&self) -> Result<Vec<u8>, Error> {
+        if self.block.file_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        decode_file(self.data.clone(), &self.block, &self.name, self.sector_size)
+    }
+}
+
+impl<R: Read + Seek> Archive<R> {
+    pub fn open(mut reader: R) -> Result<Archive<R>, Error> {
+        let (offset, header) = find_header(&mut reader)?;
+
+        let hash_table = read_table_data(
+            &mut reader,
+            offset + u64::from(header.hash_table_offset),
+            header.hash_table_entries,
+        )?;
+        let block_table = read_table_data(
+            &mut reader,
+            offset + u64::from(header.block_table_offset),
+            header.block_table_entries,
+        )?;
+
+        let mut hash_table = read_hash_table(hash_table);
+        // lookups rely on the table size being a power of two
+        let usable_size = if hash_table.len().is_power_of_two() {
+            hash_table.len()
+        } else {
+            hash_table.len().next_power_of_two() / 2
+        };
+        hash_table.truncate(usable_size);
+
+        Ok(Archive {
+            reader,
+            offset,
+            header,
+            hash_table,
+            block_table: read_block_table(block_table),
+            extra_names: Vec::new(),
+            attributes: None,
+        })
+    }
+
+    pub(crate) fn find_hash_index(&self, name: &str) -> Option<usize> {
+        if self.hash_table.is_empty() {
+            return None;
+        }
+
+        let hash_a = hash_string(name, HASH_NAME_A);
+        let hash_b = hash_string(name, HASH_NAME_B);
+        let start = hash_table_start(name, self.hash_table.len());
+
+        for i in 0..self.hash_table.len() {
+            let index = (start + i) % self.hash_table.len();
+            let entry = &self.hash_table[index];
+
+            if entry.is_empty() {
+                return None;
+            }
+
+            if entry.matches(hash_a, hash_b) {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    fn find_block(&self, name: &str) -> Option<BlockEntry> {
+        self.find_hash_index(name)
+            .and_then(|index| {
+                self.block_table
+                    .get(self.hash_table[index].block_index as usize)
+            })
+            .filter(|block| block.flags & MPQ_FILE_EXISTS != 0)
+            .cloned()
+    }
+
+    fn make_entry(
+        &self,
+        name: Option<String>,
+        block_index: usize,
+        hash_index: Option<usize>,
+    ) -> FileEntry {
+        let block = &self.block_table[block_index];
+        let hash = hash_index.map(|index| &self.hash_table[index]);
+
+        FileEntry {
+            name,
+            block_index: block_index as u32,
+            hash_index: hash_index.map(|index| index as u32),
+            hash_a: hash.map(|hash| hash.hash_a).unwrap_or(0),
+            hash_b: hash.map(|hash| hash.hash_b).unwrap_or(0),
+            locale: hash.map(|hash| hash.locale).unwrap_or(0),
+            file_pos: block.file_pos,
+            compressed_size: block.compressed_size,
+            file_size: block.file_size,
+            flags: block.flags,
+        }
+    }
+
+    /// Looks up the block of a file without reading it.
+    pub fn stat(&self, name: &str) -> Option<FileEntry> {
+        let hash_index = self.find_hash_index(name)?;
+        let block_index = self.hash_table[hash_index].block_index as usize;
+
+        if block_index >= self.block_table.len() {
+            return None;
+        }
+
+        Some(self.make_entry(Some(name.into()), block_index, Some(hash_index)))
+    }
+
+    /// Lists every block in the archive, in block table order.
+    /// Names are recovered from the `(listfile)` where possible.
+    pub fn entries(&mut self) -> Vec<FileEntry> {
+        let mut names: HashMap<(u32, u32), String> = HashMap::new();
+        let known_names = self.files().unwrap_or_default().into_iter().chain(
+            [LISTFILE_NAME, ATTRIBUTES_NAME, SIGNATURE_NAME]
+                .iter()
+                .map(|name| name.to_string()),
+        );
+
+        for name in known_names {
+            let hash = (
+                hash_string(&name, HASH_NAME_A),
+                hash_string(&name, HASH_NAME_B),
+            );
+            names.entry(hash).or_insert(name);
+        }
+
+        // the first hash entry pointing to a block wins, like during lookup
+        let mut block_hashes: HashMap<usize, usize> = HashMap::new();
+        for (hash_index, entry) in self.hash_table.iter().enumerate() {
+            if !entry.is_empty() && !entry.is_deleted() {
+                block_hashes
+                    .entry(entry.block_index as usize)
+                    .or_insert(hash_index);
+            }
+        }
+
+        (0..self.block_table.len())
+            .map(|block_index| {
+                let hash_index = block_hashes.get(&block_index).cloned();
+                let name = hash_index.and_then(|index| {
+                    let hash = &self.hash_table[index];
+                    names.get(&(hash.hash_a, hash.hash_b)).cloned()
+                });
+
+                self.make_entry(name, block_index, hash_index)
+            })
+            .collect()
+    }
+
+    /// Gives back the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub fn sector_size(&self) -> usize {
+        self.header.sector_size()
+    }
+
+    /// Reads and decompresses a file from the archive.
+    pub fn read_file(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let block = self
+            .find_block(name)
+            .ok_or_else(|| Error::file_not_found(name))?;
+
+        if block.file_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        self.reader
+            .seek(SeekFrom::Start(self.offset + u64::from(block.file_pos)))?;
+        let mut raw = vec![0; block.compressed_size as usize];
+        self.reader.read_exact(&mut raw)?;
+
+        decode_file(raw, &block, name, self.header.sector_size())
+    }
+
+    /// Reads a file without decompressing it, to copy it into another archive.
+    pub fn read_raw_file(&mut self, name: &str) -> Result<RawFile, Error> {
+        let hash_index = self
+            .find_hash_index(name)
+            .ok_or_else(|| Error::file_not_found(name))?;
+        let block_index = self.hash_table[hash_index].block_index as usize;
+        let block = self
+            .find_block(name)
+            .ok_or_else(|| Error::file_not_found(name))?;
+
+        self.reader
+            .seek(SeekFrom::Start(self.offset + u64::from(block.file_pos)))?;
+        let mut data = vec![0; block.compressed_size as usize];
+        self.reader.read_exact(&mut data)?;
+
+        let attributes = self
+            .attributes()
+            .and_then(|(options, entries)| Some((*options, entries.get(block_index)?.clone())));
+
+        Ok(RawFile {
+            name: name.into(),
+            data,
+            block,
+            sector_size: self.header.sector_size(),
+            attributes,
+        })
+    }
+
+    /// The parsed `(attributes)` file, if the archive has a valid one.
+    pub(crate) fn attributes(&mut self) -> Option<&(AttributesOptions, Vec<FileAttributes>)> {
+        if self.attributes.is_none() {
+            let block_count = self.block_table.len();
+            let attributes = self
+                .read_file(ATTRIBUTES_NAME)
+                .ok()
+                .and_then(|data| read_attributes(&data, block_count));
+
+            self.attributes = Some(attributes);
+        }
+
+        self.attributes.as_ref().unwrap().as_ref()
+    }
+
+    /// Returns the list of files from the `(listfile)` and any names added via
+    /// `add_names`, or `None` if there is neither.
+    pub fn files(&mut self) -> Option<Vec<String>> {
+        let listfile = self.read_file(LISTFILE_NAME).ok();
+
+        if listfile.is_none() && self.extra_names.is_empty() {
+            return None;
+        }
+
+        let mut files = listfile.map(|l| parse_listfile(&l)).unwrap_or_default();
+        files.extend(self.extra_names.iter().cloned());
+
+        Some(files)
+    }
+
+    /// Checks the given names against the hash table, and remembers those
+    /// which belong to files that did not have a known name yet.
+    /// Returns the newly found names.
+    pub fn add_names<I, S>(&mut self, names: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut known: HashSet<usize> = self
+            .files()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|name| self.find_hash_index(name))
+            .collect();
+
+        let mut added = Vec::new();
+        for name in names {
+            let name = name.as_ref().replace('/', "\\");
+
+            if let Some(index) = self.find_hash_index(&name) {
+                if known.insert(index) {
+                    added.push(name);
+                }
+            }
+        }
+
+        self.extra_names.extend(added.iter().cloned());
+
+        added
+    }
+}
+
+/// Searches for the archive header, which can be placed at any multiple
+/// of 512 bytes in the file (e.g. after the HM3W header of a map),
+/// or be pointed at by a user data header.
+fn find_header<R: Read + Seek>(reader: &mut R) -> Result<(u64, Header), Error> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut offset = 0;
+    let mut buf = [0u8; HEADER_SIZE_V1 as usize];
+
+    while offset + u64::from(HEADER_SIZE_V1) <= len {
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut buf)?;
+
+        if &buf[0..4] == HEADER_MAGIC {
+            return Ok((offset, Header::read(&buf)?));
+        }
+
+        if &buf[0..4] == USER_DATA_MAGIC {
+            let header_offset = offset + u64::from(LE::read_u32(&buf[8..]));
+
+            if header_offset + u64::from(HEADER_SIZE_V1) <= len {
+                reader.seek(SeekFrom::Start(header_offset))?;
+                reader.read_exact(&mut buf)?;
+
+                if let Ok(header) = Header::read(&buf) {
+                    return Ok((header_offset, header));
+                }
+            }
+        }
+
+        offset += HEADER_ALIGNMENT;
+    }
+
+    Err(Error::NoHeader)
+}
+
+fn read_table_data<R: Read + Seek>(
+    reader: &mut R,
+    pos: u64,
+    entries: u32,
+) -> Result<Vec<u8>, Error> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let size = (u64::from(entries) * 16).min(len.saturating_sub(pos));
+
+    reader.seek(SeekFrom::Start(pos))?;
+    let mut data = vec![0; size as usize];
+    reader.read_exact(&mut data)?;
+
+    Ok(data)
+}
+
+/// Decrypts and decompresses the raw data of a file block.
+fn decode_file(
+    mut raw: Vec<u8>,
+    block: &BlockEntry,
+    name: &str,
+    sector_size: usize,
+) -> Result<Vec<u8>, Error> {
+    if block.flags & MPQ_FILE_IMPLODE != 0 {
+        return Err(Error::UnsupportedCompression { mask: 0x08 });
+    }
+
+    let file_size = block.file_size as usize;
+    let key = if block.flags & MPQ_FILE_ENCRYPTED != 0 {
+        Some(file_key(
+            name,
+            block.file_pos,
+            block.file_size,
+            block.flags & MPQ_FILE_ADJUST_KEY != 0,
+        ))
+    } else {
+        None
+    };
+
+    if block.flags & MPQ_FILE_SINGLE_UNIT != 0 {
+        if let Some(key) = key {
+            decrypt_mpq_block(&mut raw, key);
+        }
+
+        if block.flags & MPQ_FILE_COMPRESS != 0 && raw.len() < file_size {
+            return decompress_sector(&raw, file_size);
+        }
+
+        raw.truncate(file_size);
+        return Ok(raw);
+    }
+
+    let sector_count = file_size.div_ceil(sector_size);
+    let mut result = Vec::with_capacity(file_size);
+
+    if block.flags & MPQ_FILE_COMPRESS == 0 {
+        for (i, sector) in raw.chunks_mut(sector_size).enumerate() {
+            if let Some(key) = key {
+                decrypt_mpq_block(sector, key.wrapping_add(i as u32));
+            }
+
+            result.extend_from_slice(sector);
+        }
+
+        result.truncate(file_size);
+        return Ok(result);
+    }
+
+    let table_entries = sector_count + 1;
+    if raw.len() < table_entries * 4 {
+        return Err(Error::Corrupted);
+    }
+
+    let mut offset_table = raw[..table_entries * 4].to_vec();
+    if let Some(key) = key {
+        decrypt_mpq_block(&mut offset_table, key.wrapping_sub(1));
+    }
+
+    for i in 0..sector_count {
+        let start = LE::read_u32(&offset_table[i * 4..]) as usize;
+        let end = LE::read_u32(&offset_table[(i + 1) * 4..]) as usize;
+
+        if start > end || end > raw.len() {
+            return Err(Error::Corrupted);
+        }
+
+        let sector = &mut raw[start..end];
+        if let Some(key) = key {
+            decrypt_mpq_block(sector, key.wrapping_add(i as u32));
+        }
+
+        let expected_size = sector_size.min(file_size - i * sector_size);
+        if sector.len() < expected_size {
+            result.extend(decompress_sector(sector, expected_size)?);
+        } else {
+            result.extend_from_slice(&sector[..expected_size]);
+        }
+    }
+
+    Ok(result)
+}
+
+fn parse_listfile(listfile: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(listfile)
+        .split(&['\r', '\n', ';'][..])
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
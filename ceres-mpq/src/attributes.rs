@@ -0,0 +1,178 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{ByteOrder, WriteBytesExt, LE};
+use md5::{Digest, Md5};
+
+const ATTRIBUTES_VERSION: u32 = 100;
+
+const ATTRIBUTE_CRC32: u32 = 0x01;
+const ATTRIBUTE_FILETIME: u32 = 0x02;
+const ATTRIBUTE_MD5: u32 = 0x04;
+
+/// Seconds between 1601-01-01 (the FILETIME epoch) and the UNIX epoch.
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Which per-file attributes go into the `(attributes)` file.
+/// No `(attributes)` file is written if all of them are disabled.
+pub struct AttributesOptions {
+    pub crc32:      bool,
+    pub md5:        bool,
+    pub timestamps: bool,
+}
+
+impl AttributesOptions {
+    pub fn all() -> AttributesOptions {
+        AttributesOptions {
+            crc32:      true,
+            md5:        true,
+            timestamps: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.crc32 || self.md5 || self.timestamps
+    }
+
+    /// Whether these attributes contain everything the other ones do.
+    pub fn covers(&self, other: &AttributesOptions) -> bool {
+        (self.crc32 || !other.crc32)
+            && (self.md5 || !other.md5)
+            && (self.timestamps || !other.timestamps)
+    }
+
+    fn from_flags(flags: u32) -> AttributesOptions {
+        AttributesOptions {
+            crc32:      flags & ATTRIBUTE_CRC32 != 0,
+            md5:        flags & ATTRIBUTE_MD5 != 0,
+            timestamps: flags & ATTRIBUTE_FILETIME != 0,
+        }
+    }
+
+    fn flags(&self) -> u32 {
+        let mut flags = 0;
+
+        if self.crc32 {
+            flags |= ATTRIBUTE_CRC32;
+        }
+        if self.timestamps {
+            flags |= ATTRIBUTE_FILETIME;
+        }
+        if self.md5 {
+            flags |= ATTRIBUTE_MD5;
+        }
+
+        flags
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileAttributes {
+    pub crc32:    u32,
+    pub filetime: u64,
+    pub md5:      [u8; 16],
+}
+
+impl FileAttributes {
+    pub fn compute(data: &[u8], options: &AttributesOptions, filetime: u64) -> FileAttributes {
+        let mut attributes = FileAttributes::default();
+
+        if options.crc32 {
+            attributes.crc32 = crc32fast::hash(data);
+        }
+        if options.md5 {
+            attributes.md5.copy_from_slice(&Md5::digest(data));
+        }
+        if options.timestamps {
+            attributes.filetime = filetime;
+        }
+
+        attributes
+    }
+}
+
+/// Converts a system time into a Windows FILETIME,
+/// i.e. 100ns intervals since 1601-01-01.
+pub fn to_filetime(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    (since_epoch.as_secs() + FILETIME_UNIX_OFFSET) * 10_000_000
+        + u64::from(since_epoch.subsec_nanos() / 100)
+}
+
+/// Parses an `(attributes)` file. Returns `None` if it is malformed,
+/// or has less entries than there are blocks.
+pub fn read_attributes(
+    data: &[u8],
+    block_count: usize,
+) -> Option<(AttributesOptions, Vec<FileAttributes>)> {
+    if data.len() < 8 || LE::read_u32(data) != ATTRIBUTES_VERSION {
+        return None;
+    }
+
+    let options = AttributesOptions::from_flags(LE::read_u32(&data[4..]));
+    let mut entries = vec![FileAttributes::default(); block_count];
+    let mut data = &data[8..];
+
+    let mut take = |size: usize| -> Option<&[u8]> {
+        if data.len() < size * block_count {
+            return None;
+        }
+
+        let (head, tail) = data.split_at(size * block_count);
+        data = tail;
+        Some(head)
+    };
+
+    if options.crc32 {
+        let crc32s = take(4)?;
+        for (entry, crc32) in entries.iter_mut().zip(crc32s.chunks_exact(4)) {
+            entry.crc32 = LE::read_u32(crc32);
+        }
+    }
+
+    if options.timestamps {
+        let filetimes = take(8)?;
+        for (entry, filetime) in entries.iter_mut().zip(filetimes.chunks_exact(8)) {
+            entry.filetime = LE::read_u64(filetime);
+        }
+    }
+
+    if options.md5 {
+        let md5s = take(16)?;
+        for (entry, md5) in entries.iter_mut().zip(md5s.chunks_exact(16)) {
+            entry.md5.copy_from_slice(md5);
+        }
+    }
+
+    Some((options, entries))
+}
+
+/// Serializes the `(attributes)` file. There must be one entry
+/// per block, including the block of the `(attributes)` file itself.
+pub fn write_attributes(entries: &[FileAttributes], options: &AttributesOptions) -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    buf.write_u32::<LE>(ATTRIBUTES_VERSION).unwrap();
+    buf.write_u32::<LE>(options.flags()).unwrap();
+
+    if options.crc32 {
+        for entry in entries {
+            buf.write_u32::<LE>(entry.crc32).unwrap();
+        }
+    }
+
+    if options.timestamps {
+        for entry in entries {
+            buf.write_u64::<LE>(entry.filetime).unwrap();
+        }
+    }
+
+    if options.md5 {
+        for entry in entries {
+            buf.extend_from_slice(&entry.md5);
+        }
+    }
+
+    buf
+}
@@ -0,0 +1,113 @@
+use std::io::{Read, Write};
+
+use bzip2::read::BzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::error::Error;
+
+const MASK_HUFFMAN: u8 = 0x01;
+const MASK_ZLIB: u8 = 0x02;
+const MASK_IMPLODE: u8 = 0x08;
+const MASK_BZIP2: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Compression method used for the sectors of a file.
+pub enum Compression {
+    None,
+    #[default]
+    Zlib,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Compression> {
+        match name {
+            "none" => Some(Compression::None),
+            "zlib" => Some(Compression::Zlib),
+            "bzip2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zlib => "zlib",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+}
+
+/// Tells which method a compressed sector was compressed with,
+/// going by its compression mask.
+pub fn sector_compression(sector: &[u8]) -> Compression {
+    match sector.first() {
+        Some(mask) if mask & MASK_BZIP2 != 0 => Compression::Bzip2,
+        _ => Compression::Zlib,
+    }
+}
+
+/// Compresses a single sector, prefixed with the compression mask.
+/// Returns `None` if compression does not make the sector smaller,
+/// in which case it must be stored as-is.
+pub fn compress_sector(data: &[u8], method: Compression) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+
+    let result = match method {
+        Compression::None => return None,
+        Compression::Zlib => {
+            out.push(MASK_ZLIB);
+            let mut encoder = ZlibEncoder::new(out, flate2::Compression::best());
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+        Compression::Bzip2 => {
+            out.push(MASK_BZIP2);
+            let mut encoder = BzEncoder::new(out, bzip2::Compression::Best);
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+    };
+
+    match result {
+        Ok(out) if out.len() < data.len() => Some(out),
+        _ => None,
+    }
+}
+
+/// Decompresses a single sector according to its compression mask.
+/// Methods are undone in the same order as the game does it.
+pub fn decompress_sector(data: &[u8], expected_size: usize) -> Result<Vec<u8>, Error> {
+    if data.is_empty() {
+        return Err(Error::Corrupted);
+    }
+
+    let mask = data[0];
+    let mut result = data[1..].to_vec();
+
+    if mask & !(MASK_ZLIB | MASK_BZIP2) != 0 {
+        // huffman, implode and adpcm are only used by the game's own archives
+        let unsupported = mask & (MASK_HUFFMAN | MASK_IMPLODE);
+        return Err(Error::UnsupportedCompression {
+            mask: if unsupported != 0 { unsupported } else { mask },
+        });
+    }
+
+    if mask & MASK_BZIP2 != 0 {
+        let mut out = Vec::with_capacity(expected_size);
+        BzDecoder::new(result.as_slice()).read_to_end(&mut out)?;
+        result = out;
+    }
+
+    if mask & MASK_ZLIB != 0 {
+        let mut out = Vec::with_capacity(expected_size);
+        ZlibDecoder::new(result.as_slice()).read_to_end(&mut out)?;
+        result = out;
+    }
+
+    if result.len() != expected_size {
+        return Err(Error::Corrupted);
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,29 @@
+pub const HEADER_MAGIC: &[u8] = b"MPQ\x1A";
+pub const USER_DATA_MAGIC: &[u8] = b"MPQ\x1B";
+pub const HEADER_SIZE_V1: u32 = 32;
+
+/// The archive header may only start at a multiple of this.
+pub const HEADER_ALIGNMENT: u64 = 512;
+
+pub const HASH_TABLE_KEY: &str = "(hash table)";
+pub const BLOCK_TABLE_KEY: &str = "(block table)";
+
+pub const LISTFILE_NAME: &str = "(listfile)";
+pub const ATTRIBUTES_NAME: &str = "(attributes)";
+pub const SIGNATURE_NAME: &str = "(signature)";
+
+pub const MPQ_FILE_IMPLODE: u32 = 0x0000_0100;
+pub const MPQ_FILE_COMPRESS: u32 = 0x0000_0200;
+pub const MPQ_FILE_ENCRYPTED: u32 = 0x0001_0000;
+pub const MPQ_FILE_ADJUST_KEY: u32 = 0x0002_0000;
+pub const MPQ_FILE_SINGLE_UNIT: u32 = 0x0100_0000;
+pub const MPQ_FILE_DELETE_MARKER: u32 = 0x0200_0000;
+pub const MPQ_FILE_EXISTS: u32 = 0x8000_0000;
+
+pub const HASH_TABLE_OFFSET: u32 = 0;
+pub const HASH_NAME_A: u32 = 1;
+pub const HASH_NAME_B: u32 = 2;
+pub const HASH_FILE_KEY: u32 = 3;
+
+pub const HASH_ENTRY_EMPTY: u32 = 0xFFFF_FFFF;
+pub const HASH_ENTRY_DELETED: u32 = 0xFFFF_FFFE;
@@ -0,0 +1,768 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use byteorder::{WriteBytesExt, LE};
+use indexmap::IndexMap;
+use rayon::prelude::*;
+
+use crate::archive::RawFile;
+use crate::attributes::*;
+use crate::compression::{compress_sector, Compression};
+use crate::consts::*;
+use crate::crypto::*;
+use crate::error::Error;
+use crate::header::Header;
+use crate::table::*;
+
+#[derive(Debug, Clone, Copy)]
+/// Options which control how a single file is stored in the archive.
+pub struct FileOptions {
+    pub encrypt:     bool,
+    pub compression: Compression,
+    pub adjust_key:  bool,
+}
+
+impl Default for FileOptions {
+    fn default() -> FileOptions {
+        FileOptions {
+            encrypt:     false,
+            compression: Compression::Zlib,
+            adjust_key:  false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Options which apply to the archive as a whole.
+pub struct CreatorOptions {
+    /// Sectors are `512 << sector_size_shift` bytes large.
+    pub sector_size_shift: u16,
+    /// Minimum amount of hash table entries. Rounded up to a power of two,
+    /// and picked based on the amount of files if not set.
+    pub hash_table_size:   Option<u32>,
+    pub attributes:        AttributesOptions,
+    /// Time stored in the timestamp attributes of new files. Defaults to the
+    /// unix epoch, so that the same files always produce the same archive.
+    pub timestamp:         Option<SystemTime>,
+    /// Leaves out the `(listfile)` and writes a header with zeroed size fields,
+    /// which the game ignores, but the World Editor and some older tools
+    /// refuse to open. Archives written this way can still be read by this crate.
+    ///
+    /// This is the same trick common map protectors use, and only an approximation
+    /// of making the map read-only: tools which ignore the header sizes, or recover
+    /// names by hashing candidates, can still open and extract the map.
+    pub protect:           bool,
+}
+
+impl Default for CreatorOptions {
+    fn default() -> CreatorOptions {
+        CreatorOptions {
+            sector_size_shift: 3,
+            hash_table_size:   None,
+            attributes:        AttributesOptions::default(),
+            timestamp:         None,
+            protect:           false,
+        }
+    }
+}
+
+/// Upper bound for the combined size of files compressed at the same time.
+/// Large files still form a batch of their own.
+const BATCH_SIZE: u64 = 64 << 20;
+
+pub(crate) enum FileData {
+    Contents(Vec<u8>),
+    /// Read when the archive is written.
+    Disk(PathBuf),
+    /// Copied from another archive.
+    Raw(RawFile),
+}
+
+impl FileData {
+    /// The uncompressed size, as far as it is known up front.
+    pub(crate) fn size(&self) -> Result<u64, Error> {
+        Ok(match self {
+            FileData::Contents(contents) => contents.len() as u64,
+            FileData::Disk(path) => fs::metadata(path)
+                .map_err(|source| Error::Read {
+                    path: path.clone(),
+                    source,
+                })?
+                .len(),
+            FileData::Raw(raw) => u64::from(raw.file_size()),
+        })
+    }
+}
+
+pub(crate) struct FileRecord {
+    pub(crate) name:    String,
+    pub(crate) data:    FileData,
+    pub(crate) options: FileOptions,
+}
+
+#[derive(Debug, Clone)]
+/// Passed to the callback of `Creator::write_with_progress`
+/// after every written file. Sizes are uncompressed sizes.
+pub struct Progress<'a> {
+    pub name:          &'a str,
+    pub files_written: usize,
+    pub files_total:   usize,
+    pub bytes_written: u64,
+    pub bytes_total:   u64,
+}
+
+#[derive(Default)]
+/// Collects files and writes them out as a new archive.
+/// A `(listfile)` containing all added files is generated automatically,
+/// unless the archive is protected.
+pub struct Creator {
+    pub(crate) files:   IndexMap<String, FileRecord>,
+    pub(crate) options: CreatorOptions,
+}
+
+impl Creator {
+    pub fn new(options: CreatorOptions) -> Creator {
+        Creator {
+            files: IndexMap::new(),
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &CreatorOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut CreatorOptions {
+        &mut self.options
+    }
+
+    /// Adds a file to the archive, replacing any file
+    /// previously added under the same (case-insensitive) name.
+    pub fn add_file<C: Into<Vec<u8>>>(&mut self, name: &str, contents: C, options: FileOptions) {
+        let name = name.replace('/', "\\");
+
+        self.files.insert(
+            name.to_ascii_uppercase(),
+            FileRecord {
+                name,
+                data: FileData::Contents(contents.into()),
+                options,
+            },
+        );
+    }
+
+    /// Adds a file from disk. It is only read when the archive is written,
+    /// so that large files do not have to stay in memory until then.
+    pub fn add_file_from_disk<P: Into<PathBuf>>(
+        &mut self,
+        name: &str,
+        path: P,
+        options: FileOptions,
+    ) {
+        let name = name.replace('/', "\\");
+
+        self.files.insert(
+            name.to_ascii_uppercase(),
+            FileRecord {
+                name,
+                data: FileData::Disk(path.into()),
+                options,
+            },
+        );
+    }
+
+    /// Adds a file read from another archive with `Archive::read_raw_file`.
+    /// Its data is copied without recompressing it, unless it has to be
+    /// re-encoded to fit the new archive (e.g. a different sector size, or
+    /// a position-adjusted encryption key), in which case it keeps its
+    /// encryption and compression settings.
+    pub fn add_raw_file(&mut self, name: &str, raw: RawFile) {
+        let name = name.replace('/', "\\");
+        let flags = raw.block.flags;
+        let options = FileOptions {
+            encrypt:     flags & MPQ_FILE_ENCRYPTED != 0,
+            compression: raw.compression(),
+            adjust_key:  flags & MPQ_FILE_ADJUST_KEY != 0,
+        };
+
+        self.files.insert(
+            name.to_ascii_uppercase(),
+            FileRecord {
+                name,
+                data: FileData::Raw(raw),
+                options,
+            },
+        );
+    }
+
+    pub fn write<W: Write + Seek>(&mut self, writer: W) -> Result<(), Error> {
+        self.write_with_progress(writer, |_| {})
+    }
+
+    /// Writes the archive, calling `progress` after every written file.
+    ///
+    /// Files are compressed in parallel, in batches of limited size,
+    /// so that files added from disk are never all in memory at once.
+    pub fn write_with_progress<W, F>(&mut self, mut writer: W, mut progress: F) -> Result<(), Error>
+    where
+        W: Write + Seek,
+        F: FnMut(&Progress),
+    {
+        let sector_size = 512usize << self.options.sector_size_shift;
+        let attributes = self.options.attributes;
+        let filetime = to_filetime(self.options.timestamp.unwrap_or(SystemTime::UNIX_EPOCH));
+
+        let mut listfile = Vec::new();
+        for record in self.files.values() {
+            listfile.extend_from_slice(record.name.as_bytes());
+            listfile.extend_from_slice(b"\r\n");
+        }
+
+        let mut names: Vec<&str> = Vec::new();
+        let mut blocks: Vec<BlockEntry> = Vec::new();
+        let mut file_attributes: Vec<FileAttributes> = Vec::new();
+
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&[0; HEADER_SIZE_V1 as usize])?;
+        let mut pos = u64::from(HEADER_SIZE_V1);
+
+        let listfile_record = FileRecord {
+            name:    LISTFILE_NAME.into(),
+            data:    FileData::Contents(listfile),
+            options: FileOptions::default(),
+        };
+
+        let listfile_record = if self.options.protect {
+            None
+        } else {
+            Some(&listfile_record)
+        };
+        let records: Vec<&FileRecord> = self.files.values().chain(listfile_record).collect();
+        let sizes = records
+            .iter()
+            .map(|record| record.data.size())
+            .collect::<Result<Vec<u64>, Error>>()?;
+        let mut status = Progress {
+            name:          "",
+            files_written: 0,
+            files_total:   records.len(),
+            bytes_written: 0,
+            bytes_total:   sizes.iter().sum(),
+        };
+
+        let mut batch_start = 0;
+        while batch_start < records.len() {
+            let mut batch_end = batch_start + 1;
+            let mut batch_size = sizes[batch_start];
+
+            while batch_end < records.len() && batch_size + sizes[batch_end] <= BATCH_SIZE {
+                batch_size += sizes[batch_end];
+                batch_end += 1;
+            }
+
+            let batch = &records[batch_start..batch_end];
+            let prepared: Vec<_> = batch
+                .par_iter()
+                .map(|record| prepare_record(record, sector_size, &attributes, filetime))
+                .collect();
+
+            for (record, prepared) in batch.iter().zip(prepared) {
+                let (data, block, record_attributes) =
+                    finish_record(record, prepared?, to_u32(pos)?);
+
+                writer.write_all(&data)?;
+                pos += data.len() as u64;
+
+                if attributes.is_enabled() {
+                    file_attributes.push(record_attributes);
+                }
+
+                names.push(&record.name);
+                blocks.push(block);
+
+                status.name = &record.name;
+                status.files_written += 1;
+                status.bytes_written += u64::from(block.file_size);
+                progress(&status);
+            }
+
+            batch_start = batch_end;
+        }
+
+        if attributes.is_enabled() {
+            // the entry for the (attributes) file itself is left blank
+            file_attributes.push(FileAttributes::default());
+
+            let record = FileRecord {
+                name:    ATTRIBUTES_NAME.into(),
+                data:    FileData::Contents(write_attributes(&file_attributes, &attributes)),
+                options: FileOptions::default(),
+            };
+            let (data, block, _) =
+                store_record(&record, to_u32(pos)?, sector_size, &attributes, filetime)?;
+
+            writer.write_all(&data)?;
+            pos += data.len() as u64;
+
+            names.push(ATTRIBUTES_NAME);
+            blocks.push(block);
+        }
+
+        let hash_table = build_hash_table(&names, self.options.hash_table_size)?;
+
+        let hash_table_offset = to_u32(pos)?;
+        write_hash_table(&mut writer, &hash_table)?;
+        pos += hash_table.len() as u64 * 16;
+
+        let block_table_offset = to_u32(pos)?;
+        write_block_table(&mut writer, &blocks)?;
+        pos += blocks.len() as u64 * 16;
+
+        // the game ignores both sizes, while the editor rejects archives
+        // which claim a header smaller than the version 1 header
+        let (header_size, archive_size) = if self.options.protect {
+            (0, 0)
+        } else {
+            (HEADER_SIZE_V1, to_u32(pos)?)
+        };
+
+        let header = Header {
+            header_size,
+            archive_size,
+            format_version: 0,
+            sector_size_shift: self.options.sector_size_shift,
+            hash_table_offset,
+            block_table_offset,
+            hash_table_entries: hash_table.len() as u32,
+            block_table_entries: blocks.len() as u32,
+        };
+
+        writer.seek(SeekFrom::Start(0))?;
+        header.write(&mut writer)?;
+        writer.seek(SeekFrom::Start(pos))?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+pub(crate) fn to_u32(pos: u64) -> Result<u32, Error> {
+    if pos > u64::from(u32::MAX) {
+        Err(Error::ArchiveTooLarge)
+    } else {
+        Ok(pos as u32)
+    }
+}
+
+/// Picks a hash table size which leaves some room for collisions.
+fn hash_table_size(file_count: usize, requested: Option<u32>) -> Result<usize, Error> {
+    match requested {
+        Some(size) => {
+            let size = (size.max(1) as usize).next_power_of_two();
+
+            if size < file_count {
+                Err(Error::HashTableTooSmall {
+                    size:  size as u32,
+                    files: file_count as u32,
+                })
+            } else {
+                Ok(size)
+            }
+        }
+        None => Ok((file_count * 4 / 3 + 1).next_power_of_two().max(16)),
+    }
+}
+
+fn build_hash_table(names: &[&str], requested_size: Option<u32>) -> Result<Vec<HashEntry>, Error> {
+    let size = hash_table_size(names.len(), requested_size)?;
+    let mut table = vec![HashEntry::blank(); size];
+
+    for (block_index, name) in names.iter().enumerate() {
+        let start = hash_table_start(name, size);
+
+        // there is always a free slot, since the size was checked above
+        let slot = (0..size)
+            .map(|i| (start + i) % size)
+            .find(|i| table[*i].is_empty())
+            .unwrap();
+
+        table[slot] = HashEntry::new(name, block_index as u32);
+    }
+
+    Ok(table)
+}
+
+/// A file which is compressed, but not encrypted yet,
+/// since the encryption key can depend on the position of the file.
+pub(crate) enum Prepared {
+    Sectors {
+        sectors:    Vec<Vec<u8>>,
+        file_size:  u32,
+        attributes: FileAttributes,
+    },
+    Raw {
+        data:       Vec<u8>,
+        block:      BlockEntry,
+        attributes: FileAttributes,
+    },
+}
+
+/// Reads and compresses a file. This is the expensive part of writing
+/// an archive, and does not depend on the position of the file,
+/// so it can run in parallel.
+pub(crate) fn prepare_record(
+    record: &FileRecord,
+    sector_size: usize,
+    attributes: &AttributesOptions,
+    filetime: u64,
+) -> Result<Prepared, Error> {
+    let contents: Cow<[u8]> = match &record.data {
+        FileData::Contents(contents) => Cow::Borrowed(contents),
+        FileData::Disk(path) => Cow::Owned(fs::read(path).map_err(|source| Error::Read {
+            path: path.clone(),
+            source,
+        })?),
+        FileData::Raw(raw) if raw.is_portable(&record.name, sector_size) => {
+            let stored_attributes = raw
+                .attributes
+                .as_ref()
+                .filter(|(options, _)| options.covers(attributes));
+
+            // attributes of the source archive are reused where possible,
+            // so that unchanged files keep their timestamps
+            let attributes = match stored_attributes {
+                Some((_, stored)) => stored.clone(),
+                None if attributes.is_enabled() => {
+                    FileAttributes::compute(&raw.decode()?, attributes, filetime)
+                }
+                None => FileAttributes::default(),
+            };
+
+            return Ok(Prepared::Raw {
+                data: raw.data.clone(),
+                block: raw.block,
+                attributes,
+            });
+        }
+        FileData::Raw(raw) => Cow::Owned(raw.decode()?),
+    };
+
+    let file_size = u32::try_from(contents.len()).map_err(|_| Error::FileTooLarge {
+        name: record.name.clone(),
+    })?;
+
+    Ok(Prepared::Sectors {
+        sectors: compress_file(&contents, record.options.compression, sector_size),
+        file_size,
+        attributes: FileAttributes::compute(&contents, attributes, filetime),
+    })
+}
+
+/// Encrypts a prepared file for its final position, returning its raw data,
+/// the block table entry describing it, and its attributes.
+pub(crate) fn finish_record(
+    record: &FileRecord,
+    prepared: Prepared,
+    file_pos: u32,
+) -> (Vec<u8>, BlockEntry, FileAttributes) {
+    let (mut sectors, file_size, attributes) = match prepared {
+        Prepared::Raw {
+            data,
+            block,
+            attributes,
+        } => return (data, BlockEntry { file_pos, ..block }, attributes),
+        Prepared::Sectors {
+            sectors,
+            file_size,
+            attributes,
+        } => (sectors, file_size, attributes),
+    };
+
+    let options = record.options;
+    let mut block = BlockEntry {
+        file_pos,
+        compressed_size: 0,
+        file_size,
+        flags: MPQ_FILE_EXISTS,
+    };
+
+    if file_size == 0 {
+        return (Vec::new(), block, attributes);
+    }
+
+    let key = if options.encrypt {
+        block.flags |= MPQ_FILE_ENCRYPTED;
+
+        if options.adjust_key {
+            block.flags |= MPQ_FILE_ADJUST_KEY;
+        }
+
+        Some(file_key(
+            &record.name,
+            file_pos,
+            file_size,
+            options.adjust_key,
+        ))
+    } else {
+        None
+    };
+
+    if let Some(key) = key {
+        for (i, sector) in sectors.iter_mut().enumerate() {
+            encrypt_mpq_block(sector, key.wrapping_add(i as u32));
+        }
+    }
+
+    let data = if options.compression == Compression::None {
+        sectors.concat()
+    } else {
+        block.flags |= MPQ_FILE_COMPRESS;
+
+        let mut offset_table = Vec::with_capacity((sectors.len() + 1) * 4);
+        let mut offset = (sectors.len() as u32 + 1) * 4;
+
+        offset_table.write_u32::<LE>(offset).unwrap();
+        for sector in &sectors {
+            offset += sector.len() as u32;
+            offset_table.write_u32::<LE>(offset).unwrap();
+        }
+
+        if let Some(key) = key {
+            encrypt_mpq_block(&mut offset_table, key.wrapping_sub(1));
+        }
+
+        let mut data = offset_table;
+        for sector in sectors {
+            data.extend(sector);
+        }
+
+        data
+    };
+
+    block.compressed_size = data.len() as u32;
+
+    (data, block, attributes)
+}
+
+/// Prepares and finishes a file in one go.
+pub(crate) fn store_record(
+    record: &FileRecord,
+    file_pos: u32,
+    sector_size: usize,
+    attributes: &AttributesOptions,
+    filetime: u64,
+) -> Result<(Vec<u8>, BlockEntry, FileAttributes), Error> {
+    let prepared = prepare_record(record, sector_size, attributes, filetime)?;
+
+    Ok(finish_record(record, prepared, file_pos))
+}
+
+/// Splits a file into sectors and compresses them in parallel.
+/// Sectors which do not get smaller are stored uncompressed.
+fn compress_file(contents: &[u8], compression: Compression, sector_size: usize) -> Vec<Vec<u8>> {
+    contents
+        .par_chunks(sector_size)
+        .map(|sector| compress_sector(sector, compression).unwrap_or_else(|| sector.to_vec()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::archive::Archive;
+
+    use super::*;
+
+    fn sample(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn write(creator: &mut Creator) -> Archive<Cursor<Vec<u8>>> {
+        let mut buf = Cursor::new(Vec::new());
+        creator.write(&mut buf).unwrap();
+
+        Archive::open(Cursor::new(buf.into_inner())).unwrap()
+    }
+
+    #[test]
+    fn compression_roundtrip() {
+        for compression in &[Compression::None, Compression::Zlib, Compression::Bzip2] {
+            let options = FileOptions {
+                compression: *compression,
+                ..Default::default()
+            };
+
+            let mut creator = Creator::default();
+            creator.add_file("war3map.j", sample(20_000), options);
+            creator.add_file("random.bin", b"\x8f\x12\x00\x7e".to_vec(), options);
+
+            let mut archive = write(&mut creator);
+            assert_eq!(archive.read_file("war3map.j").unwrap(), sample(20_000));
+            assert_eq!(
+                archive.read_file("random.bin").unwrap(),
+                b"\x8f\x12\x00\x7e"
+            );
+
+            let raw = archive.read_raw_file("war3map.j").unwrap();
+            assert_eq!(raw.compression(), *compression);
+        }
+    }
+
+    #[test]
+    fn encryption_roundtrip() {
+        for adjust_key in &[false, true] {
+            let options = FileOptions {
+                encrypt: true,
+                adjust_key: *adjust_key,
+                ..Default::default()
+            };
+
+            let mut creator = Creator::default();
+            creator.add_file("war3map.w3e", b"W3E!".to_vec(), FileOptions::default());
+            creator.add_file("scripts\\war3map.lua", sample(10_000), options);
+
+            let mut archive = write(&mut creator);
+            let entry = archive.stat("scripts\\war3map.lua").unwrap();
+            assert_ne!(entry.flags & MPQ_FILE_ENCRYPTED, 0);
+            assert_eq!(entry.flags & MPQ_FILE_ADJUST_KEY != 0, *adjust_key);
+            assert_eq!(
+                archive.read_file("scripts/war3map.lua").unwrap(),
+                sample(10_000)
+            );
+        }
+    }
+
+    #[test]
+    fn sector_size_roundtrip() {
+        for shift in 0..6 {
+            let mut creator = Creator::new(CreatorOptions {
+                sector_size_shift: shift,
+                ..Default::default()
+            });
+            creator.add_file("war3map.j", sample(70_000), FileOptions::default());
+
+            let mut archive = write(&mut creator);
+            assert_eq!(archive.sector_size(), 512 << shift);
+            assert_eq!(archive.read_file("war3map.j").unwrap(), sample(70_000));
+        }
+    }
+
+    #[test]
+    fn empty_file_roundtrip() {
+        let options = FileOptions {
+            encrypt: true,
+            ..Default::default()
+        };
+
+        let mut creator = Creator::default();
+        creator.add_file("empty.txt", Vec::new(), options);
+
+        let mut archive = write(&mut creator);
+        assert_eq!(archive.stat("empty.txt").unwrap().file_size, 0);
+        assert!(archive.read_file("empty.txt").unwrap().is_empty());
+        assert_eq!(archive.files().unwrap(), vec!["empty.txt"]);
+    }
+
+    #[test]
+    fn attributes_roundtrip() {
+        let filetime = to_filetime(SystemTime::UNIX_EPOCH);
+        let mut creator = Creator::new(CreatorOptions {
+            attributes: AttributesOptions::all(),
+            timestamp: Some(SystemTime::UNIX_EPOCH),
+            ..Default::default()
+        });
+        creator.add_file("war3map.j", sample(5000), FileOptions::default());
+
+        let mut archive = write(&mut creator);
+        let block_index = archive.stat("war3map.j").unwrap().block_index as usize;
+        let (options, entries) = archive.attributes().unwrap().clone();
+        let expected = FileAttributes::compute(&sample(5000), &AttributesOptions::all(), filetime);
+
+        assert_eq!(options, AttributesOptions::all());
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[block_index].crc32, expected.crc32);
+        assert_eq!(entries[block_index].md5, expected.md5);
+        assert_eq!(entries[block_index].filetime, filetime);
+    }
+
+    #[test]
+    fn hash_table_overflow() {
+        let mut creator = Creator::new(CreatorOptions {
+            hash_table_size: Some(2),
+            ..Default::default()
+        });
+        creator.add_file("a.txt", b"a".to_vec(), FileOptions::default());
+        creator.add_file("b.txt", b"b".to_vec(), FileOptions::default());
+
+        let result = creator.write(Cursor::new(Vec::new()));
+        assert!(matches!(
+            result,
+            Err(Error::HashTableTooSmall { size: 2, files: 3 })
+        ));
+
+        creator.options_mut().hash_table_size = Some(3);
+        let mut archive = write(&mut creator);
+        assert_eq!(archive.read_file("b.txt").unwrap(), b"b");
+    }
+
+    #[test]
+    fn raw_copy_keeps_compression() {
+        let options = FileOptions {
+            encrypt:     true,
+            compression: Compression::Bzip2,
+            adjust_key:  true,
+        };
+
+        let mut creator = Creator::default();
+        creator.add_file("war3map.j", sample(20_000), options);
+        let mut source = write(&mut creator);
+
+        // a different sector size and position force the file to be re-encoded
+        let mut creator = Creator::new(CreatorOptions {
+            sector_size_shift: 1,
+            ..Default::default()
+        });
+        creator.add_file("war3map.w3e", sample(100), FileOptions::default());
+        creator.add_raw_file("war3map.j", source.read_raw_file("war3map.j").unwrap());
+
+        let mut archive = write(&mut creator);
+        let raw = archive.read_raw_file("war3map.j").unwrap();
+        assert_eq!(raw.compression(), Compression::Bzip2);
+        assert_ne!(raw.block.flags & MPQ_FILE_ADJUST_KEY, 0);
+        assert_eq!(archive.read_file("war3map.j").unwrap(), sample(20_000));
+    }
+
+    #[test]
+    fn protected_archive_header() {
+        let mut creator = Creator::new(CreatorOptions {
+            protect: true,
+            ..Default::default()
+        });
+        creator.add_file("war3map.j", sample(5000), FileOptions::default());
+
+        let mut buf = Cursor::new(Vec::new());
+        creator.write(&mut buf).unwrap();
+        let buf = buf.into_inner();
+
+        // header size and archive size
+        assert_eq!(&buf[4..12], &[0; 8]);
+
+        let mut archive = Archive::open(Cursor::new(buf)).unwrap();
+        assert!(archive.stat("(listfile)").is_none());
+        assert_eq!(archive.read_file("war3map.j").unwrap(), sample(5000));
+    }
+
+    #[test]
+    fn missing_disk_file() {
+        let mut creator = Creator::default();
+        creator.add_file_from_disk("war3map.j", "does/not/exist.j", FileOptions::default());
+
+        let result = creator.write(Cursor::new(Vec::new()));
+        assert!(matches!(result, Err(Error::Read { .. })));
+    }
+}
@@ -0,0 +1,111 @@
+use byteorder::{ByteOrder, LE};
+use lazy_static::lazy_static;
+
+use crate::consts::*;
+
+lazy_static! {
+    static ref CRYPT_TABLE: [u32; 0x500] = generate_crypt_table();
+}
+
+fn generate_crypt_table() -> [u32; 0x500] {
+    let mut table = [0u32; 0x500];
+    let mut seed: u32 = 0x0010_0001;
+
+    for index1 in 0..0x100 {
+        let mut index2 = index1;
+
+        for _ in 0..5 {
+            seed = (seed * 125 + 3) % 0x002A_AAAB;
+            let temp1 = (seed & 0xFFFF) << 0x10;
+
+            seed = (seed * 125 + 3) % 0x002A_AAAB;
+            let temp2 = seed & 0xFFFF;
+
+            table[index2] = temp1 | temp2;
+            index2 += 0x100;
+        }
+    }
+
+    table
+}
+
+/// Hashes a file name. Names are case-insensitive,
+/// and forward slashes are treated like backslashes.
+pub fn hash_string(source: &str, hash_type: u32) -> u32 {
+    let mut seed1: u32 = 0x7FED_7FED;
+    let mut seed2: u32 = 0xEEEE_EEEE;
+
+    for c in source.bytes() {
+        let c = match c.to_ascii_uppercase() {
+            b'/' => b'\\',
+            c => c,
+        } as u32;
+
+        seed1 = CRYPT_TABLE[((hash_type << 8) + c) as usize] ^ seed1.wrapping_add(seed2);
+        seed2 = c
+            .wrapping_add(seed1)
+            .wrapping_add(seed2)
+            .wrapping_add(seed2 << 5)
+            .wrapping_add(3);
+    }
+
+    seed1
+}
+
+/// Computes the encryption key of a file, which only depends on its base name,
+/// unless the key is adjusted by the file's position and size.
+pub fn file_key(name: &str, file_pos: u32, file_size: u32, adjust: bool) -> u32 {
+    let base_name = name.rsplit(&['\\', '/'][..]).next().unwrap_or(name);
+    let key = hash_string(base_name, HASH_FILE_KEY);
+
+    if adjust {
+        key.wrapping_add(file_pos) ^ file_size
+    } else {
+        key
+    }
+}
+
+fn next_key(key: u32) -> u32 {
+    ((!key << 0x15).wrapping_add(0x1111_1111)) | (key >> 0x0B)
+}
+
+/// Encrypts the data in place. Trailing bytes which
+/// do not form a full dword are left as they are.
+pub fn encrypt_mpq_block(data: &mut [u8], mut key: u32) {
+    let mut seed: u32 = 0xEEEE_EEEE;
+
+    for chunk in data.chunks_exact_mut(4) {
+        seed = seed.wrapping_add(CRYPT_TABLE[0x400 + (key & 0xFF) as usize]);
+
+        let plain = LE::read_u32(chunk);
+        let encrypted = plain ^ key.wrapping_add(seed);
+
+        key = next_key(key);
+        seed = plain
+            .wrapping_add(seed)
+            .wrapping_add(seed << 5)
+            .wrapping_add(3);
+
+        LE::write_u32(chunk, encrypted);
+    }
+}
+
+/// Decrypts the data in place. Trailing bytes which
+/// do not form a full dword are left as they are.
+pub fn decrypt_mpq_block(data: &mut [u8], mut key: u32) {
+    let mut seed: u32 = 0xEEEE_EEEE;
+
+    for chunk in data.chunks_exact_mut(4) {
+        seed = seed.wrapping_add(CRYPT_TABLE[0x400 + (key & 0xFF) as usize]);
+
+        let plain = LE::read_u32(chunk) ^ key.wrapping_add(seed);
+
+        key = next_key(key);
+        seed = plain
+            .wrapping_add(seed)
+            .wrapping_add(seed << 5)
+            .wrapping_add(3);
+
+        LE::write_u32(chunk, plain);
+    }
+}
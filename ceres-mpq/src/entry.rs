@@ -0,0 +1,49 @@
+use crate::consts::*;
+
+#[derive(Debug, Clone)]
+/// Information about a single block in an archive,
+/// along with the hash table entry pointing to it.
+pub struct FileEntry {
+    /// Only known if the name was found in the `(listfile)`.
+    pub name:            Option<String>,
+    pub block_index:     u32,
+    /// `None` for blocks which no hash table entry points to.
+    pub hash_index:      Option<u32>,
+    pub hash_a:          u32,
+    pub hash_b:          u32,
+    pub locale:          u16,
+    pub file_pos:        u32,
+    pub compressed_size: u32,
+    pub file_size:       u32,
+    pub flags:           u32,
+}
+
+impl FileEntry {
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & MPQ_FILE_ENCRYPTED != 0
+    }
+
+    pub fn is_key_adjusted(&self) -> bool {
+        self.flags & MPQ_FILE_ADJUST_KEY != 0
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & (MPQ_FILE_COMPRESS | MPQ_FILE_IMPLODE) != 0
+    }
+
+    pub fn is_single_unit(&self) -> bool {
+        self.flags & MPQ_FILE_SINGLE_UNIT != 0
+    }
+
+    /// Deleted files either carry a delete marker, or their block
+    /// is no longer marked as existing.
+    pub fn is_deleted(&self) -> bool {
+        self.flags & MPQ_FILE_DELETE_MARKER != 0 || self.flags & MPQ_FILE_EXISTS == 0
+    }
+
+    /// The file hash as a single hex string, which identifies
+    /// a file even if its name is unknown.
+    pub fn hash_string(&self) -> String {
+        format!("{:08X}{:08X}", self.hash_a, self.hash_b)
+    }
+}
@@ -0,0 +1,37 @@
+use std::io::Error as IoError;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("IO error: {source}")]
+    Io {
+        #[from]
+        source: IoError,
+    },
+    #[error("Could not read {}: {source}", path.display())]
+    Read { path: PathBuf, source: IoError },
+    #[error("No MPQ header found")]
+    NoHeader,
+    #[error("File not found: {name}")]
+    FileNotFound { name: String },
+    #[error("File data is corrupted")]
+    Corrupted,
+    #[error("Unsupported compression method {mask:#04x}")]
+    UnsupportedCompression { mask: u8 },
+    #[error("File {name} exceeds the 4 GB limit of the MPQ format")]
+    FileTooLarge { name: String },
+    #[error("Archive exceeds the 4 GB limit of the MPQ format")]
+    ArchiveTooLarge,
+    #[error("Archive format version {version} is not supported")]
+    UnsupportedVersion { version: u16 },
+    #[error("Hash table size {size} is too small for {files} files")]
+    HashTableTooSmall { size: u32, files: u32 },
+}
+
+impl Error {
+    pub(crate) fn file_not_found(name: &str) -> Error {
+        Error::FileNotFound { name: name.into() }
+    }
+}
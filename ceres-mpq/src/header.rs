@@ -0,0 +1,59 @@
+use std::io::{Error as IoError, Write};
+
+use byteorder::{ByteOrder, WriteBytesExt, LE};
+
+use crate::consts::*;
+use crate::error::Error;
+
+#[derive(Debug, Clone)]
+/// The MPQ archive header. Only the fields of the version 1 header are used,
+/// since that is the only version WC3 understands.
+pub struct Header {
+    pub header_size:         u32,
+    pub archive_size:        u32,
+    pub format_version:      u16,
+    pub sector_size_shift:   u16,
+    pub hash_table_offset:   u32,
+    pub block_table_offset:  u32,
+    pub hash_table_entries:  u32,
+    pub block_table_entries: u32,
+}
+
+impl Header {
+    pub fn read(buf: &[u8]) -> Result<Header, Error> {
+        if buf.len() < HEADER_SIZE_V1 as usize || &buf[0..4] != HEADER_MAGIC {
+            return Err(Error::NoHeader);
+        }
+
+        Ok(Header {
+            header_size:         LE::read_u32(&buf[4..]),
+            archive_size:        LE::read_u32(&buf[8..]),
+            format_version:      LE::read_u16(&buf[12..]),
+            sector_size_shift:   LE::read_u16(&buf[14..]),
+            hash_table_offset:   LE::read_u32(&buf[16..]),
+            block_table_offset:  LE::read_u32(&buf[20..]),
+            hash_table_entries:  LE::read_u32(&buf[24..]),
+            block_table_entries: LE::read_u32(&buf[28..]),
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), IoError> {
+        writer.write_all(HEADER_MAGIC)?;
+        writer.write_u32::<LE>(self.header_size)?;
+        writer.write_u32::<LE>(self.archive_size)?;
+        writer.write_u16::<LE>(self.format_version)?;
+        writer.write_u16::<LE>(self.sector_size_shift)?;
+        writer.write_u32::<LE>(self.hash_table_offset)?;
+        writer.write_u32::<LE>(self.block_table_offset)?;
+        writer.write_u32::<LE>(self.hash_table_entries)?;
+        writer.write_u32::<LE>(self.block_table_entries)?;
+
+        Ok(())
+    }
+
+    pub fn sector_size(&self) -> usize {
+        // protected maps like to put garbage here, but the game
+        // only looks at the low bits as well
+        512 << (self.sector_size_shift & 0x1F).min(20)
+    }
+}
@@ -0,0 +1,24 @@
+//! Reading and writing of MPQ archives, as used by WC3 maps.
+//!
+//! Only the parts of the format that WC3 cares about are supported:
+//! version 1 archives with zlib and bzip2 compressed files.
+
+mod consts;
+mod crypto;
+mod header;
+mod table;
+mod compression;
+mod attributes;
+mod archive;
+mod creator;
+mod patch;
+mod entry;
+mod error;
+
+pub use crate::archive::{Archive, RawFile};
+pub use crate::attributes::AttributesOptions;
+pub use crate::compression::Compression;
+pub use crate::creator::{Creator, CreatorOptions, FileOptions, Progress};
+pub use crate::entry::FileEntry;
+pub use crate::error::Error;
+pub use crate::patch::PatchStats;
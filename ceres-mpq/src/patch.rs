@@ -0,0 +1,453 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::SystemTime;
+
+use indexmap::IndexMap;
+
+use crate::archive::Archive;
+use crate::attributes::*;
+use crate::consts::*;
+use crate::creator::{store_record, to_u32, Creator, FileData, FileOptions, FileRecord};
+use crate::error::Error;
+use crate::table::*;
+
+#[derive(Debug, Clone, Default)]
+/// What happened during `Creator::patch`.
+pub struct PatchStats {
+    /// Files appended to the archive.
+    pub written:   usize,
+    /// Files skipped because the archive already had identical contents.
+    pub unchanged: usize,
+    pub removed:   usize,
+    /// Position right after the last byte of the archive,
+    /// which is where the stream should be cut off.
+    pub end:       u64,
+}
+
+impl Creator {
+    /// Adds the files of this creator to an existing archive, and removes
+    /// the given files from it, without rewriting the rest of the archive.
+    ///
+    /// New data and new tables are appended at the end of the archive,
+    /// and the header is updated to point at them. Old versions of replaced
+    /// files are left in place as unused space, so archives grow with every
+    /// patch. If the archive has MD5 attributes, files with identical contents
+    /// are skipped. Files copied from another archive are compared by their
+    /// own MD5 attribute if they have one, so that they are not decompressed.
+    ///
+    /// New data is written right after the end of the archive, so anything
+    /// stored behind it, like the signature footer of a map, is overwritten,
+    /// even if patching fails later on. Callers which keep data there have
+    /// to restore it themselves. Apart from that, the archive is left as it
+    /// was on disk on error, but the `Archive` itself should be opened again.
+    ///
+    /// The hash table keeps its size, so adding more files than it can hold
+    /// fails with `Error::HashTableTooSmall`. The sector size of the archive
+    /// is used instead of the one in the creator options.
+    ///
+    /// If the creator options ask for protection, the `(listfile)` is removed
+    /// instead of being rewritten.
+    pub fn patch<F: Read + Write + Seek>(
+        &mut self,
+        archive: &mut Archive<F>,
+        removed: &[String],
+    ) -> Result<PatchStats, Error> {
+        if archive.header.format_version != 0 {
+            return Err(Error::UnsupportedVersion {
+                version: archive.header.format_version,
+            });
+        }
+
+        let sector_size = archive.header.sector_size();
+        let filetime = to_filetime(self.options.timestamp.unwrap_or(SystemTime::UNIX_EPOCH));
+        let old_attributes = archive.attributes().cloned();
+
+        let attributes = if self.options.attributes.is_enabled() {
+            self.options.attributes
+        } else {
+            old_attributes
+                .as_ref()
+                .map(|(options, _)| *options)
+                .unwrap_or_default()
+        };
+        let compare_md5 = old_attributes
+            .as_ref()
+            .map(|(options, _)| options.md5)
+            .unwrap_or(false);
+        let mut file_attributes = old_attributes
+            .map(|(_, entries)| entries)
+            .unwrap_or_default();
+
+        let mut names: IndexMap<String, String> = archive
+            .files()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|name| (name.to_ascii_uppercase(), name))
+            .collect();
+
+        let mut stats = PatchStats::default();
+        let mut pos = archive_end(archive);
+
+        let mut removed: Vec<&str> = removed.iter().map(|name| name.as_str()).collect();
+        if self.options.protect {
+            removed.push(LISTFILE_NAME);
+        }
+
+        for name in removed {
+            let name = name.replace('/', "\\");
+
+            if let Some(hash_index) = archive.find_hash_index(&name) {
+                let block_index = archive.hash_table[hash_index].block_index as usize;
+                archive.hash_table[hash_index].block_index = HASH_ENTRY_DELETED;
+
+                if let Some(block) = archive.block_table.get_mut(block_index) {
+                    *block = BlockEntry::default();
+                }
+
+                stats.removed += 1;
+            }
+
+            names.shift_remove(&name.to_ascii_uppercase());
+        }
+
+        for record in self.files.values() {
+            if compare_md5 && is_unchanged(archive, &file_attributes, record) {
+                stats.unchanged += 1;
+                continue;
+            }
+
+            let block_index = place_file(archive, &record.name)?;
+            let (data, block, record_attributes) =
+                store_record(record, to_u32(pos)?, sector_size, &attributes, filetime)?;
+            pos = append(archive, pos, &data)?;
+
+            archive.block_table[block_index] = block;
+            file_attributes.resize(archive.block_table.len(), FileAttributes::default());
+            file_attributes[block_index] = record_attributes;
+
+            names.insert(record.name.to_ascii_uppercase(), record.name.clone());
+            stats.written += 1;
+        }
+
+        let mut listfile = Vec::new();
+        for name in names.values() {
+            listfile.extend_from_slice(name.as_bytes());
+            listfile.extend_from_slice(b"\r\n");
+        }
+
+        let attributes_index = if attributes.is_enabled() {
+            // placed up front, so that the attributes cover every block
+            let block_index = place_file(archive, ATTRIBUTES_NAME)?;
+            file_attributes.resize(archive.block_table.len(), FileAttributes::default());
+            Some(block_index)
+        } else {
+            None
+        };
+
+        let listfile_index = if self.options.protect {
+            None
+        } else {
+            Some(place_file(archive, LISTFILE_NAME)?)
+        };
+        file_attributes.resize(archive.block_table.len(), FileAttributes::default());
+
+        if let Some(block_index) = attributes_index {
+            // the entry for the (attributes) file itself is left blank
+            file_attributes[block_index] = FileAttributes::default();
+        }
+
+        if let Some(block_index) = listfile_index {
+            let record = FileRecord {
+                name:    LISTFILE_NAME.into(),
+                data:    FileData::Contents(listfile),
+                options: FileOptions::default(),
+            };
+            let (data, block, listfile_attributes) =
+                store_record(&record, to_u32(pos)?, sector_size, &attributes, filetime)?;
+            pos = append(archive, pos, &data)?;
+            archive.block_table[block_index] = block;
+            file_attributes[block_index] = listfile_attributes;
+        }
+
+        if let Some(block_index) = attributes_index {
+            let record = FileRecord {
+                name:    ATTRIBUTES_NAME.into(),
+                data:    FileData::Contents(write_attributes(&file_attributes, &attributes)),
+                options: FileOptions::default(),
+            };
+            let (data, block, _) =
+                store_record(&record, to_u32(pos)?, sector_size, &attributes, filetime)?;
+            pos = append(archive, pos, &data)?;
+            archive.block_table[block_index] = block;
+        }
+
+        let offset = archive.offset;
+        let writer = &mut archive.reader;
+
+        let hash_table_offset = to_u32(pos)?;
+        writer.seek(SeekFrom::Start(offset + pos))?;
+        write_hash_table(&mut *writer, &archive.hash_table)?;
+        pos += archive.hash_table.len() as u64 * 16;
+
+        let block_table_offset = to_u32(pos)?;
+        write_block_table(&mut *writer, &archive.block_table)?;
+        pos += archive.block_table.len() as u64 * 16;
+
+        let header = &mut archive.header;
+        if self.options.protect {
+            header.header_size = 0;
+            header.archive_size = 0;
+        } else {
+            header.header_size = HEADER_SIZE_V1;
+            header.archive_size = to_u32(pos)?;
+        }
+        header.hash_table_offset = hash_table_offset;
+        header.block_table_offset = block_table_offset;
+        header.hash_table_entries = archive.hash_table.len() as u32;
+        header.block_table_entries = archive.block_table.len() as u32;
+
+        writer.seek(SeekFrom::Start(offset))?;
+        header.write(&mut *writer)?;
+        writer.seek(SeekFrom::Start(offset + pos))?;
+        writer.flush()?;
+
+        // the cached attributes no longer match the block table
+        archive.attributes = None;
+
+        stats.end = offset + pos;
+
+        Ok(stats)
+    }
+}
+
+/// Everything past this position (relative to the archive start)
+/// is free to be overwritten.
+fn archive_end<F: Read + Seek>(archive: &Archive<F>) -> u64 {
+    let header = &archive.header;
+    let tables = [
+        (header.hash_table_offset, header.hash_table_entries),
+        (header.block_table_offset, header.block_table_entries),
+    ];
+
+    let table_end = tables
+        .iter()
+        .map(|(offset, entries)| u64::from(*offset) + u64::from(*entries) * 16);
+    let block_end = archive
+        .block_table
+        .iter()
+        .map(|block| u64::from(block.file_pos) + u64::from(block.compressed_size));
+
+    table_end
+        .chain(block_end)
+        .chain(Some(u64::from(header.archive_size)))
+        .max()
+        .unwrap_or(0)
+}
+
+fn append<F: Read + Write + Seek>(
+    archive: &mut Archive<F>,
+    pos: u64,
+    data: &[u8],
+) -> Result<u64, Error> {
+    archive.reader.seek(SeekFrom::Start(archive.offset + pos))?;
+    archive.reader.write_all(data)?;
+
+    Ok(pos + data.len() as u64)
+}
+
+/// Whether the archive already contains the file with the same contents,
+/// according to its MD5 attribute.
+fn is_unchanged<F: Read + Seek>(
+    archive: &Archive<F>,
+    file_attributes: &[FileAttributes],
+    record: &FileRecord,
+) -> bool {
+    let block_index = match archive.find_hash_index(&record.name) {
+        Some(hash_index) => archive.hash_table[hash_index].block_index as usize,
+        None => return false,
+    };
+
+    let (block, stored) = match (
+        archive.block_table.get(block_index),
+        file_attributes.get(block_index),
+    ) {
+        (Some(block), Some(stored)) => (block, stored),
+        _ => return false,
+    };
+
+    if block.flags & MPQ_FILE_EXISTS == 0
+        || record.data.size().ok() != Some(u64::from(block.file_size))
+    {
+        return false;
+    }
+
+    let contents: Cow<[u8]> = match &record.data {
+        FileData::Contents(contents) => Cow::Borrowed(contents),
+        FileData::Disk(path) => match fs::read(path) {
+            Ok(contents) => Cow::Owned(contents),
+            Err(_) => return false,
+        },
+        FileData::Raw(raw) => match &raw.attributes {
+            Some((options, attributes)) if options.md5 => return attributes.md5 == stored.md5,
+            _ => match raw.decode() {
+                Ok(contents) => Cow::Owned(contents),
+                Err(_) => return false,
+            },
+        },
+    };
+
+    let md5_only = AttributesOptions {
+        md5: true,
+        ..Default::default()
+    };
+
+    FileAttributes::compute(&contents, &md5_only, 0).md5 == stored.md5
+}
+
+/// Returns the block index for a file, adding a hash table entry
+/// and a new block if the archive does not have the file yet.
+fn place_file<F: Read + Seek>(archive: &mut Archive<F>, name: &str) -> Result<usize, Error> {
+    let block_count = archive.block_table.len();
+
+    if let Some(hash_index) = archive.find_hash_index(name) {
+        let block_index = archive.hash_table[hash_index].block_index as usize;
+
+        if block_index < block_count {
+            return Ok(block_index);
+        }
+
+        archive.block_table.push(BlockEntry::default());
+        archive.hash_table[hash_index].block_index = block_count as u32;
+
+        return Ok(block_count);
+    }
+
+    let size = archive.hash_table.len();
+    let too_small = Error::HashTableTooSmall {
+        size:  size as u32,
+        files: block_count as u32 + 1,
+    };
+
+    if size == 0 {
+        return Err(too_small);
+    }
+
+    let start = hash_table_start(name, size);
+    let slot = (0..size)
+        .map(|i| (start + i) % size)
+        .find(|i| archive.hash_table[*i].is_empty() || archive.hash_table[*i].is_deleted())
+        .ok_or(too_small)?;
+
+    archive.block_table.push(BlockEntry::default());
+    archive.hash_table[slot] = HashEntry::new(name, block_count as u32);
+
+    Ok(block_count)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::creator::CreatorOptions;
+
+    use super::*;
+
+    fn md5_options() -> CreatorOptions {
+        CreatorOptions {
+            attributes: AttributesOptions {
+                md5: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn write(creator: &mut Creator) -> Archive<Cursor<Vec<u8>>> {
+        let mut buf = Cursor::new(Vec::new());
+        creator.write(&mut buf).unwrap();
+
+        Archive::open(Cursor::new(buf.into_inner())).unwrap()
+    }
+
+    fn reopen(archive: Archive<Cursor<Vec<u8>>>, end: u64) -> Archive<Cursor<Vec<u8>>> {
+        let mut data = archive.into_inner().into_inner();
+        data.truncate(end as usize);
+
+        Archive::open(Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn patch_appends_changes_and_removes_files() {
+        let mut creator = Creator::new(md5_options());
+        creator.add_file("war3map.j", &b"old script"[..], FileOptions::default());
+        creator.add_file("war3map.w3i", &b"info"[..], FileOptions::default());
+        creator.add_file("unused.blp", &b"texture"[..], FileOptions::default());
+        let mut archive = write(&mut creator);
+
+        let mut creator = Creator::new(md5_options());
+        creator.add_file("war3map.j", &b"new script"[..], FileOptions::default());
+        creator.add_file("war3map.w3i", &b"info"[..], FileOptions::default());
+        creator.add_file("new.mdx", &b"model"[..], FileOptions::default());
+        let stats = creator
+            .patch(&mut archive, &["unused.blp".to_string()])
+            .unwrap();
+
+        assert_eq!(stats.written, 2);
+        assert_eq!(stats.unchanged, 1);
+        assert_eq!(stats.removed, 1);
+
+        let mut archive = reopen(archive, stats.end);
+        assert_eq!(archive.read_file("war3map.j").unwrap(), b"new script");
+        assert_eq!(archive.read_file("war3map.w3i").unwrap(), b"info");
+        assert_eq!(archive.read_file("new.mdx").unwrap(), b"model");
+        assert!(archive.read_file("unused.blp").is_err());
+
+        let mut files = archive.files().unwrap();
+        files.sort();
+        assert_eq!(files, vec!["new.mdx", "war3map.j", "war3map.w3i"]);
+    }
+
+    #[test]
+    fn raw_files_are_compared_by_their_md5() {
+        let mut creator = Creator::new(md5_options());
+        creator.add_file("war3map.j", &b"script"[..], FileOptions::default());
+        creator.add_file("war3map.w3i", &b"info"[..], FileOptions::default());
+        let mut base = write(&mut creator);
+        let mut output = write(&mut creator);
+
+        let mut creator = Creator::new(md5_options());
+        let script = base.read_raw_file("war3map.j").unwrap();
+        assert!(matches!(&script.attributes, Some((options, _)) if options.md5));
+        creator.add_raw_file("war3map.j", script);
+        creator.add_file("war3map.w3i", &b"new info"[..], FileOptions::default());
+
+        let stats = creator.patch(&mut output, &[]).unwrap();
+        assert_eq!(stats.unchanged, 1);
+        assert_eq!(stats.written, 1);
+
+        let mut output = reopen(output, stats.end);
+        assert_eq!(output.read_file("war3map.j").unwrap(), b"script");
+        assert_eq!(output.read_file("war3map.w3i").unwrap(), b"new info");
+    }
+
+    #[test]
+    fn full_hash_tables_fail_the_patch() {
+        let mut creator = Creator::new(CreatorOptions {
+            hash_table_size: Some(4),
+            ..md5_options()
+        });
+        creator.add_file("a.txt", &b"a"[..], FileOptions::default());
+        let mut archive = write(&mut creator);
+
+        let mut creator = Creator::new(md5_options());
+        for name in &["b.txt", "c.txt", "d.txt"] {
+            creator.add_file(name, &b"new"[..], FileOptions::default());
+        }
+
+        match creator.patch(&mut archive, &[]) {
+            Err(Error::HashTableTooSmall { size: 4, .. }) => {}
+            other => panic!("unexpected result: {:?}", other.map(|stats| stats.written)),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{Error as IoError, Write};
+
+use byteorder::{ByteOrder, WriteBytesExt, LE};
+
+use crate::consts::*;
+use crate::crypto::*;
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HashEntry {
+    pub hash_a:      u32,
+    pub hash_b:      u32,
+    pub locale:      u16,
+    pub platform:    u16,
+    pub block_index: u32,
+}
+
+impl HashEntry {
+    pub fn blank() -> HashEntry {
+        HashEntry {
+            hash_a:      0xFFFF_FFFF,
+            hash_b:      0xFFFF_FFFF,
+            locale:      0xFFFF,
+            platform:    0xFFFF,
+            block_index: HASH_ENTRY_EMPTY,
+        }
+    }
+
+    pub fn new(name: &str, block_index: u32) -> HashEntry {
+        HashEntry {
+            hash_a: hash_string(name, HASH_NAME_A),
+            hash_b: hash_string(name, HASH_NAME_B),
+            locale: 0,
+            platform: 0,
+            block_index,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_index == HASH_ENTRY_EMPTY
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.block_index == HASH_ENTRY_DELETED
+    }
+
+    pub fn matches(&self, hash_a: u32, hash_b: u32) -> bool {
+        !self.is_empty() && !self.is_deleted() && self.hash_a == hash_a && self.hash_b == hash_b
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockEntry {
+    pub file_pos:        u32,
+    pub compressed_size: u32,
+    pub file_size:       u32,
+    pub flags:           u32,
+}
+
+/// Decrypts and parses a table. Tables which are cut short
+/// (a common protection trick) are read as far as possible.
+fn read_table<T, F>(mut data: Vec<u8>, key: &str, parse: F) -> Vec<T>
+where
+    F: Fn(&[u8]) -> T,
+{
+    decrypt_mpq_block(&mut data, hash_string(key, HASH_FILE_KEY));
+
+    data.chunks_exact(ENTRY_SIZE).map(parse).collect()
+}
+
+pub fn read_hash_table(data: Vec<u8>) -> Vec<HashEntry> {
+    read_table(data, HASH_TABLE_KEY, |buf| HashEntry {
+        hash_a:      LE::read_u32(&buf[0..]),
+        hash_b:      LE::read_u32(&buf[4..]),
+        locale:      LE::read_u16(&buf[8..]),
+        platform:    LE::read_u16(&buf[10..]),
+        block_index: LE::read_u32(&buf[12..]),
+    })
+}
+
+pub fn read_block_table(data: Vec<u8>) -> Vec<BlockEntry> {
+    read_table(data, BLOCK_TABLE_KEY, |buf| BlockEntry {
+        file_pos:        LE::read_u32(&buf[0..]),
+        compressed_size: LE::read_u32(&buf[4..]),
+        file_size:       LE::read_u32(&buf[8..]),
+        flags:           LE::read_u32(&buf[12..]),
+    })
+}
+
+pub fn write_hash_table<W: Write>(mut writer: W, table: &[HashEntry]) -> Result<(), IoError> {
+    let mut buf = Vec::with_capacity(table.len() * ENTRY_SIZE);
+
+    for entry in table {
+        buf.write_u32::<LE>(entry.hash_a)?;
+        buf.write_u32::<LE>(entry.hash_b)?;
+        buf.write_u16::<LE>(entry.locale)?;
+        buf.write_u16::<LE>(entry.platform)?;
+        buf.write_u32::<LE>(entry.block_index)?;
+    }
+
+    encrypt_mpq_block(&mut buf, hash_string(HASH_TABLE_KEY, HASH_FILE_KEY));
+    writer.write_all(&buf)
+}
+
+pub fn write_block_table<W: Write>(mut writer: W, table: &[BlockEntry]) -> Result<(), IoError> {
+    let mut buf = Vec::with_capacity(table.len() * ENTRY_SIZE);
+
+    for entry in table {
+        buf.write_u32::<LE>(entry.file_pos)?;
+        buf.write_u32::<LE>(entry.compressed_size)?;
+        buf.write_u32::<LE>(entry.file_size)?;
+        buf.write_u32::<LE>(entry.flags)?;
+    }
+
+    encrypt_mpq_block(&mut buf, hash_string(BLOCK_TABLE_KEY, HASH_FILE_KEY));
+    writer.write_all(&buf)
+}
+
+/// Index of the first hash table slot to probe for a name.
+pub fn hash_table_start(name: &str, table_size: usize) -> usize {
+    (hash_string(name, HASH_TABLE_OFFSET) as usize) & (table_size - 1)
+}